@@ -1,17 +1,18 @@
 use glib::clone;
 use log::{debug, info};
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
 use crate::app::AppRuntime;
 use crate::appop::AppOp;
 use crate::model::message::Message;
 use crate::util::i18n::i18n;
+use crate::util::permalink::Format;
 use crate::widgets::FileDialog::open;
 use gio::prelude::*;
 use gio::SimpleAction;
 use gtk::prelude::*;
 use libhandy::prelude::*;
-use matrix_sdk::identifiers::{EventId, RoomId};
+use matrix_sdk::identifiers::{EventId, RoomId, RoomIdOrAliasId};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum AppState {
@@ -21,6 +22,8 @@ pub enum AppState {
     Room,
     RoomSettings,
     MediaViewer,
+    Thread,
+    Search,
     AccountSettings,
     Directory,
 }
@@ -38,6 +41,8 @@ impl From<String> for AppState {
             "no-room" => AppState::NoRoom,
             "room" => AppState::Room,
             "media-viewer" => AppState::MediaViewer,
+            "thread" => AppState::Thread,
+            "search" => AppState::Search,
             "account-settings" => AppState::AccountSettings,
             "room-settings" => AppState::RoomSettings,
             "directory" => AppState::Directory,
@@ -54,6 +59,8 @@ impl From<AppState> for glib::Variant {
             AppState::NoRoom => "no-room".to_variant(),
             AppState::Room => "room".to_variant(),
             AppState::MediaViewer => "media-viewer".to_variant(),
+            AppState::Thread => "thread".to_variant(),
+            AppState::Search => "search".to_variant(),
             AppState::AccountSettings => "account-settings".to_variant(),
             AppState::RoomSettings => "room-setting".to_variant(),
             AppState::Directory => "directory".to_variant(),
@@ -74,6 +81,8 @@ pub fn new(appop: &AppOp) {
 
     let inv = SimpleAction::new("room_invite", None);
     let search = SimpleAction::new("search", None);
+    let search_messages = SimpleAction::new("search-messages", None);
+    let export_room = SimpleAction::new("export-room", None);
     let leave = SimpleAction::new("leave_room", None);
 
     let shortcuts = SimpleAction::new("shortcuts", None);
@@ -82,6 +91,10 @@ pub fn new(appop: &AppOp) {
     let main_menu = SimpleAction::new("main_menu", None);
 
     let open_room = SimpleAction::new("open-room", glib::VariantTy::new("s").ok());
+    let open_room_link = SimpleAction::new("open-room-link", glib::VariantTy::new("s").ok());
+    let open_permalink = SimpleAction::new("open-permalink", glib::VariantTy::new("s").ok());
+    let copy_room_link = SimpleAction::new("copy-room-link", None);
+    let copy_room_uri = SimpleAction::new("copy-room-uri", None);
     let deck_back = SimpleAction::new("deck-back", None);
     let back = SimpleAction::new("back", None);
     let media_viewer = SimpleAction::new("open-media-viewer", glib::VariantTy::new("s").ok());
@@ -92,6 +105,12 @@ pub fn new(appop: &AppOp) {
     // TODO: send file should be a message action
     let send_file = SimpleAction::new("send-file", None);
     let send_message = SimpleAction::new("send-message", None);
+    let cancel_relation = SimpleAction::new("cancel-relation", None);
+    let record_voice = SimpleAction::new("record-voice", None);
+    let cancel_voice = SimpleAction::new("cancel-voice", None);
+    let send_voice = SimpleAction::new("send-voice", None);
+    let retry_message = SimpleAction::new("retry-message", glib::VariantTy::new("s").ok());
+    let cancel_message = SimpleAction::new("cancel-message", glib::VariantTy::new("s").ok());
 
     let previous_room = SimpleAction::new("previous-room", None);
     let next_room = SimpleAction::new("next-room", None);
@@ -101,6 +120,15 @@ pub fn new(appop: &AppOp) {
     let last_room = SimpleAction::new("last-room", None);
     let older_messages = SimpleAction::new("older-messages", None);
     let newer_messages = SimpleAction::new("newer-messages", None);
+    let jump_to_unread = SimpleAction::new("jump-to-unread", None);
+    let jump_to_latest = SimpleAction::new("jump-to-latest", None);
+    let go_to_date = SimpleAction::new("go-to-date", None);
+    let mark_all_read = SimpleAction::new("mark-all-read", None);
+    let show_state_events = SimpleAction::new_stateful(
+        "show-state-events",
+        None,
+        &appop.show_state_events.to_variant(),
+    );
 
     app.add_action(&settings);
     app.add_action(&account);
@@ -111,12 +139,18 @@ pub fn new(appop: &AppOp) {
 
     app.add_action(&inv);
     app.add_action(&search);
+    app.add_action(&search_messages);
+    app.add_action(&export_room);
     app.add_action(&leave);
 
     app.add_action(&quit);
     app.add_action(&shortcuts);
     app.add_action(&about);
     app.add_action(&open_room);
+    app.add_action(&open_room_link);
+    app.add_action(&open_permalink);
+    app.add_action(&copy_room_link);
+    app.add_action(&copy_room_uri);
     app.add_action(&deck_back);
     app.add_action(&back);
     app.add_action(&directory);
@@ -127,6 +161,12 @@ pub fn new(appop: &AppOp) {
 
     app.add_action(&send_file);
     app.add_action(&send_message);
+    app.add_action(&cancel_relation);
+    app.add_action(&record_voice);
+    app.add_action(&cancel_voice);
+    app.add_action(&send_voice);
+    app.add_action(&retry_message);
+    app.add_action(&cancel_message);
 
     app.add_action(&previous_room);
     app.add_action(&next_room);
@@ -136,6 +176,11 @@ pub fn new(appop: &AppOp) {
     app.add_action(&last_room);
     app.add_action(&older_messages);
     app.add_action(&newer_messages);
+    app.add_action(&jump_to_unread);
+    app.add_action(&jump_to_latest);
+    app.add_action(&go_to_date);
+    app.add_action(&mark_all_read);
+    app.add_action(&show_state_events);
 
     // When activated, shuts down the application
     quit.connect_activate(clone!(@weak app => move |_action, _parameter| {
@@ -234,6 +279,23 @@ pub fn new(appop: &AppOp) {
         });
     }));
 
+    jump_to_unread.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.jump_to_first_unread());
+    }));
+    jump_to_latest.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.jump_to_latest();
+        });
+    }));
+    go_to_date.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.ui.show_go_to_date_dialog());
+    }));
+    mark_all_read.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.mark_all_as_read();
+        });
+    }));
+
     account.connect_activate(clone!(@strong app_runtime => move |_, _| {
         app_runtime.update_state_with(|state| {
             state.show_account_settings_dialog();
@@ -241,6 +303,31 @@ pub fn new(appop: &AppOp) {
         });
     }));
 
+    search_messages.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.open_search();
+            if state.ui.room_back_history.last() != Some(&AppState::Search) {
+                state.ui.room_back_history.push(AppState::Search);
+            }
+        });
+    }));
+
+    show_state_events.connect_activate(clone!(@strong app_runtime => move |action, _| {
+        let show = !action
+            .get_state()
+            .and_then(|state| state.get::<bool>())
+            .unwrap_or(true);
+        action.set_state(&show.to_variant());
+        app_runtime.update_state_with(move |state| {
+            state.set_show_state_events(show);
+        });
+    }));
+    export_room.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.show_export_dialog();
+        });
+    }));
+
     directory.connect_activate(clone!(@strong app_runtime => move |_, _| {
         app_runtime.update_state_with(|state| {
             state.set_state(AppState::Directory);
@@ -248,6 +335,38 @@ pub fn new(appop: &AppOp) {
         });
     }));
 
+    // Opens the room a link in a message points to, by ID or alias
+    open_room_link.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        let id = data
+            .and_then(|data| data.get_str())
+            .and_then(|id| RoomIdOrAliasId::try_from(id).ok());
+        if let Some(id) = id {
+            app_runtime.update_state_with(move |state| state.open_room_link(id));
+        }
+    }));
+
+    // Opens the room or the message a matrix.to link or a matrix: URI points to
+    open_permalink.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        if let Some(uri) = data.and_then(|data| data.get_str()).map(String::from) {
+            app_runtime.update_state_with(move |state| state.open_permalink(uri));
+        }
+    }));
+
+    copy_room_link.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            if let Some(room_id) = state.active_room.clone() {
+                state.copy_permalink(&room_id, None, Format::MatrixTo);
+            }
+        });
+    }));
+    copy_room_uri.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            if let Some(room_id) = state.active_room.clone() {
+                state.copy_permalink(&room_id, None, Format::MatrixUri);
+            }
+        });
+    }));
+
     /* TODO: We could pass a message to this to highlight it in the room history, might be
      * handy when opening the room from a notification */
     open_room.connect_activate(clone!(@strong app_runtime => move |_, data| {
@@ -323,18 +442,53 @@ pub fn new(appop: &AppOp) {
         }
     }));
 
+    cancel_relation.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.cancel_composer_relation());
+    }));
+
+    record_voice.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.start_voice_recording();
+        });
+    }));
+
+    cancel_voice.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| state.cancel_voice_recording());
+    }));
+
+    send_voice.connect_activate(clone!(@strong app_runtime => move |_, _| {
+        app_runtime.update_state_with(|state| {
+            state.send_voice_recording();
+        });
+    }));
+
+    retry_message.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        if let Some(txn_id) = data.and_then(|d| d.get_str()).map(String::from) {
+            app_runtime.update_state_with(move |state| state.retry_message(txn_id));
+        }
+    }));
+
+    cancel_message.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        if let Some(txn_id) = data.and_then(|d| d.get_str()).map(String::from) {
+            app_runtime.update_state_with(move |state| state.cancel_message(txn_id));
+        }
+    }));
+
     send_message.connect_activate(move |_, _| {
         app_runtime.update_state_with(|state| {
             let msg_entry = state.ui.sventry.view.clone();
+            let mentions = state.ui.sventry.mentions();
             if let Some(buffer) = msg_entry.get_buffer() {
                 let start = buffer.get_start_iter();
                 let end = buffer.get_end_iter();
 
-                if let Some(text) = buffer.get_text(&start, &end, false) {
-                    state.send_message(text.to_string());
-                }
+                let sent = buffer
+                    .get_text(&start, &end, false)
+                    .map_or(true, |text| state.send_message(text.to_string(), mentions));
 
-                buffer.set_text("");
+                if sent {
+                    buffer.set_text("");
+                }
             }
         });
     });
@@ -359,6 +513,8 @@ pub fn new(appop: &AppOp) {
     app.set_accels_for_action("app.last-room", &["<Ctrl>End"]);
     app.set_accels_for_action("app.older-messages", &["Page_Up"]);
     app.set_accels_for_action("app.newer-messages", &["Page_Down"]);
+    app.set_accels_for_action("app.jump-to-unread", &["<Shift>Page_Up"]);
+    app.set_accels_for_action("app.search-messages", &["<Ctrl>F"]);
     app.set_accels_for_action("app.back", &["Escape"]);
     app.set_accels_for_action("app.deck-back", &["Escape"]);
     app.set_accels_for_action("app.main_menu", &["F10"]);
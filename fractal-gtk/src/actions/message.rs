@@ -11,6 +11,7 @@ use crate::appop::AppOp;
 use crate::backend::HandleError;
 use crate::model::message::Message;
 use crate::util::i18n::i18n;
+use crate::util::permalink::Format;
 use gio::ActionGroupExt;
 use gio::ActionMapExt;
 use gio::SimpleAction;
@@ -28,25 +29,42 @@ pub fn new(app_runtime: AppRuntime) -> gio::SimpleActionGroup {
     let actions = SimpleActionGroup::new();
     /* Action for each message */
     let reply = SimpleAction::new("reply", glib::VariantTy::new("s").ok());
+    let edit = SimpleAction::new("edit", glib::VariantTy::new("s").ok());
+    let jump_to = SimpleAction::new("jump_to", glib::VariantTy::new("s").ok());
+    let open_thread = SimpleAction::new("open_thread", glib::VariantTy::new("s").ok());
     let open_with = SimpleAction::new("open_with", glib::VariantTy::new("s").ok());
     let save_as = SimpleAction::new("save_as", glib::VariantTy::new("s").ok());
     let copy_image = SimpleAction::new("copy_image", glib::VariantTy::new("s").ok());
     let copy_text = SimpleAction::new("copy_text", glib::VariantTy::new("s").ok());
+    let copy_link = SimpleAction::new("copy_link", glib::VariantTy::new("s").ok());
+    let copy_uri = SimpleAction::new("copy_uri", glib::VariantTy::new("s").ok());
+    let forward = SimpleAction::new("forward", glib::VariantTy::new("s").ok());
     let delete = SimpleAction::new("delete", glib::VariantTy::new("s").ok());
     let show_source = SimpleAction::new("show_source", glib::VariantTy::new("s").ok());
+    let show_edit_history = SimpleAction::new("show_edit_history", glib::VariantTy::new("s").ok());
 
     /* TODO: use stateful action to keep  track if the user already requested new messages */
     let load_more_messages =
         SimpleAction::new("request_older_messages", glib::VariantTy::new("s").ok());
+    let load_newer_messages =
+        SimpleAction::new("request_newer_messages", glib::VariantTy::new("s").ok());
 
     actions.add_action(&reply);
+    actions.add_action(&edit);
+    actions.add_action(&jump_to);
+    actions.add_action(&open_thread);
     actions.add_action(&open_with);
     actions.add_action(&save_as);
     actions.add_action(&copy_image);
     actions.add_action(&copy_text);
+    actions.add_action(&copy_link);
+    actions.add_action(&copy_uri);
+    actions.add_action(&forward);
     actions.add_action(&delete);
     actions.add_action(&show_source);
+    actions.add_action(&show_edit_history);
     actions.add_action(&load_more_messages);
+    actions.add_action(&load_newer_messages);
 
     show_source.connect_activate(clone!(@strong app_runtime => move |_, data| {
         let data = data.cloned();
@@ -62,6 +80,15 @@ pub fn new(app_runtime: AppRuntime) -> gio::SimpleActionGroup {
         });
     }));
 
+    show_edit_history.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        let data = data.cloned();
+        app_runtime.update_state_with(move |state| {
+            if let Some(event_id) = get_event_id(data.as_ref()) {
+                state.show_edit_history(event_id);
+            }
+        });
+    }));
+
     reply.connect_activate(clone!(@strong app_runtime => move |_, data| {
         let data = data.cloned();
         app_runtime.update_state_with(move |state| {
@@ -73,20 +100,36 @@ pub fn new(app_runtime: AppRuntime) -> gio::SimpleActionGroup {
                     error!("The action group app is not attached to the main window.");
                 }
             }
-            if let Some(buffer) = state.ui.sventry.view.get_buffer() {
-                let mut start = buffer.get_start_iter();
-                if let Some(m) = get_message(state, data.as_ref()) {
-                    let quote = m
-                        .body
-                        .lines()
-                        .map(|l| "> ".to_owned() + l)
-                        .collect::<Vec<String>>()
-                        .join("\n")
-                        + "\n"
-                        + "\n";
-                    buffer.insert(&mut start, &quote);
-                    state.ui.sventry.view.grab_focus();
-                }
+            if let Some(m) = get_message(state, data.as_ref()) {
+                state.start_replying(m);
+            }
+        });
+    }));
+
+    edit.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        let data = data.cloned();
+        app_runtime.update_state_with(move |state| {
+            if let Some(m) = get_message(state, data.as_ref()) {
+                state.start_editing(m);
+            }
+        });
+    }));
+
+    jump_to.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        let data = data.cloned();
+        app_runtime.update_state_with(move |state| {
+            if let Some(event_id) = get_event_id(data.as_ref()) {
+                state.jump_to_message(event_id);
+            }
+        });
+    }));
+
+    open_thread.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        let data = data.cloned();
+        app_runtime.update_state_with(move |state| {
+            if let Some(event_id) = get_event_id(data.as_ref()) {
+                state.open_thread(event_id);
+                state.ui.room_back_history.push(AppState::Thread);
             }
         });
     }));
@@ -192,6 +235,33 @@ pub fn new(app_runtime: AppRuntime) -> gio::SimpleActionGroup {
         });
     }));
 
+    copy_link.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        let data = data.cloned();
+        app_runtime.update_state_with(move |state| {
+            if let Some(m) = get_message(state, data.as_ref()) {
+                state.copy_permalink(&m.room, m.id, Format::MatrixTo);
+            }
+        });
+    }));
+
+    copy_uri.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        let data = data.cloned();
+        app_runtime.update_state_with(move |state| {
+            if let Some(m) = get_message(state, data.as_ref()) {
+                state.copy_permalink(&m.room, m.id, Format::MatrixUri);
+            }
+        });
+    }));
+
+    forward.connect_activate(clone!(@strong app_runtime => move |_, data| {
+        let data = data.cloned();
+        app_runtime.update_state_with(move |state| {
+            if let Some(event_id) = get_event_id(data.as_ref()) {
+                state.show_forward_dialog(event_id);
+            }
+        });
+    }));
+
     delete.connect_activate(clone!(@strong app_runtime => move |_, data| {
         let data = data.cloned();
         app_runtime.update_state_with(move |state| {
@@ -216,12 +286,21 @@ pub fn new(app_runtime: AppRuntime) -> gio::SimpleActionGroup {
         });
     }));
 
-    load_more_messages.connect_activate(move |_, data| {
+    load_more_messages.connect_activate(clone!(@strong app_runtime => move |_, data| {
         let data = data.cloned();
         app_runtime.update_state_with(move |state| {
             let id = get_room_id(data.as_ref());
             request_more_messages(state, id);
         });
+    }));
+
+    load_newer_messages.connect_activate(move |_, data| {
+        let data = data.cloned();
+        app_runtime.update_state_with(move |state| {
+            if let Some(id) = get_room_id(data.as_ref()) {
+                state.load_newer_window_messages(&id);
+            }
+        });
     });
 
     actions
@@ -233,27 +312,36 @@ fn get_message(op: &AppOp, id: Option<&glib::Variant>) -> Option<Message> {
         .and_then(|evid| get_message_by_id(op, evid))
 }
 
-fn request_more_messages(op: &AppOp, id: Option<RoomId>) -> Option<()> {
+pub fn request_more_messages(op: &AppOp, id: Option<RoomId>) -> Option<()> {
     let id = id?;
+    // A part of the history shown apart from the latest messages is extended on its own
+    if op.is_timeline_detached(&id) {
+        return op.load_older_window_messages(&id);
+    }
     let session_client = op.login_data.as_ref()?.session_client.clone();
     let r = op.rooms.get(&id)?;
     if let Some(prev_batch) = r.prev_batch.clone() {
         RUNTIME.spawn(async move {
             match room::get_room_messages(session_client, id, &prev_batch).await {
-                Ok((msgs, room, prev_batch)) => {
-                    APPOP!(show_room_messages_top, (msgs, room, prev_batch));
+                Ok((msgs, reactions, room, prev_batch)) => {
+                    APPOP!(show_room_messages_top, (msgs, reactions, room, prev_batch));
                 }
                 Err(err) => {
                     err.handle_error();
                 }
             }
         });
-    } else if let Some(msg) = r.messages.iter().next().cloned() {
+    } else if let Some(msg) = r
+        .messages
+        .iter()
+        .find(|msg| msg.thread_root.is_none())
+        .cloned()
+    {
         // no prev_batch so we use the last message to calculate that in the backend
         RUNTIME.spawn(async move {
             match room::get_room_messages_from_msg(session_client, id, msg).await {
-                Ok((msgs, room, prev_batch)) => {
-                    APPOP!(show_room_messages_top, (msgs, room, prev_batch));
+                Ok((msgs, reactions, room, prev_batch)) => {
+                    APPOP!(show_room_messages_top, (msgs, reactions, room, prev_batch));
                 }
                 Err(err) => {
                     err.handle_error();
@@ -264,8 +352,8 @@ fn request_more_messages(op: &AppOp, id: Option<RoomId>) -> Option<()> {
         // no messages and no prev_batch so we use the last since
         RUNTIME.spawn(async move {
             match room::get_room_messages(session_client, id, &from).await {
-                Ok((msgs, room, prev_batch)) => {
-                    APPOP!(show_room_messages_top, (msgs, room, prev_batch));
+                Ok((msgs, reactions, room, prev_batch)) => {
+                    APPOP!(show_room_messages_top, (msgs, reactions, room, prev_batch));
                 }
                 Err(err) => {
                     err.handle_error();
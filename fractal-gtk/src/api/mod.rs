@@ -1,2 +1,3 @@
 pub mod identity;
 pub mod r0;
+pub mod v1;
@@ -1,5 +1,6 @@
 pub mod account;
 pub mod contact;
+pub mod search;
 pub mod server;
 
 use matrix_sdk::identifiers::ServerName;
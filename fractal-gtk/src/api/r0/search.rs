@@ -0,0 +1,81 @@
+use crate::api::r0::AccessToken;
+use matrix_sdk::identifiers::RoomId;
+use matrix_sdk::reqwest::Client;
+use matrix_sdk::reqwest::Error;
+use matrix_sdk::reqwest::Request;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use url::Url;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Parameters {
+    pub access_token: AccessToken,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_batch: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Body {
+    pub search_categories: Categories,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Categories {
+    pub room_events: RoomEventsCriteria,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RoomEventsCriteria {
+    pub search_term: String,
+    pub filter: Filter,
+    pub order_by: OrderBy,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Filter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rooms: Option<Vec<RoomId>>,
+    pub types: Vec<String>,
+    pub limit: u32,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderBy {
+    Recent,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Response {
+    pub search_categories: ResultCategories,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ResultCategories {
+    pub room_events: Option<RoomEventsResults>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RoomEventsResults {
+    pub count: Option<u64>,
+    #[serde(default)]
+    pub highlights: Vec<String>,
+    #[serde(default)]
+    pub results: Vec<SearchResult>,
+    pub next_batch: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SearchResult {
+    pub result: JsonValue,
+}
+
+pub fn request(base: Url, params: &Parameters, body: &Body) -> Result<Request, Error> {
+    let url = base
+        .join("_matrix/client/r0/search")
+        .expect("Malformed URL in search");
+
+    let data = serde_json::to_vec(body).unwrap();
+
+    Client::new().post(url).query(params).body(data).build()
+}
@@ -0,0 +1,2 @@
+pub mod relations;
+pub mod timestamp_to_event;
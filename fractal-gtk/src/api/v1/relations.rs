@@ -0,0 +1,40 @@
+use crate::api::r0::AccessToken;
+use matrix_sdk::identifiers::{EventId, RoomId};
+use matrix_sdk::reqwest::Client;
+use matrix_sdk::reqwest::Error;
+use matrix_sdk::reqwest::Request;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use url::Url;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Parameters {
+    pub access_token: AccessToken,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    pub limit: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Response {
+    pub chunk: Vec<JsonValue>,
+    pub next_batch: Option<String>,
+}
+
+pub fn request(
+    base: Url,
+    room_id: &RoomId,
+    event_id: &EventId,
+    rel_type: &str,
+    params: &Parameters,
+) -> Result<Request, Error> {
+    let mut url = base
+        .join("_matrix/client/v1/rooms/")
+        .expect("Malformed URL in relations");
+    url.path_segments_mut()
+        .expect("Malformed URL in relations")
+        .pop_if_empty()
+        .extend(&[room_id.as_str(), "relations", event_id.as_str(), rel_type]);
+
+    Client::new().get(url).query(params).build()
+}
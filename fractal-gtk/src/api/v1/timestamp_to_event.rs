@@ -0,0 +1,47 @@
+use crate::api::r0::AccessToken;
+use matrix_sdk::identifiers::{EventId, RoomId};
+use matrix_sdk::reqwest::Client;
+use matrix_sdk::reqwest::Error;
+use matrix_sdk::reqwest::Request;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+pub const STABLE_PREFIX: &str = "_matrix/client/v1/rooms/";
+// Servers implementing the feature before it was stable use this prefix
+pub const UNSTABLE_PREFIX: &str = "_matrix/client/unstable/org.matrix.msc3030/rooms/";
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub enum Direction {
+    #[serde(rename = "f")]
+    Forward,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Parameters {
+    pub access_token: AccessToken,
+    // In milliseconds since the UNIX epoch
+    pub ts: i64,
+    pub dir: Direction,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Response {
+    pub event_id: EventId,
+}
+
+pub fn request(
+    base: Url,
+    prefix: &str,
+    room_id: &RoomId,
+    params: &Parameters,
+) -> Result<Request, Error> {
+    let mut url = base
+        .join(prefix)
+        .expect("Malformed URL in timestamp_to_event");
+    url.path_segments_mut()
+        .expect("Malformed URL in timestamp_to_event")
+        .pop_if_empty()
+        .extend(&[room_id.as_str(), "timestamp_to_event"]);
+
+    Client::new().get(url).query(params).build()
+}
@@ -179,6 +179,17 @@ pub fn on_startup(gtk_app: &gtk::Application) {
         });
     }));
 
+    // Links to rooms and messages opened with Fractal
+    gtk_app.connect_open(clone!(@strong app_runtime => move |_, files, _| {
+        for file in files {
+            let uri = file.get_uri().to_string();
+            app_runtime.update_state_with(move |state| state.open_permalink(uri));
+        }
+        app_runtime.update_state_with(|state| {
+            on_activate(&state.ui);
+        });
+    }));
+
     app_runtime.update_state_with(|state| {
         state
             .ui
@@ -201,6 +212,15 @@ pub fn on_startup(gtk_app: &gtk::Application) {
             Inhibit(false)
         });
 
+        // Sends right away the messages that failed once the network is back
+        gio::NetworkMonitor::get_default().connect_network_changed(
+            clone!(@strong state.app_runtime as app_runtime => move |_, available| {
+                if available {
+                    app_runtime.update_state_with(|state| state.retry_outbox());
+                }
+            }),
+        );
+
         state.init();
     });
 
@@ -217,7 +237,7 @@ fn on_activate(ui: &ui::UI) {
     ui.main_window.present();
 }
 
-fn on_shutdown(appop: &AppOp) {
+fn on_shutdown(appop: &mut AppOp) {
     appop.quit();
 }
 
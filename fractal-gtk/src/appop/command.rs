@@ -0,0 +1,446 @@
+use crate::app::RUNTIME;
+use crate::appop::AppOp;
+use crate::backend::{room, user, HandleError};
+use crate::util::i18n::{i18n, i18n_f};
+use crate::APPOP;
+use gtk::prelude::*;
+use html2pango::html_escape;
+use matrix_sdk::identifiers::{RoomIdOrAliasId, UserId};
+use std::convert::TryFrom;
+
+// The power level given by /op when none is specified, the one of moderators
+const DEFAULT_OP_LEVEL: i64 = 50;
+
+/// A command that can be typed in the composer, preceded by a slash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Me,
+    Shrug,
+    Plain,
+    Html,
+    Spoiler,
+    Join,
+    Part,
+    Invite,
+    Topic,
+    Nick,
+    MyRoomNick,
+    Ban,
+    Kick,
+    Op,
+    Deop,
+    Help,
+}
+
+impl Command {
+    pub const ALL: &'static [Self] = &[
+        Self::Me,
+        Self::Shrug,
+        Self::Plain,
+        Self::Html,
+        Self::Spoiler,
+        Self::Join,
+        Self::Part,
+        Self::Invite,
+        Self::Topic,
+        Self::Nick,
+        Self::MyRoomNick,
+        Self::Ban,
+        Self::Kick,
+        Self::Op,
+        Self::Deop,
+        Self::Help,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|cmd| cmd.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Me => "me",
+            Self::Shrug => "shrug",
+            Self::Plain => "plain",
+            Self::Html => "html",
+            Self::Spoiler => "spoiler",
+            Self::Join => "join",
+            Self::Part => "part",
+            Self::Invite => "invite",
+            Self::Topic => "topic",
+            Self::Nick => "nick",
+            Self::MyRoomNick => "myroomnick",
+            Self::Ban => "ban",
+            Self::Kick => "kick",
+            Self::Op => "op",
+            Self::Deop => "deop",
+            Self::Help => "help",
+        }
+    }
+
+    /// The arguments of the command, shown as a hint while typing it.
+    pub fn args(self) -> Option<String> {
+        match self {
+            Self::Me | Self::Plain | Self::Html | Self::Spoiler => Some(i18n("<message>")),
+            Self::Shrug => Some(i18n("[message]")),
+            Self::Join => Some(i18n("<room-address>")),
+            Self::Invite | Self::Deop => Some(i18n("<user-id>")),
+            Self::Topic => Some(i18n("<topic>")),
+            Self::Nick | Self::MyRoomNick => Some(i18n("<display-name>")),
+            Self::Ban | Self::Kick => Some(i18n("<user-id> [reason]")),
+            Self::Op => Some(i18n("<user-id> [power-level]")),
+            Self::Part | Self::Help => None,
+        }
+    }
+
+    pub fn description(self) -> String {
+        match self {
+            Self::Me => i18n("Send an action message"),
+            Self::Shrug => i18n("Prepend ¯\\_(ツ)_/¯ to a message"),
+            Self::Plain => i18n("Send a message without interpreting Markdown"),
+            Self::Html => i18n("Send a message formatted with HTML"),
+            Self::Spoiler => i18n("Send a message hidden until clicked"),
+            Self::Join => i18n("Join a room"),
+            Self::Part => i18n("Leave the current room"),
+            Self::Invite => i18n("Invite a user to the current room"),
+            Self::Topic => i18n("Change the topic of the current room"),
+            Self::Nick => i18n("Change your display name"),
+            Self::MyRoomNick => i18n("Change your display name in the current room only"),
+            Self::Ban => i18n("Ban a user from the current room"),
+            Self::Kick => i18n("Remove a user from the current room"),
+            Self::Op => i18n("Set the power level of a user"),
+            Self::Deop => i18n("Reset the power level of a user"),
+            Self::Help => i18n("List the available commands"),
+        }
+    }
+
+    /// How the command is typed, with its arguments.
+    pub fn usage(self) -> String {
+        match self.args() {
+            Some(args) => format!("/{} {}", self.name(), args),
+            None => format!("/{}", self.name()),
+        }
+    }
+
+    fn needs_args(self) -> bool {
+        !matches!(self, Self::Shrug | Self::Part | Self::Help)
+    }
+}
+
+/// What was typed in the composer.
+#[derive(Debug, PartialEq)]
+pub enum Input<'a> {
+    Message(&'a str),
+    Command(Command, &'a str),
+    // The name of a command that doesn't exist
+    Unknown(&'a str),
+}
+
+/// Tells apart messages from commands. A message starting with a slash can be
+/// sent by doubling the slash.
+pub fn parse(text: &str) -> Input {
+    if text.starts_with("//") {
+        return Input::Message(&text[1..]);
+    }
+
+    let rest = match text.strip_prefix('/') {
+        Some(rest) if !rest.is_empty() && !rest.starts_with(char::is_whitespace) => rest,
+        _ => return Input::Message(text),
+    };
+
+    let (name, args) = match rest.find(char::is_whitespace) {
+        Some(idx) => (&rest[..idx], rest[idx..].trim()),
+        None => (rest, ""),
+    };
+
+    match Command::from_name(name) {
+        Some(command) => Input::Command(command, args),
+        None => Input::Unknown(name),
+    }
+}
+
+impl AppOp {
+    /// Runs a command typed in the composer.
+    ///
+    /// Returns `false` if it couldn't be run, so it stays in the composer.
+    pub fn run_command(&mut self, command: Command, args: &str) -> bool {
+        if args.is_empty() && command.needs_args() {
+            self.show_error(i18n_f("Usage: {}", &[&command.usage()]));
+            return false;
+        }
+
+        let (session_client, uid, room_id) = match (&self.login_data, &self.active_room) {
+            (Some(ld), Some(room_id)) => {
+                (ld.session_client.clone(), ld.uid.clone(), room_id.clone())
+            }
+            _ => return false,
+        };
+
+        match command {
+            Command::Me => {
+                // Element (Riot) does not properly show emotes with Markdown;
+                // Emotes with markdown have a newline after the username
+                self.send_text(args.to_string(), None, "m.emote");
+            }
+            Command::Shrug => {
+                let body = format!("¯\\_(ツ)_/¯ {}", args);
+                self.send_text(body.trim_end().to_string(), None, "m.text");
+            }
+            Command::Plain => {
+                self.send_text(args.to_string(), None, "m.text");
+            }
+            Command::Html => {
+                self.send_text(strip_tags(args), Some(args.to_string()), "m.text");
+            }
+            Command::Spoiler => {
+                // The plain text body is shown in notifications and by clients not
+                // supporting spoilers, so it doesn't give the content away
+                let html = format!("<span data-mx-spoiler>{}</span>", html_escape(args));
+                self.send_text(i18n("[Spoiler]"), Some(html), "m.text");
+            }
+            Command::Join => {
+                let room_id = match RoomIdOrAliasId::try_from(args) {
+                    Ok(room_id) => room_id,
+                    Err(_) => {
+                        self.show_error(i18n_f("“{}” isn’t a valid room address", &[args]));
+                        return false;
+                    }
+                };
+
+                RUNTIME.spawn(async move {
+                    match room::join_room(session_client, &room_id).await {
+                        Ok(jtr) => {
+                            let jtr = Some(jtr);
+                            APPOP!(set_join_to_room, (jtr));
+                            APPOP!(reload_rooms);
+                        }
+                        Err(err) => {
+                            err.handle_error();
+                        }
+                    }
+                });
+            }
+            Command::Part => {
+                self.really_leave_active_room();
+            }
+            Command::Invite => {
+                let (user_id, _) = match self.target_user(args) {
+                    Some(target) => target,
+                    None => return false,
+                };
+                RUNTIME.spawn(async move {
+                    let query = room::invite(session_client, &room_id, &user_id).await;
+                    if let Err(err) = query {
+                        err.handle_error();
+                    }
+                });
+            }
+            Command::Topic => {
+                let topic = args.to_string();
+                RUNTIME.spawn(async move {
+                    let query = room::set_room_topic(session_client, &room_id, topic).await;
+                    if let Err(err) = query {
+                        err.handle_error();
+                    }
+                });
+            }
+            Command::Nick => {
+                let username = Some(args.to_string());
+                RUNTIME.spawn(async move {
+                    match user::set_username(session_client, &uid, username).await {
+                        Ok(username) => {
+                            APPOP!(show_new_username, (username));
+                        }
+                        Err(err) => {
+                            err.handle_error();
+                        }
+                    }
+                });
+            }
+            Command::MyRoomNick => {
+                let name = args.to_string();
+                RUNTIME.spawn(async move {
+                    let query =
+                        room::set_room_display_name(session_client, &room_id, &uid, name).await;
+                    if let Err(err) = query {
+                        err.handle_error();
+                    }
+                });
+            }
+            Command::Ban => {
+                let (user_id, reason) = match self.target_user(args) {
+                    Some(target) => target,
+                    None => return false,
+                };
+                RUNTIME.spawn(async move {
+                    let query = room::ban_user(session_client, &room_id, &user_id, reason).await;
+                    if let Err(err) = query {
+                        err.handle_error();
+                    }
+                });
+            }
+            Command::Kick => {
+                let (user_id, reason) = match self.target_user(args) {
+                    Some(target) => target,
+                    None => return false,
+                };
+                RUNTIME.spawn(async move {
+                    let query = room::kick_user(session_client, &room_id, &user_id, reason).await;
+                    if let Err(err) = query {
+                        err.handle_error();
+                    }
+                });
+            }
+            Command::Op | Command::Deop => {
+                let (user_id, level) = match self.target_user(args) {
+                    Some(target) => target,
+                    None => return false,
+                };
+                let level = match (command, level.map(|level| level.parse::<i64>())) {
+                    (Command::Deop, _) => None,
+                    (_, None) => Some(DEFAULT_OP_LEVEL),
+                    (_, Some(Ok(level))) => Some(level),
+                    (_, Some(Err(_))) => {
+                        self.show_error(i18n_f("Usage: {}", &[&command.usage()]));
+                        return false;
+                    }
+                };
+                RUNTIME.spawn(async move {
+                    let query =
+                        room::set_power_level(session_client, &room_id, &user_id, level).await;
+                    if let Err(err) = query {
+                        err.handle_error();
+                    }
+                });
+            }
+            Command::Help => {
+                self.show_commands_help();
+            }
+        }
+
+        true
+    }
+
+    // Splits the arguments of a command into the user it targets and the rest
+    fn target_user(&self, args: &str) -> Option<(UserId, Option<String>)> {
+        let (first, rest) = match args.find(char::is_whitespace) {
+            Some(idx) => (&args[..idx], args[idx..].trim()),
+            None => (args, ""),
+        };
+
+        match UserId::try_from(first) {
+            Ok(user_id) => Some((user_id, Some(rest.to_string()).filter(|r| !r.is_empty()))),
+            Err(_) => {
+                self.show_error(i18n_f("“{}” isn’t a valid user ID", &[first]));
+                None
+            }
+        }
+    }
+
+    pub fn show_commands_help(&self) {
+        let commands = Command::ALL
+            .iter()
+            .map(|cmd| format!("{}\n    {}", cmd.usage(), cmd.description()))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let flags = gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT;
+        let dialog = gtk::MessageDialog::new(
+            Some(&self.ui.main_window),
+            flags,
+            gtk::MessageType::Info,
+            gtk::ButtonsType::None,
+            &i18n("Available Commands"),
+        );
+        dialog.set_property_secondary_text(Some(&commands));
+        dialog.add_button(&i18n("OK"), gtk::ResponseType::Ok);
+        dialog.connect_response(move |d, _| d.close());
+        dialog.show_all();
+    }
+}
+
+// A rough plain text version of an HTML message, for clients not supporting HTML
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    // The entities most likely to be found in text, `&amp;` last so it doesn't
+    // make new ones
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_messages() {
+        assert_eq!(parse("hello"), Input::Message("hello"));
+        assert_eq!(parse("//escaped"), Input::Message("/escaped"));
+        assert_eq!(parse("//me waves"), Input::Message("/me waves"));
+        assert_eq!(parse("/"), Input::Message("/"));
+        assert_eq!(parse("/ not a command"), Input::Message("/ not a command"));
+        assert_eq!(parse("path/to/file"), Input::Message("path/to/file"));
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse("/me waves"), Input::Command(Command::Me, "waves"));
+        assert_eq!(
+            parse("/me \t waves  "),
+            Input::Command(Command::Me, "waves")
+        );
+        assert_eq!(parse("/part"), Input::Command(Command::Part, ""));
+        assert_eq!(
+            parse("/ban @spam:example.org too many links"),
+            Input::Command(Command::Ban, "@spam:example.org too many links")
+        );
+        // Names are case sensitive
+        assert_eq!(parse("/ME waves"), Input::Unknown("ME"));
+        assert_eq!(parse("/unknown some args"), Input::Unknown("unknown"));
+    }
+
+    #[test]
+    fn test_command_args() {
+        // Missing arguments are caught before running the command
+        assert_eq!(parse("/topic"), Input::Command(Command::Topic, ""));
+        assert!(Command::Topic.needs_args());
+        assert_eq!(parse("/shrug"), Input::Command(Command::Shrug, ""));
+        assert!(!Command::Shrug.needs_args());
+
+        // Extra arguments are kept, the commands not taking any ignore them
+        assert_eq!(parse("/help me"), Input::Command(Command::Help, "me"));
+        assert!(!Command::Help.needs_args());
+
+        for command in Command::ALL {
+            assert_eq!(Command::from_name(command.name()), Some(*command));
+        }
+    }
+
+    #[test]
+    fn test_strip_tags() {
+        assert_eq!(
+            strip_tags("<b>bold</b> and <i>italic</i>"),
+            "bold and italic"
+        );
+        assert_eq!(
+            strip_tags("<a href=\"https://gnome.org\">GNOME</a>"),
+            "GNOME"
+        );
+        assert_eq!(strip_tags("1 &lt; 2 &amp;&amp; 3 &gt; 2"), "1 < 2 && 3 > 2");
+        assert_eq!(strip_tags("&amp;lt;"), "&lt;");
+        assert_eq!(strip_tags("no tags"), "no tags");
+    }
+}
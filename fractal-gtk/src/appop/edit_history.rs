@@ -0,0 +1,45 @@
+use crate::app::RUNTIME;
+use crate::appop::AppOp;
+use crate::backend::{room, HandleError};
+use crate::model::message::Message;
+use matrix_sdk::identifiers::EventId;
+
+impl AppOp {
+    pub fn show_edit_history(&self, event_id: EventId) -> Option<()> {
+        let login_data = self.login_data.clone()?;
+        let room_id = self.active_room.clone()?;
+        // Edited messages are shown with the ID of their latest edit
+        let original = self
+            .get_message_by_id(&room_id, &event_id)
+            .and_then(|msg| msg.replace)
+            .unwrap_or(event_id);
+
+        RUNTIME.spawn(async move {
+            let query = room::get_edit_history(
+                login_data.session_client,
+                login_data.access_token,
+                room_id,
+                original,
+            )
+            .await;
+
+            match query {
+                Ok((original, edits)) => {
+                    APPOP!(show_edit_history_dialog, (original, edits));
+                }
+                Err(err) => {
+                    err.handle_error();
+                }
+            }
+        });
+        None
+    }
+
+    pub fn show_edit_history_dialog(&self, original: Message, edits: Vec<Message>) {
+        let versions = vec![(original.date, original.body)]
+            .into_iter()
+            .chain(edits.into_iter().map(|edit| (edit.date, edit.body)))
+            .collect();
+        self.ui.show_edit_history_dialog(versions);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::app::RUNTIME;
+use crate::appop::AppOp;
+use crate::backend::export::{self, ExportFormat, ExportedRoom};
+use crate::backend::HandleError;
+use chrono::prelude::*;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+impl AppOp {
+    pub fn show_export_dialog(&self) -> Option<()> {
+        let room = self.rooms.get(self.active_room.as_ref()?)?;
+        let name = room.name.clone().unwrap_or_else(|| room.id.to_string());
+        self.ui.show_export_dialog(&name);
+        None
+    }
+
+    pub fn export_room(
+        &mut self,
+        format: ExportFormat,
+        range: (DateTime<Local>, DateTime<Local>),
+        path: PathBuf,
+    ) -> Option<()> {
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        // The history is loaded backwards from the latest sync
+        let from = self.since.clone()?;
+        let room = self.rooms.get(self.active_room.as_ref()?)?;
+        let room = ExportedRoom {
+            id: room.id.clone(),
+            name: room.name.clone().unwrap_or_else(|| room.id.to_string()),
+            members: room
+                .members
+                .values()
+                .map(|member| (member.uid.clone(), member.get_alias()))
+                .collect(),
+        };
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.ui.show_export_progress(cancel.clone());
+
+        RUNTIME.spawn(async move {
+            let query =
+                export::export_room(session_client, room, from, range, format, path, cancel).await;
+            let count = match query {
+                Ok(count) => count,
+                Err(err) => {
+                    err.handle_error();
+                    None
+                }
+            };
+            APPOP!(export_finished, (count));
+        });
+
+        None
+    }
+
+    pub fn export_progress(&self, fraction: f64, count: usize) {
+        if let Some(view) = self.ui.export.as_ref() {
+            view.set_progress(fraction, count);
+        }
+    }
+
+    pub fn export_finished(&mut self, count: Option<usize>) {
+        if let Some(view) = self.ui.export.take() {
+            match count {
+                Some(count) => view.finish(count),
+                None => view.close(),
+            }
+        }
+    }
+}
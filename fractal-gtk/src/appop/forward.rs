@@ -0,0 +1,49 @@
+use crate::appop::AppOp;
+use matrix_sdk::identifiers::{EventId, RoomId};
+
+impl AppOp {
+    /// Opens the dialog to pick the rooms to forward a message of the active room to.
+    pub fn show_forward_dialog(&self, event_id: EventId) -> Option<()> {
+        let room_id = self.active_room.clone()?;
+        let msg = self.get_message_by_id(&room_id, &event_id)?;
+        if msg.redacted || msg.state.is_some() {
+            return None;
+        }
+
+        let mut rooms: Vec<(RoomId, String)> = self
+            .rooms
+            .values()
+            .filter(|room| room.membership.is_joined())
+            .map(|room| {
+                let name = room.name.clone().unwrap_or_else(|| room.id.to_string());
+                (room.id.clone(), name)
+            })
+            .collect();
+        rooms.sort_by_key(|(_, name)| name.to_lowercase());
+
+        self.ui.show_forward_dialog(room_id, event_id, rooms);
+        None
+    }
+
+    pub fn forward_message(
+        &mut self,
+        room_id: RoomId,
+        event_id: EventId,
+        targets: Vec<RoomId>,
+    ) -> Option<()> {
+        let sender = self.login_data.as_ref()?.uid.clone();
+        // Edited messages are forwarded with their current content
+        let msg = self
+            .rooms
+            .get(&room_id)?
+            .messages
+            .get_latest(&event_id)?
+            .clone();
+
+        for target in targets {
+            self.add_tmp_room_message(msg.forward(target, sender.clone()));
+        }
+        self.dequeue_message();
+        None
+    }
+}
@@ -62,6 +62,8 @@ impl AppOp {
         self.initial_sync(true);
         self.sync(true, 0);
         self.init_protocols();
+        // Sends what was left in the outbox the last time
+        self.dequeue_message();
     }
 
     pub fn bk_logout(&mut self) {
@@ -69,6 +71,8 @@ impl AppOp {
         if cache::get().destroy().is_err() {
             error!("Error removing cache file");
         }
+        self.drafts.clear();
+        self.clear_outbox();
 
         self.syncing = false;
 
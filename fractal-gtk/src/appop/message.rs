@@ -7,29 +7,75 @@ use glib::source::Continue;
 use gtk::prelude::*;
 use lazy_static::lazy_static;
 use log::error;
-use matrix_sdk::identifiers::{EventId, RoomId};
+use matrix_sdk::identifiers::{EventId, RoomId, UserId};
+use matrix_sdk::uuid::Uuid;
 use matrix_sdk::Client as MatrixClient;
 use rand::Rng;
 use serde_json::json;
 use serde_json::Value as JsonValue;
-use std::env::temp_dir;
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use url::Url;
 
 use crate::app::RUNTIME;
+use crate::appop::command::{self, Input};
 use crate::appop::room::Force;
 use crate::appop::AppOp;
+use crate::cache::{self, Draft, DraftRelation, LocalEcho, Outbox, OutboxEntry};
+use crate::globals;
 
 use crate::ui::MessageContent;
+use crate::ui::ReactionContent;
+use crate::ui::ReceiptContent;
+use crate::ui::ReplyContent;
 use crate::ui::RowType;
 use crate::widgets;
 
 use crate::model::message::Message;
+use crate::model::reaction::Reaction;
+use crate::model::room::{Receipts, Room};
+use crate::util::blurhash;
+use crate::util::cache_dir_path;
+use crate::util::i18n::{i18n, i18n_f, i18n_k};
+use crate::util::mention::{self, Mention};
 
 pub struct TmpMsg {
     pub msg: Message,
     pub widget: Option<gtk::Widget>,
+    // Identifies the message in the outbox and in the rows actions
+    pub txn_id: String,
+    // How many times in a row the message couldn't be sent
+    pub failures: u32,
+    pub failed: bool,
+}
+
+impl TmpMsg {
+    pub fn new(mut msg: Message) -> Self {
+        let txn_id = Uuid::new_v4().to_string();
+        msg.txn_id = Some(txn_id.clone());
+
+        Self {
+            msg,
+            widget: None,
+            txn_id,
+            failures: 0,
+            failed: false,
+        }
+    }
+}
+
+// The delays in milliseconds before trying again to send a message, doubled
+// after each failure
+const RETRY_BASE_DELAY: u32 = 2000;
+const RETRY_MAX_DELAY: u32 = 5 * 60 * 1000;
+
+/// The message the one being composed relates to.
+#[derive(Debug, Clone)]
+pub enum ComposerRelation {
+    // The event ID of the original message being edited
+    Edit(EventId),
+    Reply(Message),
 }
 
 impl AppOp {
@@ -46,6 +92,10 @@ impl AppOp {
     }
 
     pub fn add_room_message(&mut self, msg: Message) -> Option<()> {
+        // The latest messages aren't shown while looking at an older part of the history
+        if self.is_timeline_detached(&msg.room) {
+            return None;
+        }
         let session_client = self.login_data.as_ref()?.session_client.clone();
         if let Some(ui_msg) = self.create_new_room_message(msg) {
             if let Some(ref mut history) = self.ui.history {
@@ -65,28 +115,67 @@ impl AppOp {
         }
     }
 
-    pub fn add_tmp_room_message(&mut self, msg: Message) -> Option<()> {
-        let login_data = self.login_data.clone()?;
-        let messages = self.ui.history.as_ref()?.get_listbox();
-        if let Some(ui_msg) = self.create_new_room_message(msg.clone()) {
-            let mb = widgets::MessageBox::create_tmp(
-                login_data.session_client.clone(),
-                self.user_info_cache.clone(),
-                &ui_msg,
-            );
-            let m = mb.get_widget();
-            messages.add(m);
-
-            if let Some(w) = messages.get_children().iter().last() {
-                self.msg_queue.insert(
-                    0,
-                    TmpMsg {
-                        msg: msg.clone(),
-                        widget: Some(w.clone()),
-                    },
-                );
-            };
+    pub fn add_tmp_room_message(&mut self, msg: Message) {
+        // Messages are sent after the latest ones
+        if self.is_timeline_detached(&msg.room) {
+            self.jump_to_latest();
+        }
+        let mut tmp = TmpMsg::new(msg);
+
+        // Thread replies are only shown in the thread once sent
+        let in_thread = tmp.msg.thread_root.is_some();
+        if !in_thread && tmp.msg.replace.is_some() {
+            // Edits are shown in place of the original message right away
+            if let Some(ui_msg) = self.create_new_room_message(tmp.msg.clone()) {
+                if let (Some(login_data), Some(history)) = (&self.login_data, &mut self.ui.history)
+                {
+                    history.replace_message(
+                        login_data.session_client.clone(),
+                        self.user_info_cache.clone(),
+                        ui_msg,
+                    );
+                }
+            }
+        } else if !in_thread && self.active_room.as_ref() == Some(&tmp.msg.room) {
+            if let (Some(row), Some(history)) = (self.build_tmp_row(&tmp), &self.ui.history) {
+                history.get_listbox().add(&row);
+                tmp.widget = Some(row);
+            }
         }
+
+        self.msg_queue.insert(0, tmp);
+        self.store_outbox();
+    }
+
+    // Builds the row of a message waiting to be sent
+    fn build_tmp_row(&self, tmp: &TmpMsg) -> Option<gtk::Widget> {
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        let user_info_cache = self.user_info_cache.clone();
+        let ui_msg = self.create_new_room_message(tmp.msg.clone())?;
+        let mb = if tmp.failed {
+            widgets::MessageBox::create_failed(
+                session_client,
+                user_info_cache,
+                &ui_msg,
+                &tmp.txn_id,
+            )
+        } else {
+            widgets::MessageBox::create_tmp(session_client, user_info_cache, &ui_msg)
+        };
+
+        Some(mb.get_widget().clone().upcast())
+    }
+
+    // Rebuilds the row of a message waiting to be sent, after its state changed
+    fn refresh_tmp_row(&mut self, idx: usize) -> Option<()> {
+        let listbox = self.ui.history.as_ref()?.get_listbox();
+        let old = self.msg_queue.get(idx)?.widget.clone()?;
+        let position = old.downcast_ref::<gtk::ListBoxRow>()?.get_index();
+        let row = self.build_tmp_row(&self.msg_queue[idx])?;
+
+        listbox.remove(&old);
+        listbox.insert(&row, position);
+        self.msg_queue[idx].widget = Some(row);
         None
     }
 
@@ -102,29 +191,19 @@ impl AppOp {
     }
 
     pub fn append_tmp_msgs(&mut self) -> Option<()> {
-        let login_data = self.login_data.clone()?;
         let messages = self.ui.history.as_ref()?.get_listbox();
+        let active_room = self.active_room.clone()?;
 
-        let r = self.rooms.get(self.active_room.as_ref()?)?;
-        let mut widgets = vec![];
-        for t in self.msg_queue.iter().rev().filter(|m| m.msg.room == r.id) {
-            if let Some(ui_msg) = self.create_new_room_message(t.msg.clone()) {
-                let mb = widgets::MessageBox::create_tmp(
-                    login_data.session_client.clone(),
-                    self.user_info_cache.clone(),
-                    &ui_msg,
-                );
-                let m = mb.get_widget();
-                messages.add(m);
-
-                if let Some(w) = messages.get_children().iter().last() {
-                    widgets.push(w.clone());
-                }
+        for idx in (0..self.msg_queue.len()).rev() {
+            let tmp = &self.msg_queue[idx];
+            if tmp.msg.room != active_room || tmp.msg.thread_root.is_some() {
+                continue;
             }
-        }
 
-        for (t, w) in self.msg_queue.iter_mut().rev().zip(widgets.iter()) {
-            t.widget = Some(w.clone());
+            if let Some(row) = self.build_tmp_row(tmp) {
+                messages.add(&row);
+                self.msg_queue[idx].widget = Some(row);
+            }
         }
         None
     }
@@ -132,7 +211,8 @@ impl AppOp {
     pub fn mark_last_message_as_read(&mut self, Force(force): Force) -> Option<()> {
         let login_data = self.login_data.clone()?;
         if self.ui.main_window.is_active() || force {
-            /* Move the last viewed mark to the last message */
+            /* Move our read receipt to the last message, the fully read marker only moves
+             * once the user scrolls past messages */
             let active_room_id = self.active_room.as_ref()?;
             let room = self.rooms.get_mut(active_room_id)?;
             let uid = login_data.uid.clone();
@@ -155,7 +235,7 @@ impl AppOp {
 
             let session_client = login_data.session_client;
             RUNTIME.spawn(async move {
-                match room::mark_as_read(session_client, room_id, event_id).await {
+                match room::send_read_receipt(session_client, room_id, event_id).await {
                     Ok((r, _)) => {
                         APPOP!(clear_room_notifications, (r));
                     }
@@ -168,28 +248,283 @@ impl AppOp {
         None
     }
 
-    pub fn msg_sent(&mut self, evid: EventId) -> Option<()> {
-        let messages = self.ui.history.as_ref()?.get_listbox();
-        if let Some(ref mut m) = self.msg_queue.pop() {
-            if let Some(ref w) = m.widget {
-                messages.remove(w);
+    /// Updates the fully read marker of a room, after it was moved by another client.
+    pub fn set_fully_read(&mut self, room_id: RoomId, event_id: EventId) {
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            room.fully_read = Some(event_id);
+        }
+    }
+
+    /// Moves the fully read marker of the active room to a message the user
+    /// scrolled past, unless it's older than the current one.
+    pub fn read_up_to(&mut self, event_id: EventId) -> Option<()> {
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        let room_id = self.active_room.clone()?;
+        let room = self.rooms.get_mut(&room_id)?;
+        let date = room.messages.get(&event_id)?.date;
+        let current = room
+            .fully_read
+            .as_ref()
+            .and_then(|id| room.messages.get(id));
+        if current.map_or(false, |current| current.date >= date) {
+            return None;
+        }
+
+        room.fully_read = Some(event_id.clone());
+        RUNTIME.spawn(async move {
+            if let Err(err) = room::set_fully_read(session_client, room_id, event_id).await {
+                err.handle_error();
             }
-            m.widget = None;
-            m.msg.id = Some(evid);
-            self.show_room_messages(vec![m.msg.clone()]);
+        });
+        None
+    }
+
+    /// Scrolls to the first message after the fully read marker of the active room.
+    pub fn jump_to_first_unread(&mut self) {
+        let room_id = unwrap_or_unit_return!(self.active_room.as_ref());
+        let room = unwrap_or_unit_return!(self.rooms.get(room_id));
+        let fully_read = room.fully_read.as_ref();
+        let mut after_marker = room
+            .messages
+            .iter()
+            .skip_while(|msg| fully_read.is_none() || msg.id.as_ref() != fully_read);
+        if after_marker.next().is_none() {
+            let msg = i18n(
+                "The first unread message isn’t loaded yet. Scroll up to load older messages.",
+            );
+            self.show_error(msg);
+            return;
         }
-        self.force_dequeue_message();
+
+        // Edits, thread replies and state changes aren't unread messages
+        let first_unread = after_marker
+            .filter(|msg| {
+                !msg.redacted
+                    && msg.replace.is_none()
+                    && msg.thread_root.is_none()
+                    && msg.state.is_none()
+            })
+            .find_map(|msg| msg.id.clone());
+        match first_unread {
+            Some(event_id) => {
+                self.jump_to_message(event_id);
+            }
+            None => self.show_error(i18n("There are no unread messages.")),
+        }
+    }
+
+    /// Moves the fully read marker and our read receipt to the last message of
+    /// the active room.
+    pub fn mark_all_as_read(&mut self) -> Option<()> {
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        let room_id = self.active_room.clone()?;
+        let room = self.rooms.get_mut(&room_id)?;
+        let event_id = room.messages.iter().last()?.id.clone()?;
+        room.fully_read = Some(event_id.clone());
+
+        if let Some(history) = self.ui.history.as_ref() {
+            history.dismiss_new_divider();
+        }
+
+        RUNTIME.spawn(async move {
+            match room::mark_as_read(session_client, room_id, event_id).await {
+                Ok((r, _)) => {
+                    APPOP!(clear_room_notifications, (r));
+                }
+                Err(err) => {
+                    err.handle_error();
+                }
+            }
+        });
         None
     }
 
-    pub fn retry_send(&mut self) {
-        glib::timeout_add_local(5000, move || {
-            /* This will be removed once tmp messages are refactored */
-            APPOP!(force_dequeue_message);
+    pub fn msg_sent(&mut self, txn_id: String, evid: EventId) {
+        // The event may have already come back through sync
+        if let Some(mut tmp) = self.take_tmp_msg(&txn_id) {
+            tmp.msg.id = Some(evid);
+            if let Some(room) = self.rooms.get_mut(&tmp.msg.room) {
+                room.messages.track_local_echo(txn_id, tmp.msg.date);
+            }
+            self.show_room_messages(vec![tmp.msg]);
+        }
+        self.force_dequeue_message();
+    }
+
+    // Removes a message from the ones waiting to be sent
+    fn take_tmp_msg(&mut self, txn_id: &str) -> Option<TmpMsg> {
+        let idx = self.msg_queue.iter().position(|t| t.txn_id == txn_id)?;
+        let tmp = self.msg_queue.remove(idx);
+        if let (Some(w), Some(history)) = (&tmp.widget, &self.ui.history) {
+            history.get_listbox().remove(w);
+        }
+        self.store_outbox();
+
+        Some(tmp)
+    }
+
+    /// Marks a message as not sent and tries again later, waiting longer after
+    /// each failure.
+    pub fn send_failed(&mut self, txn_id: String) {
+        let idx = unwrap_or_unit_return!(self.msg_queue.iter().position(|t| t.txn_id == txn_id));
+        let tmp = &mut self.msg_queue[idx];
+        tmp.failed = true;
+        tmp.failures += 1;
+        let delay = retry_delay(tmp.failures);
+        self.refresh_tmp_row(idx);
+
+        self.retry_generation = self.retry_generation.wrapping_add(1);
+        self.waiting_retry = true;
+        let generation = self.retry_generation;
+        glib::timeout_add_local(delay, move || {
+            APPOP!(retry_timeout, (generation));
             Continue(false)
         });
     }
 
+    pub fn retry_timeout(&mut self, generation: u32) {
+        if generation == self.retry_generation {
+            self.retry_outbox();
+        }
+    }
+
+    /// Sends again the message that failed to be sent, without waiting.
+    pub fn retry_outbox(&mut self) {
+        if !self.waiting_retry {
+            return;
+        }
+
+        self.waiting_retry = false;
+        // Outdates the timeout of the current wait
+        self.retry_generation = self.retry_generation.wrapping_add(1);
+
+        if let Some(idx) = self.msg_queue.len().checked_sub(1) {
+            self.msg_queue[idx].failed = false;
+            self.refresh_tmp_row(idx);
+        }
+        self.force_dequeue_message();
+    }
+
+    /// Sends again a message that failed to be sent, without waiting.
+    ///
+    /// The messages before it in the outbox are sent first, to keep their order.
+    pub fn retry_message(&mut self, txn_id: String) {
+        let idx = unwrap_or_unit_return!(self
+            .msg_queue
+            .iter()
+            .position(|t| t.txn_id == txn_id && t.failed));
+        self.msg_queue[idx].failed = false;
+        self.refresh_tmp_row(idx);
+        self.retry_outbox();
+    }
+
+    /// Gives up sending a message that failed to be sent.
+    pub fn cancel_message(&mut self, txn_id: String) {
+        if !self
+            .msg_queue
+            .iter()
+            .any(|t| t.txn_id == txn_id && t.failed)
+        {
+            return;
+        }
+        self.take_tmp_msg(&txn_id);
+
+        if self.waiting_retry {
+            self.waiting_retry = false;
+            self.retry_generation = self.retry_generation.wrapping_add(1);
+            self.force_dequeue_message();
+        }
+    }
+
+    // Writes the messages waiting to be sent, and the local echoes waiting for
+    // their event, to the disk
+    fn store_outbox(&self) {
+        let entries = self
+            .msg_queue
+            .iter()
+            .map(|t| OutboxEntry::new(t.txn_id.clone(), &t.msg))
+            .collect();
+        let local_echoes = self
+            .rooms
+            .values()
+            .flat_map(|room| {
+                room.messages
+                    .local_echoes()
+                    .iter()
+                    .map(move |(txn_id, date)| {
+                        LocalEcho::new(txn_id.clone(), room.id.clone(), *date)
+                    })
+            })
+            .chain(self.restored_echoes.iter().cloned())
+            .collect();
+        let outbox = Outbox {
+            entries,
+            local_echoes,
+        };
+
+        if cache::store_outbox(&outbox).is_err() {
+            error!("Error storing the outbox");
+        }
+    }
+
+    /// Brings back the messages that weren't sent before quitting.
+    pub fn restore_outbox(&mut self) {
+        let outbox = match cache::load_outbox() {
+            Ok(outbox) => outbox,
+            Err(err) => {
+                error!("Error loading the outbox: {}", err);
+                return;
+            }
+        };
+
+        self.restored_echoes = outbox.local_echoes;
+        self.msg_queue = outbox
+            .entries
+            .into_iter()
+            .map(|entry| {
+                let (txn_id, msg) = entry.into_message();
+                TmpMsg {
+                    msg,
+                    widget: None,
+                    txn_id,
+                    failures: 0,
+                    failed: false,
+                }
+            })
+            .collect();
+    }
+
+    /// Hands the local echoes restored from the outbox to their room, once it's loaded.
+    pub fn restore_local_echoes(&mut self, room: &mut Room) {
+        let (echoes, others) = self
+            .restored_echoes
+            .drain(..)
+            .partition(|echo: &LocalEcho| echo.room == room.id);
+        self.restored_echoes = others;
+
+        for echo in echoes {
+            // The event may have already come back with the room
+            let echoed = room
+                .messages
+                .iter()
+                .any(|m| m.txn_id.as_ref() == Some(&echo.txn_id));
+            if !echoed {
+                room.messages
+                    .track_local_echo(echo.txn_id.clone(), echo.date());
+            }
+        }
+    }
+
+    /// Drops all the messages waiting to be sent.
+    pub fn clear_outbox(&mut self) {
+        self.clear_tmp_msgs();
+        self.msg_queue.clear();
+        self.sending_message = false;
+        self.waiting_retry = false;
+        self.retry_generation = self.retry_generation.wrapping_add(1);
+        self.store_outbox();
+    }
+
     pub fn force_dequeue_message(&mut self) {
         self.sending_message = false;
         self.dequeue_message();
@@ -197,81 +532,211 @@ impl AppOp {
 
     pub fn dequeue_message(&mut self) -> Option<()> {
         let session_client = self.login_data.as_ref()?.session_client.clone();
-        if self.sending_message {
+        if self.sending_message || self.waiting_retry {
             return None;
         }
 
-        self.sending_message = true;
         if let Some(next) = self.msg_queue.last() {
+            self.sending_message = true;
             let msg = next.msg.clone();
+            let txn_id = next.txn_id.clone();
             match next.msg.mtype.as_str() {
                 "m.image" | "m.file" | "m.audio" | "m.video" => {
-                    RUNTIME.spawn(attach_file(session_client, msg));
+                    RUNTIME.spawn(attach_file(session_client, txn_id, msg));
                 }
                 _ => {
-                    RUNTIME.spawn(async move {
-                        match room::send_msg(session_client, msg).await {
-                            Ok(evid) => {
-                                APPOP!(msg_sent, (evid));
-                                let initial = false;
-                                let number_tries = 0;
-                                APPOP!(sync, (initial, number_tries));
-                            }
-                            Err(err) => {
-                                err.handle_error();
-                            }
-                        }
-                    });
+                    RUNTIME.spawn(send_msg_and_manage(session_client, txn_id, msg));
                 }
             }
-        } else {
-            self.sending_message = false;
         }
         None
     }
 
-    pub fn send_message(&mut self, msg: String) {
+    pub fn start_editing(&mut self, msg: Message) {
+        // Editing an already edited message has to relate to the original event
+        let original = unwrap_or_unit_return!(msg.replace.clone().or(msg.id));
+        self.composer_relation = Some(ComposerRelation::Edit(original));
+
+        self.show_composer_relation(&i18n("Editing message"));
+        self.ui.sventry.buffer.set_text(&msg.body);
+    }
+
+    pub fn start_replying(&mut self, mut msg: Message) {
+        let sender_name = self
+            .rooms
+            .get(&msg.room)
+            .and_then(|r| r.members.get(&msg.sender))
+            .and_then(|m| m.alias.clone())
+            .unwrap_or_else(|| msg.sender.to_string());
+
+        // Replying to an edit should still refer to the original message
+        msg.id = msg.replace.take().or(msg.id);
+
+        if let Some(ComposerRelation::Edit(_)) = self.composer_relation {
+            self.ui.sventry.buffer.set_text("");
+        }
+        self.composer_relation = Some(ComposerRelation::Reply(msg));
+
+        let label = i18n_k("Replying to {name}", &[("name", &sender_name)]);
+        self.show_composer_relation(&label);
+    }
+
+    /// The sender of the message being replied to in the composer.
+    pub fn replying_to(&self) -> Option<&UserId> {
+        match &self.composer_relation {
+            Some(ComposerRelation::Reply(parent)) => Some(&parent.sender),
+            _ => None,
+        }
+    }
+
+    fn show_composer_relation(&self, label: &str) {
+        let sventry = &self.ui.sventry;
+        sventry.relation_label.set_text(label);
+        sventry.relation_box.show();
+        sventry.view.grab_focus();
+    }
+
+    pub fn cancel_composer_relation(&mut self) {
+        let relation = unwrap_or_unit_return!(self.composer_relation.take());
+        self.ui.sventry.relation_box.hide();
+        // The composer only contained the message being edited
+        if let ComposerRelation::Edit(_) = relation {
+            self.ui.sventry.buffer.set_text("");
+        }
+    }
+
+    /// Keeps what's in the composer as the draft of the active room.
+    pub fn save_draft(&mut self) {
+        let room_id = unwrap_or_unit_return!(self.active_room.clone());
+        let buffer = &self.ui.sventry.buffer;
+        let start = buffer.get_start_iter();
+        let end = buffer.get_end_iter();
+        let text = buffer
+            .get_text(&start, &end, false)
+            .map(|text| text.to_string())
+            .unwrap_or_default();
+        let cursor = buffer.get_property_cursor_position();
+
+        let relation = match self.composer_relation {
+            Some(ComposerRelation::Edit(ref original)) => {
+                Some(DraftRelation::Edit(original.clone()))
+            }
+            Some(ComposerRelation::Reply(ref parent)) => {
+                parent.id.clone().map(DraftRelation::Reply)
+            }
+            None => None,
+        };
+
+        let has_draft = !text.is_empty() || relation.is_some();
+        if has_draft {
+            let draft = Draft {
+                text,
+                cursor,
+                relation,
+            };
+            self.drafts.insert(room_id.clone(), draft);
+        } else {
+            self.drafts.remove(&room_id);
+        }
+        self.ui.roomlist.set_draft(&room_id, has_draft);
+
+        if cache::store_drafts(self.drafts.clone()).is_err() {
+            error!("Error caching the drafts");
+        }
+    }
+
+    /// Fills the composer with the draft of a room, or empties it if there's none.
+    pub fn restore_draft(&mut self, room_id: &RoomId) {
+        let draft = self.drafts.get(room_id).cloned().unwrap_or_default();
+
+        match draft.relation {
+            Some(DraftRelation::Edit(original)) => {
+                self.composer_relation = Some(ComposerRelation::Edit(original));
+                self.show_composer_relation(&i18n("Editing message"));
+            }
+            // The replied message may not be loaded anymore after a restart
+            Some(DraftRelation::Reply(parent)) => {
+                if let Some(msg) = self.get_message_by_id(room_id, &parent) {
+                    self.start_replying(msg);
+                }
+            }
+            None => {}
+        }
+
+        let buffer = &self.ui.sventry.buffer;
+        buffer.set_text(&draft.text);
+        buffer.place_cursor(&buffer.get_iter_at_offset(draft.cursor));
+    }
+
+    /// Sends what was typed in the composer, running it if it's a command.
+    /// The users and rooms it mentions are sent as links.
+    ///
+    /// Returns `false` if the text should stay in the composer, for instance
+    /// because it's an unknown command.
+    pub fn send_message(&mut self, msg: String, mentions: Vec<Mention>) -> bool {
         if msg.is_empty() {
             // Not sending empty messages
-            return;
+            return true;
         }
 
+        match command::parse(&msg) {
+            Input::Message(text) => {
+                // The text may have lost the slash escaping a command
+                let skipped = msg.len() - text.len();
+                let mentions: Vec<Mention> = mentions
+                    .into_iter()
+                    .filter(|m| m.start >= skipped)
+                    .map(|m| Mention {
+                        start: m.start - skipped,
+                        end: m.end - skipped,
+                        id: m.id,
+                    })
+                    .collect();
+
+                let formatted_body = if self.md_enabled {
+                    render_markdown(&mention::to_markdown(text, &mentions))
+                } else if !mentions.is_empty() {
+                    Some(mention::to_html(text, &mentions))
+                } else {
+                    None
+                };
+                self.send_text(text.to_string(), formatted_body, "m.text");
+                true
+            }
+            Input::Command(command, args) => self.run_command(command, args),
+            Input::Unknown(name) => {
+                self.show_error(i18n_f(
+                    "Unknown command “/{}”. Type /help to list the available commands.",
+                    &[name],
+                ));
+                false
+            }
+        }
+    }
+
+    /// Sends a text message to the active room, as a reply or an edit if
+    /// the composer relates to another message.
+    pub(super) fn send_text(&mut self, body: String, formatted_body: Option<String>, mtype: &str) {
         if let Some(room) = self.active_room.clone() {
             if let Some(sender) = self.login_data.as_ref().map(|ld| ld.uid.clone()) {
-                let body = msg.clone();
-                let mtype = String::from("m.text");
-                let mut m = Message::new(room, sender, body, mtype, None);
+                let mut m = Message::new(room, sender, body, mtype.to_string(), None);
 
-                if msg.starts_with("/me ") {
-                    m.body = msg.trim_start_matches("/me ").to_owned();
-                    m.mtype = String::from("m.emote");
+                if formatted_body.is_some() {
+                    m.formatted_body = formatted_body;
+                    m.format = Some(String::from("org.matrix.custom.html"));
                 }
 
-                // Element (Riot) does not properly show emotes with Markdown;
-                // Emotes with markdown have a newline after the username
-                if m.mtype != "m.emote" && self.md_enabled {
-                    let mut md_options = ComrakOptions::default();
-                    md_options.hardbreaks = true;
-                    let mut md_parsed_msg = markdown_to_html(&msg, &md_options);
-
-                    // Removing wrap tag: <p>..</p>\n
-                    let limit = md_parsed_msg.len() - 5;
-                    let trim = match (md_parsed_msg.get(0..3), md_parsed_msg.get(limit..)) {
-                        (Some(open), Some(close)) if open == "<p>" && close == "</p>\n" => true,
-                        _ => false,
-                    };
-                    if trim {
-                        md_parsed_msg = md_parsed_msg
-                            .get(3..limit)
-                            .unwrap_or(&md_parsed_msg)
-                            .to_string();
+                match self.composer_relation.take() {
+                    Some(ComposerRelation::Edit(original)) => {
+                        m.replace = Some(original);
                     }
-
-                    if md_parsed_msg != msg {
-                        m.formatted_body = Some(md_parsed_msg);
-                        m.format = Some(String::from("org.matrix.custom.html"));
+                    Some(ComposerRelation::Reply(parent)) => {
+                        m.in_reply_to = parent.id.clone();
+                        m.extra_content = Some(reply_fallback(&parent, &m));
                     }
+                    None => {}
                 }
+                self.ui.sventry.relation_box.hide();
 
                 self.add_tmp_room_message(m);
                 self.dequeue_message();
@@ -311,19 +776,13 @@ impl AppOp {
                             .unwrap_or_default();
 
                         let mut m = Message::new(room, sender, body, mtype.to_string(), None);
-                        let info = match mtype {
-                            "m.image" => get_image_media_info(&path, mime.as_ref()),
-                            "m.audio" => get_audio_video_media_info(&uri, mime.as_ref()),
-                            "m.video" => get_audio_video_media_info(&uri, mime.as_ref()),
-                            "m.file" => get_file_media_info(&path, mime.as_ref()),
-                            _ => None,
-                        };
-
-                        m.extra_content = info;
-                        m.local_path = Some(path);
-
-                        self.add_tmp_room_message(m);
-                        self.dequeue_message();
+                        let mime = mime.to_string();
+                        // Decoding the image or the video can take a while
+                        RUNTIME.spawn_blocking(move || {
+                            add_media_info(&mut m, &path, &uri, &mime);
+                            m.local_path = Some(path);
+                            APPOP!(add_attached_message, (m));
+                        });
                     } else {
                         error!("Can't send message: Could not query info");
                     }
@@ -338,32 +797,65 @@ impl AppOp {
         }
     }
 
-    /// This method is called when a tmp message with an attach is sent correctly
-    /// to the matrix media server and we've the real url to use so we can
-    /// replace the tmp message with the same id with this new one
-    pub fn attached_file(&mut self, msg: Message) -> Option<()> {
-        let messages = self.ui.history.as_ref()?.get_listbox();
-        let p = self.msg_queue.iter().position(|m| m.msg == msg);
-        if let Some(i) = p {
-            let w = self.msg_queue.remove(i);
-            if let Some(w) = w.widget {
-                messages.remove(&w);
-            }
-        }
+    /// Queues a file to be sent, once the info about it is gathered.
+    pub fn add_attached_message(&mut self, msg: Message) {
         self.add_tmp_room_message(msg);
-        None
+        self.dequeue_message();
+    }
+
+    /// This method is called when the files of a tmp message have been uploaded
+    /// to the matrix media server, so the message is updated with their urls
+    pub fn attached_file(&mut self, txn_id: String, msg: Message) {
+        if let Some(tmp) = self.msg_queue.iter_mut().find(|t| t.txn_id == txn_id) {
+            tmp.msg = msg;
+            self.store_outbox();
+        }
     }
 
-    /* TODO: find a better name for this function */
     pub fn show_room_messages(&mut self, newmsgs: Vec<Message>) -> Option<()> {
         let mut msgs = vec![];
+        let mut echoes = vec![];
+        let mut sent_from_here = false;
+
+        for msg in newmsgs {
+            // Sync can be faster than the response to the sending request
+            if let Some(txn_id) = msg.txn_id.clone() {
+                self.take_tmp_msg(&txn_id);
+                sent_from_here = true;
+            }
 
-        for msg in newmsgs.iter() {
             if let Some(r) = self.rooms.get_mut(&msg.room) {
-                if !r.messages.contains(msg.id.as_ref().unwrap()) {
-                    msgs.push(msg.clone());
+                match r.messages.get(msg.id.as_ref().unwrap()) {
+                    Some(known) => {
+                        // A local echo gets the date given by the server
+                        if known.date != msg.date && msg.thread_root.is_none() {
+                            echoes.push(msg.clone());
+                        }
+                    }
+                    None => msgs.push(msg.clone()),
+                }
+                r.take_new_message(msg);
+            }
+        }
+
+        // Keeps the local echoes still waiting for their event
+        if sent_from_here {
+            self.store_outbox();
+        }
+
+        for msg in echoes {
+            if self.active_room.as_ref() != Some(&msg.room) {
+                continue;
+            }
+            if let Some(ui_msg) = self.create_new_room_message(msg) {
+                if let (Some(login_data), Some(history)) = (&self.login_data, &mut self.ui.history)
+                {
+                    history.confirm_message(
+                        login_data.session_client.clone(),
+                        self.user_info_cache.clone(),
+                        ui_msg,
+                    );
                 }
-                r.take_new_message(msg.clone());
             }
         }
 
@@ -372,12 +864,17 @@ impl AppOp {
         let uid = login_data.uid;
         for msg in msgs.iter() {
             if !msg.redacted && self.active_room.as_ref().map_or(false, |x| x == &msg.room) {
-                self.add_room_message(msg.clone());
+                match msg.thread_root.as_ref() {
+                    Some(thread_root) => self.refresh_thread(&msg.room, thread_root),
+                    None => {
+                        self.add_room_message(msg.clone());
+                    }
+                }
                 msg_in_active = true;
             }
 
-            if msg.replace != None {
-                /* No need to notify (and confuse the user) about edits. */
+            if msg.replace != None || msg.state.is_some() {
+                /* No need to notify (and confuse the user) about edits or members joining. */
                 continue;
             }
 
@@ -408,6 +905,7 @@ impl AppOp {
     pub fn show_room_messages_top(
         &mut self,
         msgs: Vec<Message>,
+        reactions: Vec<Reaction>,
         room_id: RoomId,
         prev_batch: Option<String>,
     ) {
@@ -415,13 +913,19 @@ impl AppOp {
             unwrap_or_unit_return!(self.login_data.as_ref().map(|ld| ld.session_client.clone()));
         if let Some(r) = self.rooms.get_mut(&room_id) {
             r.prev_batch = prev_batch;
+            for reaction in reactions {
+                r.messages.add_reaction(reaction);
+            }
         }
 
         let active_room = self.active_room.as_ref();
         let mut list = vec![];
         for item in msgs.iter().rev() {
             /* create a list of new messages to load to the history */
-            if active_room.map_or(false, |a_room| item.room == *a_room) && !item.redacted {
+            if active_room.map_or(false, |a_room| item.room == *a_room)
+                && !item.redacted
+                && item.thread_root.is_none()
+            {
                 if let Some(ui_msg) = self.create_new_room_message(item.clone()) {
                     list.push(ui_msg);
                 }
@@ -432,21 +936,145 @@ impl AppOp {
             }
         }
 
+        // They are shown when going back to the latest messages
+        if self.is_timeline_detached(&room_id) {
+            return;
+        }
         if let Some(ref mut history) = self.ui.history {
             history.add_old_messages_in_batch(session_client, self.user_info_cache.clone(), list);
         }
     }
 
     pub fn remove_message(&mut self, room_id: RoomId, id: EventId) -> Option<()> {
+        let removed_reaction = self
+            .rooms
+            .get_mut(&room_id)
+            .and_then(|room| room.messages.remove_reaction(&id));
+        if let Some(target) = removed_reaction {
+            self.refresh_room_message(&room_id, &target);
+            return None;
+        }
+
         let message = self.get_message_by_id(&room_id, &id);
 
         if let Some(mut msg) = message {
             self.remove_room_message(msg.clone());
+            let thread_root = msg.thread_root.clone();
             if let Some(ref mut room) = self.rooms.get_mut(&msg.room) {
                 msg.redacted = true;
                 room.take_new_message(msg);
             }
+            if let Some(thread_root) = thread_root {
+                self.refresh_thread(&room_id, &thread_root);
+            }
+        }
+        None
+    }
+
+    pub fn add_reaction(&mut self, reaction: Reaction) {
+        let room_id = reaction.room.clone();
+        let target = reaction.relates_to.clone();
+        let room = unwrap_or_unit_return!(self.rooms.get_mut(&room_id));
+        if room.messages.add_reaction(reaction) {
+            self.refresh_room_message(&room_id, &target);
+        }
+    }
+
+    /// Moves the read receipts of users to the latest event they read.
+    pub fn set_receipts(&mut self, room_id: RoomId, receipts: Receipts) {
+        let room = unwrap_or_unit_return!(self.rooms.get_mut(&room_id));
+        let mut changed = HashSet::new();
+        for (event_id, readers) in receipts {
+            // Receipts can point to events that aren't shown, like reactions
+            if room.messages.get(&event_id).is_none() {
+                continue;
+            }
+
+            for (uid, ts) in readers {
+                let previous: Vec<_> = room
+                    .messages
+                    .iter()
+                    .filter(|m| m.receipt.contains_key(&uid) && m.id.as_ref() != Some(&event_id))
+                    .cloned()
+                    .collect();
+                for mut msg in previous {
+                    msg.receipt.remove(&uid);
+                    changed.extend(msg.id.clone());
+                    room.take_new_message(msg);
+                }
+
+                if let Some(mut msg) = room.messages.get(&event_id).cloned() {
+                    msg.receipt.insert(uid, ts);
+                    room.take_new_message(msg);
+                }
+            }
+            changed.insert(event_id);
+        }
+
+        for event_id in changed {
+            self.refresh_room_message(&room_id, &event_id);
+        }
+    }
+
+    /// Sends a reaction with `key` to the message, or redacts ours if we already sent one.
+    pub fn toggle_reaction(&mut self, event_id: EventId, key: String) {
+        let (session_client, uid) = unwrap_or_unit_return!(self
+            .login_data
+            .as_ref()
+            .map(|ld| (ld.session_client.clone(), ld.uid.clone())));
+        let room_id = unwrap_or_unit_return!(self.active_room.clone());
+        let room = unwrap_or_unit_return!(self.rooms.get(&room_id));
+        let event_id = room.messages.reaction_target(&event_id);
+
+        match room.messages.user_reaction(&event_id, &key, &uid).cloned() {
+            Some(reaction_id) => {
+                RUNTIME.spawn(async move {
+                    match room::redact_reaction(session_client, room_id, reaction_id).await {
+                        Ok((room_id, reaction_id)) => {
+                            APPOP!(remove_message, (room_id, reaction_id));
+                        }
+                        Err(err) => err.handle_error(),
+                    }
+                });
+            }
+            None => {
+                RUNTIME.spawn(async move {
+                    match room::send_reaction(session_client, &room_id, &event_id, &key).await {
+                        Ok(id) => {
+                            let reaction = Reaction {
+                                id,
+                                room: room_id,
+                                sender: uid,
+                                relates_to: event_id,
+                                key,
+                            };
+                            APPOP!(add_reaction, (reaction));
+                        }
+                        Err(err) => err.handle_error(),
+                    }
+                });
+            }
+        }
+    }
+
+    /// Rebuilds the row of a message shown in the active room, e.g. after its reactions changed.
+    pub(super) fn refresh_room_message(
+        &mut self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Option<()> {
+        if self.active_room.as_ref() != Some(room_id) {
+            return None;
         }
+
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        let room = self.rooms.get(room_id)?;
+        // Edited messages are shown with the content of their latest replacement
+        let msg = room.messages.get_latest(event_id)?.clone();
+
+        let ui_msg = self.create_new_room_message(msg)?;
+        let history = self.ui.history.as_mut()?;
+        history.update_message(session_client, self.user_info_cache.clone(), ui_msg);
         None
     }
 
@@ -465,7 +1093,12 @@ impl AppOp {
                 ").unwrap();
         }
 
+        if msg.state.is_some() && !self.show_state_events {
+            return None;
+        }
+
         let t = match msg.mtype.as_ref() {
+            _ if msg.state.is_some() => RowType::State,
             "m.emote" => RowType::Emote,
             "m.image" => RowType::Image,
             "m.sticker" => RowType::Sticker,
@@ -510,53 +1143,169 @@ impl AppOp {
             .copied()
             .unwrap_or_default();
         let redactable = admin != 0 || login_data.uid == msg.sender;
+        let editable = login_data.uid == msg.sender && msg.mtype == "m.text" && msg.id.is_some();
+
+        // Reactions always relate to the original event, not to its edits
+        let reactions = msg
+            .replace
+            .as_ref()
+            .or_else(|| msg.id.as_ref())
+            .and_then(|id| room.messages.reactions(id))
+            .map(|keys| {
+                keys.iter()
+                    .map(|(key, senders)| ReactionContent {
+                        key: key.clone(),
+                        senders: senders
+                            .keys()
+                            .map(|uid| {
+                                room.members
+                                    .get(uid)
+                                    .and_then(|m| m.alias.clone())
+                                    .unwrap_or_else(|| uid.to_string())
+                            })
+                            .collect(),
+                        reacted: senders.contains_key(&login_data.uid),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
 
-        let is_last_viewed = msg.receipt.contains_key(&login_data.uid);
+        // Threads always start at the original event, not at its edits
+        let thread_replies = msg
+            .replace
+            .as_ref()
+            .or_else(|| msg.id.as_ref())
+            .map_or(0, |id| room.messages.thread_replies(id).count());
+
+        let reply = msg.in_reply_to.clone().map(|id| {
+            let parent = room.messages.get_latest(&id);
+            ReplyContent {
+                sender_name: parent.map(|p| {
+                    room.members
+                        .get(&p.sender)
+                        .and_then(|m| m.alias.clone())
+                        .unwrap_or_else(|| p.sender.to_string())
+                }),
+                body: parent.map(|p| p.body.clone()),
+                id,
+            }
+        });
+
+        // Our own position is shown by the new messages divider instead
+        let mut receipts: Vec<_> = msg
+            .receipt
+            .iter()
+            .filter(|(uid, _)| **uid != login_data.uid && **uid != msg.sender)
+            .map(|(uid, ts)| ReceiptContent {
+                uid: uid.clone(),
+                name: room.members.get(uid).and_then(|m| m.alias.clone()),
+                ts: *ts,
+            })
+            .collect();
+        receipts.sort_by(|a, b| b.ts.cmp(&a.ts));
+
+        let is_last_viewed = msg.id.is_some() && msg.id == room.fully_read;
         Some(MessageContent {
             msg,
             sender_name: name,
             mtype: t,
             highlights,
             redactable,
+            editable,
+            reactions,
+            reply,
+            thread_replies,
+            receipts,
             last_viewed: is_last_viewed,
             widget: None,
         })
     }
 }
 
-/// This function opens the image, creates a thumbnail
-/// and populates the info Json with the information it has
+/// Renders a Markdown message as HTML, or returns `None` if there's no
+/// formatting in it.
+fn render_markdown(msg: &str) -> Option<String> {
+    let mut md_options = ComrakOptions::default();
+    md_options.hardbreaks = true;
+    let mut md_parsed_msg = markdown_to_html(msg, &md_options);
+
+    // Removing wrap tag: <p>..</p>\n
+    let limit = md_parsed_msg.len() - 5;
+    let trim = match (md_parsed_msg.get(0..3), md_parsed_msg.get(limit..)) {
+        (Some(open), Some(close)) if open == "<p>" && close == "</p>\n" => true,
+        _ => false,
+    };
+    if trim {
+        md_parsed_msg = md_parsed_msg
+            .get(3..limit)
+            .unwrap_or(&md_parsed_msg)
+            .to_string();
+    }
 
-fn get_image_media_info(file: &Path, mimetype: &str) -> Option<JsonValue> {
-    // We need to load the file to read the orientation in the EXIF data
-    let image = Pixbuf::from_file(&file)
-        .ok()?
-        .apply_embedded_orientation()?;
-    let size = fs::metadata(file).ok()?.len();
+    Some(md_parsed_msg).filter(|html| html != msg)
+}
 
-    // make thumbnail max 800x600
-    let thumb = Pixbuf::from_file_at_scale(&file, 800, 600, true)
-        .ok()?
-        .apply_embedded_orientation()?;
-    let mut rng = rand::thread_rng();
-    let x: u64 = rng.gen_range(1, 9_223_372_036_854_775_807);
-    let thumb_path = format!(
-        "{}/fractal_{}.png",
-        temp_dir().to_str().unwrap_or_default(),
-        x.to_string()
+// How long to wait before sending again a message that failed `failures` times
+fn retry_delay(failures: u32) -> u32 {
+    RETRY_MAX_DELAY.min(RETRY_BASE_DELAY << failures.saturating_sub(1).min(16))
+}
+
+/// Builds the quote of the replied message for clients not supporting rich replies.
+///
+/// https://matrix.org/docs/spec/client_server/r0.6.1#fallbacks-for-rich-replies
+fn reply_fallback(parent: &Message, reply: &Message) -> JsonValue {
+    let quote = parent
+        .body
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 {
+                format!("> <{}> {}", parent.sender, line)
+            } else {
+                format!("> {}", line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let html_body = |msg: &Message| {
+        msg.formatted_body.clone().unwrap_or_else(|| {
+            glib::markup_escape_text(&msg.body)
+                .to_string()
+                .replace('\n', "<br />")
+        })
+    };
+    let parent_id = parent
+        .id
+        .as_ref()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    let formatted_body = format!(
+        "<mx-reply><blockquote>\
+         <a href=\"https://matrix.to/#/{room}/{event}\">In reply to</a> \
+         <a href=\"https://matrix.to/#/{sender}\">{sender}</a><br />{quote}\
+         </blockquote></mx-reply>{body}",
+        room = parent.room,
+        event = parent_id,
+        sender = parent.sender,
+        quote = html_body(parent),
+        body = html_body(reply),
     );
-    thumb.savev(&thumb_path, "png", &[]).ok()?;
-    let thumb_size = fs::metadata(&thumb_path).ok()?.len();
+
+    json!({
+        "body": format!("{}\n\n{}", quote, reply.body),
+        "format": "org.matrix.custom.html",
+        "formatted_body": formatted_body,
+    })
+}
+
+/// Populates the info Json with the size of the image
+
+fn get_image_media_info(image: &Pixbuf, file: &Path, mimetype: &str) -> Option<JsonValue> {
+    let size = fs::metadata(file).ok()?.len();
 
     let info = json!({
         "info": {
-            "thumbnail_url": thumb_path,
-            "thumbnail_info": {
-                "w": thumb.get_width(),
-                "h": thumb.get_height(),
-                "size": thumb_size,
-                "mimetype": "image/png"
-            },
             "w": image.get_width(),
             "h": image.get_height(),
             "size": size,
@@ -568,6 +1317,91 @@ fn get_image_media_info(file: &Path, mimetype: &str) -> Option<JsonValue> {
     Some(info)
 }
 
+/// Adds the info about the file of a message being sent, with a thumbnail for
+/// images and videos.
+fn add_media_info(msg: &mut Message, path: &Path, uri: &Url, mime: &str) {
+    // The image the thumbnail and the BlurHash are made from
+    let preview = match msg.mtype.as_str() {
+        "m.image" => Pixbuf::from_file(path)
+            .ok()
+            .and_then(|pb| pb.apply_embedded_orientation()),
+        "m.video" => widgets::inline_player::get_video_frame(uri)
+            .map_err(|err| error!("Can't get the video frame: {:?}", err))
+            .ok(),
+        _ => None,
+    };
+    msg.extra_content = match msg.mtype.as_str() {
+        "m.image" => preview
+            .as_ref()
+            .and_then(|image| get_image_media_info(image, path, mime)),
+        "m.audio" => get_audio_video_media_info(uri, mime),
+        "m.video" => get_audio_video_media_info(uri, mime),
+        "m.file" => get_file_media_info(path, mime),
+        _ => None,
+    };
+
+    if let Some(preview) = preview {
+        add_thumbnail_info(msg, &preview);
+    }
+}
+
+/// Adds a BlurHash of `image` to the info of an image or video being sent, and a
+/// thumbnail to upload with it.
+///
+/// Images already smaller than a thumbnail are their own thumbnail.
+fn add_thumbnail_info(msg: &mut Message, image: &Pixbuf) -> Option<()> {
+    let (max_w, max_h) = globals::MAX_THUMBNAIL_SIZE;
+    let (w, h) = (image.get_width(), image.get_height());
+    let is_video = msg.mtype == "m.video";
+    let info = msg
+        .extra_content
+        .as_mut()?
+        .get_mut("info")?
+        .as_object_mut()?;
+
+    if let Some(blurhash) = blurhash::encode_pixbuf(image) {
+        info.insert("xyz.amorgan.blurhash".to_string(), json!(blurhash));
+    }
+    if is_video {
+        info.entry("w").or_insert_with(|| json!(w));
+        info.entry("h").or_insert_with(|| json!(h));
+    } else if w <= max_w && h <= max_h {
+        return None;
+    }
+
+    let (thumb_w, thumb_h) = if w * max_h > h * max_w {
+        (max_w.min(w), (max_w.min(w) * h / w).max(1))
+    } else {
+        ((max_h.min(h) * w / h).max(1), max_h.min(h))
+    };
+    let thumb = image.scale_simple(thumb_w, thumb_h, gdk_pixbuf::InterpType::Bilinear)?;
+    // JPEG is much smaller for photos, but has no transparency
+    let (format, mimetype, options): (_, _, &[_]) = if thumb.get_has_alpha() {
+        ("png", "image/png", &[])
+    } else {
+        ("jpeg", "image/jpeg", &[("quality", "80")])
+    };
+    let mut rng = rand::thread_rng();
+    let x: u64 = rng.gen_range(1, 9_223_372_036_854_775_807);
+    // Kept until it's uploaded, so it has to survive restarts like the outbox
+    let thumb_path = cache_dir_path(Some("thumbnails"), &format!("{}.{}", x, format)).ok()?;
+    thumb.savev(&thumb_path, format, options).ok()?;
+    let thumb_size = fs::metadata(&thumb_path).ok()?.len();
+
+    info.insert(
+        "thumbnail_info".to_string(),
+        json!({
+            "w": thumb.get_width(),
+            "h": thumb.get_height(),
+            "size": thumb_size,
+            "mimetype": mimetype,
+        }),
+    );
+    msg.local_path_thumb = Some(thumb_path);
+
+    Some(())
+}
+
 fn get_audio_video_media_info(uri: &Url, mimetype: &str) -> Option<JsonValue> {
     let size = fs::metadata(uri.to_file_path().ok()?).ok()?.len();
 
@@ -607,23 +1441,26 @@ fn get_file_media_info(file: &Path, mimetype: &str) -> Option<JsonValue> {
 
 struct NonMediaMsg;
 
-async fn attach_file(session_client: MatrixClient, mut msg: Message) -> Result<(), NonMediaMsg> {
+async fn attach_file(
+    session_client: MatrixClient,
+    txn_id: String,
+    mut msg: Message,
+) -> Result<(), NonMediaMsg> {
     let mut extra_content: Option<ExtraContent> = msg
         .extra_content
         .clone()
         .and_then(|c| serde_json::from_value(c).ok());
 
-    let thumb_url = extra_content.clone().and_then(|c| c.info.thumbnail_url);
-
-    match (msg.url.clone(), msg.local_path.as_ref(), thumb_url) {
-        (Some(url), _, Some(thumb)) if url.scheme() == "mxc" && thumb.scheme() == "mxc" => {
-            send_msg_and_manage(session_client, msg).await;
+    match (msg.url.clone(), msg.local_path.clone()) {
+        // Already uploaded before a failure or a restart
+        (Some(url), _) if url.scheme() == "mxc" => {
+            send_msg_and_manage(session_client, txn_id, msg).await;
 
             Ok(())
         }
-        (_, Some(local_path), _) => {
-            if let Some(ref local_path_thumb) = msg.local_path_thumb {
-                let response = room::upload_file(session_client.clone(), local_path_thumb)
+        (_, Some(local_path)) => {
+            if let Some(local_path_thumb) = msg.local_path_thumb.take() {
+                let response = room::upload_file(session_client.clone(), &local_path_thumb)
                     .await
                     .and_then(|response| Url::parse(&response.content_uri).map_err(Into::into));
 
@@ -637,31 +1474,42 @@ async fn attach_file(session_client: MatrixClient, mut msg: Message) -> Result<(
                     }
                     Err(err) => {
                         err.handle_error();
+                        // The thumbnail can't be described without its URL
+                        if let Some(info) = msg
+                            .extra_content
+                            .as_mut()
+                            .and_then(|c| c.get_mut("info"))
+                            .and_then(|info| info.as_object_mut())
+                        {
+                            info.remove("thumbnail_info");
+                        }
                     }
                 }
 
-                if let Err(_e) = std::fs::remove_file(local_path_thumb) {
+                if let Err(_e) = std::fs::remove_file(&local_path_thumb) {
                     error!("Can't remove thumbnail: {}", local_path_thumb.display());
                 }
             }
 
             let query = room::upload_file(session_client.clone(), &local_path)
                 .await
-                .and_then(|response| {
-                    msg.url = Some(Url::parse(&response.content_uri)?);
-                    RUNTIME.spawn(send_msg_and_manage(session_client, msg.clone()));
+                .and_then(|response| Url::parse(&response.content_uri).map_err(Into::into));
 
-                    Ok(msg)
-                });
+            if let Ok(ref url) = query {
+                msg.url = Some(url.clone());
+            }
+
+            // Keeps what was uploaded so far, in case the message has to be retried
+            let (id, m) = (txn_id.clone(), msg.clone());
+            APPOP!(attached_file, (id, m));
 
             match query {
-                Ok(msg) => {
-                    APPOP!(attached_file, (msg));
-                }
+                Ok(_) => send_msg_and_manage(session_client, txn_id, msg).await,
                 Err(err) => {
                     err.handle_error();
+                    APPOP!(send_failed, (txn_id));
                 }
-            };
+            }
 
             Ok(())
         }
@@ -669,16 +1517,67 @@ async fn attach_file(session_client: MatrixClient, mut msg: Message) -> Result<(
     }
 }
 
-async fn send_msg_and_manage(session_client: MatrixClient, msg: Message) {
+async fn send_msg_and_manage(session_client: MatrixClient, txn_id: String, msg: Message) {
     match room::send_msg(session_client, msg).await {
         Ok(evid) => {
-            APPOP!(msg_sent, (evid));
+            APPOP!(msg_sent, (txn_id, evid));
             let initial = false;
             let number_tries = 0;
             APPOP!(sync, (initial, number_tries));
         }
         Err(err) => {
             err.handle_error();
+            APPOP!(send_failed, (txn_id));
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_reply_fallback() {
+        let room = RoomId::try_from("!room:example.org").unwrap();
+        let parent = Message::new(
+            room.clone(),
+            UserId::try_from("@bob:example.org").unwrap(),
+            String::from("first\nsecond"),
+            String::from("m.text"),
+            Some(EventId::try_from("$parent:example.org").unwrap()),
+        );
+        let reply = Message::new(
+            room,
+            UserId::try_from("@alice:example.org").unwrap(),
+            String::from("answer <3"),
+            String::from("m.text"),
+            None,
+        );
+        let fallback = reply_fallback(&parent, &reply);
+
+        assert_eq!(
+            fallback["body"],
+            "> <@bob:example.org> first\n> second\n\nanswer <3"
+        );
+        assert_eq!(fallback["format"], "org.matrix.custom.html");
+        assert_eq!(
+            fallback["formatted_body"],
+            "<mx-reply><blockquote>\
+             <a href=\"https://matrix.to/#/!room:example.org/$parent:example.org\">In reply to</a> \
+             <a href=\"https://matrix.to/#/@bob:example.org\">@bob:example.org</a><br />\
+             first<br />second</blockquote></mx-reply>answer &lt;3"
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(2), 2 * RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(3), 4 * RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(8), 128 * RETRY_BASE_DELAY);
+        // Waits at most a few minutes, however many times it failed
+        assert_eq!(retry_delay(9), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(1000), RETRY_MAX_DELAY);
+    }
+}
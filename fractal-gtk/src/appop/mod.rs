@@ -11,10 +11,13 @@ use matrix_sdk::Client as MatrixClient;
 
 use crate::cache::CacheMap;
 
+use crate::util;
 use crate::util::i18n;
 
 use crate::model::room::RoomList;
 use crate::passwd::PasswordStorage;
+use crate::util::permalink::Permalink;
+use crate::util::voice::VoiceRecorder;
 
 use crate::actions::AppState;
 use crate::app::AppRuntime;
@@ -22,7 +25,11 @@ use crate::cache;
 use crate::ui;
 
 mod account;
+pub mod command;
 mod directory;
+mod edit_history;
+mod export;
+mod forward;
 mod invite;
 mod login;
 mod media_viewer;
@@ -30,15 +37,20 @@ pub mod member;
 mod message;
 mod notifications;
 mod notify;
+mod permalink;
 pub mod room;
 mod room_settings;
+mod search;
 mod start_chat;
 pub mod state;
 mod sync;
+mod thread;
+mod timeline;
 mod user;
+mod voice;
 
 use self::member::SearchType;
-use self::message::TmpMsg;
+use self::message::{ComposerRelation, TmpMsg};
 
 pub type UserInfoCache = Arc<Mutex<CacheMap<UserId, (String, PathBuf)>>>;
 
@@ -81,7 +93,14 @@ pub struct AppOp {
 
     pub syncing: bool, // TODO: Replace with a Mutex
     pub msg_queue: Vec<TmpMsg>,
+    // The local echoes restored from the outbox, until their room is loaded
+    restored_echoes: Vec<cache::LocalEcho>,
     pub sending_message: bool,
+    // Set while waiting to retry sending a message that failed
+    pub waiting_retry: bool,
+    // Bumped to outdate the pending retry timeout
+    pub retry_generation: u32,
+    pub composer_relation: Option<ComposerRelation>,
 
     pub login_data: Option<LoginData>,
 
@@ -89,8 +108,12 @@ pub struct AppOp {
     pub join_to_room: Option<RoomId>,
     pub rooms: RoomList,
     unread_rooms: usize,
-    pub unsent_messages: HashMap<RoomId, (String, i32)>,
+    pub drafts: HashMap<RoomId, cache::Draft>,
     pub typing: HashMap<RoomId, std::time::Instant>,
+    // The part of the history of the active room being looked at, apart from the latest messages
+    timeline_window: Option<timeline::TimelineWindow>,
+    // The link being opened, until its room is shown
+    pending_permalink: Option<Permalink>,
 
     pub directory_pagination: RoomSearchPagination,
     pub state: AppState,
@@ -98,6 +121,10 @@ pub struct AppOp {
 
     pub invitation_roomid: Option<RoomId>,
     pub md_enabled: bool,
+    // Whether changes of the room state are shown in the room history
+    pub show_state_events: bool,
+    // The voice message being recorded in the composer
+    voice_recorder: Option<VoiceRecorder>,
     search_type: SearchType,
 
     pub user_info_cache: UserInfoCache,
@@ -116,15 +143,23 @@ impl AppOp {
             login_data: None,
             syncing: false,
             msg_queue: vec![],
+            restored_echoes: vec![],
             sending_message: false,
+            waiting_retry: false,
+            retry_generation: 0,
+            composer_relation: None,
             state: AppState::Login,
             directory_pagination: RoomSearchPagination::Initial,
             unread_rooms: 0,
             since: None,
-            unsent_messages: HashMap::new(),
+            drafts: HashMap::new(),
             typing: HashMap::new(),
+            timeline_window: None,
+            pending_permalink: None,
 
             md_enabled: false,
+            show_state_events: util::get_state_events_schema(),
+            voice_recorder: None,
             invitation_roomid: None,
             search_type: SearchType::Invite,
 
@@ -144,6 +179,8 @@ impl AppOp {
         } else {
             None
         };
+        self.drafts = cache::load_drafts().unwrap_or_default();
+        self.restore_outbox();
 
         // FIXME: Storing and getting the password is insecure.
         //        Only the access token should be used.
@@ -179,7 +216,8 @@ impl AppOp {
         }
     }
 
-    pub fn quit(&self) {
+    pub fn quit(&mut self) {
+        self.save_draft();
         self.cache_rooms();
         self.disconnect();
     }
@@ -0,0 +1,118 @@
+use crate::appop::AppOp;
+use crate::model::room::Room;
+use crate::util::i18n::i18n;
+use crate::util::permalink::{self, Format, Permalink};
+use gio::ActionGroupExt;
+use glib::ToVariant;
+use matrix_sdk::identifiers::{EventId, RoomId};
+use std::collections::HashMap;
+
+// Links to rooms without an alias tell at most this number of servers to join through
+const MAX_VIA_SERVERS: usize = 3;
+
+impl AppOp {
+    /// Copies a link to the room, or to a message in it, to the clipboard.
+    pub fn copy_permalink(
+        &self,
+        room_id: &RoomId,
+        event_id: Option<EventId>,
+        format: Format,
+    ) -> Option<()> {
+        let room = self.rooms.get(room_id)?;
+        let link = match (&room.alias, event_id) {
+            // Aliases can be moved to other rooms, so links to messages use the room ID
+            (Some(alias), None) => Permalink {
+                room: alias.clone().into(),
+                event: None,
+                via: vec![],
+            },
+            (_, event) => Permalink {
+                room: room_id.clone().into(),
+                event,
+                via: via_servers(room),
+            },
+        };
+
+        let atom = gdk::Atom::intern("CLIPBOARD");
+        let clipboard = gtk::Clipboard::get(&atom);
+        clipboard.set_text(&link.to_uri(format));
+        None
+    }
+
+    /// Opens the room a matrix.to link or a `matrix:` URI points to, showing the
+    /// message it points to.
+    pub fn open_permalink(&mut self, uri: String) {
+        let link = match permalink::parse(&uri) {
+            Some(link) => link,
+            None => {
+                self.show_error(i18n("This link can’t be opened."));
+                return;
+            }
+        };
+
+        // The joined rooms are only known after the first sync
+        if self.since.is_none() {
+            self.pending_permalink = Some(link);
+        } else {
+            self.follow_permalink(link);
+        }
+    }
+
+    pub(super) fn follow_permalink(&mut self, link: Permalink) {
+        let joined = self
+            .rooms
+            .values()
+            .filter(|room| room.membership.is_joined())
+            .find(|room| {
+                room.id.as_str() == link.room.as_str()
+                    || room
+                        .alias
+                        .as_ref()
+                        .map_or(false, |alias| alias.as_str() == link.room.as_str())
+            })
+            .map(|room| room.id.clone());
+        let room = link.room.clone();
+        // The message is shown once the room is opened
+        self.pending_permalink = Some(link);
+
+        match joined {
+            Some(room_id) => {
+                let data = room_id.to_string().to_variant();
+                self.ui.gtk_app.activate_action("open-room", Some(&data));
+            }
+            None => self.open_room_link(room),
+        }
+    }
+
+    /// Takes the message the opened link points to, if it's in the given room.
+    pub(super) fn take_permalink_event(&mut self, room_id: &RoomId) -> Option<EventId> {
+        let link = self.pending_permalink.take()?;
+        let room = self.rooms.get(room_id)?;
+        let in_room = link.room.as_str() == room_id.as_str()
+            || room
+                .alias
+                .as_ref()
+                .map_or(false, |alias| alias.as_str() == link.room.as_str());
+
+        link.event.filter(|_| in_room)
+    }
+}
+
+// The servers of most members, which are likely to stay in the room
+fn via_servers(room: &Room) -> Vec<String> {
+    let mut servers: HashMap<&str, usize> = HashMap::new();
+    for user_id in room.members.keys() {
+        *servers.entry(user_id.server_name().as_str()).or_default() += 1;
+    }
+    if servers.is_empty() {
+        servers.insert(room.id.server_name().as_str(), 1);
+    }
+
+    let mut servers: Vec<_> = servers.into_iter().collect();
+    servers.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    servers
+        .into_iter()
+        .take(MAX_VIA_SERVERS)
+        .map(|(server, _)| server.to_string())
+        .collect()
+}
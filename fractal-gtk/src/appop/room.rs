@@ -2,7 +2,7 @@ use crate::backend::room;
 use crate::util::i18n::{i18n, i18n_k, ni18n_f};
 use log::{error, warn};
 use matrix_sdk::events::EventType;
-use matrix_sdk::identifiers::RoomId;
+use matrix_sdk::identifiers::{RoomId, RoomIdOrAliasId};
 use std::convert::TryInto;
 use std::fs::remove_file;
 use std::os::unix::fs;
@@ -15,6 +15,7 @@ use crate::appop::AppOp;
 use crate::backend::HandleError;
 
 use crate::util::cache_dir_path;
+use crate::util::set_state_events_schema;
 
 use crate::actions;
 use crate::actions::AppState;
@@ -23,8 +24,10 @@ use crate::widgets;
 
 use crate::model::{
     member::Member,
+    message::Message,
     room::{Reason, Room, RoomMembership, RoomTag},
 };
+use crate::ui::MessageContent;
 
 use crate::util::markup_text;
 
@@ -40,7 +43,7 @@ pub struct Force(pub bool);
 impl AppOp {
     pub fn remove_room(&mut self, id: RoomId) {
         self.rooms.remove(&id);
-        self.unsent_messages.remove(&id);
+        self.drafts.remove(&id);
         self.ui.roomlist.remove_room(&id);
     }
 
@@ -50,7 +53,7 @@ impl AppOp {
             self.rooms.clear();
         }
         let mut roomlist = vec![];
-        for room in rooms {
+        for mut room in rooms {
             // removing left rooms
             if let RoomMembership::Left(kicked) = room.membership.clone() {
                 if let Reason::Kicked(reason, kicker_uid) = kicked {
@@ -79,6 +82,8 @@ impl AppOp {
                 update_room.typing_users = typing_users;
                 self.update_typing_notification();
             } else {
+                self.restore_local_echoes(&mut room);
+
                 // Request all joined members for each new room
                 let session_client = login_data.session_client.clone();
                 let room_id = room.id.clone();
@@ -167,6 +172,10 @@ impl AppOp {
             }
             self.cache_rooms();
         }
+
+        for room_id in self.drafts.keys() {
+            self.ui.roomlist.set_draft(room_id, true);
+        }
     }
 
     pub fn reload_rooms(&mut self) {
@@ -190,6 +199,10 @@ impl AppOp {
                 return;
             }
 
+            self.save_draft();
+            self.cancel_composer_relation();
+            self.cancel_voice_recording();
+
             let msg_entry = self.ui.sventry.view.clone();
             let msg_entry_stack = self
                 .ui
@@ -208,29 +221,6 @@ impl AppOp {
             if user_power >= 0 || room.admins.is_empty() {
                 msg_entry.set_editable(true);
                 msg_entry_stack.set_visible_child_name("Text Entry");
-
-                if let Some(buffer) = msg_entry.get_buffer() {
-                    let start = buffer.get_start_iter();
-                    let end = buffer.get_end_iter();
-
-                    if let Some(msg) = buffer.get_text(&start, &end, false) {
-                        if let Some(ref active_room) = self.active_room {
-                            if msg.len() > 0 {
-                                if let Some(mark) = buffer.get_insert() {
-                                    let iter = buffer.get_iter_at_mark(&mark);
-                                    let msg_position = iter.get_offset();
-
-                                    self.unsent_messages.insert(
-                                        active_room.clone(),
-                                        (msg.to_string(), msg_position),
-                                    );
-                                }
-                            } else {
-                                self.unsent_messages.remove(active_room);
-                            }
-                        }
-                    }
-                }
             } else {
                 msg_entry.set_editable(false);
                 msg_entry_stack.set_visible_child_name("Disabled Entry");
@@ -258,7 +248,7 @@ impl AppOp {
             }
         });
 
-        let session_client = login_data.session_client.clone();
+        let session_client = login_data.session_client;
         let a_room = active_room.clone();
         RUNTIME.spawn(async move {
             match room::get_room_detail(session_client, a_room, EventType::RoomTopic).await {
@@ -272,25 +262,76 @@ impl AppOp {
             }
         });
 
+        // The part of the history of another room being looked at is forgotten
+        self.leave_timeline_window();
+
         /* create the intitial list of messages to fill the new room history */
-        let mut messages = vec![];
+        let messages = self.room_history_rows(&active_room, |_| true);
         if let Some(room) = self.rooms.get(&active_room) {
-            for msg in room.messages.iter().cloned() {
-                /* Make sure the message is from this room and not redacted */
-                if msg.room == active_room && !msg.redacted {
-                    let row = self.create_new_room_message(msg);
-                    if let Some(row) = row {
-                        messages.push(row);
-                    }
-                }
-            }
-
             self.set_current_room_detail(EventType::RoomName, room.name.clone());
             self.set_current_room_detail(EventType::RoomTopic, room.topic.clone());
         }
 
         self.append_tmp_msgs();
 
+        self.rebuild_room_history(active_room.clone(), messages);
+
+        self.active_room = Some(active_room.clone());
+        self.set_state(AppState::Room);
+        self.restore_draft(&active_room);
+        // Opening a link to a message shows it once the room is open
+        if let Some(event_id) = self.take_permalink_event(&active_room) {
+            self.jump_to_message(event_id);
+        }
+        /* Mark the new active room as read */
+        self.mark_last_message_as_read(Force(false));
+        self.update_typing_notification();
+    }
+
+    // The rows of the messages of a room to show in its history
+    pub(super) fn room_history_rows(
+        &self,
+        room_id: &RoomId,
+        shown: impl Fn(&Message) -> bool,
+    ) -> Vec<MessageContent> {
+        let room = match self.rooms.get(room_id) {
+            Some(room) => room,
+            None => return vec![],
+        };
+
+        room.messages
+            .iter()
+            /* Make sure the message is from this room and not redacted, thread replies
+             * are only shown in their thread */
+            .filter(|msg| {
+                msg.room == *room_id && !msg.redacted && msg.thread_root.is_none() && shown(msg)
+            })
+            .filter_map(|msg| self.create_new_room_message(msg.clone()))
+            .collect()
+    }
+
+    /// Shows or hides the changes of the room state in the room history.
+    pub fn set_show_state_events(&mut self, show: bool) -> Option<()> {
+        self.show_state_events = show;
+        set_state_events_schema(show);
+
+        // The history is shown again from the latest messages
+        self.leave_timeline_window();
+        let room_id = self.active_room.clone()?;
+        let messages = self.room_history_rows(&room_id, |_| true);
+        self.rebuild_room_history(room_id, messages);
+        self.append_tmp_msgs();
+        None
+    }
+
+    // Replaces the room history by a new one, showing the given messages
+    pub(super) fn rebuild_room_history(
+        &mut self,
+        room_id: RoomId,
+        messages: Vec<MessageContent>,
+    ) -> Option<()> {
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+
         /* make sure we remove the old room history first, because the lazy loading could try to
          * load messages */
         if let Some(history) = self.ui.history.take() {
@@ -298,23 +339,14 @@ impl AppOp {
         }
 
         let actions = actions::Message::new(self.app_runtime.clone());
-        let history = widgets::RoomHistory::new(actions, active_room.clone(), &self.ui);
+        let history = widgets::RoomHistory::new(actions, room_id, &self.ui);
         self.ui.history = if let Some(mut history) = history {
-            history.create(
-                login_data.session_client,
-                self.user_info_cache.clone(),
-                messages,
-            );
+            history.create(session_client, self.user_info_cache.clone(), messages);
             Some(history)
         } else {
             None
         };
-
-        self.active_room = Some(active_room);
-        self.set_state(AppState::Room);
-        /* Mark the new active room as read */
-        self.mark_last_message_as_read(Force(false));
-        self.update_typing_notification();
+        None
     }
 
     // FIXME: This should be a special case in a generic
@@ -325,6 +357,7 @@ impl AppOp {
             unwrap_or_unit_return!(self.login_data.as_ref().map(|ld| ld.session_client.clone()));
 
         self.rooms.remove(&room_id);
+        self.drafts.remove(&room_id);
         self.active_room = None;
         self.clear_tmp_msgs();
         self.set_state(AppState::NoRoom);
@@ -521,6 +554,32 @@ impl AppOp {
         dialog.present();
     }
 
+    /// Switches to the room a link points to, or offers to join it.
+    pub fn open_room_link(&mut self, id: RoomIdOrAliasId) {
+        let id = id.to_string();
+        let joined = self
+            .rooms
+            .values()
+            .filter(|room| room.membership.is_joined())
+            .find(|room| {
+                room.id.as_str() == id || room.alias.as_ref().map_or(false, |a| a.as_str() == id)
+            })
+            .map(|room| room.id.clone());
+
+        match joined {
+            Some(room_id) => self.set_active_room_by_id(room_id),
+            None => {
+                self.join_to_room_dialog();
+                // Filling the entry makes the dialog joinable
+                self.ui
+                    .builder
+                    .get_object::<gtk::Entry>("join_room_name")
+                    .expect("Can't find join_room_name in ui file.")
+                    .set_text(&id);
+            }
+        }
+    }
+
     pub fn join_to_room(&mut self) {
         let login_data = unwrap_or_unit_return!(self.login_data.clone());
         let try_room_id = self
@@ -0,0 +1,129 @@
+use crate::actions::AppState;
+use crate::app::RUNTIME;
+use crate::appop::AppOp;
+use crate::backend::{room, HandleError};
+use crate::ui::search::SearchResultContent;
+use matrix_sdk::identifiers::{EventId, RoomId};
+
+impl AppOp {
+    pub fn open_search(&mut self) {
+        self.ui.create_search_view(self.active_room.clone());
+        self.set_state(AppState::Search);
+        if let Some(view) = self.ui.search.as_ref() {
+            view.grab_focus();
+        }
+    }
+
+    pub fn search_messages(&mut self, term: String) -> Option<()> {
+        let view = self.ui.search.as_mut()?;
+        view.clear(term.clone());
+        if term.is_empty() {
+            return None;
+        }
+
+        let room_id = view.scope();
+        self.request_search_results(term, room_id, None)
+    }
+
+    pub fn load_more_search_results(&self) -> Option<()> {
+        let view = self.ui.search.as_ref()?;
+        let next_batch = view.next_batch.clone()?;
+        self.request_search_results(view.term.clone(), view.scope(), Some(next_batch))
+    }
+
+    fn request_search_results(
+        &self,
+        term: String,
+        room_id: Option<RoomId>,
+        next_batch: Option<String>,
+    ) -> Option<()> {
+        let login_data = self.login_data.clone()?;
+
+        RUNTIME.spawn(async move {
+            let query = room::search_messages(
+                login_data.session_client.homeserver().clone(),
+                login_data.access_token,
+                term,
+                room_id,
+                next_batch,
+            )
+            .await;
+
+            match query {
+                Ok((term, room_id, results)) => {
+                    APPOP!(add_search_results, (term, room_id, results));
+                }
+                Err(err) => {
+                    err.handle_error();
+                    APPOP!(search_failed);
+                }
+            }
+        });
+        None
+    }
+
+    pub fn add_search_results(
+        &mut self,
+        term: String,
+        room_id: Option<RoomId>,
+        results: room::SearchResults,
+    ) -> Option<()> {
+        let view = self.ui.search.as_ref()?;
+        // The search changed while the results were loading
+        if view.term != term || view.scope() != room_id {
+            return None;
+        }
+
+        // Messages of rooms we left can't be shown
+        let contents = results
+            .messages
+            .into_iter()
+            .filter_map(|msg| {
+                let room_id = msg.room.clone();
+                let room_name = self.rooms.get(&room_id).and_then(|r| r.name.clone());
+                let message = self.create_new_room_message(msg)?;
+                Some(SearchResultContent {
+                    room_id,
+                    room_name,
+                    message,
+                })
+            })
+            .collect();
+
+        self.ui.search.as_mut()?.add_results(
+            contents,
+            results.highlights,
+            results.count,
+            results.next_batch,
+        );
+        None
+    }
+
+    pub fn search_failed(&self) {
+        if let Some(view) = self.ui.search.as_ref() {
+            view.set_failed();
+        }
+    }
+
+    /// Opens the room of a search result and scrolls to it, loading the messages around
+    /// it if needed.
+    pub fn jump_to_search_result(&mut self, room_id: RoomId, event_id: EventId) {
+        // Leaving the search pops it from the back history, this makes it land in the room
+        let back_history = &mut self.ui.room_back_history;
+        if back_history.last() == Some(&AppState::Search) {
+            back_history.pop();
+        }
+        if back_history.last() != Some(&AppState::Room) {
+            back_history.push(AppState::Room);
+        }
+        back_history.push(AppState::Search);
+
+        if self.active_room.as_ref() == Some(&room_id) {
+            self.set_state(AppState::Room);
+        } else {
+            self.set_active_room_by_id(room_id);
+        }
+
+        self.jump_to_message(event_id);
+    }
+}
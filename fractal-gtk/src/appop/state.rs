@@ -11,28 +11,23 @@ impl AppOp {
             AppState::Login => self.ui.set_stack_state("login"),
             AppState::NoRoom => {
                 self.ui.set_stack_state("main_view");
-                self.ui.set_chat_state(None);
+                self.ui.set_chat_state(false);
+                self.save_draft();
+                self.cancel_composer_relation();
                 self.active_room = None;
                 self.clear_tmp_msgs();
             }
             AppState::Room => {
-                let msg = if let Some(active_room_id) = self.active_room.as_ref() {
-                    self.unsent_messages
-                        .get(active_room_id)
-                        .map(|(msg_text, msg_number)| (msg_text.as_str(), msg_number.clone()))
-                        .unwrap_or_default()
-                } else {
-                    Default::default()
-                };
-
                 self.ui.set_stack_state("main_view");
-                self.ui.set_chat_state(Some(msg));
+                self.ui.set_chat_state(true);
             }
             AppState::Directory => self.ui.set_deck_state(Some("directory"), state),
             AppState::Loading => self.ui.set_stack_state("loading"),
             AppState::AccountSettings => self.ui.set_deck_state(Some("account-settings"), state),
             AppState::RoomSettings => self.ui.set_deck_state(Some("room-settings"), state),
             AppState::MediaViewer => self.ui.set_deck_state(Some("media-viewer"), state),
+            AppState::Thread => self.ui.set_deck_state(Some("thread"), state),
+            AppState::Search => self.ui.set_deck_state(Some("search"), state),
         };
 
         //set focus for room directory
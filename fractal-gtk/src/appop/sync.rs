@@ -4,9 +4,11 @@ use crate::backend::{
     sync::{self, RoomElement, SyncRet, SyncUpdates},
     HandleError,
 };
+use crate::client::take_sync_events_json;
 use crate::model::{
     member::Member,
-    room::{Room, RoomMembership, RoomTag},
+    reaction::Reaction,
+    room::{parse_fully_read, parse_receipts, Room, RoomMembership, RoomTag},
 };
 use crate::util::i18n::i18n;
 use matrix_sdk::deserialized_responses::{JoinedRoom, SyncResponse};
@@ -16,6 +18,7 @@ use matrix_sdk::events::AnySyncRoomEvent;
 use matrix_sdk::events::AnySyncStateEvent;
 use matrix_sdk::identifiers::{RoomId, UserId};
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 
 impl AppOp {
     pub fn initial_sync(&self, show: bool) {
@@ -86,6 +89,15 @@ impl AppOp {
                                     RoomElement::RemoveMessage(room_id, msg_id) => {
                                         APPOP!(remove_message, (room_id, msg_id));
                                     }
+                                    RoomElement::Reaction(reaction) => {
+                                        APPOP!(add_reaction, (reaction));
+                                    }
+                                    RoomElement::Receipts(room_id, receipts) => {
+                                        APPOP!(set_receipts, (room_id, receipts));
+                                    }
+                                    RoomElement::FullyRead(room_id, event_id) => {
+                                        APPOP!(set_fully_read, (room_id, event_id));
+                                    }
                                 }
                             }
                         } else {
@@ -118,10 +130,18 @@ impl AppOp {
     }
 
     pub fn synced(&mut self, since: Option<String>) {
+        let first_sync = self.since.is_none();
         self.syncing = false;
         self.since = since;
         self.sync(false, 0);
         self.initial_sync(false);
+
+        // A link opened before the rooms were known
+        if first_sync {
+            if let Some(link) = self.pending_permalink.take() {
+                self.follow_permalink(link);
+            }
+        }
     }
 
     pub fn sync_error(&mut self, number_tries: u32) {
@@ -137,8 +157,10 @@ fn transform_sync_response(response: SyncResponse, initial: bool, user_id: UserI
         Some(get_sync_updates(&response.rooms.join, &user_id))
     };
 
+    let events_json = take_sync_events_json();
+
     SyncRet {
-        rooms: Room::from_sync_response(&response, user_id),
+        rooms: Room::from_sync_response(&response, &events_json, user_id),
         next_batch: response.next_batch,
         updates,
     }
@@ -203,8 +225,23 @@ fn get_sync_updates(join: &BTreeMap<RoomId, JoinedRoom>, user_id: &UserId) -> Sy
                 AnySyncRoomEvent::Message(AnySyncMessageEvent::RoomRedaction(ev)) => {
                     Some(RoomElement::RemoveMessage(room_id, ev.redacts.clone()))
                 }
+                AnySyncRoomEvent::Message(_) => Reaction::try_from((room_id, event))
+                    .ok()
+                    .map(RoomElement::Reaction),
                 _ => None,
             })
+            .chain(join.iter().filter_map(|(room_id, room)| {
+                let receipts = parse_receipts(&room.ephemeral.events);
+                if receipts.is_empty() {
+                    None
+                } else {
+                    Some(RoomElement::Receipts(room_id.clone(), receipts))
+                }
+            }))
+            .chain(join.iter().filter_map(|(room_id, room)| {
+                let event_id = parse_fully_read(&room.account_data.events, &room.ephemeral.events)?;
+                Some(RoomElement::FullyRead(room_id.clone(), event_id))
+            }))
             .collect(),
     }
 }
@@ -0,0 +1,185 @@
+use crate::actions::AppState;
+use crate::app::RUNTIME;
+use crate::appop::AppOp;
+use crate::backend::{room, HandleError};
+use crate::model::message::Message;
+use matrix_sdk::identifiers::{EventId, RoomId};
+use serde_json::json;
+use serde_json::Value as JsonValue;
+
+impl AppOp {
+    pub fn open_thread(&mut self, event_id: EventId) -> Option<()> {
+        let room_id = self.active_room.clone()?;
+        // Replies open the thread they are part of, and threads always start at the
+        // original event, not at its edits
+        let thread_root = self
+            .get_message_by_id(&room_id, &event_id)
+            .and_then(|msg| msg.thread_root.or(msg.replace))
+            .unwrap_or(event_id);
+        self.ui
+            .create_thread_view(self.app_runtime.clone(), room_id, thread_root);
+        self.show_thread();
+        self.set_state(AppState::Thread);
+        self.ui.thread.as_ref()?.grab_focus();
+        self.load_thread_replies()
+    }
+
+    /// Shows the root and the loaded replies of the open thread.
+    pub fn show_thread(&self) -> Option<()> {
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        let view = self.ui.thread.as_ref()?;
+        let room = self.rooms.get(&view.room_id)?;
+
+        let root = room
+            .messages
+            .get_latest(&view.thread_root)
+            .cloned()
+            .and_then(|msg| self.create_new_room_message(msg))
+            .map(|mut root| {
+                // The thread is already open
+                root.thread_replies = 0;
+                root
+            });
+        // Edited replies are shown with the content of their latest replacement
+        let replies = room
+            .messages
+            .thread_replies(&view.thread_root)
+            .filter_map(|msg| room.messages.get_latest(msg.id.as_ref()?))
+            .cloned()
+            .filter_map(|msg| self.create_new_room_message(msg));
+
+        let messages = root.into_iter().chain(replies).collect();
+        view.show_messages(session_client, self.user_info_cache.clone(), messages);
+        None
+    }
+
+    pub fn load_thread_replies(&self) -> Option<()> {
+        let login_data = self.login_data.clone()?;
+        let view = self.ui.thread.as_ref()?;
+        let room_id = view.room_id.clone();
+        let thread_root = view.thread_root.clone();
+        let from = view.next_batch.clone();
+
+        RUNTIME.spawn(async move {
+            let query = room::get_thread_replies(
+                login_data.session_client.homeserver().clone(),
+                login_data.access_token,
+                room_id,
+                thread_root,
+                from,
+            )
+            .await;
+
+            match query {
+                Ok((room_id, thread_root, msgs, next_batch)) => {
+                    APPOP!(add_thread_replies, (room_id, thread_root, msgs, next_batch));
+                }
+                Err(err) => {
+                    err.handle_error();
+                }
+            }
+        });
+        None
+    }
+
+    pub fn add_thread_replies(
+        &mut self,
+        room_id: RoomId,
+        thread_root: EventId,
+        msgs: Vec<Message>,
+        next_batch: Option<String>,
+    ) -> Option<()> {
+        let room = self.rooms.get_mut(&room_id)?;
+        for msg in msgs {
+            if !room.messages.contains(msg.id.as_ref()?) {
+                room.take_new_message(msg);
+            }
+        }
+
+        if let Some(view) = self.ui.thread.as_mut() {
+            if view.room_id == room_id && view.thread_root == thread_root {
+                view.next_batch = next_batch;
+            }
+        }
+
+        self.refresh_thread(&room_id, &thread_root);
+        None
+    }
+
+    pub fn send_thread_message(&mut self, body: String) -> Option<()> {
+        if body.is_empty() {
+            // Not sending empty messages
+            return None;
+        }
+
+        let sender = self.login_data.as_ref()?.uid.clone();
+        let view = self.ui.thread.as_ref()?;
+        let room = self.rooms.get(&view.room_id)?;
+        let latest = room
+            .messages
+            .thread_replies(&view.thread_root)
+            .last()
+            .and_then(|msg| msg.id.clone())
+            .unwrap_or_else(|| view.thread_root.clone());
+
+        let mtype = String::from("m.text");
+        let mut msg = Message::new(view.room_id.clone(), sender, body, mtype, None);
+        msg.thread_root = Some(view.thread_root.clone());
+        msg.extra_content = Some(thread_relates_to(&view.thread_root, &latest));
+
+        self.add_tmp_room_message(msg);
+        self.dequeue_message();
+        None
+    }
+
+    /// Updates the reply count of the thread root, and the thread if it's open.
+    pub fn refresh_thread(&mut self, room_id: &RoomId, thread_root: &EventId) {
+        self.refresh_room_message(room_id, thread_root);
+
+        let is_open = self.ui.thread.as_ref().map_or(false, |view| {
+            &view.room_id == room_id && &view.thread_root == thread_root
+        });
+        if is_open {
+            self.show_thread();
+        }
+    }
+}
+
+/// Builds the relation of a message sent to a thread, which clients not supporting
+/// threads show as a reply to the latest message of the thread.
+///
+/// https://github.com/matrix-org/matrix-doc/pull/3440
+fn thread_relates_to(thread_root: &EventId, latest: &EventId) -> JsonValue {
+    json!({
+        "m.relates_to": {
+            "rel_type": "m.thread",
+            "event_id": thread_root,
+            "is_falling_back": true,
+            "m.in_reply_to": {
+                "event_id": latest,
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::message::thread_relation;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_thread_relates_to() {
+        let root = EventId::try_from("$root:example.org").unwrap();
+        let latest = EventId::try_from("$latest:example.org").unwrap();
+        let content = thread_relates_to(&root, &latest);
+
+        assert_eq!(
+            content["m.relates_to"]["m.in_reply_to"]["event_id"],
+            "$latest:example.org"
+        );
+        // Read back as a thread reply, not as a reply to the latest message
+        let event = json!({ "content": content });
+        assert_eq!(thread_relation(&event), Some((root, true)));
+    }
+}
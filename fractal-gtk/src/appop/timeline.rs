@@ -0,0 +1,427 @@
+use crate::app::RUNTIME;
+use crate::appop::AppOp;
+use crate::backend::{room, HandleError};
+use crate::model::message::Message;
+use crate::model::reaction::Reaction;
+use crate::ui::MessageContent;
+use crate::util::i18n::i18n;
+use matrix_sdk::identifiers::{EventId, RoomId};
+use matrix_sdk::Client as MatrixClient;
+use std::collections::HashSet;
+
+/// A part of the history of a room loaded around a message, apart from the
+/// latest messages.
+#[derive(Debug, Clone)]
+pub struct TimelineWindow {
+    pub room_id: RoomId,
+    // The tokens to load the messages before and after the window
+    prev_batch: Option<String>,
+    next_batch: Option<String>,
+    // The messages only loaded for the window, forgotten when leaving it
+    messages: HashSet<EventId>,
+}
+
+impl AppOp {
+    /// Whether the history of the room shows a part of it apart from the latest messages.
+    pub fn is_timeline_detached(&self, room_id: &RoomId) -> bool {
+        self.timeline_window
+            .as_ref()
+            .map_or(false, |window| &window.room_id == room_id)
+    }
+
+    /// Scrolls to a message of the active room, loading the messages around it if
+    /// it isn't in the room history.
+    pub fn jump_to_message(&mut self, event_id: EventId) -> Option<()> {
+        let room_id = self.active_room.clone()?;
+        let target = self.shown_message_id(&room_id, event_id);
+        if self
+            .ui
+            .history
+            .as_ref()?
+            .scroll_to_message_when_shown(target.clone())
+        {
+            return None;
+        }
+
+        let room = self.rooms.get(&room_id)?;
+        if room.messages.get(&target).map_or(false, |msg| msg.redacted) {
+            self.show_error(i18n("The message can’t be shown in the room history."));
+            return None;
+        }
+
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        RUNTIME.spawn(async move {
+            match room::get_event_context(session_client, room_id, target).await {
+                Ok((room_id, event_id, chunk)) => {
+                    APPOP!(show_timeline_window, (room_id, event_id, chunk));
+                }
+                Err(err) => {
+                    err.handle_error();
+                }
+            }
+        });
+        None
+    }
+
+    // The message shown in the room history in place of the given one, since thread
+    // replies are shown under their root and edits replace the original message
+    fn shown_message_id(&self, room_id: &RoomId, event_id: EventId) -> EventId {
+        self.rooms
+            .get(room_id)
+            .and_then(|room| room.messages.get(&event_id))
+            .and_then(|msg| msg.thread_root.clone().or_else(|| msg.replace.clone()))
+            .unwrap_or(event_id)
+    }
+
+    /// Shows the messages loaded around the message being jumped to.
+    pub fn show_timeline_window(
+        &mut self,
+        room_id: RoomId,
+        event_id: EventId,
+        chunk: room::TimelineChunk,
+    ) -> Option<()> {
+        // Another room was opened in the meantime
+        if self.active_room.as_ref() != Some(&room_id) {
+            return None;
+        }
+
+        let room = self.rooms.get(&room_id)?;
+        let window_messages = self
+            .timeline_window
+            .as_ref()
+            .filter(|window| window.room_id == room_id)
+            .map(|window| &window.messages);
+        // Thread replies are loaded apart from the room history
+        let reaches_latest = chunk
+            .messages
+            .iter()
+            .filter(|msg| msg.thread_root.is_none())
+            .filter_map(|msg| msg.id.as_ref())
+            .any(|id| {
+                room.messages.contains(id) && !window_messages.map_or(false, |ids| ids.contains(id))
+            });
+
+        if reaches_latest {
+            // The message is close enough to the latest messages to load them up to it
+            self.jump_to_latest();
+            let room = self.rooms.get(&room_id)?;
+            let older: Vec<Message> = chunk
+                .messages
+                .into_iter()
+                .filter(|msg| {
+                    msg.id
+                        .as_ref()
+                        .map_or(false, |id| !room.messages.contains(id))
+                })
+                .collect();
+            if older.is_empty() {
+                if let Some(room) = self.rooms.get_mut(&room_id) {
+                    for reaction in chunk.reactions {
+                        room.messages.add_reaction(reaction);
+                    }
+                }
+            } else {
+                self.show_room_messages_top(
+                    older,
+                    chunk.reactions,
+                    room_id.clone(),
+                    chunk.prev_batch,
+                );
+            }
+        } else {
+            self.leave_timeline_window();
+            self.clear_tmp_msgs();
+
+            let mut window = TimelineWindow {
+                room_id: room_id.clone(),
+                prev_batch: chunk.prev_batch,
+                next_batch: chunk.next_batch,
+                messages: HashSet::new(),
+            };
+            let room = self.rooms.get_mut(&room_id)?;
+            for reaction in chunk.reactions {
+                room.messages.add_reaction(reaction);
+            }
+            for msg in chunk.messages {
+                if let Some(id) = msg.id.clone() {
+                    if !room.messages.contains(&id) {
+                        window.messages.insert(id);
+                    }
+                    room.take_new_message(msg);
+                }
+            }
+
+            let ids = &window.messages;
+            let messages = self.room_history_rows(&room_id, |msg| {
+                msg.id.as_ref().map_or(false, |id| ids.contains(id))
+            });
+            self.rebuild_room_history(room_id.clone(), messages);
+            self.timeline_window = Some(window);
+            if let Some(history) = self.ui.history.as_ref() {
+                history.set_detached(true);
+            }
+        }
+
+        let target = self.shown_message_id(&room_id, event_id.clone());
+        let shown = self.ui.history.as_ref().map_or(false, |history| {
+            history.scroll_to_message_when_shown(target.clone())
+        });
+        if shown {
+            None
+        } else if target != event_id {
+            // The thread root or the original message wasn't around the loaded message
+            self.jump_to_message(target)
+        } else {
+            self.show_error(i18n("The message can’t be shown in the room history."));
+            None
+        }
+    }
+
+    /// Jumps to the first message of the active room sent from the given date, in
+    /// milliseconds since the UNIX epoch.
+    pub fn go_to_date(&self, ts: i64) -> Option<()> {
+        let room_id = self.active_room.clone()?;
+        let login_data = self.login_data.clone()?;
+        RUNTIME.spawn(async move {
+            let query = room::get_event_at_date(
+                login_data.session_client.homeserver().clone(),
+                login_data.access_token,
+                room_id,
+                ts,
+            )
+            .await;
+
+            match query {
+                Ok((room_id, event_id)) => {
+                    APPOP!(jump_to_event_at_date, (room_id, event_id));
+                }
+                Err(err) => {
+                    err.handle_error();
+                }
+            }
+        });
+        None
+    }
+
+    pub fn jump_to_event_at_date(&mut self, room_id: RoomId, event_id: EventId) {
+        // Another room was opened in the meantime
+        if self.active_room.as_ref() == Some(&room_id) {
+            self.jump_to_message(event_id);
+        }
+    }
+
+    /// Loads the messages before the part of the room history being looked at.
+    pub fn load_older_window_messages(&self, room_id: &RoomId) -> Option<()> {
+        let window = self.timeline_window.as_ref()?;
+        // The start of the room was reached
+        let prev_batch = window.prev_batch.clone()?;
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        let room_id = room_id.clone();
+        RUNTIME.spawn(async move {
+            match room::get_room_messages(session_client, room_id, &prev_batch).await {
+                Ok((msgs, reactions, room, prev_batch)) => {
+                    APPOP!(add_window_messages_top, (msgs, reactions, room, prev_batch));
+                }
+                Err(err) => {
+                    err.handle_error();
+                }
+            }
+        });
+        None
+    }
+
+    pub fn add_window_messages_top(
+        &mut self,
+        msgs: Vec<Message>,
+        reactions: Vec<Reaction>,
+        room_id: RoomId,
+        prev_batch: Option<String>,
+    ) -> Option<()> {
+        // The latest messages were loaded again in the meantime
+        if !self.is_timeline_detached(&room_id) {
+            return None;
+        }
+
+        let window = self.timeline_window.as_mut()?;
+        let room = self.rooms.get_mut(&room_id)?;
+        window.prev_batch = prev_batch;
+        for reaction in reactions {
+            room.messages.add_reaction(reaction);
+        }
+        let mut added = vec![];
+        for msg in msgs {
+            let id = match msg.id.clone() {
+                Some(id) => id,
+                None => continue,
+            };
+            if window.messages.contains(&id) || room.messages.contains(&id) {
+                continue;
+            }
+            window.messages.insert(id);
+            added.push(msg.clone());
+            room.take_new_message(msg);
+        }
+
+        let list = self.history_rows(added.into_iter().rev());
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        if let Some(history) = self.ui.history.as_mut() {
+            history.add_old_messages_in_batch(session_client, self.user_info_cache.clone(), list);
+        }
+        None
+    }
+
+    /// Loads the messages after the part of the room history being looked at, going
+    /// back to the latest messages once they're reached.
+    pub fn load_newer_window_messages(&mut self, room_id: &RoomId) -> Option<()> {
+        if !self.is_timeline_detached(room_id) {
+            return None;
+        }
+
+        let next_batch = match self.timeline_window.as_ref()?.next_batch.clone() {
+            Some(next_batch) => next_batch,
+            None => return self.add_window_messages_bottom(vec![], vec![], room_id.clone(), None),
+        };
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        let room_id = room_id.clone();
+        RUNTIME.spawn(async move {
+            match room::get_room_messages_after(session_client, room_id, &next_batch).await {
+                Ok((msgs, reactions, room, next_batch)) => {
+                    APPOP!(
+                        add_window_messages_bottom,
+                        (msgs, reactions, room, next_batch)
+                    );
+                }
+                Err(err) => {
+                    err.handle_error();
+                }
+            }
+        });
+        None
+    }
+
+    pub fn add_window_messages_bottom(
+        &mut self,
+        msgs: Vec<Message>,
+        reactions: Vec<Reaction>,
+        room_id: RoomId,
+        next_batch: Option<String>,
+    ) -> Option<()> {
+        // The latest messages were loaded again in the meantime
+        if !self.is_timeline_detached(&room_id) {
+            return None;
+        }
+
+        let window = self.timeline_window.as_mut()?;
+        let room = self.rooms.get_mut(&room_id)?;
+        window.next_batch = next_batch;
+        for reaction in reactions {
+            room.messages.add_reaction(reaction);
+        }
+        // There are no newer messages to load once the end of the room is reached
+        let mut reached_latest = window.next_batch.is_none();
+        let mut added = vec![];
+        for msg in msgs {
+            let id = match msg.id.clone() {
+                Some(id) => id,
+                None => continue,
+            };
+            if window.messages.contains(&id) {
+                continue;
+            }
+            if room.messages.contains(&id) {
+                // Thread replies are loaded apart from the room history
+                if msg.thread_root.is_some() {
+                    continue;
+                }
+                reached_latest = true;
+                break;
+            }
+            window.messages.insert(id);
+            added.push(msg.clone());
+            room.take_new_message(msg);
+        }
+
+        let list = self.history_rows(added.into_iter());
+        let session_client = self.login_data.as_ref()?.session_client.clone();
+        if let Some(history) = self.ui.history.as_mut() {
+            history.add_new_messages_in_batch(
+                session_client.clone(),
+                self.user_info_cache.clone(),
+                list,
+            );
+        }
+
+        if reached_latest {
+            self.rejoin_latest_messages(session_client, &room_id);
+        }
+        None
+    }
+
+    // Makes the part of the room history being looked at continue with the latest
+    // messages, now that they were reached
+    fn rejoin_latest_messages(
+        &mut self,
+        session_client: MatrixClient,
+        room_id: &RoomId,
+    ) -> Option<()> {
+        let window = self.timeline_window.take()?;
+        let room = self.rooms.get_mut(room_id)?;
+        room.prev_batch = window.prev_batch;
+
+        // The latest messages are the ones after the last message of the window
+        let newest = room
+            .messages
+            .iter()
+            .filter(|msg| {
+                msg.id
+                    .as_ref()
+                    .map_or(false, |id| window.messages.contains(id))
+            })
+            .map(|msg| msg.date)
+            .max();
+        let ids = &window.messages;
+        let list = self.room_history_rows(room_id, |msg| {
+            newest.map_or(true, |newest| msg.date > newest)
+                && msg.id.as_ref().map_or(false, |id| !ids.contains(id))
+        });
+
+        if let Some(history) = self.ui.history.as_mut() {
+            history.add_new_messages_in_batch(session_client, self.user_info_cache.clone(), list);
+            history.set_detached(false);
+        }
+        self.append_tmp_msgs();
+        None
+    }
+
+    /// Goes back to the latest messages of the room, from a part of its history
+    /// loaded apart from them.
+    pub fn jump_to_latest(&mut self) -> Option<()> {
+        let window = self.leave_timeline_window()?;
+        if self.active_room.as_ref() != Some(&window.room_id) {
+            return None;
+        }
+
+        let messages = self.room_history_rows(&window.room_id, |_| true);
+        self.rebuild_room_history(window.room_id, messages);
+        self.append_tmp_msgs();
+        None
+    }
+
+    // Forgets the messages only loaded for the part of the room history being looked at
+    pub(super) fn leave_timeline_window(&mut self) -> Option<TimelineWindow> {
+        let window = self.timeline_window.take()?;
+        if let Some(room) = self.rooms.get_mut(&window.room_id) {
+            for id in window.messages.iter() {
+                room.messages.remove(id);
+            }
+        }
+        Some(window)
+    }
+
+    // The rows of the given messages shown in the room history
+    fn history_rows(&self, msgs: impl Iterator<Item = Message>) -> Vec<MessageContent> {
+        msgs.filter(|msg| !msg.redacted && msg.thread_root.is_none())
+            .filter_map(|msg| self.create_new_room_message(msg))
+            .collect()
+    }
+}
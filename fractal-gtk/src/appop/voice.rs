@@ -0,0 +1,86 @@
+use crate::appop::AppOp;
+use crate::model::message::Message;
+use crate::util::cache_dir_path;
+use crate::util::i18n::i18n;
+use crate::util::voice::VoiceRecorder;
+use chrono::prelude::*;
+use log::error;
+use serde_json::json;
+use std::fs;
+
+impl AppOp {
+    pub fn start_voice_recording(&mut self) -> Option<()> {
+        if self.voice_recorder.is_some() || self.active_room.is_none() {
+            return None;
+        }
+
+        let name = Local::now().format("voice-%Y%m%d-%H%M%S.ogg").to_string();
+        let path = cache_dir_path(Some("voice"), &name)
+            .map_err(|err| error!("Couldn't create the voice message file: {}", err))
+            .ok()?;
+        match VoiceRecorder::start(path) {
+            Ok(recorder) => {
+                self.voice_recorder = Some(recorder);
+                self.ui.sventry.show_recording();
+            }
+            Err(err) => {
+                error!("Couldn't start recording: {:?}", err);
+                self.show_error(i18n(
+                    "Couldn’t record a voice message. Check that a microphone is connected.",
+                ));
+            }
+        }
+
+        None
+    }
+
+    pub fn cancel_voice_recording(&mut self) {
+        if let Some(recorder) = self.voice_recorder.take() {
+            recorder.cancel();
+            self.ui.sventry.hide_recording();
+        }
+    }
+
+    /// Stops recording and sends the voice message to the active room.
+    pub fn send_voice_recording(&mut self) -> Option<()> {
+        let recorder = self.voice_recorder.take()?;
+        self.ui.sventry.hide_recording();
+
+        let voice = match recorder.stop() {
+            Some(voice) => voice,
+            None => {
+                self.show_error(i18n("Couldn’t record a voice message."));
+                return None;
+            }
+        };
+        let room = self.active_room.clone()?;
+        let sender = self.login_data.as_ref()?.uid.clone();
+        let size = fs::metadata(&voice.path).map(|meta| meta.len()).ok()?;
+
+        let mut m = Message::new(
+            room,
+            sender,
+            i18n("Voice message"),
+            "m.audio".to_string(),
+            None,
+        );
+        m.extra_content = Some(json!({
+            "info": {
+                "size": size,
+                "mimetype": "audio/ogg",
+                "duration": voice.duration,
+            },
+            "org.matrix.msc1767.audio": {
+                "duration": voice.duration,
+                "waveform": voice.waveform,
+            },
+            "org.matrix.msc3245.voice": {},
+        }));
+        m.local_path = Some(voice.path);
+
+        self.add_tmp_room_message(m);
+        self.dequeue_message();
+
+        None
+    }
+}
@@ -0,0 +1,449 @@
+//! Archives of the history of a room, as self-contained HTML, event JSON lines or
+//! plain text.
+
+use super::room::{self, RoomMessagesToError};
+use super::{dw_media, ContentType, HandleError};
+use crate::model::message::Message;
+use crate::util::i18n::i18n;
+use crate::util::sanitize::{escape, sanitize};
+use crate::APPOP;
+use chrono::prelude::*;
+use log::error;
+use matrix_sdk::identifiers::{EventId, RoomId, UserId};
+use matrix_sdk::Client as MatrixClient;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; max-width: 50em; margin: auto; }
+.message { margin: 0.5em 0; }
+.date, .edited { color: gray; font-size: smaller; }
+.sender { font-weight: bold; }
+img, video { display: block; max-width: 100%; max-height: 30em; }";
+
+/// The kind of file the history is written to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Html,
+    Json,
+    Text,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Json => "jsonl",
+            Self::Text => "txt",
+        }
+    }
+}
+
+/// The room to export, with the names to show for its members.
+#[derive(Debug, Clone)]
+pub struct ExportedRoom {
+    pub id: RoomId,
+    pub name: String,
+    pub members: HashMap<UserId, String>,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Matrix(RoomMessagesToError),
+    Io(IoError),
+}
+
+impl From<RoomMessagesToError> for ExportError {
+    fn from(err: RoomMessagesToError) -> Self {
+        Self::Matrix(err)
+    }
+}
+
+impl From<IoError> for ExportError {
+    fn from(err: IoError) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl HandleError for ExportError {
+    fn handle_error(&self) {
+        error!("Couldn’t export the room history: {:?}", self);
+        let error = i18n("Couldn’t export the chat.");
+        APPOP!(show_error, (error));
+    }
+}
+
+/* Load the messages sent between `start` and `end` going backwards from `from`, and
+ * write them to `path`. HTML archives get the media downloaded to a folder next to them.
+ * Returns the number of exported messages, or `None` if the export was cancelled.
+ */
+pub async fn export_room(
+    session_client: MatrixClient,
+    room: ExportedRoom,
+    from: String,
+    (start, end): (DateTime<Local>, DateTime<Local>),
+    format: ExportFormat,
+    path: PathBuf,
+    cancel: Arc<AtomicBool>,
+) -> Result<Option<usize>, ExportError> {
+    let now = Local::now();
+    let mut pages = vec![];
+    let mut count = 0;
+    let mut from = from;
+
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        let (messages, reactions, _, prev_batch) =
+            room::get_room_messages(session_client.clone(), room.id.clone(), &from).await?;
+        let done = messages.is_empty() && reactions.is_empty();
+        let oldest = messages.first().map(|msg| msg.date);
+        // Only the messages are exported, not the changes of the room state
+        let messages: Vec<Message> = messages
+            .into_iter()
+            .filter(|msg| msg.state.is_none() && msg.date >= start && msg.date < end)
+            .collect();
+        count += messages.len();
+        pages.push(messages);
+
+        if let Some(oldest) = oldest {
+            // The history is loaded from now back to the start of the range
+            let loaded = (now - oldest).num_seconds() as f64;
+            let total = (now - start).num_seconds().max(1) as f64;
+            let fraction = (loaded / total).min(1.0);
+            APPOP!(export_progress, (fraction, count));
+            if oldest < start {
+                break;
+            }
+        }
+
+        match prev_batch {
+            Some(prev_batch) if !done && prev_batch != from => from = prev_batch,
+            _ => break,
+        }
+    }
+
+    let messages: Vec<Message> = pages.into_iter().rev().flatten().collect();
+    let archive = match format {
+        ExportFormat::Json => json_lines(&messages),
+        ExportFormat::Text => text(&room, &messages),
+        ExportFormat::Html => {
+            let media = download_media(session_client, &messages, &path, &cancel).await?;
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            html(&room, (start, end), &messages, &media)
+        }
+    };
+    tokio::fs::write(&path, archive).await?;
+
+    Ok(Some(messages.len()))
+}
+
+fn json_lines(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .filter_map(|msg| {
+            let event: JsonValue = serde_json::from_str(msg.source.as_ref()?).ok()?;
+            serde_json::to_string(&event).ok()
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+fn text(room: &ExportedRoom, messages: &[Message]) -> String {
+    let mut text = String::new();
+    for (msg, edit) in with_latest_edits(messages) {
+        let date = msg.date.format(DATE_FORMAT);
+        let sender = sender_name(room, &msg.sender);
+        let mut body = if msg.redacted {
+            i18n("This message was deleted.")
+        } else {
+            edit.unwrap_or(msg).body.clone()
+        };
+        if let Some(url) = msg.url.as_ref() {
+            body = format!("{} <{}>", body, url);
+        }
+        if edit.is_some() {
+            body = format!("{} {}", body, i18n("(edited)"));
+        }
+
+        let line = if msg.mtype == "m.emote" {
+            format!("[{}] * {} {}\n", date, sender, body)
+        } else {
+            format!("[{}] {}: {}\n", date, sender, body)
+        };
+        text.push_str(&line);
+    }
+
+    text
+}
+
+fn html(
+    room: &ExportedRoom,
+    (start, end): (DateTime<Local>, DateTime<Local>),
+    messages: &[Message],
+    media: &HashMap<EventId, String>,
+) -> String {
+    let title = escape(&room.name);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n{}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n<p class=\"date\">{} – {}</p>\n",
+        title,
+        HTML_STYLE,
+        title,
+        start.format(DAY_FORMAT),
+        // The range ends at the start of the day after the last one
+        (end - chrono::Duration::days(1)).format(DAY_FORMAT),
+    );
+
+    for (msg, edit) in with_latest_edits(messages) {
+        let latest = edit.unwrap_or(msg);
+        let content = if msg.redacted {
+            format!("<i>{}</i>", escape(&i18n("This message was deleted.")))
+        } else if let Some(src) = msg.id.as_ref().and_then(|id| media.get(id)) {
+            media_html(msg, &escape(src))
+        } else if latest.format.as_deref() == Some("org.matrix.custom.html") {
+            sanitize(latest.formatted_body.as_deref().unwrap_or_default())
+        } else {
+            escape(&latest.body).replace('\n', "<br>")
+        };
+        let sender = escape(&sender_name(room, &msg.sender));
+        let content = if msg.mtype == "m.emote" {
+            format!("* {} {}", sender, content)
+        } else {
+            format!("<span class=\"sender\">{}</span>: {}", sender, content)
+        };
+        let edited = if edit.is_some() {
+            format!(
+                " <span class=\"edited\">{}</span>",
+                escape(&i18n("(edited)"))
+            )
+        } else {
+            String::new()
+        };
+
+        html.push_str(&format!(
+            "<div class=\"message\"><span class=\"date\">{}</span> {}{}</div>\n",
+            msg.date.format(DATE_FORMAT),
+            content,
+            edited,
+        ));
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn media_html(msg: &Message, src: &str) -> String {
+    let body = escape(&msg.body);
+    match msg.mtype.as_str() {
+        "m.image" | "m.sticker" => format!("<img src=\"{}\" alt=\"{}\">", src, body),
+        "m.video" => format!("<video src=\"{}\" controls></video>", src),
+        "m.audio" => format!("<audio src=\"{}\" controls></audio>", src),
+        _ => format!("<a href=\"{}\">{}</a>", src, body),
+    }
+}
+
+/* Download the media of the messages to a folder next to the archive, returning
+ * their paths relative to it
+ */
+async fn download_media(
+    session_client: MatrixClient,
+    messages: &[Message],
+    path: &Path,
+    cancel: &AtomicBool,
+) -> Result<HashMap<EventId, String>, IoError> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let folder = format!("{}_files", stem);
+    let dir = path.with_file_name(&folder);
+    let mut media = HashMap::new();
+
+    for msg in messages.iter().filter(|msg| !msg.redacted) {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let (id, url) = match (msg.id.as_ref(), msg.url.as_ref()) {
+            (Some(id), Some(url)) => (id, url),
+            _ => continue,
+        };
+        let media_id = match url.path_segments().and_then(|mut ps| ps.next()) {
+            Some(media_id) if !media_id.is_empty() => media_id,
+            _ => continue,
+        };
+        // The media ID is unique, the name of the file only gives its extension
+        let fname = match Path::new(&msg.body).extension() {
+            Some(ext) => format!("{}.{}", media_id, ext.to_string_lossy()),
+            None => media_id.to_string(),
+        };
+
+        tokio::fs::create_dir_all(&dir).await?;
+        let dest = dir.join(&fname);
+        match dw_media(
+            session_client.clone(),
+            url,
+            ContentType::Download,
+            Some(dest),
+        )
+        .await
+        {
+            Ok(_) => {
+                media.insert(id.clone(), format!("{}/{}", folder, fname));
+            }
+            // The message is still exported without its media
+            Err(err) => err.handle_error(),
+        }
+    }
+
+    Ok(media)
+}
+
+// The messages with their latest edit, if they were edited
+fn with_latest_edits(messages: &[Message]) -> Vec<(&Message, Option<&Message>)> {
+    let mut edits: HashMap<&EventId, &Message> = HashMap::new();
+    for msg in messages {
+        if let Some(original) = msg.replace.as_ref() {
+            edits.insert(original, msg);
+        }
+    }
+
+    messages
+        .iter()
+        .filter(|msg| msg.replace.is_none())
+        .map(|msg| {
+            let edit = msg.id.as_ref().and_then(|id| edits.get(id)).copied();
+            (msg, edit)
+        })
+        .collect()
+}
+
+fn sender_name(room: &ExportedRoom, sender: &UserId) -> String {
+    room.members
+        .get(sender)
+        .cloned()
+        .unwrap_or_else(|| sender.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use url::Url;
+
+    fn exported_room() -> ExportedRoom {
+        let alice = UserId::try_from("@alice:example.org").unwrap();
+        ExportedRoom {
+            id: RoomId::try_from("!room:example.org").unwrap(),
+            name: String::from("Cats & Dogs"),
+            members: vec![(alice, String::from("Alice"))].into_iter().collect(),
+        }
+    }
+
+    fn message(id: &str, sender: &str, body: &str, mtype: &str, minute: u32) -> Message {
+        let mut msg = Message::new(
+            RoomId::try_from("!room:example.org").unwrap(),
+            UserId::try_from(sender).unwrap(),
+            String::from(body),
+            String::from(mtype),
+            Some(EventId::try_from(id).unwrap()),
+        );
+        msg.date = Local.ymd(2020, 9, 13).and_hms(14, minute, 0);
+        msg
+    }
+
+    fn history() -> Vec<Message> {
+        let mut edit = message("$edit:example.org", "@alice:example.org", "hi", "m.text", 3);
+        edit.replace = Some(EventId::try_from("$hello:example.org").unwrap());
+        let mut image = message(
+            "$image:example.org",
+            "@bob:example.org",
+            "cat.png",
+            "m.image",
+            4,
+        );
+        image.url = Some(Url::parse("mxc://example.org/cat").unwrap());
+        let mut deleted = message("$deleted:example.org", "@bob:example.org", "", "m.text", 5);
+        deleted.redacted = true;
+
+        vec![
+            message(
+                "$hello:example.org",
+                "@alice:example.org",
+                "hello",
+                "m.text",
+                1,
+            ),
+            message(
+                "$wave:example.org",
+                "@bob:example.org",
+                "waves",
+                "m.emote",
+                2,
+            ),
+            edit,
+            image,
+            deleted,
+        ]
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(
+            text(&exported_room(), &history()),
+            "[2020-09-13 14:01] Alice: hi (edited)\n\
+             [2020-09-13 14:02] * @bob:example.org waves\n\
+             [2020-09-13 14:04] @bob:example.org: cat.png <mxc://example.org/cat>\n\
+             [2020-09-13 14:05] @bob:example.org: This message was deleted.\n"
+        );
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut messages = history();
+        messages[0].source = Some(String::from(
+            "{\n  \"type\": \"m.room.message\",\n  \"event_id\": \"$hello:example.org\"\n}",
+        ));
+
+        // Only the messages with a source are exported, each event in a line
+        let lines = json_lines(&messages);
+        assert_eq!(lines.lines().count(), 1);
+        assert!(lines.ends_with('\n'));
+        let event: JsonValue = serde_json::from_str(&lines).unwrap();
+        assert_eq!(event["event_id"], "$hello:example.org");
+    }
+
+    #[test]
+    fn test_html() {
+        let mut messages = history();
+        messages[1].body = String::from("<b>waves</b>\nagain");
+        let image_id = EventId::try_from("$image:example.org").unwrap();
+        let media = vec![(image_id, String::from("cats_files/cat.png"))]
+            .into_iter()
+            .collect();
+        let start = Local.ymd(2020, 9, 13).and_hms(0, 0, 0);
+        let end = Local.ymd(2020, 9, 14).and_hms(0, 0, 0);
+
+        let html = html(&exported_room(), (start, end), &messages, &media);
+
+        assert!(html.contains("<title>Cats &amp; Dogs</title>"));
+        assert!(html.contains("<p class=\"date\">2020-09-13 – 2020-09-13</p>"));
+        assert!(html.contains(
+            "<span class=\"sender\">Alice</span>: hi <span class=\"edited\">(edited)</span>"
+        ));
+        assert!(html.contains("* @bob:example.org &lt;b&gt;waves&lt;/b&gt;<br>again"));
+        assert!(html.contains("<img src=\"cats_files/cat.png\" alt=\"cat.png\">"));
+        assert!(html.contains("<i>This message was deleted.</i>"));
+        assert!(!html.contains("$edit:example.org"));
+    }
+}
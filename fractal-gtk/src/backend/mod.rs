@@ -21,6 +21,7 @@ use matrix_sdk::api::r0::media::get_content_thumbnail::Request as GetContentThum
 use matrix_sdk::assign;
 
 pub mod directory;
+pub mod export;
 pub mod media;
 pub mod register;
 pub mod room;
@@ -4,6 +4,7 @@ use serde_json::json;
 use matrix_sdk::{
     api::error::ErrorKind as RumaErrorKind,
     identifiers::{EventId, RoomId, RoomIdOrAliasId, UserId},
+    reqwest::Error as ReqwestError,
     Client as MatrixClient, Error as MatrixError, FromHttpResponseError, HttpError, ServerError,
 };
 use serde::Serialize;
@@ -11,27 +12,49 @@ use std::io::Error as IoError;
 use std::path::Path;
 use url::{ParseError as UrlError, Url};
 
-use std::convert::{TryFrom, TryInto};
+use std::convert::TryFrom;
 use std::time::Duration;
 
 use crate::globals;
 
 use crate::actions::AppState;
-use crate::backend::MediaError;
+use crate::api::r0::search::request as search_request;
+use crate::api::r0::search::Body as SearchBody;
+use crate::api::r0::search::Categories as SearchCategories;
+use crate::api::r0::search::Filter as SearchFilter;
+use crate::api::r0::search::OrderBy as SearchOrderBy;
+use crate::api::r0::search::Parameters as SearchParameters;
+use crate::api::r0::search::Response as SearchResponse;
+use crate::api::r0::search::RoomEventsCriteria;
+use crate::api::r0::AccessToken;
+use crate::api::v1::relations::request as relations_request;
+use crate::api::v1::relations::Parameters as RelationsParameters;
+use crate::api::v1::relations::Response as RelationsResponse;
+use crate::api::v1::timestamp_to_event::request as timestamp_to_event_request;
+use crate::api::v1::timestamp_to_event::Direction as TimestampDirection;
+use crate::api::v1::timestamp_to_event::Parameters as TimestampToEventParameters;
+use crate::api::v1::timestamp_to_event::Response as TimestampToEventResponse;
+use crate::api::v1::timestamp_to_event::STABLE_PREFIX as TIMESTAMP_STABLE_PREFIX;
+use crate::api::v1::timestamp_to_event::UNSTABLE_PREFIX as TIMESTAMP_UNSTABLE_PREFIX;
+use crate::backend::{MediaError, HTTP_CLIENT};
 use crate::util::cache_dir_path;
 
 use crate::model::{
     member::Member,
     message::Message,
+    reaction::Reaction,
     room::{Room, RoomMembership, RoomTag},
 };
 use matrix_sdk::api::r0::config::get_global_account_data::Request as GetGlobalAccountDataRequest;
 use matrix_sdk::api::r0::config::set_global_account_data::Request as SetGlobalAccountDataRequest;
 use matrix_sdk::api::r0::config::set_room_account_data::Request as SetRoomAccountDataRequest;
+use matrix_sdk::api::r0::context::get_context::Request as GetContextRequest;
 use matrix_sdk::api::r0::filter::RoomEventFilter;
 use matrix_sdk::api::r0::media::create_content::Request as CreateContentRequest;
 use matrix_sdk::api::r0::media::create_content::Response as CreateContentResponse;
+use matrix_sdk::api::r0::membership::ban_user::Request as BanUserRequest;
 use matrix_sdk::api::r0::membership::joined_members::Request as JoinedMembersRequest;
+use matrix_sdk::api::r0::membership::kick_user::Request as KickUserRequest;
 use matrix_sdk::api::r0::message::get_message_events::Request as GetMessagesEventsRequest;
 use matrix_sdk::api::r0::push::delete_pushrule::Request as DeleteRoomRulesRequest;
 use matrix_sdk::api::r0::push::get_pushrule::Request as GetRoomRulesRequest;
@@ -40,6 +63,7 @@ use matrix_sdk::api::r0::push::RuleKind;
 use matrix_sdk::api::r0::redact::redact_event::Request as RedactEventRequest;
 use matrix_sdk::api::r0::room::create_room::Request as CreateRoomRequest;
 use matrix_sdk::api::r0::room::create_room::RoomPreset;
+use matrix_sdk::api::r0::room::get_room_event::Request as GetRoomEventRequest;
 use matrix_sdk::api::r0::room::Visibility;
 use matrix_sdk::api::r0::state::get_state_events_for_key::Request as GetStateEventForKeyRequest;
 use matrix_sdk::api::r0::state::send_state_event_for_key::Request as SendStateEventForKeyRequest;
@@ -57,6 +81,7 @@ use matrix_sdk::events::tag::TagInfo;
 use matrix_sdk::events::AnyBasicEventContent;
 use matrix_sdk::events::AnyInitialStateEvent;
 use matrix_sdk::events::AnyMessageEventContent;
+use matrix_sdk::events::AnyRoomEvent;
 use matrix_sdk::events::AnyStateEventContent;
 use matrix_sdk::events::EventContent;
 use matrix_sdk::events::EventType;
@@ -64,9 +89,11 @@ use matrix_sdk::events::InitialStateEvent;
 use matrix_sdk::events::InvalidInput as NameRoomEventInvalidInput;
 use matrix_sdk::push::Action;
 use matrix_sdk::push::Tweak;
+use matrix_sdk::uuid::Uuid;
 
 use serde_json::value::to_raw_value;
 use serde_json::Error as ParseJsonError;
+use serde_json::Value as JsonValue;
 
 use super::{
     dw_media, get_prev_batch_from, get_ruma_error_kind, remove_matrix_access_token_if_present,
@@ -243,8 +270,17 @@ pub async fn get_room_messages(
     session_client: MatrixClient,
     room_id: RoomId,
     from: &str,
-) -> Result<(Vec<Message>, RoomId, Option<String>), RoomMessagesToError> {
-    let types = &["m.room.message".into(), "m.sticker".into()];
+) -> Result<(Vec<Message>, Vec<Reaction>, RoomId, Option<String>), RoomMessagesToError> {
+    let types = &[
+        "m.room.message".into(),
+        "m.sticker".into(),
+        "m.reaction".into(),
+        "m.room.member".into(),
+        "m.room.name".into(),
+        "m.room.topic".into(),
+        "m.room.avatar".into(),
+        "m.room.encryption".into(),
+    ];
 
     let request = assign!(GetMessagesEventsRequest::backward(&room_id, from), {
         to: None,
@@ -257,26 +293,148 @@ pub async fn get_room_messages(
     let response = session_client.room_messages(request).await?;
 
     let prev_batch = response.end;
-    let list: Vec<Message> = response
+    let raw_events: Vec<_> = response.chunk.into_iter().rev().collect();
+    let events: Vec<AnyRoomEvent> = raw_events
+        .iter()
+        .map(|ev| ev.deserialize())
+        .collect::<Result<_, _>>()?;
+    let jsons = raw_events.iter().map(|ev| ev.json().get());
+    let (list, reactions) = parse_room_events(events.into_iter().zip(jsons));
+
+    Ok((list, reactions, room_id, prev_batch))
+}
+
+/* Load newer messages starting by next_batch, to extend a part of the room history
+ * loaded apart from the rest
+ * https://matrix.org/docs/spec/client_server/latest.html#get-matrix-client-r0-rooms-roomid-messages
+ */
+pub async fn get_room_messages_after(
+    session_client: MatrixClient,
+    room_id: RoomId,
+    from: &str,
+) -> Result<(Vec<Message>, Vec<Reaction>, RoomId, Option<String>), RoomMessagesToError> {
+    let types = &[
+        "m.room.message".into(),
+        "m.sticker".into(),
+        "m.reaction".into(),
+        "m.room.member".into(),
+        "m.room.name".into(),
+        "m.room.topic".into(),
+        "m.room.avatar".into(),
+        "m.room.encryption".into(),
+    ];
+
+    let request = assign!(GetMessagesEventsRequest::forward(&room_id, from), {
+        to: None,
+        limit: globals::PAGE_LIMIT.into(),
+        filter: Some(assign!(RoomEventFilter::empty(), {
+            types: Some(types),
+        })),
+    });
+
+    let response = session_client.room_messages(request).await?;
+
+    // There are no newer messages once the chunk is empty
+    let next_batch = if response.chunk.is_empty() {
+        None
+    } else {
+        response.end
+    };
+    let events = response
         .chunk
+        .iter()
+        .filter_map(|ev| Some((ev.deserialize().ok()?, ev.json().get())));
+    let (list, reactions) = parse_room_events(events);
+
+    Ok((list, reactions, room_id, next_batch))
+}
+
+/// A part of the room history, with the tokens to load the messages before and after it.
+#[derive(Debug, Clone)]
+pub struct TimelineChunk {
+    pub messages: Vec<Message>,
+    pub reactions: Vec<Reaction>,
+    pub prev_batch: Option<String>,
+    pub next_batch: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct EventContextError(MatrixError);
+
+impl<T: Into<MatrixError>> From<T> for EventContextError {
+    fn from(err: T) -> Self {
+        Self(err.into())
+    }
+}
+
+impl HandleError for EventContextError {
+    fn handle_error(&self) {
+        error!("Couldn’t load the context of an event: {:?}", self);
+        let error = i18n("Couldn’t load the message.");
+        APPOP!(show_error, (error));
+    }
+}
+
+/* Load the messages around an event, which can be far from the loaded room history
+ * https://matrix.org/docs/spec/client_server/r0.6.1#get-matrix-client-r0-rooms-roomid-context-eventid
+ */
+pub async fn get_event_context(
+    session_client: MatrixClient,
+    room_id: RoomId,
+    event_id: EventId,
+) -> Result<(RoomId, EventId, TimelineChunk), EventContextError> {
+    let request = assign!(GetContextRequest::new(&room_id, &event_id), {
+        limit: globals::PAGE_LIMIT.into(),
+    });
+
+    let response = session_client.send(request, None).await?;
+
+    let raw_events: Vec<_> = response
+        .events_before
         .into_iter()
         .rev()
-        .filter_map(|ev| {
-            ev.deserialize()
-                .map(TryInto::try_into)
-                .map(Result::ok)
-                .transpose()
-        })
-        .collect::<Result<_, _>>()?;
+        .chain(response.event)
+        .chain(response.events_after)
+        .collect();
+    // Unlike the messages endpoint, the context can't be filtered by type
+    let events = raw_events
+        .iter()
+        .filter_map(|ev| Some((ev.deserialize().ok()?, ev.json().get())));
+    let (messages, reactions) = parse_room_events(events);
+
+    let chunk = TimelineChunk {
+        messages,
+        reactions,
+        prev_batch: response.start,
+        next_batch: response.end,
+    };
 
-    Ok((list, room_id, prev_batch))
+    Ok((room_id, event_id, chunk))
+}
+
+// Takes the messages and the reactions from the events, with their JSON source
+fn parse_room_events<'a>(
+    events: impl Iterator<Item = (AnyRoomEvent, &'a str)>,
+) -> (Vec<Message>, Vec<Reaction>) {
+    let mut messages = vec![];
+    let mut reactions = vec![];
+    for (ev, json) in events {
+        if let Ok(reaction) = Reaction::try_from(&ev) {
+            reactions.push(reaction);
+        } else if let Ok(mut msg) = Message::try_from(ev) {
+            msg.set_source_json(json);
+            messages.push(msg);
+        }
+    }
+
+    (messages, reactions)
 }
 
 pub async fn get_room_messages_from_msg(
     session_client: MatrixClient,
     room_id: RoomId,
     msg: Message,
-) -> Result<(Vec<Message>, RoomId, Option<String>), RoomMessagesToError> {
+) -> Result<(Vec<Message>, Vec<Reaction>, RoomId, Option<String>), RoomMessagesToError> {
     let event_id = msg.id.as_ref().ok_or(RoomMessagesToError::MessageNotSent)?;
 
     // first of all, we calculate the from param using the context api, then we call the
@@ -286,6 +444,324 @@ pub async fn get_room_messages_from_msg(
     get_room_messages(session_client, room_id, &from).await
 }
 
+#[derive(Debug)]
+pub enum ThreadRepliesError {
+    Reqwest(ReqwestError),
+    Json(ParseJsonError),
+}
+
+impl From<ReqwestError> for ThreadRepliesError {
+    fn from(err: ReqwestError) -> Self {
+        Self::Reqwest(err)
+    }
+}
+
+impl From<ParseJsonError> for ThreadRepliesError {
+    fn from(err: ParseJsonError) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl HandleError for ThreadRepliesError {
+    fn handle_error(&self) {
+        error!("Failed loading thread replies: {:?}", self);
+        let error = i18n("Couldn’t load the replies of the thread");
+        APPOP!(show_error, (error));
+    }
+}
+
+/* Load the replies of a thread, newest first, starting by from
+ * https://spec.matrix.org/v1.4/client-server-api/#get_matrixclientv1roomsroomidrelationseventidreltype
+ */
+pub async fn get_thread_replies(
+    base: Url,
+    access_token: AccessToken,
+    room_id: RoomId,
+    thread_root: EventId,
+    from: Option<String>,
+) -> Result<(RoomId, EventId, Vec<Message>, Option<String>), ThreadRepliesError> {
+    let params = RelationsParameters {
+        access_token,
+        from,
+        limit: globals::PAGE_LIMIT,
+    };
+    let request = relations_request(base, &room_id, &thread_root, "m.thread", &params)?;
+    let response_raw = HTTP_CLIENT
+        .get_client()
+        .execute(request)
+        .await?
+        .bytes()
+        .await?;
+    let response: RelationsResponse = serde_json::from_slice(&response_raw)?;
+
+    let list: Vec<Message> = response
+        .chunk
+        .into_iter()
+        .filter_map(|json| {
+            let event: AnyRoomEvent = serde_json::from_value(json.clone()).ok()?;
+            let mut msg = Message::try_from(event).ok()?;
+            msg.set_thread_from_json(&json);
+            Some(msg)
+        })
+        .collect();
+
+    Ok((room_id, thread_root, list, response.next_batch))
+}
+
+#[derive(Debug)]
+pub enum EditHistoryError {
+    NotAMessage,
+    Matrix(MatrixError),
+    Reqwest(ReqwestError),
+    Json(ParseJsonError),
+}
+
+impl From<MatrixError> for EditHistoryError {
+    fn from(err: MatrixError) -> Self {
+        Self::Matrix(err)
+    }
+}
+
+impl From<ReqwestError> for EditHistoryError {
+    fn from(err: ReqwestError) -> Self {
+        Self::Reqwest(err)
+    }
+}
+
+impl From<ParseJsonError> for EditHistoryError {
+    fn from(err: ParseJsonError) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl HandleError for EditHistoryError {
+    fn handle_error(&self) {
+        error!("Failed loading the edits of a message: {:?}", self);
+        let error = i18n("Couldn’t load the edit history of the message");
+        APPOP!(show_error, (error));
+    }
+}
+
+/* Load a message with all the edits replacing it, oldest first
+ * https://spec.matrix.org/v1.4/client-server-api/#get_matrixclientv1roomsroomidrelationseventidreltype
+ */
+pub async fn get_edit_history(
+    session_client: MatrixClient,
+    access_token: AccessToken,
+    room_id: RoomId,
+    event_id: EventId,
+) -> Result<(Message, Vec<Message>), EditHistoryError> {
+    let request = GetRoomEventRequest::new(&room_id, &event_id);
+    let response = session_client.send(request, None).await?;
+    let original = Message::try_from(response.event.deserialize()?)
+        .map_err(|_| EditHistoryError::NotAMessage)?;
+
+    let base = session_client.homeserver().clone();
+    let mut edits = vec![];
+    let mut from = None;
+    loop {
+        let params = RelationsParameters {
+            access_token: access_token.clone(),
+            from,
+            limit: globals::PAGE_LIMIT,
+        };
+        let request = relations_request(base.clone(), &room_id, &event_id, "m.replace", &params)?;
+        let response_raw = HTTP_CLIENT
+            .get_client()
+            .execute(request)
+            .await?
+            .bytes()
+            .await?;
+        let response: RelationsResponse = serde_json::from_slice(&response_raw)?;
+
+        edits.extend(response.chunk.into_iter().filter_map(|json| {
+            let event: AnyRoomEvent = serde_json::from_value(json).ok()?;
+            Message::try_from(event).ok()
+        }));
+
+        match response.next_batch {
+            Some(next_batch) => from = Some(next_batch),
+            None => break,
+        }
+    }
+
+    // Only the sender of a message can edit it
+    edits.retain(|edit| edit.sender == original.sender && !edit.redacted);
+    edits.sort_by_key(|edit| edit.date);
+
+    Ok((original, edits))
+}
+
+#[derive(Debug)]
+pub enum SearchMessagesError {
+    Reqwest(ReqwestError),
+    Json(ParseJsonError),
+}
+
+impl From<ReqwestError> for SearchMessagesError {
+    fn from(err: ReqwestError) -> Self {
+        Self::Reqwest(err)
+    }
+}
+
+impl From<ParseJsonError> for SearchMessagesError {
+    fn from(err: ParseJsonError) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl HandleError for SearchMessagesError {
+    fn handle_error(&self) {
+        error!("Failed searching messages: {:?}", self);
+        let error = i18n("Couldn’t search messages");
+        APPOP!(show_error, (error));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResults {
+    pub messages: Vec<Message>,
+    // The words matching the search term, to highlight them in the results
+    pub highlights: Vec<String>,
+    pub count: Option<u64>,
+    // The token to load the next page from, `None` once there are no more results
+    pub next_batch: Option<String>,
+}
+
+/* Search the messages of a room, or of all the joined rooms if there is none, newest
+ * first, starting by next_batch
+ * https://matrix.org/docs/spec/client_server/r0.6.1#post-matrix-client-r0-search
+ */
+pub async fn search_messages(
+    base: Url,
+    access_token: AccessToken,
+    term: String,
+    room_id: Option<RoomId>,
+    next_batch: Option<String>,
+) -> Result<(String, Option<RoomId>, SearchResults), SearchMessagesError> {
+    let params = SearchParameters {
+        access_token,
+        next_batch,
+    };
+    let body = SearchBody {
+        search_categories: SearchCategories {
+            room_events: RoomEventsCriteria {
+                search_term: term.clone(),
+                filter: SearchFilter {
+                    rooms: room_id.clone().map(|room_id| vec![room_id]),
+                    types: vec!["m.room.message".into()],
+                    limit: globals::PAGE_LIMIT,
+                },
+                order_by: SearchOrderBy::Recent,
+            },
+        },
+    };
+    let request = search_request(base, &params, &body)?;
+    let response_raw = HTTP_CLIENT
+        .get_client()
+        .execute(request)
+        .await?
+        .bytes()
+        .await?;
+    let response: SearchResponse = serde_json::from_slice(&response_raw)?;
+    let room_events = response.search_categories.room_events.unwrap_or_default();
+
+    let messages = room_events
+        .results
+        .into_iter()
+        .filter_map(|result| {
+            let event: AnyRoomEvent = serde_json::from_value(result.result.clone()).ok()?;
+            let mut msg = Message::try_from(event).ok()?;
+            msg.set_thread_from_json(&result.result);
+            Some(msg)
+        })
+        .collect();
+
+    let results = SearchResults {
+        messages,
+        highlights: room_events.highlights,
+        count: room_events.count,
+        next_batch: room_events.next_batch,
+    };
+
+    Ok((term, room_id, results))
+}
+
+#[derive(Debug)]
+pub enum EventAtDateError {
+    Unsupported,
+    NotFound,
+    Status(u16),
+    Reqwest(ReqwestError),
+    Json(ParseJsonError),
+}
+
+impl From<ReqwestError> for EventAtDateError {
+    fn from(err: ReqwestError) -> Self {
+        Self::Reqwest(err)
+    }
+}
+
+impl From<ParseJsonError> for EventAtDateError {
+    fn from(err: ParseJsonError) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl HandleError for EventAtDateError {
+    fn handle_error(&self) {
+        let error = match self {
+            Self::Unsupported => i18n("Your homeserver doesn’t support going to a date."),
+            Self::NotFound => i18n("There are no messages from this date."),
+            _ => {
+                error!("Failed finding the event at a date: {:?}", self);
+                i18n("Couldn’t find the messages from this date.")
+            }
+        };
+        APPOP!(show_error, (error));
+    }
+}
+
+/* Find the first event of a room from a date, falling back to the unstable endpoint for
+ * servers which don't support the stable one yet
+ * https://spec.matrix.org/v1.6/client-server-api/#get_matrixclientv1roomsroomidtimestamp_to_event
+ */
+pub async fn get_event_at_date(
+    base: Url,
+    access_token: AccessToken,
+    room_id: RoomId,
+    ts: i64,
+) -> Result<(RoomId, EventId), EventAtDateError> {
+    let params = TimestampToEventParameters {
+        access_token,
+        ts,
+        dir: TimestampDirection::Forward,
+    };
+
+    for prefix in &[TIMESTAMP_STABLE_PREFIX, TIMESTAMP_UNSTABLE_PREFIX] {
+        let request = timestamp_to_event_request(base.clone(), prefix, &room_id, &params)?;
+        let response = HTTP_CLIENT.get_client().execute(request).await?;
+        let status = response.status();
+        let response_raw = response.bytes().await?;
+        if status.is_success() {
+            let response: TimestampToEventResponse = serde_json::from_slice(&response_raw)?;
+            return Ok((room_id, response.event_id));
+        }
+
+        // Servers answer to the endpoints they don't know with M_UNRECOGNIZED, or
+        // without any Matrix error at all
+        let error: JsonValue = serde_json::from_slice(&response_raw).unwrap_or_default();
+        match error["errcode"].as_str() {
+            Some("M_NOT_FOUND") => return Err(EventAtDateError::NotFound),
+            Some("M_UNRECOGNIZED") => continue,
+            None if status.as_u16() == 404 || status.as_u16() == 405 => continue,
+            _ => return Err(EventAtDateError::Status(status.as_u16())),
+        }
+    }
+
+    Err(EventAtDateError::Unsupported)
+}
+
 #[derive(Debug)]
 pub enum SendMsgError {
     Matrix(MatrixError),
@@ -308,8 +784,7 @@ impl HandleError for SendMsgError {
     fn handle_error(&self) {
         match self {
             Self::Matrix(matrix_err) => {
-                error!("Failed sending message, retrying send: {}", matrix_err);
-                APPOP!(retry_send);
+                error!("Failed sending message: {}", matrix_err);
             }
             Self::ParseEvent(parse_err) => {
                 error!(
@@ -323,6 +798,11 @@ impl HandleError for SendMsgError {
 
 pub async fn send_msg(session_client: MatrixClient, msg: Message) -> Result<EventId, SendMsgError> {
     let room_id: RoomId = msg.room;
+    // Lets the event coming back through sync be matched with the local echo
+    let txn_id = msg
+        .txn_id
+        .as_deref()
+        .and_then(|id| Uuid::parse_str(id).ok());
 
     let mut event = json!({
         "body": msg.body,
@@ -338,6 +818,18 @@ pub async fn send_msg(session_client: MatrixClient, msg: Message) -> Result<Even
         event["format"] = json!(f);
     }
 
+    if let Some(in_reply_to) = msg.in_reply_to.as_ref() {
+        event["m.relates_to"] = json!({
+            "m.in_reply_to": {
+                "event_id": in_reply_to,
+            }
+        });
+    }
+
+    if let Some(replace) = msg.replace.as_ref() {
+        event = replacement_content(event, replace);
+    }
+
     let extra_content_map = msg
         .extra_content
         .into_iter()
@@ -355,13 +847,34 @@ pub async fn send_msg(session_client: MatrixClient, msg: Message) -> Result<Even
         .room_send(
             &room_id,
             AnyMessageEventContent::RoomMessage(message_event_content),
-            None,
+            txn_id,
         )
         .await?;
 
     Ok(response.event_id)
 }
 
+// Edits carry the new content in `m.new_content`, the top level body is
+// only a fallback for clients not supporting replacements.
+// https://github.com/matrix-org/matrix-doc/pull/2676
+fn replacement_content(new_content: JsonValue, replace: &EventId) -> JsonValue {
+    let mut event = new_content.clone();
+
+    if let Some(body) = new_content["body"].as_str() {
+        event["body"] = json!(format!("* {}", body));
+    }
+    if let Some(f_b) = new_content["formatted_body"].as_str() {
+        event["formatted_body"] = json!(format!("* {}", f_b));
+    }
+    event["m.new_content"] = new_content;
+    event["m.relates_to"] = json!({
+        "rel_type": "m.replace",
+        "event_id": replace,
+    });
+
+    event
+}
+
 #[derive(Debug)]
 pub struct SendTypingError(MatrixError);
 
@@ -417,6 +930,84 @@ pub async fn redact_msg(
     Ok((event_id, response.event_id))
 }
 
+#[derive(Debug)]
+pub enum SendReactionError {
+    Matrix(MatrixError),
+    ParseEvent(ParseJsonError),
+}
+
+impl From<MatrixError> for SendReactionError {
+    fn from(err: MatrixError) -> Self {
+        Self::Matrix(err)
+    }
+}
+
+impl From<ParseJsonError> for SendReactionError {
+    fn from(err: ParseJsonError) -> Self {
+        Self::ParseEvent(err)
+    }
+}
+
+impl HandleError for SendReactionError {
+    fn handle_error(&self) {
+        error!("Failed sending reaction: {:?}", self);
+        let error = i18n("Couldn’t send the reaction");
+        APPOP!(show_error, (error));
+    }
+}
+
+pub async fn send_reaction(
+    session_client: MatrixClient,
+    room_id: &RoomId,
+    event_id: &EventId,
+    key: &str,
+) -> Result<EventId, SendReactionError> {
+    let content = json!({
+        "m.relates_to": {
+            "rel_type": "m.annotation",
+            "event_id": event_id,
+            "key": key,
+        }
+    });
+
+    let raw_content = to_raw_value(&content)?;
+    let event_content = AnyMessageEventContent::from_parts("m.reaction", raw_content)?;
+    let response = session_client
+        .room_send(room_id, event_content, None)
+        .await?;
+
+    Ok(response.event_id)
+}
+
+#[derive(Debug)]
+pub struct RedactReactionError(MatrixError);
+
+impl From<MatrixError> for RedactReactionError {
+    fn from(err: MatrixError) -> Self {
+        Self(err)
+    }
+}
+
+impl HandleError for RedactReactionError {
+    fn handle_error(&self) {
+        error!("Failed removing reaction: {:?}", self.0);
+        let error = i18n("Couldn’t remove the reaction");
+        APPOP!(show_error, (error));
+    }
+}
+
+pub async fn redact_reaction(
+    session_client: MatrixClient,
+    room_id: RoomId,
+    reaction_id: EventId,
+) -> Result<(RoomId, EventId), RedactReactionError> {
+    let txn_id = Uuid::new_v4().to_string();
+    let request = RedactEventRequest::new(&room_id, &reaction_id, &txn_id);
+    session_client.send(request, None).await?;
+
+    Ok((room_id, reaction_id))
+}
+
 #[derive(Debug)]
 pub struct JoinRoomError(MatrixError);
 
@@ -498,6 +1089,53 @@ pub async fn mark_as_read(
 
     Ok((room_id, event_id))
 }
+
+#[derive(Debug)]
+pub struct SendReadReceiptError(MatrixError);
+
+impl From<MatrixError> for SendReadReceiptError {
+    fn from(err: MatrixError) -> Self {
+        Self(err)
+    }
+}
+
+impl HandleError for SendReadReceiptError {}
+
+/// Sends a read receipt for the event without moving the fully read marker.
+pub async fn send_read_receipt(
+    session_client: MatrixClient,
+    room_id: RoomId,
+    event_id: EventId,
+) -> Result<(RoomId, EventId), SendReadReceiptError> {
+    session_client.read_receipt(&room_id, &event_id).await?;
+
+    Ok((room_id, event_id))
+}
+
+#[derive(Debug)]
+pub struct SetFullyReadError(MatrixError);
+
+impl From<MatrixError> for SetFullyReadError {
+    fn from(err: MatrixError) -> Self {
+        Self(err)
+    }
+}
+
+impl HandleError for SetFullyReadError {}
+
+/// Moves the `m.fully_read` marker of the room to the event.
+pub async fn set_fully_read(
+    session_client: MatrixClient,
+    room_id: RoomId,
+    event_id: EventId,
+) -> Result<(), SetFullyReadError> {
+    session_client
+        .read_marker(&room_id, &event_id, None)
+        .await?;
+
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum SetRoomNameError {
     Matrix(MatrixError),
@@ -626,10 +1264,9 @@ impl HandleError for AttachedFileError {
     fn handle_error(&self) {
         let err_str = format!("{:?}", self);
         error!(
-            "attaching {}: retrying send",
+            "attaching {}",
             remove_matrix_access_token_if_present(&err_str).unwrap_or(err_str)
         );
-        APPOP!(retry_send);
     }
 }
 
@@ -859,6 +1496,178 @@ pub async fn invite(
     Ok(())
 }
 
+// Logs the error and shows it to the user, with the message sent by the server if any
+fn show_server_error(err: &MatrixError, msg: String) {
+    let (err_str, info) = match err {
+        MatrixError::Http(HttpError::FromHttpResponse(FromHttpResponseError::Http(
+            ServerError::Known(error),
+        ))) => (error.message.clone(), Some(error.message.clone())),
+        error => (error.to_string(), None),
+    };
+
+    error!(
+        "{}",
+        remove_matrix_access_token_if_present(&err_str).unwrap_or(err_str)
+    );
+    APPOP!(show_error_with_info, (msg, info));
+}
+
+#[derive(Debug)]
+pub struct BanUserError(MatrixError);
+
+impl From<MatrixError> for BanUserError {
+    fn from(err: MatrixError) -> Self {
+        Self(err)
+    }
+}
+
+impl HandleError for BanUserError {
+    fn handle_error(&self) {
+        show_server_error(&self.0, i18n("Couldn’t ban the user"));
+    }
+}
+
+pub async fn ban_user(
+    session_client: MatrixClient,
+    room_id: &RoomId,
+    user_id: &UserId,
+    reason: Option<String>,
+) -> Result<(), BanUserError> {
+    let request = assign!(BanUserRequest::new(room_id, user_id), {
+        reason: reason.as_deref(),
+    });
+
+    session_client.send(request, None).await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct KickUserError(MatrixError);
+
+impl From<MatrixError> for KickUserError {
+    fn from(err: MatrixError) -> Self {
+        Self(err)
+    }
+}
+
+impl HandleError for KickUserError {
+    fn handle_error(&self) {
+        show_server_error(&self.0, i18n("Couldn’t remove the user from the room"));
+    }
+}
+
+pub async fn kick_user(
+    session_client: MatrixClient,
+    room_id: &RoomId,
+    user_id: &UserId,
+    reason: Option<String>,
+) -> Result<(), KickUserError> {
+    let request = assign!(KickUserRequest::new(room_id, user_id), {
+        reason: reason.as_deref(),
+    });
+
+    session_client.send(request, None).await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct SetPowerLevelError(MatrixError);
+
+impl From<MatrixError> for SetPowerLevelError {
+    fn from(err: MatrixError) -> Self {
+        Self(err)
+    }
+}
+
+impl From<ParseJsonError> for SetPowerLevelError {
+    fn from(err: ParseJsonError) -> Self {
+        Self(err.into())
+    }
+}
+
+impl HandleError for SetPowerLevelError {
+    fn handle_error(&self) {
+        show_server_error(&self.0, i18n("Couldn’t change the power level of the user"));
+    }
+}
+
+/// Sets the power level of a user in a room. With `None`, the user gets
+/// back the default power level of the room.
+pub async fn set_power_level(
+    session_client: MatrixClient,
+    room_id: &RoomId,
+    user_id: &UserId,
+    level: Option<i64>,
+) -> Result<(), SetPowerLevelError> {
+    let request = GetStateEventForKeyRequest::new(room_id, EventType::RoomPowerLevels, "");
+    let response = session_client.send(request, None).await?;
+
+    let mut content = serde_json::to_value(&response.content)?;
+    match level {
+        Some(level) => content["users"][user_id.as_str()] = json!(level),
+        None => {
+            if let Some(users) = content["users"].as_object_mut() {
+                users.remove(user_id.as_str());
+            }
+        }
+    }
+
+    let content = AnyStateEventContent::from_parts("m.room.power_levels", to_raw_value(&content)?)?;
+    let request = SendStateEventForKeyRequest::new(room_id, "", &content);
+
+    session_client.send(request, None).await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct SetRoomDisplayNameError(MatrixError);
+
+impl From<MatrixError> for SetRoomDisplayNameError {
+    fn from(err: MatrixError) -> Self {
+        Self(err)
+    }
+}
+
+impl From<ParseJsonError> for SetRoomDisplayNameError {
+    fn from(err: ParseJsonError) -> Self {
+        Self(err.into())
+    }
+}
+
+impl HandleError for SetRoomDisplayNameError {
+    fn handle_error(&self) {
+        show_server_error(
+            &self.0,
+            i18n("Couldn’t change your display name in this room"),
+        );
+    }
+}
+
+/// Changes the display name of the user in a single room, keeping the rest
+/// of the membership untouched.
+pub async fn set_room_display_name(
+    session_client: MatrixClient,
+    room_id: &RoomId,
+    user_id: &UserId,
+    name: String,
+) -> Result<(), SetRoomDisplayNameError> {
+    let request = GetStateEventForKeyRequest::new(room_id, EventType::RoomMember, user_id.as_str());
+    let response = session_client.send(request, None).await?;
+
+    let mut content = serde_json::to_value(&response.content)?;
+    content["displayname"] = json!(name);
+
+    let content = AnyStateEventContent::from_parts("m.room.member", to_raw_value(&content)?)?;
+    let request = SendStateEventForKeyRequest::new(room_id, user_id.as_str(), &content);
+
+    session_client.send(request, None).await?;
+
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct ChangeLanguageError(MatrixError);
 
@@ -987,3 +1796,101 @@ pub async fn delete_pushrules(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replacement_content() {
+        let replace = EventId::try_from("$original:example.org").unwrap();
+        let event = replacement_content(
+            json!({
+                "body": "fixed",
+                "msgtype": "m.text",
+                "formatted_body": "<b>fixed</b>",
+                "format": "org.matrix.custom.html",
+            }),
+            &replace,
+        );
+
+        assert_eq!(event["body"], "* fixed");
+        assert_eq!(event["formatted_body"], "* <b>fixed</b>");
+        assert_eq!(event["m.new_content"]["body"], "fixed");
+        assert_eq!(event["m.new_content"]["formatted_body"], "<b>fixed</b>");
+        assert_eq!(event["m.relates_to"]["rel_type"], "m.replace");
+        assert_eq!(event["m.relates_to"]["event_id"], "$original:example.org");
+    }
+
+    #[test]
+    fn test_replacement_content_without_html() {
+        let replace = EventId::try_from("$original:example.org").unwrap();
+        let event = replacement_content(json!({ "body": "fixed", "msgtype": "m.text" }), &replace);
+
+        assert_eq!(event["body"], "* fixed");
+        assert!(event.get("formatted_body").is_none());
+        assert!(event["m.new_content"].get("m.new_content").is_none());
+    }
+
+    fn room_event(event_id: &str, type_: &str, content: JsonValue) -> String {
+        json!({
+            "type": type_,
+            "event_id": event_id,
+            "room_id": "!room:example.org",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 1_600_000_000_000u64,
+            "content": content,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_parse_room_events() {
+        let jsons = vec![
+            room_event(
+                "$message:example.org",
+                "m.room.message",
+                json!({ "msgtype": "m.text", "body": "hello" }),
+            ),
+            room_event(
+                "$reaction:example.org",
+                "m.reaction",
+                json!({ "m.relates_to": {
+                    "rel_type": "m.annotation",
+                    "event_id": "$message:example.org",
+                    "key": "👍",
+                }}),
+            ),
+            room_event(
+                "$reply:example.org",
+                "m.room.message",
+                json!({
+                    "msgtype": "m.text",
+                    "body": "in the thread",
+                    "m.relates_to": {
+                        "rel_type": "m.thread",
+                        "event_id": "$message:example.org",
+                    },
+                }),
+            ),
+        ];
+        let events = jsons
+            .iter()
+            .map(|json| (serde_json::from_str(json).unwrap(), json.as_str()));
+
+        let (messages, reactions) = parse_room_events(events);
+
+        let message_id = EventId::try_from("$message:example.org").unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].id, Some(message_id.clone()));
+        assert_eq!(messages[0].thread_root, None);
+        assert_eq!(
+            messages[1].id,
+            Some(EventId::try_from("$reply:example.org").unwrap())
+        );
+        assert_eq!(messages[1].thread_root, Some(message_id.clone()));
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].relates_to, message_id);
+        assert_eq!(reactions[0].key, "👍");
+    }
+}
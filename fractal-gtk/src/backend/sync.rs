@@ -1,5 +1,6 @@
 use crate::globals;
-use crate::model::room::Room;
+use crate::model::reaction::Reaction;
+use crate::model::room::{Receipts, Room};
 use log::error;
 use matrix_sdk::api::r0::filter::Filter as EventFilter;
 use matrix_sdk::api::r0::filter::FilterDefinition;
@@ -27,6 +28,9 @@ pub enum RoomElement {
     NewAvatar(RoomId),
     MemberEvent(StateEvent<MemberEventContent>),
     RemoveMessage(RoomId, EventId),
+    Reaction(Reaction),
+    Receipts(RoomId, Receipts),
+    FullyRead(RoomId, EventId),
 }
 
 #[derive(Debug)]
@@ -66,8 +70,18 @@ pub async fn sync(
 ) -> Result<SyncResponse, SyncError> {
     let initial = since.is_none();
     let timeline_not_types = [String::from("m.call.*")];
-    let timeline_types = [String::from("m.room.message"), String::from("m.sticker")];
+    let timeline_types = [
+        String::from("m.room.message"),
+        String::from("m.sticker"),
+        String::from("m.reaction"),
+        String::from("m.room.member"),
+        String::from("m.room.name"),
+        String::from("m.room.topic"),
+        String::from("m.room.avatar"),
+        String::from("m.room.encryption"),
+    ];
     let state_types = [String::from("m.room.*")];
+    let ephemeral_types = [String::from("m.receipt")];
     let sync_settings = if !initial {
         SyncSettings::new().timeout(Duration::from_secs(30))
     } else {
@@ -84,7 +98,7 @@ pub async fn sync(
                     types: Some(&timeline_types),
                 }),
                 ephemeral: assign!(RoomEventFilter::empty(), {
-                    types: Some(&[]),
+                    types: Some(&ephemeral_types),
                 }),
                 state: assign!(RoomEventFilter::empty(), {
                     types: Some(&state_types),
@@ -5,7 +5,7 @@ use crate::util::cache_dir_path;
 use matrix_sdk::Client as MatrixClient;
 
 use anyhow::Error;
-use matrix_sdk::identifiers::{DeviceId, UserId};
+use matrix_sdk::identifiers::{DeviceId, RoomId, UserId};
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::time::{Duration, Instant};
@@ -14,10 +14,14 @@ use crate::widgets::AvatarData;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+mod outbox;
 mod state;
+pub use self::outbox::{load_outbox, store_outbox, LocalEcho, Outbox, OutboxEntry};
 pub use self::state::get;
 pub use self::state::AppState;
+use self::state::Drafts;
 pub use self::state::FCache;
+pub use self::state::{Draft, DraftRelation};
 
 // user info cache, uid -> (name, avatar)
 #[derive(Clone, Debug)]
@@ -97,6 +101,14 @@ pub fn load() -> Result<CacheData, Error> {
     })
 }
 
+pub fn store_drafts(drafts: HashMap<RoomId, Draft>) -> Result<(), Error> {
+    get().save_drafts(Drafts { drafts })
+}
+
+pub fn load_drafts() -> Result<HashMap<RoomId, Draft>, Error> {
+    Ok(get().get_drafts()?.drafts)
+}
+
 pub fn remove_from_cache(user_info_cache: UserInfoCache, user_id: &UserId) {
     user_info_cache.lock().unwrap().remove(&user_id);
     if let Ok(dest) = cache_dir_path(None, &user_id.to_string()) {
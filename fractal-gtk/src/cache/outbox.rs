@@ -0,0 +1,162 @@
+use anyhow::Error;
+use chrono::prelude::*;
+use matrix_sdk::identifiers::{EventId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::PathBuf;
+use url::Url;
+
+use crate::model::message::Message;
+use crate::util::cache_dir_path;
+
+/// The messages sent from here, as they're kept on disk so they survive restarts
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Outbox {
+    // The messages waiting to be sent
+    pub entries: Vec<OutboxEntry>,
+    // The messages already sent whose event hasn't come back from the server
+    pub local_echoes: Vec<LocalEcho>,
+}
+
+/// A message waiting to be sent
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OutboxEntry {
+    pub txn_id: String,
+    pub room: RoomId,
+    pub sender: UserId,
+    pub mtype: String,
+    pub body: String,
+    // Milliseconds since the Unix epoch
+    pub date: i64,
+    pub url: Option<Url>,
+    pub local_path: Option<PathBuf>,
+    pub local_path_thumb: Option<PathBuf>,
+    pub formatted_body: Option<String>,
+    pub format: Option<String>,
+    pub in_reply_to: Option<EventId>,
+    pub replace: Option<EventId>,
+    pub thread_root: Option<EventId>,
+    pub extra_content: Option<JsonValue>,
+}
+
+impl OutboxEntry {
+    pub fn new(txn_id: String, msg: &Message) -> Self {
+        Self {
+            txn_id,
+            room: msg.room.clone(),
+            sender: msg.sender.clone(),
+            mtype: msg.mtype.clone(),
+            body: msg.body.clone(),
+            date: msg.date.timestamp_millis(),
+            url: msg.url.clone(),
+            local_path: msg.local_path.clone(),
+            local_path_thumb: msg.local_path_thumb.clone(),
+            formatted_body: msg.formatted_body.clone(),
+            format: msg.format.clone(),
+            in_reply_to: msg.in_reply_to.clone(),
+            replace: msg.replace.clone(),
+            thread_root: msg.thread_root.clone(),
+            extra_content: msg.extra_content.clone(),
+        }
+    }
+
+    pub fn into_message(self) -> (String, Message) {
+        let mut msg = Message::new(self.room, self.sender, self.body, self.mtype, None);
+        msg.txn_id = Some(self.txn_id.clone());
+        msg.date = Local.timestamp_millis(self.date);
+        msg.url = self.url;
+        msg.local_path = self.local_path;
+        msg.local_path_thumb = self.local_path_thumb;
+        msg.formatted_body = self.formatted_body;
+        msg.format = self.format;
+        msg.in_reply_to = self.in_reply_to;
+        msg.replace = self.replace;
+        msg.thread_root = self.thread_root;
+        msg.extra_content = self.extra_content;
+
+        (self.txn_id, msg)
+    }
+}
+
+/// A message already sent, shown at the date it was sent from here until its
+/// event comes back from the server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LocalEcho {
+    pub txn_id: String,
+    pub room: RoomId,
+    // Milliseconds since the Unix epoch
+    pub date: i64,
+}
+
+impl LocalEcho {
+    pub fn new(txn_id: String, room: RoomId, date: DateTime<Local>) -> Self {
+        Self {
+            txn_id,
+            room,
+            date: date.timestamp_millis(),
+        }
+    }
+
+    pub fn date(&self) -> DateTime<Local> {
+        Local.timestamp_millis(self.date)
+    }
+}
+
+pub fn store_outbox(outbox: &Outbox) -> Result<(), Error> {
+    let path = cache_dir_path(None, "outbox.json")?;
+    fs::write(path, serde_json::to_vec(outbox)?)?;
+
+    Ok(())
+}
+
+pub fn load_outbox() -> Result<Outbox, Error> {
+    let path = cache_dir_path(None, "outbox.json")?;
+    if !path.exists() {
+        return Ok(Default::default());
+    }
+
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_outbox_entry_round_trip() {
+        let mut msg = Message::new(
+            RoomId::try_from("!room:example.org").unwrap(),
+            UserId::try_from("@alice:example.org").unwrap(),
+            String::from("photo.png"),
+            String::from("m.image"),
+            None,
+        );
+        msg.date = Local.timestamp_millis(1_600_000_000_123);
+        msg.local_path = Some(PathBuf::from("/home/alice/photo.png"));
+        msg.url = Some(Url::parse("mxc://example.org/photo").unwrap());
+        msg.in_reply_to = Some(EventId::try_from("$parent:example.org").unwrap());
+        msg.extra_content = Some(json!({ "info": { "mimetype": "image/png" } }));
+
+        let entry = OutboxEntry::new(String::from("txn"), &msg);
+        let stored = serde_json::to_string(&entry).unwrap();
+        let entry: OutboxEntry = serde_json::from_str(&stored).unwrap();
+        let (txn_id, restored) = entry.into_message();
+
+        assert_eq!(txn_id, "txn");
+        assert_eq!(restored.txn_id.as_deref(), Some("txn"));
+        assert_eq!(restored.room, msg.room);
+        assert_eq!(restored.sender, msg.sender);
+        assert_eq!(restored.mtype, "m.image");
+        assert_eq!(restored.body, "photo.png");
+        assert_eq!(restored.date, msg.date);
+        assert_eq!(restored.local_path, msg.local_path);
+        assert_eq!(restored.url, msg.url);
+        assert_eq!(restored.in_reply_to, msg.in_reply_to);
+        assert_eq!(restored.extra_content, msg.extra_content);
+        // Restored messages haven't been sent yet
+        assert_eq!(restored.id, None);
+    }
+}
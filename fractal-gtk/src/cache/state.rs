@@ -5,11 +5,12 @@ use serde::{Deserialize, Serialize};
 
 use anyhow::{anyhow, Error};
 
+use std::collections::HashMap;
 use std::fs::remove_dir_all;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use crate::util::cache_dir_path;
-use matrix_sdk::identifiers::{DeviceId, UserId};
+use matrix_sdk::identifiers::{DeviceId, EventId, RoomId, UserId};
 
 // Models
 
@@ -30,6 +31,33 @@ impl Model for AppState {
     }
 }
 
+/// A message left unsent in the composer of a room
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Draft {
+    pub text: String,
+    pub cursor: i32,
+    pub relation: Option<DraftRelation>,
+}
+
+/// The message a draft relates to
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum DraftRelation {
+    Edit(EventId),
+    Reply(EventId),
+}
+
+/// The drafts of all the rooms, stored as a whole
+#[derive(Serialize, Deserialize, Default)]
+pub struct Drafts {
+    pub drafts: HashMap<RoomId, Draft>,
+}
+
+impl Model for Drafts {
+    fn key(&self) -> String {
+        "drafts".to_string()
+    }
+}
+
 // Cache
 #[derive(Clone)]
 pub struct FCache {
@@ -72,6 +100,18 @@ impl FCache {
 
         Ok(())
     }
+
+    pub fn get_drafts(&self) -> Result<Drafts, Error> {
+        let cache = self.get_store();
+        Drafts::get(cache.as_ref().unwrap(), "drafts")
+    }
+
+    pub fn save_drafts(&self, drafts: Drafts) -> Result<(), Error> {
+        let cache = self.get_store();
+        drafts.store(cache.as_ref().unwrap())?;
+
+        Ok(())
+    }
 }
 
 // The cache object, it's the same for the whole process
@@ -84,3 +124,34 @@ lazy_static! {
 pub fn get() -> FCache {
     CACHE.clone()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_store_drafts() {
+        let path = std::env::temp_dir().join(format!("fractal-drafts-{}.mdl", std::process::id()));
+        let cache = Cache::new(path.to_str().unwrap()).unwrap();
+        let room = RoomId::try_from("!room:example.org").unwrap();
+        let parent = EventId::try_from("$parent:example.org").unwrap();
+        let draft = Draft {
+            text: String::from("half written ✍"),
+            cursor: 4,
+            relation: Some(DraftRelation::Reply(parent.clone())),
+        };
+        let mut drafts = HashMap::new();
+        drafts.insert(room.clone(), draft);
+        Drafts { drafts }.store(&cache).unwrap();
+
+        let mut drafts = Drafts::get(&cache, "drafts").unwrap().drafts;
+        drop(cache);
+        let _ = remove_dir_all(&path);
+
+        let draft = drafts.remove(&room).unwrap();
+        assert_eq!(draft.text, "half written ✍");
+        assert_eq!(draft.cursor, 4);
+        assert!(matches!(draft.relation, Some(DraftRelation::Reply(id)) if id == parent));
+    }
+}
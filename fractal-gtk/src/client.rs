@@ -3,13 +3,16 @@ use crate::globals;
 use async_trait::async_trait;
 
 use gio::prelude::*;
+use lazy_static::lazy_static;
 use matrix_sdk::{
-    reqwest, Client as MatrixClient, ClientConfig as MatrixClientConfig, Error as MatrixSdkError,
-    HttpError, HttpSend,
+    identifiers::EventId, reqwest, Client as MatrixClient, ClientConfig as MatrixClientConfig,
+    Error as MatrixSdkError, HttpError, HttpSend,
 };
+use serde_json::Value as JsonValue;
 use url::Url;
 
-use std::convert::TryInto;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -81,6 +84,12 @@ thread_local! {
         gio::ProxyResolver::get_default().expect("Couldn't get proxy resolver");
 }
 
+// matrix-sdk only hands out the deserialized events of a sync, which lack the
+// fields of unstable features, like threads or the waveform of voice messages
+lazy_static! {
+    static ref SYNC_EVENTS_JSON: Mutex<HashMap<EventId, String>> = Default::default();
+}
+
 #[derive(Debug)]
 struct ClientInner {
     client: matrix_sdk::reqwest::Client,
@@ -135,10 +144,39 @@ impl HttpSend for Client {
         req: http::Request<Vec<u8>>,
         duration: Option<Duration>,
     ) -> Result<http::Response<Vec<u8>>, HttpError> {
-        self.get_client().send_request(req, duration).await
+        let is_sync = req.uri().path().ends_with("/sync");
+        let response = self.get_client().send_request(req, duration).await?;
+
+        if is_sync && response.status().is_success() {
+            *SYNC_EVENTS_JSON.lock().unwrap() = timeline_events_json(response.body());
+        }
+
+        Ok(response)
     }
 }
 
+fn timeline_events_json(body: &[u8]) -> HashMap<EventId, String> {
+    let response: JsonValue = serde_json::from_slice(body).unwrap_or_default();
+
+    response["rooms"]["join"]
+        .as_object()
+        .into_iter()
+        .flat_map(|rooms| rooms.values())
+        .filter_map(|room| room["timeline"]["events"].as_array())
+        .flatten()
+        .filter_map(|event| {
+            let event_id = EventId::try_from(event["event_id"].as_str()?).ok()?;
+            Some((event_id, event.to_string()))
+        })
+        .collect()
+}
+
+/// Takes the JSON of the timeline events of the joined rooms from the last
+/// sync, by event ID.
+pub fn take_sync_events_json() -> HashMap<EventId, String> {
+    std::mem::take(&mut *SYNC_EVENTS_JSON.lock().unwrap())
+}
+
 pub fn get_matrix_client<U>(homeserver_url: U) -> Result<MatrixClient, MatrixSdkError>
 where
     U: TryInto<Url>,
@@ -147,3 +185,32 @@ where
     let config = MatrixClientConfig::new().client(client);
     MatrixClient::new_with_config(homeserver_url, config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_timeline_events_json() {
+        let body = json!({
+            "next_batch": "s1",
+            "rooms": { "join": { "!room:example.org": { "timeline": { "events": [
+                {
+                    "type": "m.room.message",
+                    "event_id": "$event:example.org",
+                    "content": { "body": "hi", "org.matrix.msc3245.voice": {} },
+                },
+                { "type": "m.room.message", "content": { "body": "no ID" } },
+            ]}}}},
+        })
+        .to_string();
+        let events_json = timeline_events_json(body.as_bytes());
+
+        assert_eq!(events_json.len(), 1);
+        let event_id = EventId::try_from("$event:example.org").unwrap();
+        let event: JsonValue = serde_json::from_str(&events_json[&event_id]).unwrap();
+        assert!(event["content"]["org.matrix.msc3245.voice"].is_object());
+        assert!(timeline_events_json(b"not json").is_empty());
+    }
+}
@@ -14,12 +14,14 @@ pub const DEVICE_NAME: &str = "Fractal";
 pub const MSG_ICON_SIZE: i32 = 40;
 pub const USERLIST_ICON_SIZE: i32 = 30;
 pub const PILL_ICON_SIZE: i32 = 18;
+pub const RECEIPT_ICON_SIZE: i32 = 16;
 pub const MINUTES_TO_SPLIT_MSGS: i64 = 30;
 pub const PLACEHOLDER_TEXT: &str = "Matrix username, email or phone number";
 pub const ELEMENT_REGISTER_URL: &str = "https://app.element.io/#/register";
 
 pub const MAX_IMAGE_SIZE: (i32, i32) = (600, 400);
 pub const MAX_STICKER_SIZE: (i32, i32) = (200, 130);
+pub const MAX_THUMBNAIL_SIZE: (i32, i32) = (800, 600);
 
 lazy_static! {
     pub static ref DEFAULT_HOMESERVER: Url =
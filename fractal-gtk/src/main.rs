@@ -38,6 +38,11 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .short("v")
                 .multiple(true)
                 .help("Sets the level of verbosity"),
+        )
+        .arg(
+            clap::Arg::with_name("URI")
+                .multiple(true)
+                .help("Opens matrix.to links or matrix: URIs to rooms or messages"),
         );
 
     let clap_args = clap_app.get_matches();
@@ -63,8 +68,9 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Initialize GStreamer. This checks, among other things, what plugins are available
     gst::init()?;
 
-    // Create a Application with default flags
-    let application = gtk::Application::new(Some(config::APP_ID), gio::ApplicationFlags::empty())?;
+    // Create a Application which opens the links given as arguments
+    let application =
+        gtk::Application::new(Some(config::APP_ID), gio::ApplicationFlags::HANDLES_OPEN)?;
 
     application.set_resource_base_path(Some("/org/gnome/Fractal"));
 
@@ -72,7 +78,18 @@ fn main() -> Result<(), Box<dyn Error>> {
         app::on_startup(application);
     });
 
-    application.run(&[]);
+    // The other arguments were already handled by clap
+    let program = std::env::args().next().unwrap_or_default();
+    let args: Vec<String> = std::iter::once(program)
+        .chain(
+            clap_args
+                .values_of("URI")
+                .into_iter()
+                .flatten()
+                .map(String::from),
+        )
+        .collect();
+    application.run(&args);
 
     Ok(())
 }
@@ -11,6 +11,11 @@ pub struct Info {
     pub size: u32,
     pub mimetype: String,
     pub orientation: Option<i32>,
+    #[serde(
+        rename = "xyz.amorgan.blurhash",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub blurhash: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
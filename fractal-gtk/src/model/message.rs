@@ -1,3 +1,4 @@
+use super::state_change::StateChange;
 use chrono::prelude::*;
 use chrono::DateTime;
 use matrix_sdk::{
@@ -5,10 +6,12 @@ use matrix_sdk::{
         room::message::{MessageEventContent, RedactedMessageEventContent, Relation},
         sticker::{RedactedStickerEventContent, StickerEventContent},
         AnyMessageEvent, AnyRedactedMessageEvent, AnyRedactedSyncMessageEvent, AnyRoomEvent,
-        AnySyncMessageEvent, AnySyncRoomEvent, EventContent, MessageEvent, RedactedMessageEvent,
+        AnyStateEvent, AnySyncMessageEvent, AnySyncRoomEvent, EventContent, MessageEvent,
+        RedactedMessageEvent, StateEvent, StateEventContent,
     },
     identifiers::{EventId, RoomId, UserId},
 };
+use serde::Deserialize;
 use serde_json::Value as JsonValue;
 use std::cmp::Ordering;
 use std::collections::HashMap;
@@ -34,6 +37,9 @@ pub struct Message {
     // of writing this, using two separate data structures for each
     // use case is just too difficult.
     pub id: Option<EventId>,
+    // The transaction id of a message sent from this device, used to match the
+    // local echo with the event coming back from the server.
+    pub txn_id: Option<String>,
     pub formatted_body: Option<String>,
     pub format: Option<String>,
     pub source: Option<String>,
@@ -43,10 +49,14 @@ pub struct Message {
     pub in_reply_to: Option<EventId>,
     // The event ID of the message this replaces.
     pub replace: Option<EventId>,
+    // The event ID of the message starting the thread this is part of.
+    pub thread_root: Option<EventId>,
     // This can be used for the client to add more values to the message on sending
     // for example for images attachment the "info" field can be attached as
     // Some(json!({"info": {"h": 296, "w": 296, "mimetype": "image/png", "orientation": 0, "size": 8796}});
     pub extra_content: Option<JsonValue>,
+    // The change of the room state, for state events shown in the history.
+    pub state: Option<StateChange>,
 }
 
 impl Eq for Message {}
@@ -80,6 +90,16 @@ impl PartialOrd for Message {
 
 impl From<MessageEvent<MessageEventContent>> for Message {
     fn from(msg: MessageEvent<MessageEventContent>) -> Self {
+        let mut message = Self::from_message_event(msg);
+        if message.in_reply_to.is_some() {
+            message.strip_reply_fallback();
+        }
+        message
+    }
+}
+
+impl Message {
+    fn from_message_event(msg: MessageEvent<MessageEventContent>) -> Self {
         let source = serde_json::to_string_pretty(&msg).ok();
 
         let initial_message = Self {
@@ -89,6 +109,7 @@ impl From<MessageEvent<MessageEventContent>> for Message {
             // It is mandatory for a message event to have
             // an event_id field
             id: Some(msg.event_id),
+            txn_id: msg.unsigned.transaction_id,
             mtype: String::new(),
             body: String::new(),
             url: None,
@@ -102,6 +123,8 @@ impl From<MessageEvent<MessageEventContent>> for Message {
             redacted: false,
             in_reply_to: None,
             replace: None,
+            thread_root: None,
+            state: None,
             extra_content: None,
         };
 
@@ -253,6 +276,34 @@ impl From<MessageEvent<MessageEventContent>> for Message {
             _ => initial_message,
         }
     }
+
+    fn from_state_event<C: StateEventContent>(ev: StateEvent<C>, state: StateChange) -> Self {
+        let source = serde_json::to_string_pretty(&ev).ok();
+
+        Self {
+            sender: ev.sender,
+            date: ev.origin_server_ts.into(),
+            room: ev.room_id,
+            id: Some(ev.event_id),
+            txn_id: None,
+            mtype: String::from(ev.content.event_type()),
+            body: String::new(),
+            url: None,
+            local_path: None,
+            thumb: None,
+            local_path_thumb: None,
+            formatted_body: None,
+            format: None,
+            source,
+            receipt: HashMap::new(),
+            redacted: false,
+            in_reply_to: None,
+            replace: None,
+            thread_root: None,
+            extra_content: None,
+            state: Some(state),
+        }
+    }
 }
 
 impl From<RedactedMessageEvent<RedactedMessageEventContent>> for Message {
@@ -266,6 +317,7 @@ impl From<RedactedMessageEvent<RedactedMessageEventContent>> for Message {
             // It is mandatory for a message event to have
             // an event_id field
             id: Some(msg.event_id),
+            txn_id: None,
             mtype: String::from(msg.content.event_type()),
             body: String::new(),
             url: None,
@@ -279,6 +331,8 @@ impl From<RedactedMessageEvent<RedactedMessageEventContent>> for Message {
             redacted: true,
             in_reply_to: None,
             replace: None,
+            thread_root: None,
+            state: None,
             extra_content: None,
         }
     }
@@ -296,6 +350,7 @@ impl From<MessageEvent<StickerEventContent>> for Message {
             // It is mandatory for a message event to have
             // an event_id field
             id: Some(msg.event_id),
+            txn_id: msg.unsigned.transaction_id,
             mtype: String::from(msg.content.event_type()),
             body: msg.content.body,
             url: url.clone(),
@@ -314,6 +369,8 @@ impl From<MessageEvent<StickerEventContent>> for Message {
             redacted: false,
             in_reply_to: None,
             replace: None,
+            thread_root: None,
+            state: None,
             extra_content: None,
         }
     }
@@ -330,6 +387,7 @@ impl From<RedactedMessageEvent<RedactedStickerEventContent>> for Message {
             // It is mandatory for a message event to have
             // an event_id field
             id: Some(msg.event_id),
+            txn_id: None,
             mtype: String::from(msg.content.event_type()),
             body: String::new(),
             url: None,
@@ -343,6 +401,8 @@ impl From<RedactedMessageEvent<RedactedStickerEventContent>> for Message {
             redacted: true,
             in_reply_to: None,
             replace: None,
+            thread_root: None,
+            state: None,
             extra_content: None,
         }
     }
@@ -365,6 +425,39 @@ impl TryFrom<AnyRoomEvent> for Message {
             AnyRoomEvent::RedactedMessage(AnyRedactedMessageEvent::Sticker(
                 redacted_sticker_event,
             )) => Ok(Self::from(redacted_sticker_event)),
+            AnyRoomEvent::State(state_event) => Self::try_from(state_event),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TryFrom<AnyStateEvent> for Message {
+    type Error = ();
+
+    fn try_from(event: AnyStateEvent) -> Result<Self, Self::Error> {
+        match event {
+            AnyStateEvent::RoomMember(ev) => {
+                let state = StateChange::from_member(
+                    &ev.content,
+                    ev.prev_content.as_ref(),
+                    &ev.sender,
+                    &ev.state_key,
+                )
+                .ok_or(())?;
+                Ok(Self::from_state_event(ev, state))
+            }
+            AnyStateEvent::RoomName(ev) => {
+                let name = ev.content.name().map(String::from);
+                Ok(Self::from_state_event(ev, StateChange::Name(name)))
+            }
+            AnyStateEvent::RoomTopic(ev) => {
+                let topic = ev.content.topic.clone();
+                Ok(Self::from_state_event(ev, StateChange::Topic(topic)))
+            }
+            AnyStateEvent::RoomAvatar(ev) => Ok(Self::from_state_event(ev, StateChange::Avatar)),
+            AnyStateEvent::RoomEncryption(ev) => {
+                Ok(Self::from_state_event(ev, StateChange::Encryption))
+            }
             _ => Err(()),
         }
     }
@@ -389,6 +482,9 @@ impl TryFrom<(RoomId, AnySyncRoomEvent)> for Message {
             AnySyncRoomEvent::RedactedMessage(AnyRedactedSyncMessageEvent::Sticker(
                 redacted_sticker_event,
             )) => Ok(Self::from(redacted_sticker_event.into_full_event(room_id))),
+            AnySyncRoomEvent::State(state_event) => {
+                Self::try_from(state_event.into_full_event(room_id))
+            }
             _ => Err(()),
         }
     }
@@ -405,6 +501,7 @@ impl Message {
         let date = Local::now();
         Message {
             id,
+            txn_id: None,
             sender,
             mtype,
             body,
@@ -421,6 +518,8 @@ impl Message {
             redacted: false,
             in_reply_to: None,
             replace: None,
+            thread_root: None,
+            state: None,
             extra_content: None,
         }
     }
@@ -433,15 +532,148 @@ impl Message {
         }
     }
 
+    /// Removes the quote of the replied message included for clients not supporting
+    /// rich replies.
+    ///
+    /// https://matrix.org/docs/spec/client_server/r0.6.1#fallbacks-for-rich-replies
+    fn strip_reply_fallback(&mut self) {
+        if self.body.starts_with("> ") {
+            let lines: Vec<&str> = self
+                .body
+                .lines()
+                .skip_while(|line| line.starts_with('>'))
+                .collect();
+            // The quote is separated from the reply by an empty line
+            let lines = match lines.split_first() {
+                Some((first, rest)) if first.is_empty() => rest,
+                _ => &lines[..],
+            };
+            self.body = lines.join("\n");
+        }
+
+        if let Some(formatted_body) = self.formatted_body.as_mut() {
+            let start = formatted_body.find("<mx-reply>");
+            let end = formatted_body
+                .find("</mx-reply>")
+                .map(|end| end + "</mx-reply>".len());
+            if let (Some(start), Some(end)) = (start, end) {
+                if start < end {
+                    formatted_body.replace_range(start..end, "");
+                }
+            }
+        }
+    }
+
+    /// Builds the message of an event received through a sync, keeping its JSON
+    /// when it's in `events_json`, by event ID.
+    pub fn from_sync_event(
+        room_id: RoomId,
+        event: AnySyncRoomEvent,
+        events_json: &HashMap<EventId, String>,
+    ) -> Option<Self> {
+        let mut msg = Self::try_from((room_id, event)).ok()?;
+        if let Some(json) = msg.id.as_ref().and_then(|id| events_json.get(id)) {
+            msg.set_source_json(json);
+        }
+        Some(msg)
+    }
+
+    /// Keeps the JSON of the event the message comes from, with the fields dropped
+    /// when deserializing it.
+    pub fn set_source_json(&mut self, json: &str) {
+        if let Ok(mut event) = serde_json::from_str::<JsonValue>(json) {
+            // Events received through a sync don't have the room they're in
+            if let Some(event) = event.as_object_mut() {
+                event
+                    .entry("room_id")
+                    .or_insert_with(|| self.room.as_str().into());
+            }
+            self.set_thread_from_json(&event);
+            self.source = serde_json::to_string_pretty(&event).ok();
+        }
+    }
+
+    /// Sets the thread the message is part of from the JSON of its event.
+    pub fn set_thread_from_json(&mut self, event: &JsonValue) {
+        if let Some((thread_root, is_falling_back)) = thread_relation(event) {
+            self.thread_root = Some(thread_root);
+            // The reply is only there for clients not supporting threads
+            if is_falling_back {
+                self.in_reply_to = None;
+            }
+        }
+    }
+
     /// Returns all event IDs this message relates to.
     pub fn relations(&self) -> Vec<EventId> {
-        vec![self.in_reply_to.as_ref(), self.replace.as_ref()]
-            .into_iter()
-            .flat_map(|r| r.into_iter())
-            .cloned()
+        vec![
+            self.in_reply_to.as_ref(),
+            self.replace.as_ref(),
+            self.thread_root.as_ref(),
+        ]
+        .into_iter()
+        .flat_map(|r| r.into_iter())
+        .cloned()
+        .collect()
+    }
+
+    /// Returns a message with the same content to send to another room.
+    ///
+    /// Media keep their `mxc://` URL, so they aren't uploaded again. Replies, edits and
+    /// threads are left out, since the related messages aren't in the other room.
+    pub fn forward(&self, room: RoomId, sender: UserId) -> Self {
+        // Stickers and server notices can't be sent as room messages
+        let mtype = match self.mtype.as_str() {
+            "m.sticker" => "m.image",
+            "m.server_notice" => "m.notice",
+            mtype => mtype,
+        };
+        let mut msg = Self::new(room, sender, self.body.clone(), mtype.to_string(), None);
+        msg.url = self.url.clone();
+        msg.thumb = self.thumb.clone();
+        msg.formatted_body = self.formatted_body.clone();
+        msg.format = self.format.clone();
+        msg.extra_content = self.source.as_deref().and_then(forwarded_content);
+        msg
+    }
+
+    /// Returns the waveform of a voice message, with values from 0 to 1024.
+    ///
+    /// https://github.com/matrix-org/matrix-doc/pull/3245
+    pub fn voice_waveform(&self) -> Option<Vec<u16>> {
+        self.unstable_content()?["org.matrix.msc1767.audio"]["waveform"]
+            .as_array()?
+            .iter()
+            .map(|value| value.as_u64().map(|value| value.min(1024) as u16))
             .collect()
     }
 
+    /// Returns the BlurHash of an image and the size of the image, to show a
+    /// placeholder until it's loaded.
+    ///
+    /// https://github.com/matrix-org/matrix-doc/pull/2448
+    pub fn blurhash(&self) -> Option<(String, (u32, u32))> {
+        let content = self.unstable_content()?;
+        let info = &content["info"];
+        let blurhash = info["xyz.amorgan.blurhash"].as_str()?;
+        let size =
+            |info: &JsonValue| Some((info["w"].as_u64()? as u32, info["h"].as_u64()? as u32));
+        let size = size(info).or_else(|| size(&info["thumbnail_info"]))?;
+
+        Some((blurhash.to_string(), size))
+    }
+
+    // The content with the fields dropped when deserializing the event, taken
+    // from the JSON kept in the source or from the fields added to a message
+    // being sent
+    fn unstable_content(&self) -> Option<JsonValue> {
+        if let Some(extra_content) = self.extra_content.clone() {
+            return Some(extra_content);
+        }
+        let mut event: JsonValue = serde_json::from_str(self.source.as_deref()?).ok()?;
+        Some(event["content"].take())
+    }
+
     /// Generates an unique transaction id for this message
     /// The txn_id is generated using the md5sum of a concatenation of the message room id, the
     /// message body and the date.
@@ -458,3 +690,255 @@ impl Message {
         self.receipt = receipt;
     }
 }
+
+// Fields of a forwarded message that are set from the `Message`, or that relate it to
+// other messages
+const FORWARD_SKIPPED_FIELDS: &[&str] = &[
+    "body",
+    "msgtype",
+    "url",
+    "format",
+    "formatted_body",
+    "m.new_content",
+    "m.relates_to",
+];
+
+#[derive(Deserialize)]
+struct ThreadEventContent {
+    #[serde(rename = "m.relates_to")]
+    relates_to: ThreadRelation,
+}
+
+#[derive(Deserialize)]
+struct ThreadRelation {
+    rel_type: String,
+    event_id: EventId,
+    #[serde(default)]
+    is_falling_back: bool,
+}
+
+/// Returns the thread root of an event and whether its reply relation is only a
+/// fallback for clients not supporting threads.
+///
+/// The thread relation is dropped when deserializing the event, so it's read from
+/// its JSON representation.
+/// https://github.com/matrix-org/matrix-doc/pull/3440
+pub fn thread_relation(event: &JsonValue) -> Option<(EventId, bool)> {
+    let content: ThreadEventContent = serde_json::from_value(event["content"].clone()).ok()?;
+    let relation = content.relates_to;
+    if relation.rel_type != "m.thread" {
+        return None;
+    }
+
+    Some((relation.event_id, relation.is_falling_back))
+}
+
+// The fields of the event content not kept in a `Message`, like the info of media
+fn forwarded_content(source: &str) -> Option<JsonValue> {
+    let event: JsonValue = serde_json::from_str(source).ok()?;
+    let content = &event["content"];
+    // Edits have the current content apart from the fallback
+    let content = content.get("m.new_content").unwrap_or(content);
+    let extra_content = content
+        .as_object()?
+        .iter()
+        .filter(|(key, _)| !FORWARD_SKIPPED_FIELDS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    Some(JsonValue::Object(extra_content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // A message event as it's received in the timeline of a sync, without its room
+    fn sync_message(content: JsonValue) -> Message {
+        let json = json!({
+            "type": "m.room.message",
+            "event_id": "$event:example.org",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 1_600_000_000_000u64,
+            "content": content,
+        })
+        .to_string();
+        let event: AnySyncRoomEvent = serde_json::from_str(&json).unwrap();
+        let room_id = RoomId::try_from("!room:example.org").unwrap();
+        let event_id = EventId::try_from("$event:example.org").unwrap();
+        let events_json = vec![(event_id, json)].into_iter().collect();
+        Message::from_sync_event(room_id, event, &events_json).unwrap()
+    }
+
+    #[test]
+    fn test_voice_waveform_from_sync() {
+        let msg = sync_message(json!({
+            "msgtype": "m.audio",
+            "body": "Voice message",
+            "url": "mxc://example.org/voice",
+            "info": { "mimetype": "audio/ogg", "duration": 2000 },
+            "org.matrix.msc1767.audio": { "duration": 2000, "waveform": [0, 512, 2048] },
+            "org.matrix.msc3245.voice": {},
+        }));
+
+        assert_eq!(msg.voice_waveform(), Some(vec![0, 512, 1024]));
+        let source: JsonValue = serde_json::from_str(msg.source.as_deref().unwrap()).unwrap();
+        assert_eq!(source["room_id"], "!room:example.org");
+    }
+
+    #[test]
+    fn test_blurhash_from_sync() {
+        let msg = sync_message(json!({
+            "msgtype": "m.image",
+            "body": "image.png",
+            "url": "mxc://example.org/image",
+            "info": {
+                "mimetype": "image/png",
+                "w": 640,
+                "h": 480,
+                "xyz.amorgan.blurhash": "LEHV6nWB2yk8pyo0adR*.7kCMdnj",
+            },
+        }));
+
+        assert_eq!(
+            msg.blurhash(),
+            Some((String::from("LEHV6nWB2yk8pyo0adR*.7kCMdnj"), (640, 480)))
+        );
+        assert_eq!(msg.voice_waveform(), None);
+    }
+
+    #[test]
+    fn test_strip_reply_fallback() {
+        let msg = sync_message(json!({
+            "msgtype": "m.text",
+            "body": "> <@bob:example.org> first line\n> second line\n\nmy reply\n> quoting myself",
+            "format": "org.matrix.custom.html",
+            "formatted_body": "<mx-reply><blockquote>first line</blockquote></mx-reply>my <b>reply</b>",
+            "m.relates_to": { "m.in_reply_to": { "event_id": "$parent:example.org" } },
+        }));
+
+        assert_eq!(
+            msg.in_reply_to,
+            Some(EventId::try_from("$parent:example.org").unwrap())
+        );
+        assert_eq!(msg.body, "my reply\n> quoting myself");
+        assert_eq!(msg.formatted_body.as_deref(), Some("my <b>reply</b>"));
+    }
+
+    #[test]
+    fn test_keeps_quotes_out_of_replies() {
+        let msg = sync_message(json!({
+            "msgtype": "m.text",
+            "body": "> not a reply\n\nbut a quote",
+        }));
+
+        assert_eq!(msg.body, "> not a reply\n\nbut a quote");
+    }
+
+    #[test]
+    fn test_thread_relation() {
+        let root = EventId::try_from("$root:example.org").unwrap();
+        let event = json!({ "content": { "m.relates_to": {
+            "rel_type": "m.thread",
+            "event_id": "$root:example.org",
+        }}});
+        assert_eq!(thread_relation(&event), Some((root.clone(), false)));
+
+        let event = json!({ "content": { "m.relates_to": {
+            "rel_type": "m.thread",
+            "event_id": "$root:example.org",
+            "is_falling_back": true,
+            "m.in_reply_to": { "event_id": "$latest:example.org" },
+        }}});
+        assert_eq!(thread_relation(&event), Some((root, true)));
+
+        let event = json!({ "content": { "m.relates_to": {
+            "rel_type": "m.replace",
+            "event_id": "$root:example.org",
+        }}});
+        assert_eq!(thread_relation(&event), None);
+        assert_eq!(
+            thread_relation(&json!({ "content": { "body": "hi" } })),
+            None
+        );
+    }
+
+    #[test]
+    fn test_thread_reply_from_sync() {
+        let msg = sync_message(json!({
+            "msgtype": "m.text",
+            "body": "in the thread",
+            "m.relates_to": {
+                "rel_type": "m.thread",
+                "event_id": "$root:example.org",
+                "is_falling_back": true,
+                "m.in_reply_to": { "event_id": "$latest:example.org" },
+            },
+        }));
+
+        assert_eq!(
+            msg.thread_root,
+            Some(EventId::try_from("$root:example.org").unwrap())
+        );
+        // The reply is only there for clients not supporting threads
+        assert_eq!(msg.in_reply_to, None);
+    }
+
+    #[test]
+    fn test_forwarded_content() {
+        let source = json!({ "content": {
+            "msgtype": "m.image",
+            "body": "cat.png",
+            "url": "mxc://example.org/cat",
+            "info": { "mimetype": "image/png", "w": 640, "h": 480 },
+            "m.relates_to": { "m.in_reply_to": { "event_id": "$other:example.org" } },
+        }});
+        assert_eq!(
+            forwarded_content(&source.to_string()),
+            Some(json!({ "info": { "mimetype": "image/png", "w": 640, "h": 480 } }))
+        );
+
+        let edit = json!({ "content": {
+            "msgtype": "m.text",
+            "body": "* fixed",
+            "m.new_content": {
+                "msgtype": "m.text",
+                "body": "fixed",
+                "org.example.custom": true,
+            },
+            "m.relates_to": { "rel_type": "m.replace", "event_id": "$original:example.org" },
+        }});
+        assert_eq!(
+            forwarded_content(&edit.to_string()),
+            Some(json!({ "org.example.custom": true }))
+        );
+        assert_eq!(forwarded_content("not json"), None);
+    }
+
+    #[test]
+    fn test_forward_reply() {
+        let msg = sync_message(json!({
+            "msgtype": "m.image",
+            "body": "cat.png",
+            "url": "mxc://example.org/cat",
+            "info": { "mimetype": "image/png" },
+            "m.relates_to": { "m.in_reply_to": { "event_id": "$other:example.org" } },
+        }));
+        let room = RoomId::try_from("!other:example.org").unwrap();
+        let sender = UserId::try_from("@bob:example.org").unwrap();
+
+        let forwarded = msg.forward(room.clone(), sender.clone());
+
+        assert_eq!(forwarded.room, room);
+        assert_eq!(forwarded.sender, sender);
+        assert_eq!(forwarded.mtype, "m.image");
+        assert_eq!(forwarded.url, msg.url);
+        assert_eq!(forwarded.id, None);
+        assert_eq!(forwarded.in_reply_to, None);
+        assert_eq!(
+            forwarded.extra_content,
+            Some(json!({ "info": { "mimetype": "image/png" } }))
+        );
+    }
+}
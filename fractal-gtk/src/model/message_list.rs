@@ -1,6 +1,8 @@
 use crate::model::message::Message;
-use matrix_sdk::identifiers::EventId;
-use std::collections::{HashMap, HashSet};
+use crate::model::reaction::Reaction;
+use chrono::prelude::*;
+use matrix_sdk::identifiers::{EventId, UserId};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::iter;
 use std::iter::FromIterator;
 use std::slice::Iter;
@@ -10,6 +12,13 @@ use std::slice::Iter;
 pub struct MessageList {
     messages: Vec<Message>,
     relating_messages: HashMap<EventId, HashSet<EventId>>,
+    // Reactions by annotated event and key, with the reaction event sent by each user
+    reactions: HashMap<EventId, BTreeMap<String, HashMap<UserId, EventId>>>,
+    // The annotated event and key of every reaction, needed to handle redactions
+    reaction_targets: HashMap<EventId, (EventId, String)>,
+    // The local date of the messages sent from here, by transaction id, until
+    // their event comes back from the server
+    local_echoes: HashMap<String, DateTime<Local>>,
 }
 
 impl MessageList {
@@ -24,6 +33,22 @@ impl MessageList {
             .find(|m| m.id.as_ref() == Some(event_id))
     }
 
+    /// Returns the message with the given event ID, with the content of its latest edit.
+    pub fn get_latest(&self, event_id: &EventId) -> Option<&Message> {
+        self.messages
+            .iter()
+            .filter(|m| m.replace.as_ref() == Some(event_id))
+            .last()
+            .or_else(|| self.get(event_id))
+    }
+
+    /// Returns the loaded replies in the thread started by the message with the given id.
+    pub fn thread_replies<'a>(&'a self, root: &'a EventId) -> impl Iterator<Item = &'a Message> {
+        self.messages.iter().filter(move |m| {
+            m.thread_root.as_ref() == Some(root) && m.replace.is_none() && !m.redacted
+        })
+    }
+
     /// Whether the message with the given id is in the room.
     pub fn contains(&self, msg_id: &EventId) -> bool {
         self.get(msg_id).is_some()
@@ -43,20 +68,37 @@ impl MessageList {
             self.remove_relations(&id);
         }
 
-        // Deduplication only happens for messages with the same date, so we have
-        // to manually go through the message list and remove possible duplicates.
-        //
-        // This is necessary due to the special case of just-sent messages.
-        // They don't contain the “official” timestamp from the server, but the
-        // time they were sent from Fractal. Due to this circumstance, we might
-        // end up with two messages having the same id, but different dates. We
-        // brute-force-fix this by searching all messages for duplicates.
-        self.messages.retain(|m| m.id != msg.id);
-
         if !msg.redacted {
             self.populate_relations(&msg);
         }
 
+        // A local echo is stored at the date it was sent from here, so it has
+        // to be replaced once its event comes back from the server.
+        let echo_date = msg
+            .txn_id
+            .as_ref()
+            .filter(|txn_id| {
+                let date = self.local_echoes.get(*txn_id);
+                date.map_or(false, |date| *date != msg.date)
+            })
+            .and_then(|txn_id| self.local_echoes.remove(txn_id));
+        if let Some(date) = echo_date {
+            let echo = self
+                .messages
+                .binary_search_by(|m| m.date.cmp(&date).then_with(|| m.id.cmp(&msg.id)));
+            if let Ok(idx) = echo {
+                // The date given by the server is usually close enough to keep
+                // the message at the same place
+                let after_prev = idx == 0 || self.messages[idx - 1] < msg;
+                let before_next = self.messages.get(idx + 1).map_or(true, |next| msg < *next);
+                if after_prev && before_next {
+                    self.messages[idx] = msg;
+                    return;
+                }
+                self.messages.remove(idx);
+            }
+        }
+
         match self.messages.binary_search(&msg) {
             Ok(idx) => self.messages[idx] = msg,
             Err(idx) => self.messages.insert(idx, msg),
@@ -65,6 +107,96 @@ impl MessageList {
         // debug_assert!(self.messages.is_sorted());
     }
 
+    /// Remembers the local date of a message sent from here, so it's replaced
+    /// by the event with the same transaction id coming back from the server.
+    pub fn track_local_echo(&mut self, txn_id: String, date: DateTime<Local>) {
+        self.local_echoes.insert(txn_id, date);
+    }
+
+    /// Returns the local date of the messages sent from here whose event hasn't
+    /// come back yet, by transaction id.
+    pub fn local_echoes(&self) -> &HashMap<String, DateTime<Local>> {
+        &self.local_echoes
+    }
+
+    /// Removes the message with the given event ID, returning it if it was in the list.
+    pub fn remove(&mut self, event_id: &EventId) -> Option<Message> {
+        let idx = self
+            .messages
+            .iter()
+            .position(|m| m.id.as_ref() == Some(event_id))?;
+        self.remove_relations(event_id);
+        self.relating_messages.remove(event_id);
+
+        Some(self.messages.remove(idx))
+    }
+
+    /// Returns the reactions to the message with the given id, grouped by key.
+    pub fn reactions(
+        &self,
+        event_id: &EventId,
+    ) -> Option<&BTreeMap<String, HashMap<UserId, EventId>>> {
+        self.reactions.get(event_id)
+    }
+
+    /// Returns the ID of the reaction the given user sent with `key` to a message.
+    pub fn user_reaction(
+        &self,
+        event_id: &EventId,
+        key: &str,
+        user_id: &UserId,
+    ) -> Option<&EventId> {
+        self.reactions.get(event_id)?.get(key)?.get(user_id)
+    }
+
+    /// Returns the ID of the event a reaction to the message with the given id
+    /// has to relate to, which is the original event for edits.
+    pub fn reaction_target(&self, event_id: &EventId) -> EventId {
+        self.get(event_id)
+            .and_then(|m| m.replace.clone())
+            .unwrap_or_else(|| event_id.clone())
+    }
+
+    /// Records a reaction to a message, which doesn't need to be loaded.
+    ///
+    /// Returns `false` if the reaction was already known.
+    pub fn add_reaction(&mut self, reaction: Reaction) -> bool {
+        if self.reaction_targets.contains_key(&reaction.id) {
+            return false;
+        }
+
+        self.reaction_targets.insert(
+            reaction.id.clone(),
+            (reaction.relates_to.clone(), reaction.key.clone()),
+        );
+        self.reactions
+            .entry(reaction.relates_to)
+            .or_default()
+            .entry(reaction.key)
+            .or_default()
+            .insert(reaction.sender, reaction.id);
+        true
+    }
+
+    /// Removes the reaction with the given id.
+    ///
+    /// Returns the ID of the message it annotated, if the reaction was known.
+    pub fn remove_reaction(&mut self, reaction_id: &EventId) -> Option<EventId> {
+        let (target, key) = self.reaction_targets.remove(reaction_id)?;
+        let keys = self.reactions.get_mut(&target)?;
+        if let Some(senders) = keys.get_mut(&key) {
+            senders.retain(|_, id| id != reaction_id);
+            if senders.is_empty() {
+                keys.remove(&key);
+            }
+        }
+        if keys.is_empty() {
+            self.reactions.remove(&target);
+        }
+
+        Some(target)
+    }
+
     /// Updates records of those relations the message is involved in.
     ///
     /// This updates both, relating and related, messages.
@@ -78,6 +210,9 @@ impl MessageList {
         if let Some(replace_id) = &msg.replace {
             self.update_relating(replace_id, iter::once(&id).cloned().collect());
         }
+        if let Some(thread_root) = &msg.thread_root {
+            self.update_relating(thread_root, iter::once(&id).cloned().collect());
+        }
     }
 
     /// Remove all outgoing relations for the given event.
@@ -113,7 +248,11 @@ impl MessageList {
     fn find_and_get_relating(&self, id: &EventId) -> HashSet<EventId> {
         self.messages
             .iter()
-            .filter(|m| m.replace.as_ref() == Some(id) || m.in_reply_to.as_ref() == Some(id))
+            .filter(|m| {
+                m.replace.as_ref() == Some(id)
+                    || m.in_reply_to.as_ref() == Some(id)
+                    || m.thread_root.as_ref() == Some(id)
+            })
             .map(|m| m.id.clone().unwrap())
             .collect()
     }
@@ -128,3 +267,236 @@ impl FromIterator<Message> for MessageList {
         message_list
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{LocalEcho, Outbox, OutboxEntry};
+    use chrono::Duration;
+    use matrix_sdk::events::AnySyncRoomEvent;
+    use matrix_sdk::identifiers::RoomId;
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    fn room_id() -> RoomId {
+        RoomId::try_from("!room:example.org").unwrap()
+    }
+
+    // A message sent from here, once its event ID is known
+    fn local_echo(event_id: &str, date: DateTime<Local>) -> Message {
+        let sender = UserId::try_from("@alice:example.org").unwrap();
+        let mut msg = Message::new(
+            room_id(),
+            sender,
+            String::from("hello"),
+            String::from("m.text"),
+            Some(EventId::try_from(event_id).unwrap()),
+        );
+        msg.date = date;
+        msg
+    }
+
+    // The event of a message sent from here, as it comes back through sync
+    fn remote_echo(event_id: &str, txn_id: &str, date: DateTime<Local>) -> Message {
+        let event: AnySyncRoomEvent = serde_json::from_value(json!({
+            "type": "m.room.message",
+            "event_id": event_id,
+            "sender": "@alice:example.org",
+            "origin_server_ts": date.timestamp_millis(),
+            "content": { "msgtype": "m.text", "body": "hello" },
+            "unsigned": { "transaction_id": txn_id },
+        }))
+        .unwrap();
+        Message::from_sync_event(room_id(), event, &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn test_echo_after_restoring_outbox() {
+        let event_id = EventId::try_from("$event:example.org").unwrap();
+        let date = Local.timestamp_millis(1_600_000_000_000);
+
+        // A message waiting to be sent when quitting, kept on disk
+        let msg = local_echo("$event:example.org", date);
+        let outbox = Outbox {
+            entries: vec![OutboxEntry::new(String::from("txn"), &msg)],
+            local_echoes: vec![],
+        };
+        let stored = serde_json::to_vec(&outbox).unwrap();
+
+        // Once restored and sent, it's shown at its local date
+        let outbox: Outbox = serde_json::from_slice(&stored).unwrap();
+        let (txn_id, mut local_echo) = outbox.entries.into_iter().next().unwrap().into_message();
+        local_echo.id = Some(event_id.clone());
+        let mut list = MessageList::new();
+        list.track_local_echo(txn_id, local_echo.date);
+        list.add(local_echo);
+
+        // The date it's shown at is kept on disk until its event comes back
+        let local_echoes: Vec<_> = list
+            .local_echoes()
+            .iter()
+            .map(|(txn_id, date)| LocalEcho::new(txn_id.clone(), room_id(), *date))
+            .collect();
+        let stored = serde_json::to_vec(&local_echoes).unwrap();
+        let local_echoes: Vec<LocalEcho> = serde_json::from_slice(&stored).unwrap();
+        assert_eq!(local_echoes[0].txn_id, "txn");
+        assert_eq!(local_echoes[0].date(), date);
+
+        // Its event comes back through sync with the date given by the server
+        let remote_echo = remote_echo("$event:example.org", "txn", date + Duration::seconds(2));
+        list.add(remote_echo.clone());
+
+        assert_eq!(list.iter().count(), 1);
+        assert_eq!(list.get(&event_id).map(|m| m.date), Some(remote_echo.date));
+        assert!(list.local_echoes().is_empty());
+    }
+
+    #[test]
+    fn test_echo_moved_after_later_messages() {
+        let date = Local.timestamp_millis(1_600_000_000_000);
+        let mut list = MessageList::new();
+        list.track_local_echo(String::from("txn"), date);
+        list.add(local_echo("$echo:example.org", date));
+        list.add(local_echo(
+            "$other:example.org",
+            date + Duration::seconds(1),
+        ));
+
+        // The server received it after the other message
+        list.add(remote_echo(
+            "$echo:example.org",
+            "txn",
+            date + Duration::seconds(2),
+        ));
+
+        let ids: Vec<_> = list
+            .iter()
+            .map(|m| m.id.clone().unwrap().to_string())
+            .collect();
+        assert_eq!(ids, vec!["$other:example.org", "$echo:example.org"]);
+        assert!(list.local_echoes().is_empty());
+    }
+
+    fn reaction(id: &str, sender: &str, relates_to: &str, key: &str) -> Reaction {
+        Reaction {
+            id: EventId::try_from(id).unwrap(),
+            room: room_id(),
+            sender: UserId::try_from(sender).unwrap(),
+            relates_to: EventId::try_from(relates_to).unwrap(),
+            key: key.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_reactions_grouped_by_key() {
+        let target = EventId::try_from("$msg:example.org").unwrap();
+        let alice = UserId::try_from("@alice:example.org").unwrap();
+        let mut list = MessageList::new();
+
+        assert!(list.add_reaction(reaction(
+            "$r1:example.org",
+            "@alice:example.org",
+            "$msg:example.org",
+            "👍"
+        )));
+        assert!(list.add_reaction(reaction(
+            "$r2:example.org",
+            "@bob:example.org",
+            "$msg:example.org",
+            "👍"
+        )));
+        assert!(list.add_reaction(reaction(
+            "$r3:example.org",
+            "@alice:example.org",
+            "$msg:example.org",
+            "🎉"
+        )));
+        // The same reaction can come from sync and from the response to sending it
+        assert!(!list.add_reaction(reaction(
+            "$r1:example.org",
+            "@alice:example.org",
+            "$msg:example.org",
+            "👍"
+        )));
+
+        let reactions = list.reactions(&target).unwrap();
+        let keys: Vec<_> = reactions.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["🎉", "👍"]);
+        assert_eq!(reactions["👍"].len(), 2);
+        assert_eq!(
+            list.user_reaction(&target, "🎉", &alice),
+            Some(&EventId::try_from("$r3:example.org").unwrap())
+        );
+    }
+
+    #[test]
+    fn test_remove_reaction() {
+        let target = EventId::try_from("$msg:example.org").unwrap();
+        let alice = UserId::try_from("@alice:example.org").unwrap();
+        let mut list = MessageList::new();
+        list.add_reaction(reaction(
+            "$r1:example.org",
+            "@alice:example.org",
+            "$msg:example.org",
+            "👍",
+        ));
+        list.add_reaction(reaction(
+            "$r2:example.org",
+            "@alice:example.org",
+            "$msg:example.org",
+            "🎉",
+        ));
+
+        let r1 = EventId::try_from("$r1:example.org").unwrap();
+        assert_eq!(list.remove_reaction(&r1), Some(target.clone()));
+        assert_eq!(list.remove_reaction(&r1), None);
+        assert_eq!(list.user_reaction(&target, "👍", &alice), None);
+        assert!(list.reactions(&target).unwrap().get("👍").is_none());
+
+        let r2 = EventId::try_from("$r2:example.org").unwrap();
+        list.remove_reaction(&r2);
+        assert!(list.reactions(&target).is_none());
+    }
+
+    #[test]
+    fn test_reaction_target_of_edit() {
+        let date = Local.timestamp_millis(1_600_000_000_000);
+        let original = EventId::try_from("$original:example.org").unwrap();
+        let mut edit = local_echo("$edit:example.org", date + Duration::seconds(1));
+        edit.replace = Some(original.clone());
+        let mut list = MessageList::new();
+        list.add(local_echo("$original:example.org", date));
+        list.add(edit);
+
+        let edit_id = EventId::try_from("$edit:example.org").unwrap();
+        assert_eq!(list.reaction_target(&edit_id), original);
+        assert_eq!(list.reaction_target(&original), original);
+        // Messages that aren't loaded are reacted to as they are
+        let unknown = EventId::try_from("$unknown:example.org").unwrap();
+        assert_eq!(list.reaction_target(&unknown), unknown);
+    }
+
+    #[test]
+    fn test_remove_edit() {
+        let date = Local.timestamp_millis(1_600_000_000_000);
+        let original = EventId::try_from("$original:example.org").unwrap();
+        let edit_id = EventId::try_from("$edit:example.org").unwrap();
+        let mut edit = local_echo("$edit:example.org", date + Duration::seconds(1));
+        edit.replace = Some(original.clone());
+        let mut list = MessageList::new();
+        list.add(local_echo("$original:example.org", date));
+        list.add(edit);
+        assert_eq!(
+            list.get_latest(&original).unwrap().id,
+            Some(edit_id.clone())
+        );
+
+        let removed = list.remove(&edit_id).unwrap();
+        assert_eq!(removed.id, Some(edit_id.clone()));
+        assert!(!list.contains(&edit_id));
+        assert!(list.relating_messages[&original].is_empty());
+        assert!(!list.relating_messages.contains_key(&edit_id));
+        assert_eq!(list.get_latest(&original).unwrap().id, Some(original));
+        assert!(list.remove(&edit_id).is_none());
+    }
+}
@@ -2,4 +2,6 @@ pub mod fileinfo;
 pub mod member;
 pub mod message;
 pub mod message_list;
+pub mod reaction;
 pub mod room;
+pub mod state_change;
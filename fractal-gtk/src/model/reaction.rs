@@ -0,0 +1,81 @@
+use matrix_sdk::events::{AnyRoomEvent, AnySyncRoomEvent};
+use matrix_sdk::identifiers::{EventId, RoomId, UserId};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::convert::TryFrom;
+
+/// An `m.reaction` event annotating another event with a key, usually an emoji.
+///
+/// The reaction events are not part of the stable spec yet, so they are parsed
+/// from the JSON representation of the event.
+/// https://github.com/matrix-org/matrix-doc/pull/2677
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reaction {
+    pub id: EventId,
+    pub room: RoomId,
+    pub sender: UserId,
+    // The event ID of the annotated event.
+    pub relates_to: EventId,
+    pub key: String,
+}
+
+#[derive(Deserialize)]
+struct ReactionEvent {
+    event_id: EventId,
+    room_id: Option<RoomId>,
+    sender: UserId,
+    content: ReactionEventContent,
+}
+
+#[derive(Deserialize)]
+struct ReactionEventContent {
+    #[serde(rename = "m.relates_to")]
+    relates_to: Annotation,
+}
+
+#[derive(Deserialize)]
+struct Annotation {
+    rel_type: String,
+    event_id: EventId,
+    key: String,
+}
+
+impl Reaction {
+    fn from_json(event: JsonValue, room_id: Option<RoomId>) -> Option<Self> {
+        if event["type"] != "m.reaction" {
+            return None;
+        }
+
+        let event: ReactionEvent = serde_json::from_value(event).ok()?;
+        let annotation = event.content.relates_to;
+        if annotation.rel_type != "m.annotation" {
+            return None;
+        }
+
+        Some(Self {
+            id: event.event_id,
+            room: room_id.or(event.room_id)?,
+            sender: event.sender,
+            relates_to: annotation.event_id,
+            key: annotation.key,
+        })
+    }
+}
+
+impl TryFrom<&AnyRoomEvent> for Reaction {
+    type Error = ();
+
+    fn try_from(event: &AnyRoomEvent) -> Result<Self, Self::Error> {
+        let json = serde_json::to_value(event).map_err(|_| ())?;
+        Self::from_json(json, None).ok_or(())
+    }
+}
+
+impl TryFrom<(RoomId, &AnySyncRoomEvent)> for Reaction {
+    type Error = ();
+
+    fn try_from((room_id, event): (RoomId, &AnySyncRoomEvent)) -> Result<Self, Self::Error> {
+        let json = serde_json::to_value(event).map_err(|_| ())?;
+        Self::from_json(json, Some(room_id)).ok_or(())
+    }
+}
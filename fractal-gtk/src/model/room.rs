@@ -2,6 +2,7 @@ use crate::model::member::Member;
 use crate::model::member::MemberList;
 use crate::model::message::Message;
 use crate::model::message_list::MessageList;
+use crate::model::reaction::Reaction;
 use anyhow::anyhow;
 use chrono::DateTime;
 use chrono::Utc;
@@ -18,7 +19,7 @@ use matrix_sdk::identifiers::{EventId, RoomAliasId, RoomId, UserId};
 use serde::{Deserialize, Serialize};
 use serde_json::value::Value;
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::convert::{TryFrom, TryInto};
+use std::convert::TryFrom;
 use url::{ParseError as UrlError, Url};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -130,6 +131,8 @@ pub struct Room {
     pub prev_batch: Option<String>,
     pub typing_users: Vec<Member>,
     pub language: Option<String>,
+    // The last event the user read, the new messages divider is shown after it
+    pub fully_read: Option<EventId>,
 
     /// Hashmap with the room users power levels
     /// the key will be the userid and the value will be the level
@@ -157,12 +160,19 @@ impl Room {
             prev_batch: Default::default(),
             typing_users: Default::default(),
             language: Default::default(),
+            fully_read: Default::default(),
             admins: Default::default(),
             default_power_level: -1,
         }
     }
 
-    pub fn from_sync_response(response: &SyncResponse, user_id: UserId) -> Vec<Self> {
+    /// Builds the rooms of a sync response. The messages keep the JSON of their
+    /// event when it's in `events_json`, by event ID.
+    pub fn from_sync_response(
+        response: &SyncResponse,
+        events_json: &HashMap<EventId, String>,
+        user_id: UserId,
+    ) -> Vec<Self> {
         // getting the list of direct rooms
         let direct: HashSet<RoomId> = response
             .account_data
@@ -190,8 +200,15 @@ impl Room {
                 .and(Some(RoomTag::Favourite))
                 .unwrap_or(RoomTag::None);
 
+            // Members joining in the timeline aren't part of the state before it
+            let timeline_stevents = room.timeline.events.iter().filter_map(|event| match event {
+                AnySyncRoomEvent::State(ev) => Some(ev),
+                _ => None,
+            });
             let members: MemberList = stevents
                 .iter()
+                .copied()
+                .chain(timeline_stevents)
                 .filter_map(|event| match event {
                     AnySyncStateEvent::RoomMember(ev) => parse_room_member(ev),
                     _ => None,
@@ -252,7 +269,9 @@ impl Room {
                     .timeline
                     .events
                     .iter()
-                    .filter_map(|event| (k.clone(), event.clone()).try_into().ok())
+                    .filter_map(|event| {
+                        Message::from_sync_event(k.clone(), event.clone(), events_json)
+                    })
                     .collect(),
                 admins: stevents
                     .iter()
@@ -288,42 +307,16 @@ impl Room {
                 ..Self::new(k.clone(), RoomMembership::Joined(room_tag))
             };
 
-            let receipts: HashMap<EventId, HashMap<UserId, i64>> = room
-                .ephemeral
+            for reaction in room
+                .timeline
                 .events
                 .iter()
-                .filter_map(|event| match event {
-                    AnySyncEphemeralRoomEvent::Receipt(ev) => Some(ev.content.0.clone()),
-                    _ => None,
-                })
-                .take(1)
-                .flatten()
-                .map(|(event_id, receipts)| {
-                    let receipts = receipts
-                        .read
-                        .into_iter()
-                        .flatten()
-                        .map(|(uid, receipt)| {
-                            let ts = receipt
-                                .ts
-                                .map(DateTime::<Utc>::from)
-                                .map(|time| time.timestamp())
-                                .unwrap_or_default();
-                            (uid, ts)
-                        })
-                        .inspect(|(_, ts)| {
-                            debug!("Value of timestamp 'ts': {:?}", ts);
-                            if *ts == 0 {
-                                info!(
-                                    "Possibly malformed timestamp, working around synapse bug 4898"
-                                );
-                            };
-                        })
-                        .collect();
+                .filter_map(|event| Reaction::try_from((k.clone(), event)).ok())
+            {
+                r.messages.add_reaction(reaction);
+            }
 
-                    (event_id, receipts)
-                })
-                .collect();
+            let receipts = parse_receipts(&room.ephemeral.events);
 
             let changed_msgs: Vec<_> = r
                 .messages
@@ -338,15 +331,7 @@ impl Room {
                 r.take_new_message(msg);
             }
 
-            if let Some(event_id) = room.ephemeral.events.iter().find_map(|event| match event {
-                AnySyncEphemeralRoomEvent::FullyRead(ev) => Some(ev.content.event_id.clone()),
-                _ => None,
-            }) {
-                if let Some(mut msg) = r.messages.get(&event_id).cloned() {
-                    msg.receipt.insert(user_id.clone(), 0);
-                    r.take_new_message(msg);
-                }
-            }
+            r.fully_read = parse_fully_read(&room.account_data.events, &room.ephemeral.events);
 
             r
         });
@@ -457,6 +442,62 @@ impl Room {
     }
 }
 
+/// The event the user read up to in the room, from the `m.fully_read` marker.
+pub fn parse_fully_read(
+    account_data: &[AnyBasicEvent],
+    ephemeral: &[AnySyncEphemeralRoomEvent],
+) -> Option<EventId> {
+    let from_account_data = account_data.iter().find_map(|event| match event {
+        AnyBasicEvent::Custom(ev) if ev.content.event_type == "m.fully_read" => {
+            let event_id = ev.content.json["event_id"].as_str()?;
+            EventId::try_from(event_id).ok()
+        }
+        _ => None,
+    });
+
+    from_account_data.or_else(|| {
+        ephemeral.iter().find_map(|event| match event {
+            AnySyncEphemeralRoomEvent::FullyRead(ev) => Some(ev.content.event_id.clone()),
+            _ => None,
+        })
+    })
+}
+
+/// The users who read each event, with the time they read it.
+pub type Receipts = HashMap<EventId, HashMap<UserId, i64>>;
+
+/// Gathers the read receipts of the users from the ephemeral events of a room.
+pub fn parse_receipts(events: &[AnySyncEphemeralRoomEvent]) -> Receipts {
+    let mut receipts = Receipts::new();
+    let contents = events.iter().filter_map(|event| match event {
+        AnySyncEphemeralRoomEvent::Receipt(ev) => Some(ev.content.0.clone()),
+        _ => None,
+    });
+    for (event_id, receipt) in contents.flatten() {
+        let readers = receipt
+            .read
+            .into_iter()
+            .flatten()
+            .map(|(uid, receipt)| {
+                let ts = receipt
+                    .ts
+                    .map(DateTime::<Utc>::from)
+                    .map(|time| time.timestamp())
+                    .unwrap_or_default();
+                (uid, ts)
+            })
+            .inspect(|(_, ts)| {
+                debug!("Value of timestamp 'ts': {:?}", ts);
+                if *ts == 0 {
+                    info!("Possibly malformed timestamp, working around synapse bug 4898");
+                };
+            });
+        receipts.entry(event_id).or_default().extend(readers);
+    }
+
+    receipts
+}
+
 impl TryFrom<PublicRoomsChunk> for Room {
     type Error = UrlError;
 
@@ -539,3 +580,87 @@ fn parse_room_member(msg: &SyncStateEvent<MemberEventContent>) -> Option<Member>
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ephemeral(event: Value) -> AnySyncEphemeralRoomEvent {
+        serde_json::from_value(event).unwrap()
+    }
+
+    fn event_id(id: &str) -> EventId {
+        EventId::try_from(id).unwrap()
+    }
+
+    fn user_id(id: &str) -> UserId {
+        UserId::try_from(id).unwrap()
+    }
+
+    #[test]
+    fn test_parse_receipts() {
+        let events = vec![
+            ephemeral(json!({
+                "type": "m.receipt",
+                "content": {
+                    "$first:example.org": {
+                        "m.read": { "@alice:example.org": { "ts": 1600000000000u64 } }
+                    }
+                }
+            })),
+            ephemeral(json!({
+                "type": "m.typing",
+                "content": { "user_ids": ["@carol:example.org"] }
+            })),
+            ephemeral(json!({
+                "type": "m.receipt",
+                "content": {
+                    "$first:example.org": {
+                        "m.read": { "@bob:example.org": { "ts": 1600000060000u64 } }
+                    },
+                    "$second:example.org": {
+                        "m.read": { "@carol:example.org": {} }
+                    }
+                }
+            })),
+        ];
+
+        let receipts = parse_receipts(&events);
+
+        assert_eq!(receipts.len(), 2);
+        let first = &receipts[&event_id("$first:example.org")];
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[&user_id("@alice:example.org")], 1600000000);
+        assert_eq!(first[&user_id("@bob:example.org")], 1600000060);
+        let second = &receipts[&event_id("$second:example.org")];
+        assert_eq!(second[&user_id("@carol:example.org")], 0);
+    }
+
+    #[test]
+    fn test_parse_fully_read() {
+        let account_data: Vec<AnyBasicEvent> = vec![serde_json::from_value(json!({
+            "type": "m.fully_read",
+            "content": { "event_id": "$marker:example.org" }
+        }))
+        .unwrap()];
+        let typing = ephemeral(json!({
+            "type": "m.typing",
+            "content": { "user_ids": [] }
+        }));
+        let fully_read = ephemeral(json!({
+            "type": "m.fully_read",
+            "content": { "event_id": "$ephemeral:example.org" }
+        }));
+
+        assert_eq!(
+            parse_fully_read(&account_data, &[typing.clone(), fully_read.clone()]),
+            Some(event_id("$marker:example.org"))
+        );
+        assert_eq!(
+            parse_fully_read(&[], &[typing.clone(), fully_read]),
+            Some(event_id("$ephemeral:example.org"))
+        );
+        assert_eq!(parse_fully_read(&[], &[typing]), None);
+    }
+}
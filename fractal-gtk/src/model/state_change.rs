@@ -0,0 +1,145 @@
+use matrix_sdk::events::room::member::{MemberEventContent, MembershipState};
+use matrix_sdk::identifiers::UserId;
+
+/// How the membership of a user in a room changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MembershipChange {
+    Joined,
+    Left,
+    Invited,
+    Kicked,
+    Banned,
+    Unbanned,
+    // The display name or avatar of the member changed
+    ProfileChanged,
+}
+
+/// A change of the state of a room shown in its history.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateChange {
+    Membership {
+        change: MembershipChange,
+        // The display name of the affected member, or their ID if they have none
+        name: String,
+    },
+    Name(Option<String>),
+    Topic(String),
+    Avatar,
+    Encryption,
+}
+
+impl StateChange {
+    /* Compare a membership event with the previous membership of the user, returns `None`
+     * for changes we don't show, e.g. knocking
+     */
+    pub fn from_member(
+        content: &MemberEventContent,
+        prev_content: Option<&MemberEventContent>,
+        sender: &UserId,
+        state_key: &str,
+    ) -> Option<Self> {
+        let prev_membership = prev_content.map(|prev| &prev.membership);
+        let change = match content.membership {
+            MembershipState::Join if prev_membership == Some(&MembershipState::Join) => {
+                MembershipChange::ProfileChanged
+            }
+            MembershipState::Join => MembershipChange::Joined,
+            MembershipState::Invite => MembershipChange::Invited,
+            MembershipState::Ban => MembershipChange::Banned,
+            MembershipState::Leave if prev_membership == Some(&MembershipState::Ban) => {
+                MembershipChange::Unbanned
+            }
+            // Rejecting an invite counts as leaving too
+            MembershipState::Leave if sender.as_str() == state_key => MembershipChange::Left,
+            MembershipState::Leave => MembershipChange::Kicked,
+            _ => return None,
+        };
+
+        // Members leaving usually drop their display name, the previous one is kept
+        let name = content
+            .displayname
+            .clone()
+            .or_else(|| prev_content.and_then(|prev| prev.displayname.clone()))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| state_key.to_string());
+
+        Some(Self::Membership { change, name })
+    }
+
+    pub fn membership(&self) -> Option<MembershipChange> {
+        match self {
+            Self::Membership { change, .. } => Some(*change),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    fn member(membership: &str, displayname: Option<&str>) -> MemberEventContent {
+        serde_json::from_value(json!({
+            "membership": membership,
+            "displayname": displayname,
+        }))
+        .unwrap()
+    }
+
+    fn change(
+        content: &MemberEventContent,
+        prev_content: Option<&MemberEventContent>,
+        sender: &str,
+    ) -> Option<StateChange> {
+        let sender = UserId::try_from(sender).unwrap();
+        StateChange::from_member(content, prev_content, &sender, "@bob:example.org")
+    }
+
+    fn membership(change: MembershipChange, name: &str) -> Option<StateChange> {
+        Some(StateChange::Membership {
+            change,
+            name: name.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_from_member() {
+        let joined = member("join", Some("Bob"));
+        let left = member("leave", None);
+        let banned = member("ban", None);
+        let bob = "@bob:example.org";
+        let alice = "@alice:example.org";
+
+        assert_eq!(
+            change(&joined, None, bob),
+            membership(MembershipChange::Joined, "Bob")
+        );
+        assert_eq!(
+            change(&member("join", Some("Robert")), Some(&joined), bob),
+            membership(MembershipChange::ProfileChanged, "Robert")
+        );
+        assert_eq!(
+            change(&left, Some(&joined), bob),
+            membership(MembershipChange::Left, "Bob")
+        );
+        assert_eq!(
+            change(&left, Some(&joined), alice),
+            membership(MembershipChange::Kicked, "Bob")
+        );
+        assert_eq!(
+            change(&banned, Some(&joined), alice),
+            membership(MembershipChange::Banned, "Bob")
+        );
+        assert_eq!(
+            change(&left, Some(&banned), alice),
+            membership(MembershipChange::Unbanned, bob)
+        );
+        assert_eq!(
+            change(&member("invite", Some("")), None, alice),
+            membership(MembershipChange::Invited, bob)
+        );
+        assert_eq!(change(&member("knock", None), None, bob), None);
+    }
+}
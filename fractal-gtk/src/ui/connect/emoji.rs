@@ -0,0 +1,12 @@
+use gtk::prelude::*;
+
+use crate::widgets::EmojiPicker;
+
+use crate::ui::UI;
+
+pub fn connect(ui: &UI) {
+    let view = ui.sventry.view.clone().upcast::<gtk::TextView>();
+    let picker = EmojiPicker::new(view);
+
+    ui.sventry.emoji.set_popover(Some(&picker.popover));
+}
@@ -1,5 +1,6 @@
 mod autocomplete;
 mod directory;
+mod emoji;
 mod headerbar;
 mod invite;
 mod join_room;
@@ -19,6 +20,7 @@ impl UI {
         headerbar::connect(self);
         send::connect(self, app_runtime.clone());
         markdown::connect(self, app_runtime.clone());
+        emoji::connect(self);
         autocomplete::connect(self, app_runtime.clone());
         language::connect(self, app_runtime.clone());
         directory::connect(self, app_runtime.clone());
@@ -0,0 +1,85 @@
+use super::UI;
+use crate::util::diff::{diff, Change};
+use crate::util::i18n::i18n;
+use chrono::prelude::*;
+use glib::markup_escape_text;
+use gtk::prelude::*;
+
+impl UI {
+    /// Shows the versions of an edited message, oldest first, each one with its
+    /// changes from the previous one highlighted.
+    pub fn show_edit_history_dialog(&self, versions: Vec<(DateTime<Local>, String)>) {
+        let dialog = gtk::Dialog::with_buttons(
+            Some(i18n("Edit History").as_str()),
+            Some(&self.main_window),
+            gtk::DialogFlags::MODAL
+                | gtk::DialogFlags::USE_HEADER_BAR
+                | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[],
+        );
+        dialog.set_default_size(400, 480);
+
+        let listbox = gtk::ListBox::new();
+        listbox.set_selection_mode(gtk::SelectionMode::None);
+        let mut previous: Option<&str> = None;
+        for (idx, (date, body)) in versions.iter().enumerate() {
+            let date_format = if idx == 0 {
+                i18n("Sent %c")
+            } else {
+                i18n("Edited %c")
+            };
+            let date_text = date.format(&date_format).to_string();
+            let date_label = gtk::Label::new(Some(date_text.as_str()));
+            date_label.set_xalign(0.0);
+            date_label.get_style_context().add_class("dim-label");
+
+            let markup = match previous {
+                Some(previous) => diff_markup(previous, body),
+                None => markup_escape_text(body).to_string(),
+            };
+            let body_label = gtk::Label::new(None);
+            body_label.set_markup(&markup);
+            body_label.set_xalign(0.0);
+            body_label.set_line_wrap(true);
+            body_label.set_line_wrap_mode(pango::WrapMode::WordChar);
+            body_label.set_selectable(true);
+
+            let row = gtk::Box::new(gtk::Orientation::Vertical, 6);
+            row.set_margin_top(12);
+            row.set_margin_bottom(12);
+            row.set_margin_start(12);
+            row.set_margin_end(12);
+            row.pack_start(&date_label, false, false, 0);
+            row.pack_start(&body_label, false, false, 0);
+            listbox.add(&row);
+
+            previous = Some(body);
+        }
+
+        let scroll = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scroll.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scroll.set_vexpand(true);
+        scroll.add(&listbox);
+        scroll.show_all();
+        dialog.get_content_area().add(&scroll);
+        dialog.present();
+    }
+}
+
+// The new text, with the words added and removed since the old one highlighted
+fn diff_markup(old: &str, new: &str) -> String {
+    diff(old, new)
+        .into_iter()
+        .map(|change| match change {
+            Change::Same(word) => markup_escape_text(word).to_string(),
+            Change::Added(word) => format!(
+                "<span background=\"#33d17a\" bgalpha=\"30%\">{}</span>",
+                markup_escape_text(word)
+            ),
+            Change::Removed(word) => format!(
+                "<span background=\"#e01b24\" bgalpha=\"30%\" strikethrough=\"true\">{}</span>",
+                markup_escape_text(word)
+            ),
+        })
+        .collect()
+}
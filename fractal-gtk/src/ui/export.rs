@@ -0,0 +1,195 @@
+use super::go_to_date::selected_day;
+use super::UI;
+use crate::backend::export::ExportFormat;
+use crate::util::i18n::{i18n, ni18n_f};
+use crate::APPOP;
+use chrono::prelude::*;
+use glib::clone;
+use gtk::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+impl UI {
+    pub fn show_export_dialog(&self, room_name: &str) {
+        let dialog = gtk::Dialog::with_buttons(
+            Some(i18n("Export Chat").as_str()),
+            Some(&self.main_window),
+            gtk::DialogFlags::MODAL
+                | gtk::DialogFlags::USE_HEADER_BAR
+                | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[],
+        );
+
+        let format = gtk::ComboBoxText::new();
+        format.append(Some("html"), &i18n("Web Page with Media"));
+        format.append(Some("json"), &i18n("Event JSON Lines"));
+        format.append(Some("text"), &i18n("Plain Text"));
+        format.set_active_id(Some("html"));
+
+        // The range starts on the first day of the current month and ends today
+        let since = gtk::Calendar::new();
+        since.select_day(1);
+        let until = gtk::Calendar::new();
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(12);
+        grid.set_margin_top(12);
+        grid.set_margin_bottom(12);
+        grid.set_margin_start(12);
+        grid.set_margin_end(12);
+        let format_label = gtk::Label::new(Some(i18n("Format").as_str()));
+        format_label.set_halign(gtk::Align::End);
+        grid.attach(&format_label, 0, 0, 1, 1);
+        grid.attach(&format, 1, 0, 2, 1);
+        let since_label = gtk::Label::new(Some(i18n("From").as_str()));
+        since_label.set_halign(gtk::Align::Start);
+        let until_label = gtk::Label::new(Some(i18n("To").as_str()));
+        until_label.set_halign(gtk::Align::Start);
+        grid.attach(&since_label, 1, 1, 1, 1);
+        grid.attach(&until_label, 2, 1, 1, 1);
+        grid.attach(&since, 1, 2, 1, 1);
+        grid.attach(&until, 2, 2, 1, 1);
+        grid.show_all();
+        dialog.get_content_area().add(&grid);
+        dialog.present();
+
+        if let Some(hbar) = dialog.get_header_bar() {
+            let headerbar = hbar.downcast::<gtk::HeaderBar>().unwrap();
+            let closebtn = gtk::Button::with_label(i18n("Cancel").as_str());
+            let okbtn = gtk::Button::with_label(i18n("Export").as_str());
+            okbtn.get_style_context().add_class("suggested-action");
+
+            headerbar.set_show_close_button(false);
+            headerbar.pack_start(&closebtn);
+            headerbar.pack_end(&okbtn);
+            headerbar.show_all();
+
+            closebtn.connect_clicked(clone!(@strong dialog => move |_| {
+                dialog.close();
+            }));
+            let room_name = room_name.replace('/', "-");
+            okbtn.connect_clicked(clone!(@strong dialog => move |_| {
+                let format = match format.get_active_id().as_deref() {
+                    Some("json") => ExportFormat::Json,
+                    Some("text") => ExportFormat::Text,
+                    _ => ExportFormat::Html,
+                };
+                let (since, until) = match (selected_day(&since), selected_day(&until)) {
+                    (Some(since), Some(until)) => (since.min(until), since.max(until)),
+                    _ => return,
+                };
+                // The last day is exported as a whole
+                let range = (since.and_hms(0, 0, 0), until.succ().and_hms(0, 0, 0));
+
+                let file_chooser = gtk::FileChooserNative::new(
+                    Some(i18n("Export Chat As").as_str()),
+                    Some(&dialog),
+                    gtk::FileChooserAction::Save,
+                    Some(i18n("_Export").as_str()),
+                    Some(i18n("_Cancel").as_str()),
+                );
+                file_chooser.set_do_overwrite_confirmation(true);
+                file_chooser.set_current_folder(dirs::download_dir().unwrap_or_default());
+                file_chooser.set_current_name(&format!("{}.{}", room_name, format.extension()));
+                if file_chooser.run() != gtk::ResponseType::Accept {
+                    return;
+                }
+
+                if let Some(path) = file_chooser.get_filename() {
+                    APPOP!(export_room, (format, range, path));
+                    dialog.close();
+                }
+            }));
+
+            okbtn.grab_focus();
+        }
+    }
+
+    pub fn show_export_progress(&mut self, cancel: Arc<AtomicBool>) {
+        let dialog = gtk::Dialog::with_buttons(
+            Some(i18n("Exporting Chat").as_str()),
+            Some(&self.main_window),
+            gtk::DialogFlags::MODAL
+                | gtk::DialogFlags::USE_HEADER_BAR
+                | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[],
+        );
+        dialog.set_default_size(360, -1);
+
+        let label = gtk::Label::new(Some(i18n("Loading messages…").as_str()));
+        label.set_halign(gtk::Align::Start);
+        let progress = gtk::ProgressBar::new();
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_margin_top(12);
+        content.set_margin_bottom(12);
+        content.set_margin_start(12);
+        content.set_margin_end(12);
+        content.pack_start(&label, false, false, 0);
+        content.pack_start(&progress, false, false, 0);
+        content.show_all();
+        dialog.get_content_area().add(&content);
+
+        // Closing the dialog in any way stops the export, if it isn't done yet
+        dialog.connect_destroy(move |_| {
+            cancel.store(true, Ordering::Relaxed);
+        });
+
+        let button = gtk::Button::with_label(i18n("Cancel").as_str());
+        button.connect_clicked(clone!(@weak dialog => move |_| {
+            dialog.close();
+        }));
+        if let Some(hbar) = dialog.get_header_bar() {
+            let headerbar = hbar.downcast::<gtk::HeaderBar>().unwrap();
+            headerbar.set_show_close_button(false);
+            headerbar.pack_start(&button);
+            headerbar.show_all();
+        }
+        dialog.present();
+
+        self.export = Some(ExportView {
+            dialog,
+            label,
+            progress,
+            button,
+        });
+    }
+}
+
+// The progress of an export of the history of a room
+pub struct ExportView {
+    dialog: gtk::Dialog,
+    label: gtk::Label,
+    progress: gtk::ProgressBar,
+    button: gtk::Button,
+}
+
+impl ExportView {
+    pub fn set_progress(&self, fraction: f64, count: usize) {
+        let text = ni18n_f(
+            "Found {} message",
+            "Found {} messages",
+            count as u32,
+            &[&count.to_string()],
+        );
+        self.label.set_text(&text);
+        self.progress.set_fraction(fraction);
+    }
+
+    pub fn finish(&self, count: usize) {
+        let text = ni18n_f(
+            "Exported {} message",
+            "Exported {} messages",
+            count as u32,
+            &[&count.to_string()],
+        );
+        self.label.set_text(&text);
+        self.progress.set_fraction(1.0);
+        self.button.set_label(&i18n("Close"));
+    }
+
+    pub fn close(&self) {
+        self.dialog.close();
+    }
+}
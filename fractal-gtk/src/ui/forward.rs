@@ -0,0 +1,119 @@
+use super::UI;
+use crate::util::i18n::i18n;
+use crate::APPOP;
+use glib::clone;
+use gtk::prelude::*;
+use matrix_sdk::identifiers::{EventId, RoomId};
+use std::cell::Cell;
+use std::rc::Rc;
+
+impl UI {
+    pub fn show_forward_dialog(
+        &self,
+        room_id: RoomId,
+        event_id: EventId,
+        rooms: Vec<(RoomId, String)>,
+    ) {
+        let dialog = gtk::Dialog::with_buttons(
+            Some(i18n("Forward Message").as_str()),
+            Some(&self.main_window),
+            gtk::DialogFlags::MODAL
+                | gtk::DialogFlags::USE_HEADER_BAR
+                | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[],
+        );
+        dialog.set_default_size(360, 480);
+
+        let search = gtk::SearchEntry::new();
+        search.set_placeholder_text(Some(i18n("Search for rooms").as_str()));
+
+        let listbox = gtk::ListBox::new();
+        listbox.set_selection_mode(gtk::SelectionMode::None);
+        let scroll = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scroll.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scroll.set_shadow_type(gtk::ShadowType::In);
+        scroll.set_vexpand(true);
+        scroll.add(&listbox);
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_margin_top(12);
+        content.set_margin_bottom(12);
+        content.set_margin_start(12);
+        content.set_margin_end(12);
+        content.pack_start(&search, false, false, 0);
+        content.pack_start(&scroll, true, true, 0);
+        content.show_all();
+        dialog.get_content_area().add(&content);
+
+        // The rows are in the same order as the rooms, so they're found by their index
+        let rooms: Vec<(RoomId, String, gtk::CheckButton)> = rooms
+            .into_iter()
+            .map(|(id, name)| {
+                let check = gtk::CheckButton::with_label(&name);
+                check.set_margin_top(6);
+                check.set_margin_bottom(6);
+                check.set_margin_start(6);
+                check.set_margin_end(6);
+                check.show();
+                listbox.add(&check);
+                (id, name.to_lowercase(), check)
+            })
+            .collect();
+        let rooms = Rc::new(rooms);
+
+        listbox.set_filter_func(Some(Box::new(
+            clone!(@strong rooms, @weak search => @default-return true, move |row| {
+                let term = search.get_text().to_lowercase();
+                rooms
+                    .get(row.get_index() as usize)
+                    .map_or(true, |(_, name, _)| name.contains(term.as_str()))
+            }),
+        )));
+        search.connect_search_changed(clone!(@weak listbox => move |_| {
+            listbox.invalidate_filter();
+        }));
+
+        dialog.present();
+
+        if let Some(hbar) = dialog.get_header_bar() {
+            let headerbar = hbar.downcast::<gtk::HeaderBar>().unwrap();
+            let closebtn = gtk::Button::with_label(i18n("Cancel").as_str());
+            let okbtn = gtk::Button::with_label(i18n("Forward").as_str());
+            okbtn.get_style_context().add_class("suggested-action");
+            okbtn.set_sensitive(false);
+
+            headerbar.set_show_close_button(false);
+            headerbar.pack_start(&closebtn);
+            headerbar.pack_end(&okbtn);
+            headerbar.show_all();
+
+            // Forwarding is possible once a room is picked
+            let picked = Rc::new(Cell::new(0));
+            for (_, _, check) in rooms.iter() {
+                check.connect_toggled(clone!(@strong picked, @weak okbtn => move |check| {
+                    if check.get_active() {
+                        picked.set(picked.get() + 1);
+                    } else {
+                        picked.set(picked.get() - 1);
+                    }
+                    okbtn.set_sensitive(picked.get() > 0);
+                }));
+            }
+
+            closebtn.connect_clicked(clone!(@strong dialog => move |_| {
+                dialog.close();
+            }));
+            okbtn.connect_clicked(clone!(@strong rooms, @strong dialog => move |_| {
+                let targets: Vec<RoomId> = rooms
+                    .iter()
+                    .filter(|(_, _, check)| check.get_active())
+                    .map(|(id, _, _)| id.clone())
+                    .collect();
+                APPOP!(forward_message, (room_id, event_id, targets));
+                dialog.close();
+            }));
+        }
+
+        search.grab_focus();
+    }
+}
@@ -0,0 +1,67 @@
+use super::UI;
+use crate::util::i18n::i18n;
+use crate::APPOP;
+use chrono::prelude::*;
+use glib::clone;
+use gtk::prelude::*;
+
+impl UI {
+    pub fn show_go_to_date_dialog(&self) {
+        let dialog = gtk::Dialog::with_buttons(
+            Some(i18n("Go to Date").as_str()),
+            Some(&self.main_window),
+            gtk::DialogFlags::MODAL
+                | gtk::DialogFlags::USE_HEADER_BAR
+                | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[],
+        );
+
+        // The calendar starts on the current day
+        let calendar = gtk::Calendar::new();
+        calendar.set_margin_top(12);
+        calendar.set_margin_bottom(12);
+        calendar.set_margin_start(12);
+        calendar.set_margin_end(12);
+        calendar.show();
+        dialog.get_content_area().add(&calendar);
+        dialog.present();
+
+        if let Some(hbar) = dialog.get_header_bar() {
+            let headerbar = hbar.downcast::<gtk::HeaderBar>().unwrap();
+            let closebtn = gtk::Button::with_label(i18n("Cancel").as_str());
+            let okbtn = gtk::Button::with_label(i18n("Go").as_str());
+            okbtn.get_style_context().add_class("suggested-action");
+
+            headerbar.set_show_close_button(false);
+            headerbar.pack_start(&closebtn);
+            headerbar.pack_end(&okbtn);
+            headerbar.show_all();
+
+            closebtn.connect_clicked(clone!(@strong dialog => move |_| {
+                dialog.close();
+            }));
+            okbtn.connect_clicked(clone!(@strong calendar, @strong dialog => move |_| {
+                if let Some(ts) = start_of_day(&calendar) {
+                    APPOP!(go_to_date, (ts));
+                }
+                dialog.close();
+            }));
+            calendar.connect_day_selected_double_click(clone!(@weak okbtn => move |_| {
+                okbtn.clicked();
+            }));
+
+            okbtn.grab_focus();
+        }
+    }
+}
+
+// The local midnight of the selected day, in milliseconds since the UNIX epoch
+fn start_of_day(calendar: &gtk::Calendar) -> Option<i64> {
+    Some(selected_day(calendar)?.and_hms(0, 0, 0).timestamp_millis())
+}
+
+pub(super) fn selected_day(calendar: &gtk::Calendar) -> Option<Date<Local>> {
+    // Months are counted from 0 by the calendar
+    let (year, month, day) = calendar.get_date();
+    Local.ymd_opt(year as i32, month + 1, day).single()
+}
@@ -3,19 +3,26 @@ use crate::model::{member::Member, message::Message};
 use crate::util::i18n::i18n;
 use crate::widgets::{self, SVEntry};
 use gtk::prelude::*;
+use matrix_sdk::identifiers::{EventId, UserId};
 
 pub mod about;
 pub mod account;
 pub mod attach;
 pub mod connect;
 pub mod directory;
+pub mod edit_history;
+pub mod export;
+pub mod forward;
+pub mod go_to_date;
 pub mod invite;
 pub mod media_viewer;
 pub mod member;
 pub mod notify;
 pub mod room_settings;
+pub mod search;
 pub mod start_chat;
 pub mod state;
+pub mod thread;
 pub mod user;
 
 pub struct UI {
@@ -29,6 +36,9 @@ pub struct UI {
     pub history: Option<widgets::RoomHistory>,
     pub roomlist: widgets::RoomList,
     pub media_viewer: Option<widgets::MediaViewer>,
+    pub thread: Option<thread::ThreadView>,
+    pub search: Option<search::SearchView>,
+    pub export: Option<export::ExportView>,
     pub room_back_history: Vec<AppState>,
     pub invite_list: Vec<(Member, gtk::TextChildAnchor)>,
     pub leaflet: libhandy::Leaflet,
@@ -138,6 +148,9 @@ impl UI {
             history: None,
             roomlist: widgets::RoomList::new(None, None),
             media_viewer: None,
+            thread: None,
+            search: None,
+            export: None,
             room_back_history: vec![],
             invite_list: vec![],
             leaflet,
@@ -172,10 +185,41 @@ pub struct MessageContent {
     pub mtype: RowType,
     pub highlights: Vec<String>,
     pub redactable: bool,
+    pub editable: bool,
+    pub reactions: Vec<ReactionContent>,
+    pub reply: Option<ReplyContent>,
+    // The number of loaded replies in the thread started by this message
+    pub thread_replies: usize,
+    // The other users who read up to this message, the latest first
+    pub receipts: Vec<ReceiptContent>,
     pub last_viewed: bool,
     pub widget: Option<widgets::MessageBox>,
 }
 
+#[derive(Debug, Clone)]
+pub struct ReactionContent {
+    pub key: String,
+    // Display names of the users who reacted with this key
+    pub senders: Vec<String>,
+    pub reacted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceiptContent {
+    pub uid: UserId,
+    pub name: Option<String>,
+    // When the user read the message, as a UNIX timestamp
+    pub ts: i64,
+}
+
+// The replied message, whose content is only known if it's loaded
+#[derive(Debug, Clone)]
+pub struct ReplyContent {
+    pub id: EventId,
+    pub sender_name: Option<String>,
+    pub body: Option<String>,
+}
+
 /* To-Do: this should be moved to a file collecting all structs used in the UI */
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum RowType {
@@ -188,4 +232,6 @@ pub enum RowType {
     Video,
     File,
     Emoji,
+    // A change of the room state, e.g. a member joining
+    State,
 }
@@ -0,0 +1,340 @@
+use super::{MessageContent, UI};
+use crate::util::i18n::{i18n, ni18n_f};
+use crate::APPOP;
+use glib::clone;
+use gtk::prelude::*;
+use matrix_sdk::identifiers::RoomId;
+
+impl UI {
+    pub fn create_search_view(&mut self, room_id: Option<RoomId>) {
+        let panel = SearchView::new(room_id);
+
+        // remove old panel
+        if let Some(widget) = self.subview_stack.get_child_by_name("search") {
+            self.subview_stack.remove(&widget);
+        }
+
+        self.subview_stack.add_named(&panel.page, "search");
+
+        self.search = Some(panel);
+    }
+}
+
+// A message matching the search, with the name of the room it was sent in
+#[derive(Debug, Clone)]
+pub struct SearchResultContent {
+    pub room_id: RoomId,
+    pub room_name: Option<String>,
+    pub message: MessageContent,
+}
+
+// The messages found on the server, shown instead of the room
+#[derive(Debug, Clone)]
+pub struct SearchView {
+    // The room the search is scoped to, `None` if there is no active room
+    pub room_id: Option<RoomId>,
+    pub term: String,
+    // The token to load more results from, `None` once all results are loaded
+    pub next_batch: Option<String>,
+    highlights: Vec<String>,
+    page: gtk::Box,
+    entry: gtk::SearchEntry,
+    room_button: gtk::RadioButton,
+    listbox: gtk::ListBox,
+    status: gtk::Label,
+    load_more: gtk::Button,
+}
+
+impl SearchView {
+    pub fn new(room_id: Option<RoomId>) -> SearchView {
+        let builder = gtk::Builder::new();
+
+        builder
+            .add_from_resource("/org/gnome/Fractal/ui/search.ui")
+            .expect("Can't load ui file: search.ui");
+
+        let page = builder
+            .get_object::<gtk::Box>("search_box")
+            .expect("Can't find search_box in ui file.");
+        let entry = builder
+            .get_object::<gtk::SearchEntry>("search_entry")
+            .expect("Can't find search_entry in ui file.");
+        let room_button = builder
+            .get_object::<gtk::RadioButton>("search_room_button")
+            .expect("Can't find search_room_button in ui file.");
+        let all_button = builder
+            .get_object::<gtk::RadioButton>("search_all_button")
+            .expect("Can't find search_all_button in ui file.");
+        let listbox = builder
+            .get_object::<gtk::ListBox>("search_listbox")
+            .expect("Can't find search_listbox in ui file.");
+        let status = builder
+            .get_object::<gtk::Label>("search_status_label")
+            .expect("Can't find search_status_label in ui file.");
+        let load_more = builder
+            .get_object::<gtk::Button>("search_load_more_button")
+            .expect("Can't find search_load_more_button in ui file.");
+
+        // Without an open room, only searching all the rooms makes sense
+        if room_id.is_none() {
+            all_button.set_active(true);
+            room_button.set_sensitive(false);
+        }
+
+        entry.connect_activate(|entry| {
+            let term = entry.get_text().trim().to_string();
+            APPOP!(search_messages, (term));
+        });
+        room_button.connect_toggled(clone!(@weak entry => move |_| {
+            entry.activate();
+        }));
+        load_more.connect_clicked(|button| {
+            button.set_sensitive(false);
+            APPOP!(load_more_search_results);
+        });
+
+        SearchView {
+            room_id,
+            term: String::new(),
+            next_batch: None,
+            highlights: vec![],
+            page,
+            entry,
+            room_button,
+            listbox,
+            status,
+            load_more,
+        }
+    }
+
+    /// The room to search in, `None` to search in all the joined rooms.
+    pub fn scope(&self) -> Option<RoomId> {
+        self.room_id
+            .clone()
+            .filter(|_| self.room_button.get_active())
+    }
+
+    /// Removes the results of the previous search, while the new one is running.
+    pub fn clear(&mut self, term: String) {
+        for row in self.listbox.get_children() {
+            self.listbox.remove(&row);
+        }
+
+        self.term = term;
+        self.next_batch = None;
+        self.highlights = vec![];
+        self.status.set_text(&i18n("Searching…"));
+        self.status.set_visible(!self.term.is_empty());
+        self.load_more.set_visible(false);
+    }
+
+    /// Adds a page of results below the ones already shown.
+    pub fn add_results(
+        &mut self,
+        results: Vec<SearchResultContent>,
+        highlights: Vec<String>,
+        count: Option<u64>,
+        next_batch: Option<String>,
+    ) {
+        for word in highlights {
+            if !self.highlights.contains(&word) {
+                self.highlights.push(word);
+            }
+        }
+        // Servers may not tell which words matched
+        let mut words = self.highlights.clone();
+        words.extend(self.term.split_whitespace().map(String::from));
+
+        let show_room = self.scope().is_none();
+        for result in results {
+            let row = build_result_row(&result, &words, show_room);
+            self.listbox.add(&row);
+        }
+
+        let shown = self.listbox.get_children().len();
+        if shown == 0 {
+            self.status.set_text(&i18n("No messages found"));
+        } else {
+            let count = count.map_or(shown, |count| count as usize);
+            self.status.set_text(&ni18n_f(
+                "{} message found",
+                "{} messages found",
+                count as u32,
+                &[&count.to_string()],
+            ));
+        }
+        self.status.set_visible(true);
+
+        self.next_batch = next_batch;
+        self.load_more.set_visible(self.next_batch.is_some());
+        self.load_more.set_sensitive(true);
+    }
+
+    /// Shows that loading the results failed, so the search can be started again.
+    pub fn set_failed(&self) {
+        self.status.set_visible(false);
+        self.load_more.set_visible(self.next_batch.is_some());
+        self.load_more.set_sensitive(true);
+    }
+
+    pub fn grab_focus(&self) {
+        self.entry.grab_focus();
+    }
+}
+
+fn build_result_row(
+    result: &SearchResultContent,
+    words: &[String],
+    show_room: bool,
+) -> gtk::ListBoxRow {
+    let msg = &result.message;
+
+    let sender = gtk::Label::new(Some(
+        msg.sender_name
+            .as_deref()
+            .unwrap_or_else(|| msg.msg.sender.as_str()),
+    ));
+    sender.set_ellipsize(pango::EllipsizeMode::End);
+    sender.set_halign(gtk::Align::Start);
+    sender.get_style_context().add_class("username");
+
+    let date = gtk::Label::new(Some(
+        &msg.msg.date.format(&i18n("%B %e, %Y, %R")).to_string(),
+    ));
+    date.set_halign(gtk::Align::End);
+    date.set_hexpand(true);
+    date.get_style_context().add_class("timestamp");
+    date.get_style_context().add_class("dim-label");
+
+    let header = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    header.pack_start(&sender, false, false, 0);
+    if show_room {
+        let name = result
+            .room_name
+            .clone()
+            .unwrap_or_else(|| result.room_id.to_string());
+        let room = gtk::Label::new(Some(&name));
+        room.set_ellipsize(pango::EllipsizeMode::End);
+        room.get_style_context().add_class("dim-label");
+        header.pack_start(&room, false, false, 0);
+    }
+    header.pack_end(&date, false, false, 0);
+
+    let body = gtk::Label::new(None);
+    body.set_markup(&highlight_markup(&msg.msg.body, words));
+    body.set_line_wrap(true);
+    body.set_line_wrap_mode(pango::WrapMode::WordChar);
+    body.set_xalign(0.0);
+    body.set_selectable(true);
+    body.get_style_context().add_class("msg-body");
+
+    let jump = gtk::Button::with_label(&i18n("Jump to Message"));
+    jump.set_halign(gtk::Align::End);
+    jump.get_style_context().add_class("flat");
+    if let Some(event_id) = msg.msg.id.clone() {
+        let room_id = result.room_id.clone();
+        jump.connect_clicked(move |_| {
+            let room_id = room_id.clone();
+            let event_id = event_id.clone();
+            APPOP!(jump_to_search_result, (room_id, event_id));
+        });
+    } else {
+        jump.set_sensitive(false);
+    }
+
+    let bx = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    bx.pack_start(&header, false, false, 0);
+    bx.pack_start(&body, false, false, 0);
+    bx.pack_start(&jump, false, false, 0);
+    bx.set_margin_top(12);
+    bx.set_margin_start(12);
+    bx.set_margin_end(12);
+
+    let row = gtk::ListBoxRow::new();
+    row.set_activatable(false);
+    row.add(&bx);
+    row.get_style_context().add_class("search-result");
+    row.show_all();
+
+    row
+}
+
+// Escapes the text for Pango markup, making the matching words bold
+fn highlight_markup(text: &str, words: &[String]) -> String {
+    let words: Vec<Vec<char>> = words
+        .iter()
+        .filter(|word| !word.is_empty())
+        .map(|word| word.chars().flat_map(char::to_lowercase).collect())
+        .collect();
+
+    let mut markup = String::with_capacity(text.len());
+    let mut plain = String::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(idx, _)) = chars.peek() {
+        let matched = words.iter().find_map(|word| match_len(&text[idx..], word));
+        match matched {
+            Some(len) => {
+                markup.push_str(&glib::markup_escape_text(&plain));
+                plain.clear();
+                let end = idx + len;
+                markup.push_str("<b>");
+                markup.push_str(&glib::markup_escape_text(&text[idx..end]));
+                markup.push_str("</b>");
+                while chars.peek().map_or(false, |&(i, _)| i < end) {
+                    chars.next();
+                }
+            }
+            None => {
+                if let Some((_, c)) = chars.next() {
+                    plain.push(c);
+                }
+            }
+        }
+    }
+    markup.push_str(&glib::markup_escape_text(&plain));
+
+    markup
+}
+
+// The length in bytes of the start of the text matching the lowercase word, if it does
+fn match_len(text: &str, word: &[char]) -> Option<usize> {
+    let mut lowercase = vec![];
+    for (idx, c) in text.char_indices() {
+        if lowercase.len() >= word.len() {
+            return Some(idx).filter(|_| lowercase == word);
+        }
+        lowercase.extend(c.to_lowercase());
+        if !word.starts_with(&lowercase) {
+            return None;
+        }
+    }
+
+    Some(text.len()).filter(|_| lowercase == word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_markup() {
+        let words = vec!["hello".to_string(), "World".to_string(), String::new()];
+        assert_eq!(
+            highlight_markup("Hello <World> & HELLO!", &words),
+            "<b>Hello</b> &lt;<b>World</b>&gt; &amp; <b>HELLO</b>!"
+        );
+        assert_eq!(highlight_markup("Hell", &words), "Hell");
+        assert_eq!(
+            highlight_markup("Wörld", &["wörld".to_string()]),
+            "<b>Wörld</b>"
+        );
+    }
+
+    #[test]
+    fn test_match_len() {
+        let word: Vec<char> = "wörld".chars().collect();
+        assert_eq!(match_len("WÖRLD peace", &word), Some(6));
+        assert_eq!(match_len("wörl", &word), None);
+        assert_eq!(match_len("world", &word), None);
+    }
+}
@@ -39,7 +39,7 @@ impl UI {
             .set_visible_child_name(state);
     }
 
-    pub fn set_chat_state(&mut self, msg: Option<(&str, i32)>) {
+    pub fn set_chat_state(&mut self, in_room: bool) {
         let deck = self
             .builder
             .get_object::<libhandy::Deck>("main_deck")
@@ -53,18 +53,15 @@ impl UI {
             .get_object::<libhandy::HeaderBar>("room_header_bar")
             .expect("Can't find room_header_bar in ui file.");
 
-        match msg {
-            None => {
-                self.set_state_no_room(&headerbar);
-                self.leaflet.navigate(libhandy::NavigationDirection::Back);
-                stack.set_visible_child_name("noroom");
-            }
-            Some((msg_text, msg_number)) => {
-                self.set_state_room(&headerbar, msg_text, msg_number);
-                self.leaflet
-                    .navigate(libhandy::NavigationDirection::Forward);
-                stack.set_visible_child_name("room_view");
-            }
+        if in_room {
+            self.set_state_room(&headerbar);
+            self.leaflet
+                .navigate(libhandy::NavigationDirection::Forward);
+            stack.set_visible_child_name("room_view");
+        } else {
+            self.set_state_no_room(&headerbar);
+            self.leaflet.navigate(libhandy::NavigationDirection::Back);
+            stack.set_visible_child_name("noroom");
         }
 
         if deck
@@ -75,19 +72,12 @@ impl UI {
         }
     }
 
-    fn set_state_room(&self, headerbar: &libhandy::HeaderBar, msg_text: &str, msg_number: i32) {
+    fn set_state_room(&self, headerbar: &libhandy::HeaderBar) {
         for ch in headerbar.get_children().iter() {
             ch.show();
         }
 
         self.sventry.view.grab_focus();
-
-        if let Some(buffer) = self.sventry.view.get_buffer() {
-            buffer.set_text(msg_text);
-
-            let iter = buffer.get_iter_at_offset(msg_number);
-            buffer.place_cursor(&iter);
-        }
     }
 
     // WORKAROUND this is needed because NoRoom isn't a real app state
@@ -0,0 +1,125 @@
+use super::{MessageContent, UI};
+use crate::actions;
+use crate::app::AppRuntime;
+use crate::appop::UserInfoCache;
+use crate::widgets;
+use crate::APPOP;
+use glib::clone;
+use gtk::prelude::*;
+use matrix_sdk::identifiers::{EventId, RoomId};
+use matrix_sdk::Client as MatrixClient;
+
+impl UI {
+    pub fn create_thread_view(
+        &mut self,
+        app_runtime: AppRuntime,
+        room_id: RoomId,
+        thread_root: EventId,
+    ) {
+        let panel = ThreadView::new(room_id, thread_root);
+
+        let actions = actions::Message::new(app_runtime);
+        panel.listbox.insert_action_group("message", Some(&actions));
+
+        // remove old panel
+        if let Some(widget) = self.subview_stack.get_child_by_name("thread") {
+            self.subview_stack.remove(&widget);
+        }
+
+        self.subview_stack.add_named(&panel.page, "thread");
+
+        self.thread = Some(panel);
+    }
+}
+
+// The messages of a thread, shown next to the room
+#[derive(Debug, Clone)]
+pub struct ThreadView {
+    pub room_id: RoomId,
+    pub thread_root: EventId,
+    // The token to load older replies from, `None` once the whole thread is loaded
+    pub next_batch: Option<String>,
+    page: gtk::Box,
+    listbox: gtk::ListBox,
+    entry: gtk::Entry,
+    load_more: gtk::Button,
+}
+
+impl ThreadView {
+    pub fn new(room_id: RoomId, thread_root: EventId) -> ThreadView {
+        let builder = gtk::Builder::new();
+
+        builder
+            .add_from_resource("/org/gnome/Fractal/ui/thread.ui")
+            .expect("Can't load ui file: thread.ui");
+
+        let page = builder
+            .get_object::<gtk::Box>("thread_box")
+            .expect("Can't find thread_box in ui file.");
+        let listbox = builder
+            .get_object::<gtk::ListBox>("thread_listbox")
+            .expect("Can't find thread_listbox in ui file.");
+        let entry = builder
+            .get_object::<gtk::Entry>("thread_entry")
+            .expect("Can't find thread_entry in ui file.");
+        let send = builder
+            .get_object::<gtk::Button>("thread_send_button")
+            .expect("Can't find thread_send_button in ui file.");
+        let load_more = builder
+            .get_object::<gtk::Button>("thread_load_more_button")
+            .expect("Can't find thread_load_more_button in ui file.");
+
+        entry.connect_activate(|entry| {
+            let body = entry.get_text().to_string();
+            entry.set_text("");
+            APPOP!(send_thread_message, (body));
+        });
+        send.connect_clicked(clone!(@weak entry => move |_| {
+            entry.activate();
+        }));
+        load_more.connect_clicked(|button| {
+            button.set_sensitive(false);
+            APPOP!(load_thread_replies);
+        });
+
+        ThreadView {
+            room_id,
+            thread_root,
+            next_batch: None,
+            page,
+            listbox,
+            entry,
+            load_more,
+        }
+    }
+
+    /// Replaces the shown messages, the first one being the root of the thread.
+    pub fn show_messages(
+        &self,
+        session_client: MatrixClient,
+        user_info_cache: UserInfoCache,
+        messages: Vec<MessageContent>,
+    ) {
+        for row in self.listbox.get_children() {
+            self.listbox.remove(&row);
+        }
+
+        for msg in messages {
+            let mb = widgets::MessageBox::create(
+                session_client.clone(),
+                user_info_cache.clone(),
+                &msg,
+                true,
+                false,
+            );
+            self.listbox.add(mb.get_widget());
+        }
+
+        self.load_more.set_visible(self.next_batch.is_some());
+        self.load_more.set_sensitive(true);
+    }
+
+    pub fn grab_focus(&self) {
+        self.entry.grab_focus();
+    }
+}
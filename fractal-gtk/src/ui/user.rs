@@ -82,3 +82,35 @@ impl UI {
         eb.connect_button_press_event(move |_, _| Inhibit(false));
     }
 }
+
+/// Shows the avatar, name and ID of a user in a popover pointing to a widget,
+/// such as the pill of a user mentioned in a message.
+pub fn show_member_info(
+    relative_to: &gtk::Widget,
+    session_client: MatrixClient,
+    user_info_cache: UserInfoCache,
+    user_id: UserId,
+    username: Option<String>,
+) {
+    let avatar = widgets::Avatar::avatar_new(Some(40));
+    let data = avatar.circle(user_id.to_string(), username.clone(), 40, None, None);
+    download_to_cache(session_client, user_info_cache, user_id.clone(), data);
+
+    let name = gtk::Label::new(username.as_deref());
+    let uid = gtk::Label::new(Some(user_id.as_ref()));
+    uid.get_style_context().add_class("member-uid");
+    uid.set_selectable(true);
+
+    let info = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    info.set_border_width(12);
+    info.pack_start(&avatar, false, false, 0);
+    if username.is_some() {
+        info.pack_start(&name, false, false, 0);
+    }
+    info.pack_start(&uid, false, false, 0);
+    info.show_all();
+
+    let popover = gtk::Popover::new(Some(relative_to));
+    popover.add(&info);
+    popover.popup();
+}
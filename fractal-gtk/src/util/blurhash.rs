@@ -0,0 +1,303 @@
+//! Compact placeholders for images, see https://blurha.sh
+//!
+//! The hash is sent with media in the `xyz.amorgan.blurhash` field of their info.
+//! https://github.com/matrix-org/matrix-doc/pull/2448
+
+use gdk_pixbuf::{Colorspace, Pixbuf};
+use std::f64::consts::PI;
+
+const BASE83: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+// Encoding larger images only takes longer, the hash has the same precision
+const ENCODE_SIZE: i32 = 64;
+// Placeholders are decoded at this width and scaled up, since they're blurry anyway
+const DECODE_SIZE: i32 = 32;
+
+/// Computes the hash of packed RGB pixels with `components_x` × `components_y`
+/// components, from 1 to 9 each.
+pub fn encode(
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    components_x: usize,
+    components_y: usize,
+) -> Option<String> {
+    let components_valid = |c| (1..=9).contains(&c);
+    if !components_valid(components_x)
+        || !components_valid(components_y)
+        || width == 0
+        || height == 0
+        || rgb.len() < width * height * 3
+    {
+        return None;
+    }
+
+    let mut factors = Vec::with_capacity(components_x * components_y);
+    for j in 0..components_y {
+        for i in 0..components_x {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0; 3];
+            for y in 0..height {
+                for x in 0..width {
+                    let basis = normalisation
+                        * (PI * i as f64 * x as f64 / width as f64).cos()
+                        * (PI * j as f64 * y as f64 / height as f64).cos();
+                    let pixel = &rgb[(y * width + x) * 3..];
+                    for (channel, value) in factor.iter_mut().enumerate() {
+                        *value += basis * srgb_to_linear(pixel[channel]);
+                    }
+                }
+            }
+            let scale = 1.0 / (width * height) as f64;
+            factors.push([factor[0] * scale, factor[1] * scale, factor[2] * scale]);
+        }
+    }
+
+    let (dc, ac) = factors.split_first()?;
+    let mut hash = String::new();
+    encode83(
+        ((components_x - 1) + (components_y - 1) * 9) as u32,
+        1,
+        &mut hash,
+    );
+
+    let maximum_value = if ac.is_empty() {
+        encode83(0, 1, &mut hash);
+        1.0
+    } else {
+        let actual_max = ac
+            .iter()
+            .flat_map(|factor| factor.iter())
+            .fold(0.0, |max: f64, value| max.max(value.abs()));
+        let quantised_max = (actual_max * 166.0 - 0.5).floor().max(0.0).min(82.0);
+        encode83(quantised_max as u32, 1, &mut hash);
+        (quantised_max + 1.0) / 166.0
+    };
+
+    let dc_value = (u32::from(linear_to_srgb(dc[0])) << 16)
+        + (u32::from(linear_to_srgb(dc[1])) << 8)
+        + u32::from(linear_to_srgb(dc[2]));
+    encode83(dc_value, 4, &mut hash);
+
+    for factor in ac {
+        let quantise = |value: f64| {
+            (sign_pow(value / maximum_value, 0.5) * 9.0 + 9.5)
+                .floor()
+                .max(0.0)
+                .min(18.0) as u32
+        };
+        let value = quantise(factor[0]) * 19 * 19 + quantise(factor[1]) * 19 + quantise(factor[2]);
+        encode83(value, 2, &mut hash);
+    }
+
+    Some(hash)
+}
+
+/// Returns the packed RGB pixels of the placeholder of `hash`, or `None` if
+/// it isn't valid.
+pub fn decode(hash: &str, width: usize, height: usize) -> Option<Vec<u8>> {
+    if hash.len() < 6 || !hash.is_ascii() {
+        return None;
+    }
+
+    let size_flag = decode83(&hash[0..1])?;
+    let components_y = (size_flag / 9 + 1) as usize;
+    let components_x = (size_flag % 9 + 1) as usize;
+    if hash.len() != 4 + 2 * components_x * components_y {
+        return None;
+    }
+
+    let maximum_value = (decode83(&hash[1..2])? + 1) as f64 / 166.0;
+    let dc = decode83(&hash[2..6])?;
+    let mut colors = vec![[
+        srgb_to_linear((dc >> 16) as u8),
+        srgb_to_linear((dc >> 8) as u8),
+        srgb_to_linear(dc as u8),
+    ]];
+    for i in 1..components_x * components_y {
+        let value = decode83(&hash[4 + i * 2..6 + i * 2])?;
+        let unquantise = |q: u32| sign_pow((q as f64 - 9.0) / 9.0, 2.0) * maximum_value;
+        colors.push([
+            unquantise(value / (19 * 19)),
+            unquantise((value / 19) % 19),
+            unquantise(value % 19),
+        ]);
+    }
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = [0.0; 3];
+            for j in 0..components_y {
+                for i in 0..components_x {
+                    let basis = (PI * x as f64 * i as f64 / width as f64).cos()
+                        * (PI * y as f64 * j as f64 / height as f64).cos();
+                    let color = colors[i + j * components_x];
+                    for (channel, value) in pixel.iter_mut().enumerate() {
+                        *value += color[channel] * basis;
+                    }
+                }
+            }
+            rgb.extend(pixel.iter().map(|value| linear_to_srgb(*value)));
+        }
+    }
+
+    Some(rgb)
+}
+
+/// Computes the hash of an image, with more components along its longer side.
+pub fn encode_pixbuf(pixbuf: &Pixbuf) -> Option<String> {
+    let (width, height) = (pixbuf.get_width(), pixbuf.get_height());
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+    let (scaled_width, scaled_height) = scaled_size(width, height, ENCODE_SIZE);
+    let scaled = pixbuf.scale_simple(
+        scaled_width,
+        scaled_height,
+        gdk_pixbuf::InterpType::Bilinear,
+    )?;
+
+    let bytes = scaled.read_pixel_bytes()?;
+    let rowstride = scaled.get_rowstride() as usize;
+    let channels = scaled.get_n_channels() as usize;
+    let (scaled_width, scaled_height) = (scaled_width as usize, scaled_height as usize);
+    let mut rgb = Vec::with_capacity(scaled_width * scaled_height * 3);
+    for y in 0..scaled_height {
+        for x in 0..scaled_width {
+            let offset = y * rowstride + x * channels;
+            rgb.extend_from_slice(bytes.get(offset..offset + 3)?);
+        }
+    }
+
+    let (components_x, components_y) = if width > height { (4, 3) } else { (3, 4) };
+    encode(
+        &rgb,
+        scaled_width,
+        scaled_height,
+        components_x,
+        components_y,
+    )
+}
+
+/// Returns the placeholder of `hash` for an image of `width` × `height`.
+pub fn decode_pixbuf(hash: &str, width: i32, height: i32) -> Option<Pixbuf> {
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+    let (decoded_width, decoded_height) = scaled_size(width, height, DECODE_SIZE);
+    let rgb = decode(hash, decoded_width as usize, decoded_height as usize)?;
+
+    Pixbuf::from_mut_slice(
+        rgb,
+        Colorspace::Rgb,
+        false,
+        8,
+        decoded_width,
+        decoded_height,
+        decoded_width * 3,
+    )
+    .scale_simple(width, height, gdk_pixbuf::InterpType::Bilinear)
+}
+
+// Fits `width` × `height` in a square of `size`, keeping the aspect ratio
+fn scaled_size(width: i32, height: i32, size: i32) -> (i32, i32) {
+    if width > height {
+        (size, (size * height / width).max(1))
+    } else {
+        ((size * width / height).max(1), size)
+    }
+}
+
+fn encode83(value: u32, length: u32, hash: &mut String) {
+    for i in 1..=length {
+        let digit = (value / 83u32.pow(length - i)) % 83;
+        hash.push(char::from(BASE83[digit as usize]));
+    }
+}
+
+fn decode83(chars: &str) -> Option<u32> {
+    chars.bytes().try_fold(0, |value, c| {
+        let digit = BASE83.iter().position(|b| *b == c)? as u32;
+        Some(value * 83 + digit)
+    })
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = f64::from(value) / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u8 {
+    let v = value.max(0.0).min(1.0);
+    if v <= 0.003_130_8 {
+        (v * 12.92 * 255.0 + 0.5) as u8
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u8
+    }
+}
+
+fn sign_pow(value: f64, exp: f64) -> f64 {
+    value.abs().powf(exp).copysign(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base83() {
+        let mut hash = String::new();
+        encode83(0, 1, &mut hash);
+        encode83(82, 1, &mut hash);
+        encode83(123_456, 4, &mut hash);
+        assert_eq!(hash, "0~0H]Z");
+        assert_eq!(decode83(&hash[0..1]), Some(0));
+        assert_eq!(decode83(&hash[1..2]), Some(82));
+        assert_eq!(decode83(&hash[2..6]), Some(123_456));
+        assert_eq!(decode83("\""), None);
+    }
+
+    #[test]
+    fn test_encode_solid_color() {
+        let rgb = [255, 128, 0].repeat(16);
+        let hash = encode(&rgb, 4, 4, 1, 1).unwrap();
+        assert_eq!(hash.len(), 6);
+        assert_eq!(&hash[0..2], "00");
+        assert_eq!(decode83(&hash[2..6]), Some((255 << 16) + (128 << 8)));
+
+        assert_eq!(decode(&hash, 2, 2), Some([255, 128, 0].repeat(4)));
+    }
+
+    #[test]
+    fn test_decode() {
+        // The example of the reference implementation
+        let rgb = decode("LEHV6nWB2yk8pyo0adR*.7kCMdnj", 32, 32).unwrap();
+        assert_eq!(rgb.len(), 32 * 32 * 3);
+
+        assert_eq!(decode("LEHV6nWB2yk8pyo0adR*.7kCMdn", 32, 32), None);
+        assert_eq!(decode("", 32, 32), None);
+        assert_eq!(decode("LEHV6nWB2yk8pyo0adR*.7kCMdn\"", 32, 32), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // A horizontal gradient from black to white
+        let rgb: Vec<u8> = (0..8)
+            .flat_map(|_| (0..8).flat_map(|x| vec![(x * 255 / 7) as u8; 3]))
+            .collect();
+        let hash = encode(&rgb, 8, 8, 4, 3).unwrap();
+        assert_eq!(hash.len(), 4 + 2 * 4 * 3);
+
+        // The placeholder is blurry, but keeps the dark and light sides
+        let decoded = decode(&hash, 8, 8).unwrap();
+        let (left, right) = (decoded[0], decoded[7 * 3]);
+        assert!(left < 100, "{}", left);
+        assert!(right > 200, "{}", right);
+    }
+}
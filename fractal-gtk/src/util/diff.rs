@@ -0,0 +1,121 @@
+//! The differences between two versions of a text, word by word.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+/// Returns the changes turning `old` into `new`.
+///
+/// Words keep the whitespace following them, so the words that weren't removed
+/// make up `new`.
+pub fn diff<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let old = words(old);
+    let new = words(new);
+    let same = |a: &str, b: &str| a.trim_end() == b.trim_end();
+
+    // The length of the longest common subsequence of the remaining words
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if same(old[i], new[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if same(old[i], new[j]) {
+            changes.push(Change::Same(new[j]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            changes.push(Change::Removed(old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(new[j]));
+            j += 1;
+        }
+    }
+    changes.extend(old[i..].iter().map(|word| Change::Removed(word)));
+    changes.extend(new[j..].iter().map(|word| Change::Added(word)));
+
+    changes
+}
+
+// Splits the text in words, each with the whitespace after it
+fn words(text: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = 0;
+    let mut after_word = false;
+    for (idx, c) in text.char_indices() {
+        if c.is_whitespace() {
+            after_word = true;
+        } else if after_word {
+            words.push(&text[start..idx]);
+            start = idx;
+            after_word = false;
+        }
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Change::*;
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            words("Hello  big\nworld"),
+            vec!["Hello  ", "big\n", "world"]
+        );
+        assert_eq!(words(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(
+            diff("Hello world", "Hello big world"),
+            vec![Same("Hello "), Added("big "), Same("world")]
+        );
+        assert_eq!(
+            diff("Hello big world", "Hello world!"),
+            vec![
+                Same("Hello "),
+                Removed("big "),
+                Removed("world"),
+                Added("world!")
+            ]
+        );
+        assert_eq!(
+            diff("Hello world", "Hello world again"),
+            vec![Same("Hello "), Same("world "), Added("again")]
+        );
+    }
+
+    #[test]
+    fn test_diff_keeps_new_text() {
+        let old = "The quick brown fox jumps over the lazy dog";
+        let new = "The quick red fox jumped over the dog, twice";
+        let text: String = diff(old, new)
+            .into_iter()
+            .filter_map(|change| match change {
+                Same(word) | Added(word) => Some(word),
+                Removed(_) => None,
+            })
+            .collect();
+        assert_eq!(text, new);
+    }
+}
@@ -0,0 +1,650 @@
+//! The emoji that can be picked or completed from their shortcode in the composer.
+
+use crate::util::i18n::i18n;
+use gio::{Settings, SettingsExt, SettingsSchemaSource};
+use log::error;
+
+// How many recently used emoji are remembered
+const MAX_RECENT: usize = 24;
+
+/// The modifiers of the skin tones, the first one being the default yellow.
+pub const SKIN_TONES: [&str; 6] = [
+    "",
+    "\u{1F3FB}",
+    "\u{1F3FC}",
+    "\u{1F3FD}",
+    "\u{1F3FE}",
+    "\u{1F3FF}",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Smileys,
+    People,
+    Nature,
+    Food,
+    Activities,
+    Travel,
+    Objects,
+    Symbols,
+    Flags,
+}
+
+impl Category {
+    pub const ALL: &'static [Self] = &[
+        Self::Smileys,
+        Self::People,
+        Self::Nature,
+        Self::Food,
+        Self::Activities,
+        Self::Travel,
+        Self::Objects,
+        Self::Symbols,
+        Self::Flags,
+    ];
+
+    pub fn name(self) -> String {
+        match self {
+            Self::Smileys => i18n("Smileys & Emotion"),
+            Self::People => i18n("People & Body"),
+            Self::Nature => i18n("Animals & Nature"),
+            Self::Food => i18n("Food & Drink"),
+            Self::Activities => i18n("Activities"),
+            Self::Travel => i18n("Travel & Places"),
+            Self::Objects => i18n("Objects"),
+            Self::Symbols => i18n("Symbols"),
+            Self::Flags => i18n("Flags"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Emoji {
+    pub emoji: &'static str,
+    pub shortcode: &'static str,
+    pub keywords: &'static [&'static str],
+    pub category: Category,
+    // Whether it can be shown with another skin tone
+    pub skin_tones: bool,
+}
+
+impl Emoji {
+    /// The emoji with one of `SKIN_TONES`, if it supports them.
+    pub fn with_skin_tone(&self, tone: usize) -> String {
+        let modifier = match SKIN_TONES.get(tone) {
+            Some(modifier) if self.skin_tones => modifier,
+            _ => return self.emoji.to_string(),
+        };
+
+        // The modifier follows the base character, replacing its presentation selector
+        let mut chars = self.emoji.chars().filter(|c| *c != '\u{FE0F}');
+        let mut emoji: String = chars.next().into_iter().collect();
+        emoji.push_str(modifier);
+        emoji.extend(chars);
+        emoji
+    }
+
+    fn matches(&self, search: &str) -> bool {
+        self.shortcode.contains(search) || self.keywords.iter().any(|k| k.starts_with(search))
+    }
+}
+
+macro_rules! emoji {
+    ($emoji:expr, $shortcode:expr, $category:ident, [$($keyword:expr),*]) => {
+        Emoji {
+            emoji: $emoji,
+            shortcode: $shortcode,
+            keywords: &[$($keyword),*],
+            category: Category::$category,
+            skin_tones: false,
+        }
+    };
+    (tones $emoji:expr, $shortcode:expr, $category:ident, [$($keyword:expr),*]) => {
+        Emoji {
+            emoji: $emoji,
+            shortcode: $shortcode,
+            keywords: &[$($keyword),*],
+            category: Category::$category,
+            skin_tones: true,
+        }
+    };
+}
+
+/// All the emoji, in the order they are shown in the picker.
+///
+/// This is a selection of the most used emoji, not all of Unicode: the other ones
+/// can still be typed with the input method or pasted. The shortcodes are the
+/// ones of gemoji, which other Matrix clients use too.
+pub static EMOJI: &[Emoji] = &[
+    emoji!("😀", "grinning", Smileys, ["face", "smile", "happy"]),
+    emoji!("😃", "smiley", Smileys, ["face", "happy", "joy"]),
+    emoji!("😄", "smile", Smileys, ["face", "happy", "laugh"]),
+    emoji!("😁", "grin", Smileys, ["face", "happy", "teeth"]),
+    emoji!("😆", "laughing", Smileys, ["face", "happy", "satisfied"]),
+    emoji!("😅", "sweat_smile", Smileys, ["face", "hot", "relief"]),
+    emoji!("🤣", "rofl", Smileys, ["face", "floor", "laugh"]),
+    emoji!("😂", "joy", Smileys, ["face", "tears", "laugh"]),
+    emoji!("🙂", "slightly_smiling_face", Smileys, ["face", "smile"]),
+    emoji!("🙃", "upside_down_face", Smileys, ["face", "silly"]),
+    emoji!("😉", "wink", Smileys, ["face", "flirt"]),
+    emoji!("😊", "blush", Smileys, ["face", "smile", "proud"]),
+    emoji!("😇", "innocent", Smileys, ["face", "angel", "halo"]),
+    emoji!(
+        "🥰",
+        "smiling_face_with_three_hearts",
+        Smileys,
+        ["face", "love", "crush"]
+    ),
+    emoji!("😍", "heart_eyes", Smileys, ["face", "love", "crush"]),
+    emoji!("🤩", "star_struck", Smileys, ["face", "eyes", "wow"]),
+    emoji!("😘", "kissing_heart", Smileys, ["face", "kiss", "love"]),
+    emoji!("😋", "yum", Smileys, ["face", "tongue", "delicious"]),
+    emoji!("😛", "stuck_out_tongue", Smileys, ["face", "tongue"]),
+    emoji!(
+        "😜",
+        "stuck_out_tongue_winking_eye",
+        Smileys,
+        ["face", "tongue", "prank"]
+    ),
+    emoji!("🤪", "zany_face", Smileys, ["face", "crazy", "goofy"]),
+    emoji!(
+        "🤑",
+        "money_mouth_face",
+        Smileys,
+        ["face", "rich", "dollar"]
+    ),
+    emoji!("🤗", "hugs", Smileys, ["face", "hug"]),
+    emoji!("🤭", "hand_over_mouth", Smileys, ["face", "oops", "quiet"]),
+    emoji!("🤫", "shushing_face", Smileys, ["face", "silence", "quiet"]),
+    emoji!("🤔", "thinking", Smileys, ["face", "hmm", "wonder"]),
+    emoji!(
+        "🤐",
+        "zipper_mouth_face",
+        Smileys,
+        ["face", "secret", "silence"]
+    ),
+    emoji!(
+        "🤨",
+        "raised_eyebrow",
+        Smileys,
+        ["face", "suspicious", "skeptic"]
+    ),
+    emoji!("😐", "neutral_face", Smileys, ["face", "meh"]),
+    emoji!("😑", "expressionless", Smileys, ["face", "blank"]),
+    emoji!("😶", "no_mouth", Smileys, ["face", "mute", "silence"]),
+    emoji!("😏", "smirk", Smileys, ["face", "smug"]),
+    emoji!("😒", "unamused", Smileys, ["face", "meh", "bored"]),
+    emoji!("🙄", "roll_eyes", Smileys, ["face", "eyes", "whatever"]),
+    emoji!("😬", "grimacing", Smileys, ["face", "awkward", "teeth"]),
+    emoji!("😌", "relieved", Smileys, ["face", "calm", "whew"]),
+    emoji!("😔", "pensive", Smileys, ["face", "sad", "dejected"]),
+    emoji!("😪", "sleepy", Smileys, ["face", "tired"]),
+    emoji!("😴", "sleeping", Smileys, ["face", "zzz", "tired"]),
+    emoji!("😷", "mask", Smileys, ["face", "sick", "ill"]),
+    emoji!(
+        "🤒",
+        "face_with_thermometer",
+        Smileys,
+        ["face", "sick", "fever"]
+    ),
+    emoji!("🤢", "nauseated_face", Smileys, ["face", "sick", "disgust"]),
+    emoji!("🤮", "vomiting_face", Smileys, ["face", "sick", "puke"]),
+    emoji!("🥵", "hot_face", Smileys, ["face", "heat", "sweat"]),
+    emoji!("🥶", "cold_face", Smileys, ["face", "freezing", "ice"]),
+    emoji!(
+        "🤯",
+        "exploding_head",
+        Smileys,
+        ["face", "mind", "blown", "shocked"]
+    ),
+    emoji!(
+        "🥳",
+        "partying_face",
+        Smileys,
+        ["face", "party", "celebration"]
+    ),
+    emoji!("😎", "sunglasses", Smileys, ["face", "cool"]),
+    emoji!("🤓", "nerd_face", Smileys, ["face", "geek", "glasses"]),
+    emoji!("😕", "confused", Smileys, ["face", "unsure"]),
+    emoji!("😟", "worried", Smileys, ["face", "nervous"]),
+    emoji!("🙁", "slightly_frowning_face", Smileys, ["face", "sad"]),
+    emoji!("😮", "open_mouth", Smileys, ["face", "surprise", "wow"]),
+    emoji!("😲", "astonished", Smileys, ["face", "amazed", "shocked"]),
+    emoji!("😳", "flushed", Smileys, ["face", "embarrassed", "blush"]),
+    emoji!("🥺", "pleading_face", Smileys, ["face", "puppy", "beg"]),
+    emoji!("😨", "fearful", Smileys, ["face", "scared", "fear"]),
+    emoji!("😰", "cold_sweat", Smileys, ["face", "nervous", "anxious"]),
+    emoji!("😢", "cry", Smileys, ["face", "sad", "tear"]),
+    emoji!("😭", "sob", Smileys, ["face", "sad", "cry", "tears"]),
+    emoji!("😱", "scream", Smileys, ["face", "horror", "shocked"]),
+    emoji!("😖", "confounded", Smileys, ["face", "frustrated"]),
+    emoji!("😩", "weary", Smileys, ["face", "tired", "frustrated"]),
+    emoji!("🥱", "yawning_face", Smileys, ["face", "bored", "tired"]),
+    emoji!("😤", "triumph", Smileys, ["face", "smug", "huff"]),
+    emoji!("😡", "rage", Smileys, ["face", "angry", "mad"]),
+    emoji!("😠", "angry", Smileys, ["face", "mad", "annoyed"]),
+    emoji!("🤬", "cursing_face", Smileys, ["face", "swearing", "angry"]),
+    emoji!("😈", "smiling_imp", Smileys, ["face", "devil", "evil"]),
+    emoji!("💀", "skull", Smileys, ["dead", "danger", "death"]),
+    emoji!("💩", "poop", Smileys, ["hankey", "shit", "crap"]),
+    emoji!("🤡", "clown_face", Smileys, ["face", "clown"]),
+    emoji!("👻", "ghost", Smileys, ["halloween", "spooky"]),
+    emoji!("👽", "alien", Smileys, ["ufo", "space"]),
+    emoji!("🤖", "robot", Smileys, ["face", "bot", "machine"]),
+    emoji!("😺", "smiley_cat", Smileys, ["cat", "happy"]),
+    emoji!("🙈", "see_no_evil", Smileys, ["monkey", "blind", "ignore"]),
+    emoji!("🙉", "hear_no_evil", Smileys, ["monkey", "deaf"]),
+    emoji!("🙊", "speak_no_evil", Smileys, ["monkey", "mute", "oops"]),
+    emoji!("💯", "100", Smileys, ["score", "perfect", "hundred"]),
+    emoji!("💥", "boom", Smileys, ["collision", "explode"]),
+    emoji!("💦", "sweat_drops", Smileys, ["water", "splash"]),
+    emoji!("💤", "zzz", Smileys, ["sleep", "tired"]),
+    emoji!("❤️", "heart", Smileys, ["love", "like", "red"]),
+    emoji!("🧡", "orange_heart", Smileys, ["love", "like"]),
+    emoji!("💛", "yellow_heart", Smileys, ["love", "like"]),
+    emoji!("💚", "green_heart", Smileys, ["love", "like"]),
+    emoji!("💙", "blue_heart", Smileys, ["love", "like"]),
+    emoji!("💜", "purple_heart", Smileys, ["love", "like"]),
+    emoji!("🖤", "black_heart", Smileys, ["love", "dark"]),
+    emoji!("💔", "broken_heart", Smileys, ["sad", "breakup"]),
+    emoji!("💕", "two_hearts", Smileys, ["love", "affection"]),
+    emoji!(tones "👋", "wave", People, ["hand", "hello", "goodbye"]),
+    emoji!(tones "🤚", "raised_back_of_hand", People, ["hand", "stop"]),
+    emoji!(tones "✋", "hand", People, ["raised_hand", "stop", "high_five"]),
+    emoji!(tones "🖖", "vulcan_salute", People, ["hand", "spock", "prosper"]),
+    emoji!(tones "👌", "ok_hand", People, ["hand", "fine", "perfect"]),
+    emoji!(tones "🤏", "pinching_hand", People, ["hand", "small", "tiny"]),
+    emoji!(tones "✌️", "v", People, ["hand", "victory", "peace"]),
+    emoji!(tones "🤞", "crossed_fingers", People, ["hand", "luck", "hopeful"]),
+    emoji!(tones "🤟", "love_you_gesture", People, ["hand", "ily"]),
+    emoji!(tones "🤘", "metal", People, ["hand", "horns", "rock"]),
+    emoji!(tones "🤙", "call_me_hand", People, ["hand", "shaka"]),
+    emoji!(tones "👈", "point_left", People, ["hand", "direction"]),
+    emoji!(tones "👉", "point_right", People, ["hand", "direction"]),
+    emoji!(tones "👆", "point_up_2", People, ["hand", "direction"]),
+    emoji!(tones "👇", "point_down", People, ["hand", "direction"]),
+    emoji!(tones "👍", "+1", People, ["thumbsup", "yes", "approve", "like"]),
+    emoji!(tones "👎", "-1", People, ["thumbsdown", "no", "dislike"]),
+    emoji!(tones "✊", "fist_raised", People, ["hand", "power"]),
+    emoji!(tones "👊", "fist_oncoming", People, ["punch", "bump"]),
+    emoji!(tones "👏", "clap", People, ["hands", "applause", "congrats"]),
+    emoji!(tones "🙌", "raised_hands", People, ["hooray", "celebration"]),
+    emoji!(tones "👐", "open_hands", People, ["hug"]),
+    emoji!(tones "🤲", "palms_up_together", People, ["prayer", "cupped"]),
+    emoji!("🤝", "handshake", People, ["deal", "agreement", "meeting"]),
+    emoji!(tones "🙏", "pray", People, ["please", "thanks", "hope"]),
+    emoji!(tones "✍️", "writing_hand", People, ["write", "hand"]),
+    emoji!(tones "💪", "muscle", People, ["flex", "strong", "biceps"]),
+    emoji!("🧠", "brain", People, ["smart", "mind"]),
+    emoji!("👀", "eyes", People, ["look", "see", "watch"]),
+    emoji!(tones "👶", "baby", People, ["child", "newborn"]),
+    emoji!(tones "🧒", "child", People, ["kid", "young"]),
+    emoji!(tones "🧑", "adult", People, ["person"]),
+    emoji!(tones "👩", "woman", People, ["lady", "person"]),
+    emoji!(tones "👨", "man", People, ["guy", "person"]),
+    emoji!(tones "🧓", "older_adult", People, ["old", "elder"]),
+    emoji!(tones "🙋", "raising_hand", People, ["question", "hello", "me"]),
+    emoji!(tones "🤷", "shrug", People, ["whatever", "dunno", "doubt"]),
+    emoji!(tones "🤦", "facepalm", People, ["disbelief", "exasperation"]),
+    emoji!(tones "🙇", "bow", People, ["sorry", "respect"]),
+    emoji!(tones "🧑‍💻", "technologist", People, ["coder", "developer", "hacker"]),
+    emoji!(tones "🏃", "runner", People, ["running", "exercise", "hurry"]),
+    emoji!(tones "💃", "dancer", People, ["dance", "party"]),
+    emoji!("🐶", "dog", Nature, ["puppy", "pet"]),
+    emoji!("🐱", "cat", Nature, ["kitten", "pet"]),
+    emoji!("🐭", "mouse", Nature, ["rodent"]),
+    emoji!("🦊", "fox_face", Nature, ["fox", "firefox"]),
+    emoji!("🐻", "bear", Nature, ["teddy"]),
+    emoji!("🐼", "panda_face", Nature, ["panda"]),
+    emoji!("🐨", "koala", Nature, ["marsupial"]),
+    emoji!("🐯", "tiger", Nature, ["cat", "wild"]),
+    emoji!("🦁", "lion", Nature, ["cat", "king"]),
+    emoji!("🐮", "cow", Nature, ["moo", "farm"]),
+    emoji!("🐷", "pig", Nature, ["oink", "farm"]),
+    emoji!("🐸", "frog", Nature, ["toad"]),
+    emoji!("🐵", "monkey_face", Nature, ["monkey"]),
+    emoji!("🐔", "chicken", Nature, ["bird", "farm"]),
+    emoji!("🐧", "penguin", Nature, ["bird", "linux", "tux"]),
+    emoji!("🦆", "duck", Nature, ["bird"]),
+    emoji!("🦉", "owl", Nature, ["bird", "wise"]),
+    emoji!("🦄", "unicorn", Nature, ["magic", "fantasy"]),
+    emoji!("🐝", "bee", Nature, ["honeybee", "insect"]),
+    emoji!("🐛", "bug", Nature, ["insect", "caterpillar"]),
+    emoji!("🦋", "butterfly", Nature, ["insect", "pretty"]),
+    emoji!("🐌", "snail", Nature, ["slow"]),
+    emoji!("🐢", "turtle", Nature, ["slow", "tortoise"]),
+    emoji!("🐍", "snake", Nature, ["python", "serpent"]),
+    emoji!("🐙", "octopus", Nature, ["tentacles"]),
+    emoji!("🦀", "crab", Nature, ["rust", "ferris"]),
+    emoji!("🐳", "whale", Nature, ["sea", "ocean", "docker"]),
+    emoji!("🐬", "dolphin", Nature, ["sea", "flipper"]),
+    emoji!("🐟", "fish", Nature, ["sea"]),
+    emoji!("🌸", "cherry_blossom", Nature, ["flower", "spring"]),
+    emoji!("🌹", "rose", Nature, ["flower", "love"]),
+    emoji!("🌻", "sunflower", Nature, ["flower", "summer"]),
+    emoji!("🌱", "seedling", Nature, ["plant", "grow"]),
+    emoji!("🌲", "evergreen_tree", Nature, ["pine", "forest"]),
+    emoji!("🌵", "cactus", Nature, ["plant", "desert"]),
+    emoji!("🍀", "four_leaf_clover", Nature, ["luck", "lucky"]),
+    emoji!("🍁", "maple_leaf", Nature, ["autumn", "canada"]),
+    emoji!("🍄", "mushroom", Nature, ["fungus"]),
+    emoji!("🌍", "earth_africa", Nature, ["globe", "world"]),
+    emoji!("🌙", "crescent_moon", Nature, ["night", "moon"]),
+    emoji!("⭐", "star", Nature, ["night", "favorite"]),
+    emoji!("☀️", "sunny", Nature, ["sun", "weather"]),
+    emoji!("🌈", "rainbow", Nature, ["weather", "pride"]),
+    emoji!("☁️", "cloud", Nature, ["weather"]),
+    emoji!("⚡", "zap", Nature, ["lightning", "thunder", "fast"]),
+    emoji!("❄️", "snowflake", Nature, ["winter", "cold"]),
+    emoji!("🔥", "fire", Nature, ["flame", "hot", "lit"]),
+    emoji!("💧", "droplet", Nature, ["water", "drop"]),
+    emoji!("🍏", "green_apple", Food, ["fruit"]),
+    emoji!("🍎", "apple", Food, ["fruit", "red"]),
+    emoji!("🍌", "banana", Food, ["fruit"]),
+    emoji!("🍉", "watermelon", Food, ["fruit", "summer"]),
+    emoji!("🍇", "grapes", Food, ["fruit", "wine"]),
+    emoji!("🍓", "strawberry", Food, ["fruit"]),
+    emoji!("🍒", "cherries", Food, ["fruit"]),
+    emoji!("🍑", "peach", Food, ["fruit"]),
+    emoji!("🥑", "avocado", Food, ["fruit", "guacamole"]),
+    emoji!("🍅", "tomato", Food, ["vegetable"]),
+    emoji!("🥕", "carrot", Food, ["vegetable"]),
+    emoji!("🌽", "corn", Food, ["vegetable", "maize"]),
+    emoji!("🥐", "croissant", Food, ["bread", "french"]),
+    emoji!("🍞", "bread", Food, ["toast", "loaf"]),
+    emoji!("🧀", "cheese", Food, ["swiss"]),
+    emoji!("🍳", "fried_egg", Food, ["breakfast", "cooking"]),
+    emoji!("🍔", "hamburger", Food, ["burger", "fast_food"]),
+    emoji!("🍟", "fries", Food, ["chips", "fast_food"]),
+    emoji!("🍕", "pizza", Food, ["italian", "slice"]),
+    emoji!("🌮", "taco", Food, ["mexican"]),
+    emoji!("🍜", "ramen", Food, ["noodles", "soup"]),
+    emoji!("🍣", "sushi", Food, ["japanese", "fish"]),
+    emoji!("🍦", "icecream", Food, ["dessert", "sweet"]),
+    emoji!("🍩", "doughnut", Food, ["donut", "sweet"]),
+    emoji!("🍪", "cookie", Food, ["biscuit", "sweet"]),
+    emoji!("🎂", "birthday", Food, ["cake", "party"]),
+    emoji!("🍫", "chocolate_bar", Food, ["sweet", "candy"]),
+    emoji!("🍿", "popcorn", Food, ["movie", "snack"]),
+    emoji!("☕", "coffee", Food, ["cafe", "espresso", "tea"]),
+    emoji!("🍵", "tea", Food, ["green", "matcha"]),
+    emoji!("🍺", "beer", Food, ["drink", "pub"]),
+    emoji!("🍻", "beers", Food, ["drinks", "cheers"]),
+    emoji!("🍷", "wine_glass", Food, ["drink", "wine"]),
+    emoji!(
+        "🥂",
+        "clinking_glasses",
+        Food,
+        ["cheers", "toast", "celebration"]
+    ),
+    emoji!("⚽", "soccer", Activities, ["football", "sports"]),
+    emoji!("🏀", "basketball", Activities, ["sports", "ball"]),
+    emoji!("🏈", "football", Activities, ["sports", "american"]),
+    emoji!("🎾", "tennis", Activities, ["sports", "ball"]),
+    emoji!("🏆", "trophy", Activities, ["win", "award", "prize"]),
+    emoji!("🥇", "1st_place_medal", Activities, ["gold", "winner"]),
+    emoji!("🎉", "tada", Activities, ["party", "hooray", "congrats"]),
+    emoji!("🎊", "confetti_ball", Activities, ["party", "celebration"]),
+    emoji!("🎈", "balloon", Activities, ["party", "birthday"]),
+    emoji!("🎁", "gift", Activities, ["present", "birthday"]),
+    emoji!("🎄", "christmas_tree", Activities, ["xmas", "holiday"]),
+    emoji!("🎃", "jack_o_lantern", Activities, ["halloween", "pumpkin"]),
+    emoji!(
+        "🎮",
+        "video_game",
+        Activities,
+        ["play", "controller", "console"]
+    ),
+    emoji!("🎲", "game_die", Activities, ["dice", "random", "luck"]),
+    emoji!("♟️", "chess_pawn", Activities, ["chess", "game"]),
+    emoji!("🎯", "dart", Activities, ["target", "bullseye"]),
+    emoji!("🎨", "art", Activities, ["design", "paint", "palette"]),
+    emoji!("🎵", "musical_note", Activities, ["music", "sound"]),
+    emoji!("🎸", "guitar", Activities, ["music", "rock"]),
+    emoji!("🎬", "clapper", Activities, ["movie", "film"]),
+    emoji!("🚗", "car", Travel, ["automobile", "drive"]),
+    emoji!("🚕", "taxi", Travel, ["cab", "car"]),
+    emoji!("🚌", "bus", Travel, ["public_transport"]),
+    emoji!("🚲", "bike", Travel, ["bicycle", "cycling"]),
+    emoji!("🚂", "steam_locomotive", Travel, ["train"]),
+    emoji!("🚆", "train", Travel, ["railway"]),
+    emoji!("✈️", "airplane", Travel, ["flight", "plane", "travel"]),
+    emoji!("🚀", "rocket", Travel, ["launch", "space", "ship_it"]),
+    emoji!("🛸", "flying_saucer", Travel, ["ufo", "alien"]),
+    emoji!("⛵", "boat", Travel, ["sailboat", "sea"]),
+    emoji!("🏠", "house", Travel, ["home", "building"]),
+    emoji!("🏢", "office", Travel, ["building", "work"]),
+    emoji!("🏰", "european_castle", Travel, ["castle"]),
+    emoji!("⛺", "tent", Travel, ["camping"]),
+    emoji!(
+        "🏖️",
+        "beach_umbrella",
+        Travel,
+        ["beach", "vacation", "holiday"]
+    ),
+    emoji!("🗻", "mount_fuji", Travel, ["mountain", "japan"]),
+    emoji!("🌋", "volcano", Travel, ["mountain", "eruption"]),
+    emoji!("🗺️", "world_map", Travel, ["map", "travel"]),
+    emoji!("⌛", "hourglass", Travel, ["time", "wait"]),
+    emoji!("⏰", "alarm_clock", Travel, ["time", "morning"]),
+    emoji!("📱", "iphone", Objects, ["phone", "mobile", "smartphone"]),
+    emoji!("💻", "computer", Objects, ["laptop", "pc"]),
+    emoji!("⌨️", "keyboard", Objects, ["typing", "computer"]),
+    emoji!("🖥️", "desktop_computer", Objects, ["screen", "monitor"]),
+    emoji!("🖱️", "computer_mouse", Objects, ["click"]),
+    emoji!("💾", "floppy_disk", Objects, ["save", "disk"]),
+    emoji!("📷", "camera", Objects, ["photo", "picture"]),
+    emoji!("🎥", "movie_camera", Objects, ["film", "video"]),
+    emoji!("📺", "tv", Objects, ["television"]),
+    emoji!("🔋", "battery", Objects, ["power", "energy"]),
+    emoji!("🔌", "electric_plug", Objects, ["power", "plug"]),
+    emoji!("💡", "bulb", Objects, ["idea", "light"]),
+    emoji!("🔦", "flashlight", Objects, ["torch", "light"]),
+    emoji!("📚", "books", Objects, ["library", "read", "study"]),
+    emoji!("📖", "book", Objects, ["open_book", "read"]),
+    emoji!("📝", "memo", Objects, ["note", "write", "pencil"]),
+    emoji!("✏️", "pencil2", Objects, ["write", "edit"]),
+    emoji!("📎", "paperclip", Objects, ["attachment"]),
+    emoji!("📌", "pushpin", Objects, ["pin", "location"]),
+    emoji!("📅", "date", Objects, ["calendar", "schedule"]),
+    emoji!(
+        "📈",
+        "chart_with_upwards_trend",
+        Objects,
+        ["graph", "growth"]
+    ),
+    emoji!(
+        "📉",
+        "chart_with_downwards_trend",
+        Objects,
+        ["graph", "decline"]
+    ),
+    emoji!("📦", "package", Objects, ["box", "shipping", "release"]),
+    emoji!("✉️", "email", Objects, ["envelope", "letter", "mail"]),
+    emoji!("🔒", "lock", Objects, ["security", "private", "encrypted"]),
+    emoji!("🔓", "unlock", Objects, ["security", "open"]),
+    emoji!("🔑", "key", Objects, ["password", "lock"]),
+    emoji!("🔨", "hammer", Objects, ["tool", "build"]),
+    emoji!("🔧", "wrench", Objects, ["tool", "fix", "spanner"]),
+    emoji!("⚙️", "gear", Objects, ["settings", "cog"]),
+    emoji!(
+        "🧪",
+        "test_tube",
+        Objects,
+        ["science", "experiment", "test"]
+    ),
+    emoji!("🔬", "microscope", Objects, ["science", "research"]),
+    emoji!("💊", "pill", Objects, ["medicine", "drug"]),
+    emoji!("🛒", "shopping_cart", Objects, ["shop", "buy"]),
+    emoji!("💰", "moneybag", Objects, ["money", "dollar", "rich"]),
+    emoji!("🔔", "bell", Objects, ["notification", "sound"]),
+    emoji!("🔕", "no_bell", Objects, ["mute", "silent"]),
+    emoji!("📣", "mega", Objects, ["announcement", "megaphone"]),
+    emoji!("✅", "white_check_mark", Symbols, ["check", "done", "yes"]),
+    emoji!("✔️", "heavy_check_mark", Symbols, ["check", "done", "yes"]),
+    emoji!("❌", "x", Symbols, ["cross", "no", "delete"]),
+    emoji!("❓", "question", Symbols, ["what", "confused"]),
+    emoji!("❗", "exclamation", Symbols, ["important", "bang"]),
+    emoji!("⚠️", "warning", Symbols, ["caution", "danger"]),
+    emoji!("⛔", "no_entry", Symbols, ["forbidden", "stop"]),
+    emoji!("🚫", "no_entry_sign", Symbols, ["forbidden", "prohibited"]),
+    emoji!("♻️", "recycle", Symbols, ["environment", "green"]),
+    emoji!("➕", "heavy_plus_sign", Symbols, ["plus", "add"]),
+    emoji!("➖", "heavy_minus_sign", Symbols, ["minus", "remove"]),
+    emoji!("➡️", "arrow_right", Symbols, ["next", "direction"]),
+    emoji!("⬅️", "arrow_left", Symbols, ["previous", "direction"]),
+    emoji!("⬆️", "arrow_up", Symbols, ["direction"]),
+    emoji!("⬇️", "arrow_down", Symbols, ["direction"]),
+    emoji!(
+        "🔄",
+        "arrows_counterclockwise",
+        Symbols,
+        ["sync", "refresh", "reload"]
+    ),
+    emoji!("🆗", "ok", Symbols, ["okay", "fine"]),
+    emoji!("🆕", "new", Symbols, ["fresh"]),
+    emoji!("🆒", "cool", Symbols, ["nice"]),
+    emoji!("🔴", "red_circle", Symbols, ["red", "record"]),
+    emoji!("🟢", "green_circle", Symbols, ["green", "online"]),
+    emoji!(
+        "💬",
+        "speech_balloon",
+        Symbols,
+        ["chat", "comment", "message"]
+    ),
+    emoji!("💭", "thought_balloon", Symbols, ["think", "bubble"]),
+    emoji!("♾️", "infinity", Symbols, ["forever", "unlimited"]),
+    emoji!("🏳️‍🌈", "rainbow_flag", Flags, ["pride", "lgbt"]),
+    emoji!("🏳️‍⚧️", "transgender_flag", Flags, ["pride", "trans"]),
+    emoji!("🏴‍☠️", "pirate_flag", Flags, ["jolly_roger", "pirate"]),
+    emoji!("🏁", "checkered_flag", Flags, ["finish", "race"]),
+    emoji!(
+        "🚩",
+        "triangular_flag_on_post",
+        Flags,
+        ["red_flag", "warning"]
+    ),
+    emoji!("🇪🇺", "eu", Flags, ["european_union", "europe"]),
+    emoji!("🇺🇳", "united_nations", Flags, ["un"]),
+];
+
+/// Searches emoji by shortcode or keyword, the best matches first.
+pub fn search(search: &str) -> Vec<&'static Emoji> {
+    let search = search.to_lowercase();
+    let mut found: Vec<&'static Emoji> = EMOJI.iter().filter(|e| e.matches(&search)).collect();
+    // Exact shortcodes first, then the ones starting with the search
+    found.sort_by_key(|e| {
+        if e.shortcode == search {
+            0
+        } else if e.shortcode.starts_with(&search) {
+            1
+        } else {
+            2
+        }
+    });
+
+    found
+}
+
+fn get_settings() -> Option<Settings> {
+    SettingsSchemaSource::get_default()
+        .and_then(|s| s.lookup("org.gnome.Fractal", true))
+        .map(|_| Settings::new("org.gnome.Fractal"))
+}
+
+/// The emoji used recently, the last one first.
+pub fn get_recent() -> Vec<String> {
+    get_settings()
+        .map(|settings| {
+            settings
+                .get_strv("recent-emoji")
+                .iter()
+                .map(|e| e.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn add_recent(emoji: &str) {
+    let settings = match get_settings() {
+        Some(settings) => settings,
+        None => return,
+    };
+
+    let mut recent = get_recent();
+    recent.retain(|e| e != emoji);
+    recent.insert(0, emoji.to_string());
+    recent.truncate(MAX_RECENT);
+
+    let recent: Vec<&str> = recent.iter().map(String::as_str).collect();
+    if let Err(err) = settings.set_strv("recent-emoji", &recent) {
+        error!("Can't save the recent emoji: {:?}", err);
+    }
+}
+
+/// The index in `SKIN_TONES` of the skin tone picked by the user.
+pub fn get_skin_tone() -> usize {
+    get_settings()
+        .map(|settings| settings.get_int("emoji-skin-tone") as usize)
+        .filter(|tone| *tone < SKIN_TONES.len())
+        .unwrap_or_default()
+}
+
+pub fn set_skin_tone(tone: usize) {
+    if let Some(settings) = get_settings() {
+        if let Err(err) = settings.set_int("emoji-skin-tone", tone as i32) {
+            error!("Can't save the emoji skin tone: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortcodes(search_text: &str) -> Vec<&'static str> {
+        search(search_text).iter().map(|e| e.shortcode).collect()
+    }
+
+    #[test]
+    fn test_search_ranking() {
+        // The exact shortcode, then the ones starting with the search, then the
+        // ones containing it or with a matching keyword, in the order of the table
+        assert_eq!(
+            shortcodes("smile"),
+            vec![
+                "smile",
+                "smiley",
+                "smiley_cat",
+                "grinning",
+                "sweat_smile",
+                "slightly_smiling_face",
+                "blush",
+            ]
+        );
+        assert_eq!(shortcodes("heart")[0], "heart");
+    }
+
+    #[test]
+    fn test_search_matching() {
+        // Searches aren't case sensitive
+        assert_eq!(shortcodes("SMILE"), shortcodes("smile"));
+        // Keywords only match from their start
+        assert!(shortcodes("happ").contains(&"grinning"));
+        assert!(!shortcodes("appy").contains(&"grinning"));
+        assert!(shortcodes("not an emoji").is_empty());
+    }
+
+    #[test]
+    fn test_shortcodes_are_unique() {
+        for (i, emoji) in EMOJI.iter().enumerate() {
+            assert!(
+                EMOJI[i + 1..]
+                    .iter()
+                    .all(|e| e.shortcode != emoji.shortcode),
+                "{} is used twice",
+                emoji.shortcode
+            );
+        }
+    }
+}
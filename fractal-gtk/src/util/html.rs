@@ -0,0 +1,561 @@
+//! A parser for the HTML subset allowed in the formatted body of messages.
+//!
+//! https://matrix.org/docs/spec/client_server/r0.6.1#m-room-message-msgtypes
+
+use super::sanitize::sanitize;
+use html2pango::{html_escape, markup_links};
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, parse_fragment, Attribute, QualName};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::collections::HashSet;
+
+/// A block of a formatted message, laid out vertically.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    Heading(u8, Vec<Inline>),
+    List {
+        // The number of the first item, for ordered lists
+        start: Option<u32>,
+        items: Vec<Vec<Block>>,
+    },
+    Quote(Vec<Block>),
+    Code {
+        language: Option<String>,
+        text: String,
+    },
+    Table(Vec<Vec<TableCell>>),
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableCell {
+    pub header: bool,
+    pub content: Vec<Inline>,
+}
+
+/// Text inside a block, with its formatting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Styled(Style, Vec<Inline>),
+    Link(String, Vec<Inline>),
+    // Content hidden until clicked, with the reason it's hidden
+    Spoiler(Option<String>, Vec<Inline>),
+    LineBreak,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Style {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Code,
+    Superscript,
+    Subscript,
+    Color(String),
+    Background(String),
+}
+
+/// Parses the formatted body of a message, once sanitized.
+pub fn parse(html: &str) -> Vec<Block> {
+    let dom = parse_fragment(
+        RcDom::default(),
+        Default::default(),
+        QualName::new(None, ns!(html), local_name!("body")),
+        vec![],
+    )
+    .one(sanitize(html));
+
+    // The fragment is parsed as the children of an html element
+    let root = dom.document.children.borrow().first().cloned();
+    root.map(|root| parse_blocks(&root.children.borrow()))
+        .unwrap_or_default()
+}
+
+fn parse_blocks(nodes: &[Handle]) -> Vec<Block> {
+    let mut blocks = vec![];
+    let mut inlines = vec![];
+
+    for node in nodes {
+        let (tag, attrs) = match &node.data {
+            NodeData::Element { name, attrs, .. } => (name.local.to_string(), attrs.borrow()),
+            NodeData::Text { .. } => {
+                inlines.extend(parse_inline(node));
+                continue;
+            }
+            _ => continue,
+        };
+
+        let block = match tag.as_str() {
+            "p" | "div" | "details" | "summary" => {
+                flush_paragraph(&mut blocks, &mut inlines);
+                blocks.extend(parse_blocks(&node.children.borrow()));
+                continue;
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = tag[1..].parse().unwrap_or(1);
+                Block::Heading(level, trim(parse_inlines(&node.children.borrow())))
+            }
+            "ul" | "ol" => {
+                let start = if tag == "ol" {
+                    Some(
+                        get_attr(&attrs, "start")
+                            .and_then(|s| s.parse().ok())
+                            .unwrap_or(1),
+                    )
+                } else {
+                    None
+                };
+                let items = node
+                    .children
+                    .borrow()
+                    .iter()
+                    .filter(|child| element_name(child).as_deref() == Some("li"))
+                    .map(|li| parse_blocks(&li.children.borrow()))
+                    .collect();
+                Block::List { start, items }
+            }
+            "blockquote" => Block::Quote(parse_blocks(&node.children.borrow())),
+            "pre" => parse_code(node),
+            "table" => Block::Table(parse_table(node)),
+            "hr" => Block::Rule,
+            // The fallback of rich replies, the replied message is shown apart
+            "mx-reply" => continue,
+            _ => {
+                inlines.extend(parse_inline(node));
+                continue;
+            }
+        };
+
+        flush_paragraph(&mut blocks, &mut inlines);
+        blocks.push(block);
+    }
+
+    flush_paragraph(&mut blocks, &mut inlines);
+    blocks
+}
+
+// Ends the paragraph made of the text found between blocks
+fn flush_paragraph(blocks: &mut Vec<Block>, inlines: &mut Vec<Inline>) {
+    let paragraph = trim(inlines.drain(..).collect());
+    if !paragraph.is_empty() {
+        blocks.push(Block::Paragraph(paragraph));
+    }
+}
+
+fn parse_inlines(nodes: &[Handle]) -> Vec<Inline> {
+    nodes.iter().flat_map(parse_inline).collect()
+}
+
+fn parse_inline(node: &Handle) -> Vec<Inline> {
+    let (tag, attrs) = match &node.data {
+        NodeData::Element { name, attrs, .. } => (name.local.to_string(), attrs.borrow()),
+        NodeData::Text { contents } => {
+            return vec![Inline::Text(collapse_whitespace(&contents.borrow()))];
+        }
+        _ => return vec![],
+    };
+    let children = || parse_inlines(&node.children.borrow());
+
+    let inline = match tag.as_str() {
+        "b" | "strong" => Inline::Styled(Style::Bold, children()),
+        "i" | "em" => Inline::Styled(Style::Italic, children()),
+        "u" => Inline::Styled(Style::Underline, children()),
+        "s" | "del" | "strike" => Inline::Styled(Style::Strikethrough, children()),
+        "code" => Inline::Styled(Style::Code, children()),
+        "sup" => Inline::Styled(Style::Superscript, children()),
+        "sub" => Inline::Styled(Style::Subscript, children()),
+        "a" => match get_attr(&attrs, "href") {
+            Some(href) => Inline::Link(href, children()),
+            None => return children(),
+        },
+        "span" if get_attr(&attrs, "data-mx-spoiler").is_some() => {
+            let reason = get_attr(&attrs, "data-mx-spoiler").filter(|r| !r.is_empty());
+            Inline::Spoiler(reason, children())
+        }
+        "span" | "font" => {
+            let color = get_attr(&attrs, "data-mx-color").or_else(|| get_attr(&attrs, "color"));
+            let background = get_attr(&attrs, "data-mx-bg-color");
+            let mut content = children();
+            if let Some(color) = background.filter(|c| is_color(c)) {
+                content = vec![Inline::Styled(Style::Background(color), content)];
+            }
+            if let Some(color) = color.filter(|c| is_color(c)) {
+                content = vec![Inline::Styled(Style::Color(color), content)];
+            }
+            return content;
+        }
+        "br" => Inline::LineBreak,
+        "img" => match get_attr(&attrs, "alt").or_else(|| get_attr(&attrs, "title")) {
+            Some(alt) => Inline::Text(alt),
+            None => return vec![],
+        },
+        "mx-reply" => return vec![],
+        _ => return children(),
+    };
+
+    vec![inline]
+}
+
+fn parse_code(pre: &Handle) -> Block {
+    // The language is given by the class of the code element, if any
+    let language = pre
+        .children
+        .borrow()
+        .iter()
+        .find_map(|child| match &child.data {
+            NodeData::Element { name, attrs, .. } if &*name.local == "code" => {
+                get_attr(&attrs.borrow(), "class")?
+                    .split_whitespace()
+                    .find_map(|class| class.strip_prefix("language-"))
+                    .map(String::from)
+            }
+            _ => None,
+        });
+
+    let mut text = text_content(pre);
+    if text.ends_with('\n') {
+        text.pop();
+    }
+
+    Block::Code { language, text }
+}
+
+fn parse_table(table: &Handle) -> Vec<Vec<TableCell>> {
+    let mut rows = vec![];
+    for child in table.children.borrow().iter() {
+        match element_name(child).as_deref() {
+            Some("thead") | Some("tbody") | Some("tfoot") => rows.extend(parse_table(child)),
+            Some("tr") => {
+                let row = child
+                    .children
+                    .borrow()
+                    .iter()
+                    .filter_map(|cell| {
+                        let header = match element_name(cell).as_deref() {
+                            Some("th") => true,
+                            Some("td") => false,
+                            _ => return None,
+                        };
+                        let content = trim(parse_inlines(&cell.children.borrow()));
+                        Some(TableCell { header, content })
+                    })
+                    .collect();
+                rows.push(row);
+            }
+            _ => {}
+        }
+    }
+
+    rows
+}
+
+fn element_name(node: &Handle) -> Option<String> {
+    match &node.data {
+        NodeData::Element { name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+fn get_attr(attrs: &[Attribute], name: &str) -> Option<String> {
+    attrs
+        .iter()
+        .find(|attr| &*attr.name.local == name)
+        .map(|attr| attr.value.to_string())
+}
+
+fn text_content(node: &Handle) -> String {
+    match &node.data {
+        NodeData::Text { contents } => contents.borrow().to_string(),
+        _ => node.children.borrow().iter().map(text_content).collect(),
+    }
+}
+
+// Whitespace outside of code blocks is shown as a single space, like browsers do
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                collapsed.push(' ');
+            }
+            in_space = true;
+        } else {
+            collapsed.push(c);
+            in_space = false;
+        }
+    }
+
+    collapsed
+}
+
+// Removes the whitespace at the start and the end of a block, and of its lines
+fn trim(mut inlines: Vec<Inline>) -> Vec<Inline> {
+    if let Some(Inline::Text(text)) = inlines.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    for i in 1..inlines.len() {
+        if let (Inline::LineBreak, Inline::Text(text)) = (&inlines[i - 1], &inlines[i]) {
+            inlines[i] = Inline::Text(text.trim_start().to_string());
+        }
+    }
+    if let Some(Inline::Text(text)) = inlines.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    inlines.retain(|inline| !matches!(inline, Inline::Text(text) if text.is_empty()));
+
+    inlines
+}
+
+fn is_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Builds the Pango markup of the text of a block.
+///
+/// Spoilers are numbered in order, only the ones in `revealed` are shown.
+/// The hidden ones are links to `spoiler:<number>`, to be revealed on click.
+pub fn to_markup(inlines: &[Inline], revealed: &HashSet<usize>) -> String {
+    let mut markup = String::new();
+    let mut spoilers = 0;
+    push_markup(&mut markup, inlines, revealed, &mut spoilers, false);
+
+    markup
+}
+
+/// Whether the text of a block has a spoiler.
+pub fn has_spoilers(inlines: &[Inline]) -> bool {
+    count_spoilers(inlines) > 0
+}
+
+fn count_spoilers(inlines: &[Inline]) -> usize {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Spoiler(_, content) => 1 + count_spoilers(content),
+            Inline::Styled(_, content) | Inline::Link(_, content) => count_spoilers(content),
+            Inline::Text(_) | Inline::LineBreak => 0,
+        })
+        .sum()
+}
+
+fn push_markup(
+    markup: &mut String,
+    inlines: &[Inline],
+    revealed: &HashSet<usize>,
+    spoilers: &mut usize,
+    // Links can't be nested
+    in_link: bool,
+) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) if in_link => markup.push_str(&html_escape(text)),
+            Inline::Text(text) => markup.push_str(&markup_links(&html_escape(text))),
+            Inline::LineBreak => markup.push('\n'),
+            Inline::Styled(style, content) => {
+                let (open, close) = match style {
+                    Style::Bold => ("<b>".to_string(), "</b>"),
+                    Style::Italic => ("<i>".to_string(), "</i>"),
+                    Style::Underline => ("<u>".to_string(), "</u>"),
+                    Style::Strikethrough => ("<s>".to_string(), "</s>"),
+                    Style::Code => ("<tt>".to_string(), "</tt>"),
+                    Style::Superscript => ("<sup>".to_string(), "</sup>"),
+                    Style::Subscript => ("<sub>".to_string(), "</sub>"),
+                    Style::Color(color) => (format!("<span foreground=\"{}\">", color), "</span>"),
+                    Style::Background(color) => {
+                        (format!("<span background=\"{}\">", color), "</span>")
+                    }
+                };
+                markup.push_str(&open);
+                push_markup(markup, content, revealed, spoilers, in_link);
+                markup.push_str(close);
+            }
+            Inline::Link(_, content) if in_link => {
+                push_markup(markup, content, revealed, spoilers, in_link);
+            }
+            Inline::Link(href, content) => {
+                // The link is left out until the spoilers in it are revealed, so
+                // clicking on them reveals them instead of opening it
+                let first = *spoilers;
+                let hidden =
+                    (first..first + count_spoilers(content)).any(|id| !revealed.contains(&id));
+                if hidden {
+                    push_markup(markup, content, revealed, spoilers, in_link);
+                } else {
+                    markup.push_str(&format!("<a href=\"{}\">", html_escape(href)));
+                    push_markup(markup, content, revealed, spoilers, true);
+                    markup.push_str("</a>");
+                }
+            }
+            Inline::Spoiler(reason, content) => {
+                let id = *spoilers;
+                *spoilers += 1;
+
+                if let Some(reason) = reason {
+                    markup.push_str(&format!("<i>({})</i> ", html_escape(reason)));
+                }
+
+                // Spoilers in a hidden spoiler are hidden along with it
+                if revealed.contains(&id) || in_link {
+                    push_markup(markup, content, revealed, spoilers, in_link);
+                } else {
+                    markup.push_str(&format!("<a href=\"spoiler:{}\">", id));
+                    markup.push_str(HIDDEN_SPAN);
+                    push_markup(markup, content, revealed, spoilers, true);
+                    markup.push_str("</span></a>");
+                }
+            }
+        }
+    }
+}
+
+// Hidden spoilers are drawn over a background of the same color
+const HIDDEN_SPAN: &str = "<span foreground=\"#888a85\" background=\"#888a85\" underline=\"none\">";
+
+/// The number of the spoiler a link of the markup reveals.
+pub fn spoiler_id(uri: &str) -> Option<usize> {
+    uri.strip_prefix("spoiler:")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(text.to_string())
+    }
+
+    #[test]
+    fn test_nested_lists() {
+        let blocks = parse("<ol start=\"3\"><li>one<ul><li>nested</li></ul></li><li>two</li></ol>");
+        let nested = Block::List {
+            start: None,
+            items: vec![vec![Block::Paragraph(vec![text("nested")])]],
+        };
+        assert_eq!(
+            blocks,
+            vec![Block::List {
+                start: Some(3),
+                items: vec![
+                    vec![Block::Paragraph(vec![text("one")]), nested],
+                    vec![Block::Paragraph(vec![text("two")])],
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_tables() {
+        let blocks = parse(
+            "<table><thead><tr><th>Name</th><th>Value</th></tr></thead>\
+             <tbody><tr><td><b>x</b></td><td> 1 </td></tr></tbody></table>",
+        );
+        let cell = |header, content| TableCell { header, content };
+        assert_eq!(
+            blocks,
+            vec![Block::Table(vec![
+                vec![
+                    cell(true, vec![text("Name")]),
+                    cell(true, vec![text("Value")])
+                ],
+                vec![
+                    cell(false, vec![Inline::Styled(Style::Bold, vec![text("x")])]),
+                    cell(false, vec![text("1")]),
+                ],
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_code_language() {
+        let blocks = parse("<pre><code class=\"language-rust\">fn main() {\n}\n</code></pre>");
+        assert_eq!(
+            blocks,
+            vec![Block::Code {
+                language: Some(String::from("rust")),
+                text: String::from("fn main() {\n}"),
+            }]
+        );
+
+        let blocks = parse("<pre><code>  keep   spaces</code></pre>");
+        assert_eq!(
+            blocks,
+            vec![Block::Code {
+                language: None,
+                text: String::from("  keep   spaces"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_markup_escaping() {
+        let blocks = parse("<p>1 &lt; 2 &amp;&amp; <b>&lt;i&gt;</b></p>");
+        let inlines = match &blocks[..] {
+            [Block::Paragraph(inlines)] => inlines,
+            _ => panic!("Not a paragraph: {:?}", blocks),
+        };
+        assert_eq!(
+            to_markup(inlines, &HashSet::new()),
+            "1 &lt; 2 &amp;&amp; <b>&lt;i&gt;</b>"
+        );
+
+        let inlines = vec![Inline::Styled(
+            Style::Color(String::from("#ff0000")),
+            vec![text("red")],
+        )];
+        assert_eq!(
+            to_markup(&inlines, &HashSet::new()),
+            "<span foreground=\"#ff0000\">red</span>"
+        );
+    }
+
+    #[test]
+    fn test_spoilers() {
+        let blocks = parse("<p>The end: <span data-mx-spoiler=\"plot\">they win</span></p>");
+        let inlines = match &blocks[..] {
+            [Block::Paragraph(inlines)] => inlines,
+            _ => panic!("Not a paragraph: {:?}", blocks),
+        };
+        assert!(has_spoilers(inlines));
+
+        let hidden = to_markup(inlines, &HashSet::new());
+        assert_eq!(
+            hidden,
+            format!(
+                "The end: <i>(plot)</i> <a href=\"spoiler:0\">{}they win</span></a>",
+                HIDDEN_SPAN
+            )
+        );
+        assert_eq!(spoiler_id("spoiler:0"), Some(0));
+
+        let revealed: HashSet<usize> = vec![0].into_iter().collect();
+        assert_eq!(
+            to_markup(inlines, &revealed),
+            "The end: <i>(plot)</i> they win"
+        );
+    }
+
+    #[test]
+    fn test_spoilers_in_links() {
+        let inlines = vec![Inline::Link(
+            String::from("https://gnome.org"),
+            vec![Inline::Spoiler(None, vec![text("secret")])],
+        )];
+
+        // The spoiler stays hidden, and reveals it on click instead of opening the link
+        let hidden = to_markup(&inlines, &HashSet::new());
+        assert_eq!(
+            hidden,
+            format!("<a href=\"spoiler:0\">{}secret</span></a>", HIDDEN_SPAN)
+        );
+
+        let revealed: HashSet<usize> = vec![0].into_iter().collect();
+        assert_eq!(
+            to_markup(&inlines, &revealed),
+            "<a href=\"https://gnome.org\">secret</a>"
+        );
+    }
+}
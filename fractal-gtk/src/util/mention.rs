@@ -0,0 +1,213 @@
+//! Users and rooms mentioned in the composer, sent as matrix.to links.
+//!
+//! https://matrix.org/docs/spec/client_server/r0.6.1#user-room-and-group-mentions
+
+use crate::util::sanitize::escape;
+use matrix_sdk::identifiers::{RoomIdOrAliasId, UserId};
+use std::convert::TryFrom;
+
+// The text tags marking mentions in the composer are named after this and the
+// ID they link to
+const TAG_PREFIX: &str = "mention:";
+
+/// A mention in some text, with the byte range of its name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mention {
+    pub start: usize,
+    pub end: usize,
+    // The ID or alias of the user or room
+    pub id: String,
+}
+
+const MATRIX_TO_PREFIX: &str = "https://matrix.to/#/";
+
+/// What a matrix.to link points to, when it's a user or a room.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    User(UserId),
+    Room(RoomIdOrAliasId),
+}
+
+pub fn matrix_to_uri(id: &str) -> String {
+    format!("{}{}", MATRIX_TO_PREFIX, id)
+}
+
+/// Parses a matrix.to link to a user or a room, but not to an event in a room.
+pub fn parse_matrix_to(uri: &str) -> Option<Target> {
+    let path = uri.strip_prefix(MATRIX_TO_PREFIX)?;
+    // The query holds the servers to join the room through
+    let path = path.split('?').next()?;
+    // Links to events are followed by their ID
+    if path.contains('/') {
+        return None;
+    }
+    let id = percent_decode(path)?;
+
+    match id.chars().next()? {
+        '@' => UserId::try_from(id.as_str()).ok().map(Target::User),
+        '#' | '!' => RoomIdOrAliasId::try_from(id.as_str())
+            .ok()
+            .map(Target::Room),
+        _ => None,
+    }
+}
+
+pub fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut iter = text.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+pub fn tag_name(id: &str) -> String {
+    format!("{}{}", TAG_PREFIX, id)
+}
+
+/// The ID a mention tag links to, if it is one.
+pub fn id_from_tag_name(name: &str) -> Option<&str> {
+    name.strip_prefix(TAG_PREFIX)
+}
+
+/// Turns the mentions into links in Markdown text.
+pub fn to_markdown(text: &str, mentions: &[Mention]) -> String {
+    replace(text, mentions, escape_markdown, |name, id| {
+        format!("[{}](<{}>)", escape_markdown(name), matrix_to_uri(id))
+    })
+}
+
+/// Turns the mentions into links in plain text, as HTML.
+pub fn to_html(text: &str, mentions: &[Mention]) -> String {
+    let plain = |text: &str| escape(text).replace('\n', "<br>");
+    replace(text, mentions, plain, |name, id| {
+        format!(
+            "<a href=\"{}\">{}</a>",
+            escape(&matrix_to_uri(id)),
+            escape(name)
+        )
+    })
+}
+
+fn replace(
+    text: &str,
+    mentions: &[Mention],
+    other: impl Fn(&str) -> String,
+    link: impl Fn(&str, &str) -> String,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    for mention in mentions {
+        let name = match text.get(mention.start..mention.end) {
+            Some(name) if mention.start >= pos && !name.is_empty() => name,
+            _ => continue,
+        };
+
+        out.push_str(&other(&text[pos..mention.start]));
+        out.push_str(&link(name, &mention.id));
+        pos = mention.end;
+    }
+    out.push_str(&other(&text[pos..]));
+
+    out
+}
+
+// Only the link text is escaped, the rest of the text is Markdown written by the user
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mention(text: &str, name: &str, id: &str) -> Mention {
+        let start = text.find(name).unwrap();
+        Mention {
+            start,
+            end: start + name.len(),
+            id: id.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_to_html() {
+        let text = "Hi Alice <3\nsee #gnome";
+        let mentions = vec![
+            mention(text, "Alice", "@alice:gnome.org"),
+            mention(text, "#gnome", "#gnome:gnome.org"),
+        ];
+        assert_eq!(
+            to_html(text, &mentions),
+            "Hi <a href=\"https://matrix.to/#/@alice:gnome.org\">Alice</a> &lt;3<br>see \
+             <a href=\"https://matrix.to/#/#gnome:gnome.org\">#gnome</a>"
+        );
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let text = "**Hi** [Bob]";
+        let mentions = vec![mention(text, "[Bob]", "@bob:gnome.org")];
+        assert_eq!(
+            to_markdown(text, &mentions),
+            "**Hi** [\\[Bob\\]](<https://matrix.to/#/@bob:gnome.org>)"
+        );
+    }
+
+    #[test]
+    fn test_parse_matrix_to() {
+        let user = UserId::try_from("@alice:gnome.org").unwrap();
+        let alias = RoomIdOrAliasId::try_from("#gnome:gnome.org").unwrap();
+        let room = RoomIdOrAliasId::try_from("!abc:gnome.org").unwrap();
+
+        let target = parse_matrix_to("https://matrix.to/#/@alice:gnome.org");
+        assert_eq!(target, Some(Target::User(user)));
+        let target = parse_matrix_to("https://matrix.to/#/%23gnome%3Agnome.org");
+        assert_eq!(target, Some(Target::Room(alias)));
+        let target = parse_matrix_to("https://matrix.to/#/!abc:gnome.org?via=gnome.org");
+        assert_eq!(target, Some(Target::Room(room)));
+
+        // Links to events aren't mentions
+        let target = parse_matrix_to("https://matrix.to/#/!abc:gnome.org/$event:gnome.org");
+        assert_eq!(target, None);
+        assert_eq!(
+            parse_matrix_to("https://gnome.org/#/@alice:gnome.org"),
+            None
+        );
+        assert_eq!(parse_matrix_to("https://matrix.to/#/%2"), None);
+    }
+
+    #[test]
+    fn test_ignores_invalid_mentions() {
+        let text = "Hi Alice";
+        let mentions = vec![
+            mention(text, "Alice", "@alice:gnome.org"),
+            // Overlapping the previous one
+            mention(text, "Ali", "@ali:gnome.org"),
+            Mention {
+                start: 6,
+                end: 42,
+                id: "@out:gnome.org".to_string(),
+            },
+        ];
+        assert_eq!(
+            to_html(text, &mentions),
+            "Hi <a href=\"https://matrix.to/#/@alice:gnome.org\">Alice</a>"
+        );
+    }
+}
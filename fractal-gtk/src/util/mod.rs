@@ -14,7 +14,15 @@ use std::io::Error as IoError;
 use std::path::PathBuf;
 use std::sync::mpsc::SendError;
 
+pub mod blurhash;
+pub mod diff;
+pub mod emoji;
+pub mod html;
 pub mod i18n;
+pub mod mention;
+pub mod permalink;
+pub mod sanitize;
+pub mod voice;
 
 pub fn cache_dir_path(dir: Option<&str>, name: &str) -> Result<PathBuf, IoError> {
     let path = CACHE_PATH.join(dir.unwrap_or_default());
@@ -65,6 +73,28 @@ pub fn set_markdown_schema(md: bool) {
     }
 }
 
+pub fn get_state_events_schema() -> bool {
+    SettingsSchemaSource::get_default()
+        .and_then(|s| s.lookup("org.gnome.Fractal", true))
+        .map(|_| {
+            let settings: Settings = Settings::new("org.gnome.Fractal");
+            settings.get_boolean("show-state-events")
+        })
+        .unwrap_or(true)
+}
+
+pub fn set_state_events_schema(show: bool) {
+    if SettingsSchemaSource::get_default()
+        .and_then(|s| s.lookup("org.gnome.Fractal", true))
+        .is_some()
+    {
+        let settings: Settings = Settings::new("org.gnome.Fractal");
+        if let Err(err) = settings.set_boolean("show-state-events", show) {
+            error!("Can't save whether state events are shown: {:?}", err);
+        }
+    }
+}
+
 pub fn get_border_radius(ctx: &gtk::StyleContext) -> i32 {
     let state = ctx.get_state();
     gtk::StyleContextExt::get_property(ctx, "border-radius", state)
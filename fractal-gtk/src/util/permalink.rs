@@ -0,0 +1,210 @@
+//! Links to rooms and to messages in them, as matrix.to links or `matrix:` URIs.
+//!
+//! https://matrix.org/docs/spec/appendices#matrix-to-navigation
+//! https://github.com/matrix-org/matrix-doc/blob/master/proposals/2312-matrix-uri.md
+
+use crate::util::mention::{matrix_to_uri, percent_decode};
+use matrix_sdk::identifiers::{EventId, RoomIdOrAliasId};
+use std::convert::TryFrom;
+
+const MATRIX_TO_PREFIX: &str = "https://matrix.to/#/";
+const MATRIX_SCHEME: &str = "matrix:";
+
+/// The kind of link to make.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    MatrixTo,
+    MatrixUri,
+}
+
+/// A room, or a message in it, a link points to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Permalink {
+    pub room: RoomIdOrAliasId,
+    pub event: Option<EventId>,
+    // The servers to join the room through
+    pub via: Vec<String>,
+}
+
+impl Permalink {
+    pub fn to_uri(&self, format: Format) -> String {
+        let mut uri = match format {
+            Format::MatrixTo => {
+                let mut path = percent_encode(self.room.as_str());
+                if let Some(event) = &self.event {
+                    path.push('/');
+                    path.push_str(&percent_encode(event.as_str()));
+                }
+                matrix_to_uri(&path)
+            }
+            Format::MatrixUri => {
+                let (kind, id) = self.room.as_str().split_at(1);
+                let kind = if kind == "#" { "r" } else { "roomid" };
+                let mut uri = format!("{}{}/{}", MATRIX_SCHEME, kind, percent_encode(id));
+                if let Some(event) = &self.event {
+                    uri.push_str("/e/");
+                    uri.push_str(&percent_encode(&event.as_str()[1..]));
+                }
+                uri
+            }
+        };
+
+        for (idx, server) in self.via.iter().enumerate() {
+            uri.push(if idx == 0 { '?' } else { '&' });
+            uri.push_str("via=");
+            uri.push_str(&percent_encode(server));
+        }
+
+        uri
+    }
+}
+
+/// Parses a matrix.to link or a `matrix:` URI to a room or to a message.
+pub fn parse(uri: &str) -> Option<Permalink> {
+    let (path, query) = match uri.find('?') {
+        Some(idx) => (&uri[..idx], Some(&uri[idx + 1..])),
+        None => (uri, None),
+    };
+
+    let (room, event) = if let Some(path) = path.strip_prefix(MATRIX_TO_PREFIX) {
+        let mut segments = path.split('/').map(percent_decode);
+        let room = segments.next()??;
+        let event = match segments.next() {
+            Some(event) => Some(event?),
+            None => None,
+        };
+        if segments.next().is_some() {
+            return None;
+        }
+        (room, event)
+    } else if let Some(path) = path.strip_prefix(MATRIX_SCHEME) {
+        let segments: Vec<_> = path.split('/').collect();
+        let (kind, id, event) = match segments.as_slice() {
+            [kind, id] => (*kind, *id, None),
+            [kind, id, "e", event] => (*kind, *id, Some(*event)),
+            _ => return None,
+        };
+        let sigil = match kind {
+            "r" => '#',
+            "roomid" => '!',
+            _ => return None,
+        };
+        let room = format!("{}{}", sigil, percent_decode(id)?);
+        let event = match event {
+            Some(event) => Some(format!("${}", percent_decode(event)?)),
+            None => None,
+        };
+        (room, event)
+    } else {
+        return None;
+    };
+
+    let room = match room.chars().next()? {
+        '#' | '!' => RoomIdOrAliasId::try_from(room.as_str()).ok()?,
+        _ => return None,
+    };
+    let event = match event {
+        Some(event) => Some(EventId::try_from(event.as_str()).ok()?),
+        None => None,
+    };
+    let via = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter_map(|param| param.strip_prefix("via="))
+        .filter_map(percent_decode)
+        .collect();
+
+    Some(Permalink { room, event, via })
+}
+
+// Encodes everything but the characters allowed in a path segment of a URI
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~!$'()*+,;:@".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permalink(room: &str, event: Option<&str>, via: &[&str]) -> Permalink {
+        Permalink {
+            room: RoomIdOrAliasId::try_from(room).unwrap(),
+            event: event.map(|event| EventId::try_from(event).unwrap()),
+            via: via.iter().map(|server| server.to_string()).collect(),
+        }
+    }
+
+    // Event IDs of recent room versions are hashes, which can have slashes
+    const EVENT: &str = "$acR1l0raoZnm60CBwAVgqbZqoO/mYU81xysh1u7XcJk";
+    const ENCODED_EVENT: &str = "acR1l0raoZnm60CBwAVgqbZqoO%2FmYU81xysh1u7XcJk";
+
+    #[test]
+    fn test_to_uri() {
+        let link = permalink("!abc:gnome.org", Some(EVENT), &["gnome.org", "matrix.org"]);
+        assert_eq!(
+            link.to_uri(Format::MatrixTo),
+            format!(
+                "https://matrix.to/#/!abc:gnome.org/${}?via=gnome.org&via=matrix.org",
+                ENCODED_EVENT
+            )
+        );
+        assert_eq!(
+            link.to_uri(Format::MatrixUri),
+            format!(
+                "matrix:roomid/abc:gnome.org/e/{}?via=gnome.org&via=matrix.org",
+                ENCODED_EVENT
+            )
+        );
+
+        let link = permalink("#gnome:gnome.org", None, &[]);
+        assert_eq!(
+            link.to_uri(Format::MatrixTo),
+            "https://matrix.to/#/%23gnome:gnome.org"
+        );
+        assert_eq!(link.to_uri(Format::MatrixUri), "matrix:r/gnome:gnome.org");
+    }
+
+    #[test]
+    fn test_parse() {
+        let link = permalink("!abc:gnome.org", Some(EVENT), &["gnome.org"]);
+        let uri = format!(
+            "https://matrix.to/#/!abc:gnome.org/${}?via=gnome.org",
+            ENCODED_EVENT
+        );
+        assert_eq!(parse(&uri), Some(link.clone()));
+        let uri = format!(
+            "matrix:roomid/abc:gnome.org/e/{}?via=gnome.org&action=join",
+            ENCODED_EVENT
+        );
+        assert_eq!(parse(&uri), Some(link));
+
+        let link = permalink("#gnome:gnome.org", None, &[]);
+        assert_eq!(
+            parse("https://matrix.to/#/#gnome:gnome.org"),
+            Some(link.clone())
+        );
+        assert_eq!(parse("matrix:r/gnome%3Agnome.org"), Some(link));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        // Links to users aren't permalinks
+        assert_eq!(parse("https://matrix.to/#/@alice:gnome.org"), None);
+        assert_eq!(parse("matrix:u/alice:gnome.org"), None);
+        assert_eq!(parse("matrix:roomid/abc:gnome.org/e"), None);
+        assert_eq!(
+            parse("https://matrix.to/#/!abc:gnome.org/$event:gnome.org/more"),
+            None
+        );
+        assert_eq!(parse("https://gnome.org/#/!abc:gnome.org"), None);
+    }
+}
@@ -0,0 +1,306 @@
+//! Cleans up the HTML of messages, keeping only the tags, attributes and URL
+//! schemes allowed by the specification.
+//!
+//! https://matrix.org/docs/spec/client_server/r0.6.1#m-room-message-msgtypes
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, parse_fragment, QualName};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use url::Url;
+
+// Deeper tags are dropped, only their text is kept
+const MAX_DEPTH: usize = 100;
+
+const ALLOWED_TAGS: &[&str] = &[
+    "font",
+    "del",
+    "s",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "p",
+    "a",
+    "ul",
+    "ol",
+    "sup",
+    "sub",
+    "li",
+    "b",
+    "i",
+    "u",
+    "strong",
+    "em",
+    "strike",
+    "code",
+    "hr",
+    "br",
+    "div",
+    "table",
+    "thead",
+    "tbody",
+    "tr",
+    "th",
+    "td",
+    "caption",
+    "pre",
+    "span",
+    "img",
+    "details",
+    "summary",
+    "mx-reply",
+];
+
+// Tags whose content isn't meant to be shown as text
+const DROPPED_TAGS: &[&str] = &[
+    "script", "style", "head", "title", "iframe", "object", "embed", "noscript", "template", "svg",
+    "math", "textarea", "select",
+];
+
+const VOID_TAGS: &[&str] = &["br", "hr", "img"];
+
+const LINK_SCHEMES: &[&str] = &["https", "http", "ftp", "mailto", "magnet"];
+
+/// Returns the HTML of a message with only what's allowed in it.
+pub fn sanitize(html: &str) -> String {
+    let dom = parse_fragment(
+        RcDom::default(),
+        Default::default(),
+        QualName::new(None, ns!(html), local_name!("body")),
+        vec![],
+    )
+    .one(html);
+
+    let mut out = String::with_capacity(html.len());
+    // The fragment is parsed as the children of an html element
+    if let Some(root) = dom.document.children.borrow().first() {
+        for child in root.children.borrow().iter() {
+            push_node(&mut out, child, 0);
+        }
+    }
+
+    out
+}
+
+fn push_node(out: &mut String, node: &Handle, depth: usize) {
+    let (name, attrs) = match &node.data {
+        NodeData::Text { contents } => {
+            out.push_str(&escape(&contents.borrow()));
+            return;
+        }
+        NodeData::Element { name, attrs, .. } => (&*name.local, attrs.borrow()),
+        _ => return,
+    };
+
+    if DROPPED_TAGS.contains(&name) {
+        return;
+    }
+
+    if depth >= MAX_DEPTH {
+        push_text(out, node);
+        return;
+    }
+
+    let allowed = ALLOWED_TAGS.contains(&name);
+    if allowed {
+        out.push('<');
+        out.push_str(name);
+        for attr in attrs.iter() {
+            let attr_name = &*attr.name.local;
+            if let Some(value) = allowed_attr(name, attr_name, &attr.value) {
+                out.push_str(&format!(" {}=\"{}\"", attr_name, escape(&value)));
+            }
+        }
+        out.push('>');
+
+        if VOID_TAGS.contains(&name) {
+            return;
+        }
+    }
+
+    for child in node.children.borrow().iter() {
+        push_node(out, child, depth + 1);
+    }
+
+    if allowed {
+        out.push_str(&format!("</{}>", name));
+    }
+}
+
+// Pushes the text of a node without walking it recursively, as it may be
+// nested too deep for that
+fn push_text(out: &mut String, node: &Handle) {
+    let mut stack = vec![node.clone()];
+    while let Some(node) = stack.pop() {
+        match &node.data {
+            NodeData::Text { contents } => out.push_str(&escape(&contents.borrow())),
+            NodeData::Element { name, .. } if DROPPED_TAGS.contains(&&*name.local) => {}
+            _ => stack.extend(node.children.borrow().iter().rev().cloned()),
+        }
+    }
+}
+
+// Returns the value to keep for an attribute of a tag, if it's allowed
+fn allowed_attr(tag: &str, attr: &str, value: &str) -> Option<String> {
+    match (tag, attr) {
+        ("font", "data-mx-bg-color")
+        | ("font", "data-mx-color")
+        | ("font", "color")
+        | ("span", "data-mx-bg-color")
+        | ("span", "data-mx-color")
+        | ("span", "data-mx-spoiler")
+        | ("a", "name")
+        | ("img", "width")
+        | ("img", "height")
+        | ("img", "alt")
+        | ("img", "title")
+        | ("ol", "start") => Some(value.to_string()),
+        ("a", "target") if value == "_blank" => Some(value.to_string()),
+        ("a", "href") if has_scheme(value, LINK_SCHEMES) => Some(value.to_string()),
+        ("img", "src") if has_scheme(value, &["mxc"]) => Some(value.to_string()),
+        ("code", "class") => {
+            let classes: Vec<&str> = value
+                .split_whitespace()
+                .filter(|class| class.starts_with("language-"))
+                .collect();
+            Some(classes.join(" ")).filter(|classes| !classes.is_empty())
+        }
+        _ => None,
+    }
+}
+
+fn has_scheme(url: &str, schemes: &[&str]) -> bool {
+    Url::parse(url.trim()).map_or(false, |url| schemes.contains(&url.scheme()))
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_allowed_html() {
+        let html = "<p>Some <b>bold</b> and <a href=\"https://gnome.org\">link</a></p>";
+        assert_eq!(sanitize(html), html);
+
+        let html = "<ol start=\"3\"><li>one</li></ol>";
+        assert_eq!(sanitize(html), html);
+
+        let html = "<pre><code class=\"language-rust\">fn main() {}</code></pre>";
+        assert_eq!(sanitize(html), html);
+
+        let html = "<span data-mx-spoiler=\"plot\">hidden</span><br><hr>";
+        assert_eq!(sanitize(html), html);
+
+        let html = "<s>struck</s> and <del>deleted</del>";
+        assert_eq!(sanitize(html), html);
+    }
+
+    #[test]
+    fn test_removes_scripts() {
+        let out = sanitize("hi<script>alert('xss')</script><style>p {}</style>");
+        assert_eq!(out, "hi");
+
+        let out = sanitize("<svg><script>alert(1)</script></svg>text");
+        assert_eq!(out, "text");
+    }
+
+    #[test]
+    fn test_unwraps_unknown_tags() {
+        let out = sanitize("<marquee><b>moving</b></marquee><custom>text</custom>");
+        assert_eq!(out, "<b>moving</b>text");
+
+        let out = sanitize("<form action=\"https://evil.com\"><input value=\"x\">send</form>");
+        assert_eq!(out, "send");
+    }
+
+    #[test]
+    fn test_removes_attributes() {
+        let out = sanitize("<p onclick=\"alert(1)\" style=\"color: red\">text</p>");
+        assert_eq!(out, "<p>text</p>");
+
+        let out = sanitize("<img src=\"mxc://server/id\" onerror=\"alert(1)\" alt=\"cat\">");
+        assert_eq!(out, "<img src=\"mxc://server/id\" alt=\"cat\">");
+
+        let out = sanitize("<code class=\"language-rust evil\">x</code>");
+        assert_eq!(out, "<code class=\"language-rust\">x</code>");
+
+        let out = sanitize("<a href=\"https://gnome.org\" target=\"_top\">x</a>");
+        assert_eq!(out, "<a href=\"https://gnome.org\">x</a>");
+    }
+
+    #[test]
+    fn test_removes_unknown_schemes() {
+        let out = sanitize("<a href=\"javascript:alert(1)\">x</a>");
+        assert_eq!(out, "<a>x</a>");
+
+        let out = sanitize("<a href=\" JavaScript:alert(1)\">x</a>");
+        assert_eq!(out, "<a>x</a>");
+
+        let out = sanitize("<a href=\"data:text/html,<script>alert(1)</script>\">x</a>");
+        assert_eq!(out, "<a>x</a>");
+
+        let out = sanitize("<a href=\"/relative\">x</a>");
+        assert_eq!(out, "<a>x</a>");
+
+        let out = sanitize("<img src=\"https://tracker.com/pixel.png\" alt=\"pixel\">");
+        assert_eq!(out, "<img alt=\"pixel\">");
+
+        let out = sanitize("<a href=\"mailto:me@gnome.org\">mail</a>");
+        assert_eq!(out, "<a href=\"mailto:me@gnome.org\">mail</a>");
+    }
+
+    #[test]
+    fn test_escapes_text_and_attributes() {
+        let out = sanitize("1 &lt; 2 &amp;&amp; <b>3 &gt; 2</b>");
+        assert_eq!(out, "1 &lt; 2 &amp;&amp; <b>3 &gt; 2</b>");
+
+        let out = sanitize("<font color='\"><script>alert(1)</script>'>x</font>");
+        assert_eq!(
+            out,
+            "<font color=\"&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;\">x</font>"
+        );
+    }
+
+    #[test]
+    fn test_removes_comments() {
+        let out = sanitize("a<!-- <script>alert(1)</script> -->b");
+        assert_eq!(out, "ab");
+    }
+
+    #[test]
+    fn test_limits_depth() {
+        let html = format!(
+            "{}deep{}",
+            "<b>".repeat(MAX_DEPTH * 2),
+            "</b>".repeat(MAX_DEPTH * 2)
+        );
+        let out = sanitize(&html);
+        assert_eq!(out.matches("<b>").count(), MAX_DEPTH);
+        assert_eq!(out.matches("</b>").count(), MAX_DEPTH);
+        assert!(out.contains("deep"));
+
+        // Too deep to be walked recursively
+        let html = format!("<div>{}deep</div>", "<span>".repeat(10_000));
+        let out = sanitize(&html);
+        assert!(out.starts_with("<div>") && out.ends_with("</div>"));
+        assert!(out.contains("deep"));
+    }
+}
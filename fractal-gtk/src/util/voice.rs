@@ -0,0 +1,249 @@
+//! Recording of voice messages and their waveforms.
+
+use anyhow::{anyhow, Error as AnyhowError};
+use glib::clone;
+use gst::prelude::*;
+use gst::ClockTime;
+use log::error;
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+
+/// The number of bars in the waveform of a voice message.
+pub const WAVEFORM_SAMPLES: usize = 100;
+/// The highest value of a waveform bar.
+pub const WAVEFORM_MAX: u16 = 1024;
+
+/// A finished recording, ready to be sent.
+#[derive(Debug, Clone)]
+pub struct VoiceMessage {
+    pub path: PathBuf,
+    // In milliseconds
+    pub duration: u64,
+    pub waveform: Vec<u16>,
+}
+
+/// Records the microphone into an Opus file until it is stopped or cancelled.
+#[derive(Debug)]
+pub struct VoiceRecorder {
+    pipeline: gst::Pipeline,
+    path: PathBuf,
+    // The peak of every `level` interval, in dB
+    levels: Rc<RefCell<Vec<f64>>>,
+    watch: Option<glib::SourceId>,
+    started: Instant,
+}
+
+impl VoiceRecorder {
+    pub fn start(path: PathBuf) -> Result<Self, AnyhowError> {
+        let pipeline = gst::parse_launch(
+            "autoaudiosrc ! audioconvert ! audioresample ! level interval=100000000 \
+             ! opusenc ! oggmux ! filesink name=sink",
+        )?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| anyhow!("The recording pipeline is not a gst::Pipeline"))?;
+        let location = path
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid recording path: {}", path.display()))?;
+        pipeline
+            .get_by_name("sink")
+            .ok_or_else(|| anyhow!("The recording pipeline has no sink"))?
+            .set_property("location", &location)?;
+
+        let levels = Rc::new(RefCell::new(vec![]));
+        let bus = pipeline
+            .get_bus()
+            .ok_or_else(|| anyhow!("The recording pipeline has no bus"))?;
+        let watch = bus.add_watch_local(clone!(@strong levels => move |_, msg| {
+            if let Some(peak) = peak(msg) {
+                levels.borrow_mut().push(peak);
+            } else if let gst::MessageView::Error(err) = msg.view() {
+                error!("Error while recording: {}", err.get_error());
+            }
+            glib::Continue(true)
+        }))?;
+
+        if let Err(err) = pipeline.set_state(gst::State::Playing) {
+            glib::source_remove(watch);
+            let _ = pipeline.set_state(gst::State::Null);
+            let _ = fs::remove_file(&path);
+            return Err(err.into());
+        }
+
+        Ok(Self {
+            pipeline,
+            path,
+            levels,
+            watch: Some(watch),
+            started: Instant::now(),
+        })
+    }
+
+    /// Finishes writing the file, returns `None` if the recording failed.
+    pub fn stop(mut self) -> Option<VoiceMessage> {
+        if let Some(watch) = self.watch.take() {
+            glib::source_remove(watch);
+        }
+        let duration = self.started.elapsed().as_millis() as u64;
+
+        // The muxer only writes a complete file once the stream has ended
+        self.pipeline.send_event(gst::event::Eos::new());
+        let bus = self.pipeline.get_bus()?;
+        let finished = bus.timed_pop_filtered(
+            ClockTime::from_seconds(5),
+            &[gst::MessageType::Eos, gst::MessageType::Error],
+        );
+        let _ = self.pipeline.set_state(gst::State::Null);
+
+        match finished.as_ref().map(|msg| msg.view()) {
+            Some(gst::MessageView::Eos(_)) => Some(VoiceMessage {
+                path: self.path.clone(),
+                duration,
+                waveform: waveform(&self.levels.borrow(), WAVEFORM_SAMPLES),
+            }),
+            Some(gst::MessageView::Error(err)) => {
+                error!("Couldn't finish the recording: {}", err.get_error());
+                let _ = fs::remove_file(&self.path);
+                None
+            }
+            _ => {
+                error!("Timed out finishing the recording");
+                let _ = fs::remove_file(&self.path);
+                None
+            }
+        }
+    }
+
+    /// Stops recording and throws the file away.
+    pub fn cancel(self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+        if let Err(err) = fs::remove_file(&self.path) {
+            error!("Couldn't remove {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+impl Drop for VoiceRecorder {
+    fn drop(&mut self) {
+        if let Some(watch) = self.watch.take() {
+            glib::source_remove(watch);
+        }
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+/// Decodes an audio file and returns its loudness every 100 ms, in dB.
+///
+/// This blocks until the whole file is read, so it shouldn't run on the main
+/// thread.
+pub fn audio_levels(path: &Path) -> Result<Vec<f64>, AnyhowError> {
+    let pipeline = gst::parse_launch(
+        "filesrc name=src ! decodebin ! audioconvert ! level interval=100000000 \
+         ! fakesink sync=false",
+    )?
+    .downcast::<gst::Pipeline>()
+    .map_err(|_| anyhow!("The decoding pipeline is not a gst::Pipeline"))?;
+    let location = path
+        .to_str()
+        .ok_or_else(|| anyhow!("Invalid audio path: {}", path.display()))?;
+    pipeline
+        .get_by_name("src")
+        .ok_or_else(|| anyhow!("The decoding pipeline has no source"))?
+        .set_property("location", &location)?;
+    let bus = pipeline
+        .get_bus()
+        .ok_or_else(|| anyhow!("The decoding pipeline has no bus"))?;
+
+    pipeline.set_state(gst::State::Playing)?;
+    let mut levels = vec![];
+    let result = loop {
+        let msg = match bus.timed_pop_filtered(
+            gst::CLOCK_TIME_NONE,
+            &[
+                gst::MessageType::Element,
+                gst::MessageType::Eos,
+                gst::MessageType::Error,
+            ],
+        ) {
+            Some(msg) => msg,
+            None => break Err(anyhow!("The decoding pipeline stopped unexpectedly")),
+        };
+        match msg.view() {
+            gst::MessageView::Eos(_) => break Ok(levels),
+            gst::MessageView::Error(err) => break Err(err.get_error().into()),
+            _ => levels.extend(peak(&msg)),
+        }
+    };
+    let _ = pipeline.set_state(gst::State::Null);
+
+    result
+}
+
+// The loudest channel of a message posted by the `level` element
+fn peak(msg: &gst::Message) -> Option<f64> {
+    let structure = match msg.view() {
+        gst::MessageView::Element(element) => element.get_structure()?,
+        _ => return None,
+    };
+    if structure.get_name() != "level" {
+        return None;
+    }
+
+    structure
+        .get::<glib::ValueArray>("peak")
+        .ok()??
+        .iter()
+        .filter_map(|value| value.get_some::<f64>().ok())
+        .fold(None, |max: Option<f64>, peak| {
+            Some(max.map_or(peak, |max| max.max(peak)))
+        })
+}
+
+/// Reduces loudness levels in dB to at most `samples` bars, scaled so the
+/// loudest one is `WAVEFORM_MAX`.
+pub fn waveform(levels_db: &[f64], samples: usize) -> Vec<u16> {
+    let samples = samples.min(levels_db.len());
+    let amplitudes: Vec<f64> = (0..samples)
+        .map(|i| {
+            let bucket =
+                &levels_db[i * levels_db.len() / samples..(i + 1) * levels_db.len() / samples];
+            bucket
+                .iter()
+                .map(|db| 10f64.powf(db / 20.0))
+                .fold(0.0, f64::max)
+        })
+        .collect();
+
+    let loudest = amplitudes.iter().cloned().fold(0.0, f64::max);
+    if loudest <= 0.0 {
+        return vec![0; samples];
+    }
+    amplitudes
+        .into_iter()
+        .map(|amplitude| (amplitude / loudest * f64::from(WAVEFORM_MAX)).round() as u16)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waveform() {
+        assert_eq!(waveform(&[], WAVEFORM_SAMPLES), Vec::<u16>::new());
+        assert_eq!(
+            waveform(&[0.0, -6.0206, f64::NEG_INFINITY], WAVEFORM_SAMPLES),
+            vec![1024, 512, 0]
+        );
+        assert_eq!(waveform(&[f64::NEG_INFINITY; 3], 2), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_waveform_buckets() {
+        assert_eq!(waveform(&[-20.0, 0.0, -20.0, -20.0], 2), vec![1024, 102]);
+        // Quiet recordings are scaled up
+        assert_eq!(waveform(&[-40.0, -46.0206], 2), vec![1024, 512]);
+    }
+}
@@ -1,15 +1,110 @@
-use crate::app::AppRuntime;
-use crate::appop::{member::member_level, AppOp};
+use crate::app::{AppRuntime, RUNTIME};
+use crate::appop::{command::Command, member::member_level, AppOp};
+use crate::backend::{room, HandleError};
 use crate::model::member::Member;
+use crate::model::room::Room;
 use crate::ui::member::build_memberbox_widget;
+use crate::util::emoji::{self, Emoji};
+use crate::util::mention;
+use crate::APPOP;
 use glib::clone;
 use gtk::prelude::*;
 use gtk::TextTag;
 use log::info;
+use matrix_sdk::identifiers::{RoomAliasId, RoomId, UserId};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
+// The number of characters to type after the colon before emoji are suggested
+const MIN_SHORTCODE_LEN: usize = 2;
+const MAX_EMOJI_SUGGESTIONS: usize = 8;
+const MAX_ROOM_SUGGESTIONS: usize = 5;
+const MAX_MEMBER_SUGGESTIONS: usize = 5;
+
+/// How well a member matches what was typed, the best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MemberMatch {
+    Prefix,
+    WordPrefix,
+    Substring,
+    // The typed characters appear in order in the name
+    Fuzzy,
+}
+
+impl MemberMatch {
+    fn find(search: &str, name: &str) -> Option<Self> {
+        if name.starts_with(search) {
+            Some(Self::Prefix)
+        } else if name
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word.starts_with(search))
+        {
+            Some(Self::WordPrefix)
+        } else if name.contains(search) {
+            Some(Self::Substring)
+        } else if is_subsequence(search, name) {
+            Some(Self::Fuzzy)
+        } else {
+            None
+        }
+    }
+}
+
+/// An entry of the autocompletion popover.
+#[derive(Debug, Clone)]
+pub enum Suggestion {
+    Member(Member),
+    Command(Command),
+    Emoji(&'static Emoji),
+    Room {
+        id: RoomId,
+        name: Option<String>,
+        alias: Option<RoomAliasId>,
+    },
+}
+
+impl Suggestion {
+    // The text replacing what was typed when the suggestion is picked
+    fn completion(&self) -> String {
+        match self {
+            Self::Member(member) => member
+                .alias
+                .clone()
+                .unwrap_or_default()
+                .trim_end_matches(" (IRC)")
+                .to_owned(),
+            Self::Command(command) => format!("/{} ", command.name()),
+            Self::Emoji(emoji) => emoji.with_skin_tone(emoji::get_skin_tone()),
+            Self::Room { id, name, alias } => alias
+                .as_ref()
+                .map(ToString::to_string)
+                .or_else(|| name.clone())
+                .unwrap_or_else(|| id.to_string()),
+        }
+    }
+
+    // What the completion links to when the message is sent
+    fn mention_id(&self) -> Option<String> {
+        match self {
+            Self::Member(member) => Some(member.uid.to_string()),
+            Self::Room { id, alias, .. } => Some(
+                alias
+                    .as_ref()
+                    .map_or_else(|| id.to_string(), ToString::to_string),
+            ),
+            Self::Command(_) | Self::Emoji(_) => None,
+        }
+    }
+
+    // Called once the suggestion is picked, not only selected with the arrows
+    fn picked(&self) {
+        if let Self::Emoji(_) = self {
+            emoji::add_recent(&self.completion());
+        }
+    }
+}
+
 pub struct Autocomplete {
     app_runtime: AppRuntime,
     entry: sourceview4::View,
@@ -20,6 +115,8 @@ pub struct Autocomplete {
     popover_position: Option<i32>,
     popover_search: Option<String>,
     popover_closing: bool,
+    // The rooms whose members were fetched to be completed
+    members_requested: HashSet<RoomId>,
 }
 
 impl Autocomplete {
@@ -40,6 +137,7 @@ impl Autocomplete {
             popover_position: None,
             popover_search: None,
             popover_closing: false,
+            members_requested: HashSet::new(),
         }
     }
 
@@ -259,12 +357,22 @@ impl Autocomplete {
                             let first = p1.iter().collect::<String>();
                             if own.borrow().popover_position.is_none() {
                                 if !is_tab {
-                                    if let Some(at_pos) = first.rfind('@') {
+                                    if is_command_name(&first) {
+                                        own.borrow_mut().popover_position = Some(0);
+                                    } else if let Some(colon_pos) =
+                                        token_start(&first, ':', MIN_SHORTCODE_LEN)
+                                    {
+                                        own.borrow_mut().popover_position = Some(colon_pos as i32);
+                                    } else if let Some(hash_pos) = token_start(&first, '#', 1) {
+                                        own.borrow_mut().popover_position = Some(hash_pos as i32);
+                                    } else if let Some(at_pos) = first.rfind('@') {
+                                        let at_pos = first[..at_pos].chars().count();
                                         own.borrow_mut().popover_position = Some(at_pos as i32);
                                     }
                                 } else if let Some(space_pos) =
                                     first.rfind(|c: char| c.is_whitespace())
                                 {
+                                    let space_pos = first[..space_pos].chars().count();
                                     own.borrow_mut().popover_position = Some(space_pos as i32 + 1);
                                 } else {
                                     own.borrow_mut().popover_position = Some(0);
@@ -275,17 +383,17 @@ impl Autocomplete {
                         if own.borrow().popover_position.is_some() {
                             app_runtime.update_state_with(clone!(@strong own => move |state| {
                                 let list = own
-                                    .borrow()
+                                    .borrow_mut()
                                     .autocomplete(text, buffer.get_property_cursor_position(), state);
                                 let widget_list = own
                                     .borrow_mut()
                                     .autocomplete_show_popover(list, state);
-                                for (alias, widget) in widget_list.iter() {
+                                for (suggestion, widget) in widget_list.iter() {
                                     widget.connect_key_press_event(clone!(
                                     @strong own,
-                                    @strong alias
+                                    @strong suggestion
                                     => move |_, ev| {
-                                        own.borrow_mut().autocomplete_insert(alias.clone());
+                                        own.borrow_mut().autocomplete_insert(&suggestion);
                                         let ev = ev
                                             .downcast_ref::<gdk::EventKey>()
                                             .unwrap();
@@ -293,6 +401,7 @@ impl Autocomplete {
                                         if ev.get_keyval() == gdk::keys::constants::Return
                                             || ev.get_keyval() == gdk::keys::constants::Tab
                                         {
+                                            suggestion.picked();
                                             own.borrow_mut().autocomplete_enter();
                                         }
                                         Inhibit(true)
@@ -300,9 +409,10 @@ impl Autocomplete {
 
                                     widget.connect_button_press_event(clone!(
                                     @strong own,
-                                    @strong alias
+                                    @strong suggestion
                                     => move |_, _| {
-                                        own.borrow_mut().autocomplete_insert(alias.clone());
+                                        own.borrow_mut().autocomplete_insert(&suggestion);
+                                        suggestion.picked();
                                         own.borrow_mut().autocomplete_enter();
                                         Inhibit(true)
                                     }));
@@ -317,23 +427,45 @@ impl Autocomplete {
         });
     }
 
-    pub fn autocomplete_insert(&mut self, alias: String) {
+    pub fn autocomplete_insert(&mut self, suggestion: &Suggestion) {
         if let Some(start_pos) = self.popover_position {
+            let completion = suggestion.completion();
             if let Some(buffer) = self.entry.get_buffer() {
                 if let Some(mark) = buffer.get_insert() {
                     let mut start_iter = buffer.get_iter_at_offset(start_pos as i32);
                     let mut end_iter = buffer.get_iter_at_mark(&mark);
                     buffer.delete(&mut start_iter, &mut end_iter);
-                    buffer.insert(&mut start_iter, &alias);
+                    buffer.insert(&mut start_iter, &completion);
                     buffer.place_cursor(&start_iter);
+
+                    // Marks the completion so it's sent as a link
+                    if let Some(id) = suggestion.mention_id().filter(|_| !completion.is_empty()) {
+                        let name = mention::tag_name(&id);
+                        let tag = buffer.get_tag_table().and_then(|table| {
+                            table.lookup(&name).or_else(|| {
+                                let tag = TextTag::new(Some(&name));
+                                table.add(&tag);
+                                Some(tag)
+                            })
+                        });
+                        if let Some(tag) = tag {
+                            let mention_start = buffer.get_iter_at_offset(start_pos);
+                            buffer.apply_tag(&tag, &mention_start, &start_iter);
+                        }
+                    }
                 }
             }
 
+            // Only mentions are highlighted
+            if suggestion.mention_id().is_none() {
+                return;
+            }
+
             /* highlight member inside the entry */
             /* we need to set the highlight here the first time
              * because the ui changes from others are blocked as long we hold the look */
             if let Some(buffer) = self.entry.get_buffer() {
-                self.highlighted_entry.push(alias);
+                self.highlighted_entry.push(completion);
 
                 let start_iter = buffer.get_start_iter();
                 let end_iter = buffer.get_end_iter();
@@ -377,8 +509,11 @@ impl Autocomplete {
                 .map(|alias| alias.to_lowercase())
             {
                 for (index, text) in input.match_indices(&alias) {
-                    let start_iter = buffer.get_iter_at_offset(index as i32);
-                    let end_iter = buffer.get_iter_at_offset((index + text.len()) as i32);
+                    // The buffer is indexed by characters, not bytes
+                    let start = input[..index].chars().count();
+                    let end = start + text.chars().count();
+                    let start_iter = buffer.get_iter_at_offset(start as i32);
+                    let end_iter = buffer.get_iter_at_offset(end as i32);
 
                     buffer.apply_tag_by_name("alias-highlight", &start_iter, &end_iter);
                 }
@@ -419,9 +554,9 @@ impl Autocomplete {
 
     pub fn autocomplete_show_popover(
         &mut self,
-        list: Vec<Member>,
+        list: Vec<Suggestion>,
         op: &AppOp,
-    ) -> HashMap<String, gtk::EventBox> {
+    ) -> Vec<(Suggestion, gtk::EventBox)> {
         let session_client = op
             .login_data
             .as_ref()
@@ -433,32 +568,38 @@ impl Autocomplete {
             self.listbox.remove(ch);
         }
 
-        let widget_list: HashMap<String, gtk::EventBox> = list
+        let widget_list: Vec<(Suggestion, gtk::EventBox)> = list
             .into_iter()
-            .map(|member| {
-                let alias = member
-                    .alias
-                    .clone()
-                    .unwrap_or_default()
-                    .trim_end_matches(" (IRC)")
-                    .to_owned();
-                let member_level = member_level(op.active_room.as_ref(), &op.rooms, &member.uid);
-                let widget = build_memberbox_widget(
-                    session_client.clone(),
-                    user_info_cache.clone(),
-                    member,
-                    member_level,
-                    true,
-                );
-
-                (alias, widget)
+            .map(|suggestion| {
+                let widget = match suggestion {
+                    Suggestion::Member(ref member) => {
+                        let member_level =
+                            member_level(op.active_room.as_ref(), &op.rooms, &member.uid);
+                        build_memberbox_widget(
+                            session_client.clone(),
+                            user_info_cache.clone(),
+                            member.clone(),
+                            member_level,
+                            true,
+                        )
+                    }
+                    Suggestion::Command(command) => build_command_widget(command),
+                    Suggestion::Emoji(emoji) => build_emoji_widget(emoji),
+                    Suggestion::Room {
+                        ref id,
+                        ref name,
+                        ref alias,
+                    } => build_room_widget(id, name.as_deref(), alias.as_ref()),
+                };
+
+                (suggestion, widget)
             })
             .collect();
 
         if !widget_list.is_empty() {
             widget_list
-                .values()
-                .for_each(|widget| self.listbox.add(widget));
+                .iter()
+                .for_each(|(_, widget)| self.listbox.add(widget));
 
             self.popover.set_relative_to(Some(&self.entry));
             self.popover
@@ -481,20 +622,50 @@ impl Autocomplete {
         widget_list
     }
 
-    pub fn autocomplete(&self, text: Option<String>, pos: i32, op: &AppOp) -> Vec<Member> {
-        let mut list: Vec<Member> = vec![];
+    pub fn autocomplete(&mut self, text: Option<String>, pos: i32, op: &AppOp) -> Vec<Suggestion> {
+        let mut list: Vec<Suggestion> = vec![];
         let rooms = &op.rooms;
         match text {
             None => {}
             Some(txt) => {
                 if let Some(at_pos) = self.popover_position {
-                    let last = {
+                    let typed = {
                         let start = at_pos as usize;
                         let end = pos as usize;
-                        txt.get(start..end)
+                        // Positions are in characters, not bytes
+                        txt.chars()
+                            .skip(start)
+                            .take(end.saturating_sub(start))
+                            .collect::<String>()
                     };
+                    let last = Some(typed.as_str()).filter(|typed| !typed.is_empty());
                     if let Some(last) = last {
                         info!("Matching string '{}'", last);
+                        if at_pos == 0 && is_command_name(last) {
+                            return Command::ALL
+                                .iter()
+                                .filter(|command| command.name().starts_with(&last[1..]))
+                                .map(|command| Suggestion::Command(*command))
+                                .collect();
+                        }
+
+                        if let Some(shortcode) = last.strip_prefix(':') {
+                            let shortcode = shortcode.trim_end_matches(':');
+                            if shortcode.is_empty() || shortcode.contains(char::is_whitespace) {
+                                return list;
+                            }
+
+                            return emoji::search(shortcode)
+                                .into_iter()
+                                .take(MAX_EMOJI_SUGGESTIONS)
+                                .map(Suggestion::Emoji)
+                                .collect();
+                        }
+
+                        if let Some(search) = last.strip_prefix('#') {
+                            return search_rooms(&search.to_lowercase(), op);
+                        }
+
                         /*remove @ from string*/
                         let w = if last.starts_with('@') {
                             last[1..].to_lowercase()
@@ -502,22 +673,12 @@ impl Autocomplete {
                             last.to_lowercase()
                         };
 
-                        /* Search for the 5 most recent active users */
                         if let Some(aroom) = op.active_room.clone() {
                             if let Some(r) = rooms.get(&aroom) {
-                                let mut count = 0;
-                                for (_, m) in r.members.iter() {
-                                    let alias = &m.alias.clone().unwrap_or_default().to_lowercase();
-                                    let uid = m.uid.localpart().to_lowercase();
-                                    if alias.starts_with(&w) || uid.starts_with(&w) {
-                                        list.push(m.clone());
-                                        count += 1;
-                                        /* Search only for 5 matching users */
-                                        if count > 4 {
-                                            break;
-                                        }
-                                    }
+                                if (r.members.len() as u64) < r.n_members {
+                                    self.load_members(&aroom, op);
                                 }
+                                list = search_members(&w, r, op.replying_to());
                             }
                         }
                     }
@@ -526,4 +687,313 @@ impl Autocomplete {
         };
         list
     }
+
+    // Fetches the members missing from a big room, once
+    fn load_members(&mut self, room_id: &RoomId, op: &AppOp) {
+        if !self.members_requested.insert(room_id.clone()) {
+            return;
+        }
+
+        let session_client =
+            unwrap_or_unit_return!(op.login_data.as_ref().map(|ld| ld.session_client.clone()));
+        let room_id = room_id.clone();
+        RUNTIME.spawn(async move {
+            match room::get_room_members(session_client, room_id).await {
+                Ok((room, members)) => {
+                    APPOP!(set_room_members, (room, members));
+                }
+                Err(err) => {
+                    err.handle_error();
+                }
+            }
+        });
+    }
+}
+
+// Where a word starting with the sigil, like the `:shortcode:` of an emoji or
+// a `#room`, is being typed at the end of the text, once it's long enough to
+// be searched
+fn token_start(text: &str, sigil: char, min_len: usize) -> Option<usize> {
+    let sigil_pos = text.rfind(sigil)?;
+    let token = &text[sigil_pos + sigil.len_utf8()..];
+    let after_space = text[..sigil_pos]
+        .chars()
+        .last()
+        .map_or(true, char::is_whitespace);
+
+    if after_space && token.chars().count() >= min_len && !token.contains(char::is_whitespace) {
+        Some(text[..sigil_pos].chars().count())
+    } else {
+        None
+    }
+}
+
+// The members matching the search, the ones who spoke last and match best first
+fn search_members(search: &str, room: &Room, replying_to: Option<&UserId>) -> Vec<Suggestion> {
+    // The position of the last message of each sender, from the most recent
+    let mut last_spoke: HashMap<&UserId, usize> = HashMap::new();
+    for msg in room.messages.iter().rev() {
+        let next = last_spoke.len();
+        last_spoke.entry(&msg.sender).or_insert(next);
+    }
+
+    let mut found: Vec<_> = room
+        .members
+        .values()
+        .filter_map(|member| {
+            let alias = member.alias.clone().unwrap_or_default().to_lowercase();
+            let localpart = member.uid.localpart().to_lowercase();
+            let quality = [&alias, &localpart]
+                .iter()
+                .filter_map(|name| MemberMatch::find(search, name))
+                .min()?;
+            Some((member, quality, alias))
+        })
+        .collect();
+
+    // Fuzzy matches are too loose to come before the others, even for active users
+    found.sort_by_cached_key(|(member, quality, alias)| {
+        let not_replied = replying_to != Some(&member.uid);
+        let recency = last_spoke.get(&member.uid).copied().unwrap_or(usize::MAX);
+        (
+            *quality == MemberMatch::Fuzzy,
+            not_replied,
+            recency,
+            *quality,
+            alias.clone(),
+        )
+    });
+
+    found
+        .into_iter()
+        .take(MAX_MEMBER_SUGGESTIONS)
+        .map(|(member, _, _)| Suggestion::Member(member.clone()))
+        .collect()
+}
+
+// Whether all the characters of the search appear in the text in the same order
+fn is_subsequence(search: &str, text: &str) -> bool {
+    let mut chars = text.chars();
+    search.chars().all(|c| chars.any(|t| t == c))
+}
+
+// The joined rooms whose alias or name matches the search
+fn search_rooms(search: &str, op: &AppOp) -> Vec<Suggestion> {
+    if search.contains(char::is_whitespace) {
+        return vec![];
+    }
+
+    let mut rooms: Vec<_> = op
+        .rooms
+        .values()
+        .filter(|room| room.membership.is_joined())
+        .filter(|room| {
+            let alias = room
+                .alias
+                .as_ref()
+                .map(|alias| alias.as_str()[1..].to_lowercase());
+            let name = room.name.as_ref().map(|name| name.to_lowercase());
+            alias.map_or(false, |alias| alias.starts_with(search))
+                || name.map_or(false, |name| name.contains(search))
+        })
+        .collect();
+    // The ones with an alias first, as they make the nicest links
+    rooms.sort_by_key(|room| (room.alias.is_none(), room.name.clone()));
+
+    rooms
+        .into_iter()
+        .take(MAX_ROOM_SUGGESTIONS)
+        .map(|room| Suggestion::Room {
+            id: room.id.clone(),
+            name: room.name.clone(),
+            alias: room.alias.clone(),
+        })
+        .collect()
+}
+
+// Whether the text is the beginning of a command, before its arguments
+fn is_command_name(text: &str) -> bool {
+    text.starts_with('/') && !text.starts_with("//") && !text.contains(char::is_whitespace)
+}
+
+fn build_command_widget(command: Command) -> gtk::EventBox {
+    let event_box = gtk::EventBox::new();
+    let v = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+    let usage = gtk::Label::new(Some(&command.usage()));
+    usage.set_halign(gtk::Align::Start);
+    usage.get_style_context().add_class("command-usage");
+
+    let description = gtk::Label::new(Some(&command.description()));
+    description.set_halign(gtk::Align::Start);
+    description.set_ellipsize(pango::EllipsizeMode::End);
+    description
+        .get_style_context()
+        .add_class("command-description");
+
+    v.set_margin_start(3);
+    v.pack_start(&usage, true, true, 0);
+    v.pack_start(&description, true, true, 0);
+
+    event_box.add(&v);
+    event_box.show_all();
+    event_box
+}
+
+fn build_emoji_widget(emoji: &Emoji) -> gtk::EventBox {
+    let event_box = gtk::EventBox::new();
+    let h = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+
+    let glyph = gtk::Label::new(Some(&emoji.with_skin_tone(emoji::get_skin_tone())));
+    glyph.get_style_context().add_class("emoji-suggestion");
+
+    let shortcode = gtk::Label::new(Some(&format!(":{}:", emoji.shortcode)));
+    shortcode.set_halign(gtk::Align::Start);
+    shortcode.set_ellipsize(pango::EllipsizeMode::End);
+
+    h.set_margin_start(3);
+    h.pack_start(&glyph, false, false, 0);
+    h.pack_start(&shortcode, true, true, 0);
+
+    event_box.add(&h);
+    event_box.show_all();
+    event_box
+}
+
+fn build_room_widget(
+    id: &RoomId,
+    name: Option<&str>,
+    alias: Option<&RoomAliasId>,
+) -> gtk::EventBox {
+    let event_box = gtk::EventBox::new();
+    let v = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+    let title = gtk::Label::new(name.or_else(|| alias.map(RoomAliasId::as_str)));
+    title.set_halign(gtk::Align::Start);
+    title.set_ellipsize(pango::EllipsizeMode::End);
+
+    let address = alias.map_or_else(|| id.to_string(), ToString::to_string);
+    let address = gtk::Label::new(Some(&address));
+    address.set_halign(gtk::Align::Start);
+    address.set_ellipsize(pango::EllipsizeMode::End);
+    address.get_style_context().add_class("room-address");
+
+    v.set_margin_start(3);
+    v.pack_start(&title, true, true, 0);
+    v.pack_start(&address, true, true, 0);
+
+    event_box.add(&v);
+    event_box.show_all();
+    event_box
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::message::Message;
+    use crate::model::room::{RoomMembership, RoomTag};
+    use chrono::prelude::*;
+    use matrix_sdk::identifiers::EventId;
+    use std::convert::TryFrom;
+
+    fn room_with_members(members: &[(&str, &str)]) -> Room {
+        let room_id = RoomId::try_from("!room:example.org").unwrap();
+        let mut room = Room::new(room_id, RoomMembership::Joined(RoomTag::None));
+        for (uid, alias) in members {
+            let uid = UserId::try_from(*uid).unwrap();
+            let member = Member {
+                uid: uid.clone(),
+                alias: Some(alias.to_string()),
+                avatar: None,
+            };
+            room.members.insert(uid, member);
+        }
+        room
+    }
+
+    // Adds a message from each sender, the last one being the most recent
+    fn speak(room: &mut Room, senders: &[&str]) {
+        for (i, sender) in senders.iter().enumerate() {
+            let id = EventId::try_from(format!("$msg{}:example.org", i).as_str()).unwrap();
+            let mut msg = Message::new(
+                room.id.clone(),
+                UserId::try_from(*sender).unwrap(),
+                String::from("hi"),
+                String::from("m.text"),
+                Some(id),
+            );
+            msg.date = Local.timestamp(1_600_000_000 + i as i64, 0);
+            room.messages.add(msg);
+        }
+    }
+
+    fn uids(suggestions: Vec<Suggestion>) -> Vec<String> {
+        suggestions
+            .into_iter()
+            .filter_map(|suggestion| match suggestion {
+                Suggestion::Member(member) => Some(member.uid.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_member_match() {
+        assert_eq!(MemberMatch::find("ali", "alice"), Some(MemberMatch::Prefix));
+        assert_eq!(
+            MemberMatch::find("smi", "john smith"),
+            Some(MemberMatch::WordPrefix)
+        );
+        assert_eq!(
+            MemberMatch::find("ice", "alice"),
+            Some(MemberMatch::Substring)
+        );
+        assert_eq!(MemberMatch::find("alc", "alice"), Some(MemberMatch::Fuzzy));
+        assert_eq!(MemberMatch::find("cla", "alice"), None);
+    }
+
+    #[test]
+    fn test_members_ranked_by_activity() {
+        let mut room = room_with_members(&[
+            ("@alice:example.org", "Alice"),
+            ("@alan:example.org", "Alan"),
+            ("@albert:example.org", "Albert"),
+            ("@xavier:example.org", "Xavier Lanza"),
+            ("@bob:example.org", "Bob"),
+        ]);
+        speak(
+            &mut room,
+            &[
+                "@alice:example.org",
+                "@alan:example.org",
+                "@xavier:example.org",
+            ],
+        );
+
+        // Xavier spoke last, but only matches loosely
+        assert_eq!(
+            uids(search_members("al", &room, None)),
+            vec![
+                "@alan:example.org",
+                "@alice:example.org",
+                "@albert:example.org",
+                "@xavier:example.org",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replied_member_first() {
+        let mut room = room_with_members(&[
+            ("@alice:example.org", "Alice"),
+            ("@alan:example.org", "Alan"),
+        ]);
+        speak(&mut room, &["@alice:example.org", "@alan:example.org"]);
+        let alice = UserId::try_from("@alice:example.org").unwrap();
+
+        assert_eq!(
+            uids(search_members("al", &room, Some(&alice))),
+            vec!["@alice:example.org", "@alan:example.org"]
+        );
+    }
 }
@@ -1,5 +1,4 @@
 use glib::clone;
-use glib::source::Continue;
 use gtk::prelude::*;
 use gtk::RevealerTransitionType;
 
@@ -40,19 +39,6 @@ impl NewMessageDivider {
         row.add(&revealer);
         row.show_all();
 
-        /* Self destruction
-         * destroy the NewMessageDivider after it's added to the History with a couple of
-         * seconds delay */
-        row.connect_parent_set(clone!(@weak revealer => move |_, _| {
-            glib::timeout_add_local(5000, clone!(
-                @weak revealer as r
-                => @default-return Continue(false), move || {
-                    /* when the user closes the room the divider gets destroyed and this timeout
-                     * does nothing, but that's fine */
-                    r.set_reveal_child(false);
-                    Continue(false)
-                }));
-        }));
         revealer.connect_property_child_revealed_notify(clone!(
         @weak row as r
         => move |_| {
@@ -67,6 +53,11 @@ impl NewMessageDivider {
         }
     }
 
+    /// Hides the divider, which then removes itself from the history.
+    pub fn dismiss(&self) {
+        self.revealer.set_reveal_child(false);
+    }
+
     pub fn get_widget(&self) -> &gtk::ListBoxRow {
         &self.widget
     }
@@ -0,0 +1,190 @@
+use gtk::prelude::*;
+
+use crate::util::emoji::{self, Category, EMOJI, SKIN_TONES};
+use crate::util::i18n::i18n;
+
+// Shown in the skin tone selector
+const SKIN_TONE_SAMPLE: &str = "✋";
+const EMOJI_PER_LINE: u32 = 8;
+
+/// A popover to pick emoji from, inserting them at the cursor of a text view.
+#[derive(Clone)]
+pub struct EmojiPicker {
+    pub popover: gtk::Popover,
+    view: gtk::TextView,
+    search: gtk::SearchEntry,
+    skin_tone: gtk::ComboBoxText,
+    content: gtk::Box,
+}
+
+impl EmojiPicker {
+    pub fn new(view: gtk::TextView) -> Self {
+        let popover = gtk::Popover::new(None::<&gtk::Widget>);
+        popover.get_style_context().add_class("emoji-picker");
+
+        let search = gtk::SearchEntry::new();
+        search.set_placeholder_text(Some(&i18n("Search Emoji")));
+        search.set_hexpand(true);
+
+        let skin_tone = gtk::ComboBoxText::new();
+        skin_tone.set_tooltip_text(Some(&i18n("Skin Tone")));
+        for (idx, modifier) in SKIN_TONES.iter().enumerate() {
+            let sample = format!("{}{}", SKIN_TONE_SAMPLE, modifier);
+            skin_tone.append(Some(&idx.to_string()), &sample);
+        }
+        skin_tone.set_active_id(Some(&emoji::get_skin_tone().to_string()));
+
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        header.pack_start(&search, true, true, 0);
+        header.pack_start(&skin_tone, false, false, 0);
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        let hadjust: Option<&gtk::Adjustment> = None;
+        let vadjust: Option<&gtk::Adjustment> = None;
+        let scroll = gtk::ScrolledWindow::new(hadjust, vadjust);
+        scroll.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scroll.set_min_content_height(300);
+        scroll.add(&content);
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        container.set_border_width(6);
+        container.pack_start(&header, false, false, 0);
+        container.pack_start(&scroll, true, true, 0);
+        container.show_all();
+        popover.add(&container);
+
+        let picker = Self {
+            popover,
+            view,
+            search,
+            skin_tone,
+            content,
+        };
+        picker.connect();
+        picker
+    }
+
+    fn connect(&self) {
+        let picker = self.clone();
+        self.popover.connect_show(move |_| {
+            picker.search.set_text("");
+            picker.refresh();
+            picker.search.grab_focus();
+        });
+
+        let picker = self.clone();
+        self.search
+            .connect_search_changed(move |_| picker.refresh());
+
+        // Enter picks the best match
+        let picker = self.clone();
+        self.search.connect_activate(move |search| {
+            let tone = emoji::get_skin_tone();
+            if let Some(found) = emoji::search(&search.get_text()).first() {
+                picker.pick(&found.with_skin_tone(tone));
+            }
+        });
+
+        let picker = self.clone();
+        self.skin_tone.connect_changed(move |combo| {
+            let tone = combo
+                .get_active_id()
+                .and_then(|id| id.parse().ok())
+                .unwrap_or_default();
+            emoji::set_skin_tone(tone);
+            picker.refresh();
+        });
+    }
+
+    fn pick(&self, emoji: &str) {
+        if let Some(buffer) = self.view.get_buffer() {
+            buffer.insert_at_cursor(emoji);
+        }
+        emoji::add_recent(emoji);
+
+        self.popover.popdown();
+        self.view.grab_focus();
+    }
+
+    // Shows the emoji matching the search, or all of them by category
+    fn refresh(&self) {
+        for child in self.content.get_children().iter() {
+            self.content.remove(child);
+        }
+
+        let tone = emoji::get_skin_tone();
+        let text = self.search.get_text();
+        let search = text.trim();
+
+        if !search.is_empty() {
+            let found: Vec<_> = emoji::search(search)
+                .into_iter()
+                .map(|e| (e.with_skin_tone(tone), Some(e.shortcode)))
+                .collect();
+
+            if found.is_empty() {
+                let label = gtk::Label::new(Some(&i18n("No emoji found")));
+                label.get_style_context().add_class("dim-label");
+                label.set_margin_top(12);
+                self.content.pack_start(&label, false, false, 0);
+            } else {
+                self.add_section(None, found);
+            }
+        } else {
+            let recent = emoji::get_recent();
+            if !recent.is_empty() {
+                let recent = recent.into_iter().map(|e| (e, None)).collect();
+                self.add_section(Some(&i18n("Recently Used")), recent);
+            }
+
+            for category in Category::ALL {
+                let in_category = EMOJI
+                    .iter()
+                    .filter(|e| e.category == *category)
+                    .map(|e| (e.with_skin_tone(tone), Some(e.shortcode)))
+                    .collect();
+                self.add_section(Some(&category.name()), in_category);
+            }
+        }
+
+        self.content.show_all();
+    }
+
+    fn add_section(&self, title: Option<&str>, emoji: Vec<(String, Option<&str>)>) {
+        if let Some(title) = title {
+            let label = gtk::Label::new(Some(title));
+            label.set_halign(gtk::Align::Start);
+            label.get_style_context().add_class("emoji-section");
+            self.content.pack_start(&label, false, false, 0);
+        }
+
+        let flowbox = gtk::FlowBox::new();
+        flowbox.set_selection_mode(gtk::SelectionMode::None);
+        flowbox.set_activate_on_single_click(true);
+        flowbox.set_homogeneous(true);
+        flowbox.set_min_children_per_line(EMOJI_PER_LINE);
+        flowbox.set_max_children_per_line(EMOJI_PER_LINE);
+
+        for (glyph, shortcode) in emoji {
+            let label = gtk::Label::new(Some(&glyph));
+            label.get_style_context().add_class("emoji");
+            if let Some(shortcode) = shortcode {
+                label.set_tooltip_text(Some(&format!(":{}:", shortcode)));
+            }
+            flowbox.add(&label);
+        }
+
+        let picker = self.clone();
+        flowbox.connect_child_activated(move |_, child| {
+            let glyph = child
+                .get_child()
+                .and_then(|label| label.downcast::<gtk::Label>().ok())
+                .map(|label| label.get_text());
+            if let Some(glyph) = glyph {
+                picker.pick(&glyph);
+            }
+        });
+
+        self.content.pack_start(&flowbox, false, false, 0);
+    }
+}
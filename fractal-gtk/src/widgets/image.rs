@@ -1,5 +1,6 @@
 use crate::app::RUNTIME;
 use crate::backend::media;
+use crate::util::blurhash;
 use crate::util::get_border_radius;
 use either::Either;
 use gdk::prelude::GdkContextExt;
@@ -30,6 +31,8 @@ pub struct Image {
     pub thumb: bool,
     pub fixed_size: bool,
     pub centered: bool,
+    /// The BlurHash of the image and its full size, shown until it's loaded
+    pub blurhash: Option<(String, (i32, i32))>,
 }
 
 impl Image {
@@ -73,6 +76,7 @@ impl Image {
             fixed_size: false,
             centered: false,
             shrink_to_fit: false,
+            blurhash: None,
         }
     }
 
@@ -96,7 +100,13 @@ impl Image {
         self
     }
 
+    pub fn blurhash(mut self, blurhash: Option<(String, (i32, i32))>) -> Image {
+        self.blurhash = blurhash;
+        self
+    }
+
     pub fn build(self, session_client: MatrixClient) -> Image {
+        self.draw_placeholder();
         self.draw();
         self.load_async(session_client);
 
@@ -262,6 +272,18 @@ impl Image {
         });
     }
 
+    /// Shows the BlurHash as the image until the real one replaces it.
+    fn draw_placeholder(&self) {
+        if let Some((ref hash, (w, h))) = self.blurhash {
+            // Decoded at the size it's drawn at, it's blurry anyway
+            let (w, h) = match self.max_size {
+                Some((max_w, max_h)) => adjust_to(w, h, max_w, max_h),
+                None => (w, h),
+            };
+            *self.pixbuf.lock().unwrap() = blurhash::decode_pixbuf(hash, w, h);
+        }
+    }
+
     /// If `path` starts with mxc this func download the img async, in other case the image is loaded
     /// in the `image` widget scaled to size
     pub fn load_async(&self, session_client: MatrixClient) {
@@ -18,6 +18,8 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::backend::media;
+use anyhow::anyhow;
+use gdk_pixbuf::Pixbuf;
 use glib::clone;
 
 use gst::prelude::*;
@@ -37,7 +39,7 @@ use fragile::Fragile;
 
 use std::cell::RefCell;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use matrix_sdk::Client as MatrixClient;
@@ -45,6 +47,10 @@ use url::Url;
 
 use crate::app::RUNTIME;
 use crate::util::i18n::i18n;
+use crate::util::voice;
+
+// Longer audio files are most likely not voice messages, so no waveform is computed for them
+const WAVEFORM_MAX_DURATION: u64 = 5 * 60;
 
 pub trait PlayerExt {
     fn play(&self);
@@ -69,6 +75,9 @@ struct PlayerTimes {
     duration: gtk::Label,
     slider: gtk::Scale,
     slider_update: Rc<SignalHandlerId>,
+    // Replaces the slider when the waveform of the audio is known
+    waveform: gtk::DrawingArea,
+    samples: Rc<RefCell<Vec<u16>>>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -110,9 +119,61 @@ impl PlayerTimes {
         self.slider.block_signal(&self.slider_update);
         self.slider.set_value(seconds);
         self.slider.unblock_signal(&self.slider_update);
+        self.waveform.queue_draw();
 
         self.progressed.set_text(&format_duration(seconds as u32));
     }
+
+    fn set_waveform(&self, samples: Vec<u16>) {
+        if samples.is_empty() {
+            return;
+        }
+
+        *self.samples.borrow_mut() = samples;
+        self.slider.hide();
+        self.waveform.show();
+        self.waveform.queue_draw();
+    }
+
+    // The part of the audio played so far, from 0 to 1
+    fn progress(&self) -> f64 {
+        let adjustment = self.slider.get_adjustment();
+        let range = adjustment.get_upper() - adjustment.get_lower();
+        if range > 0.0 {
+            (self.slider.get_value() - adjustment.get_lower()) / range
+        } else {
+            0.0
+        }
+    }
+
+    /// Draws a bar for every sample, the ones already played more opaque.
+    fn draw_waveform(&self, cr: &cairo::Context) {
+        let samples = self.samples.borrow();
+        if samples.is_empty() {
+            return;
+        }
+
+        let width = f64::from(self.waveform.get_allocated_width());
+        let height = f64::from(self.waveform.get_allocated_height());
+        let style = self.waveform.get_style_context();
+        let color = style.get_color(style.get_state());
+        let bar_width = width / samples.len() as f64;
+        let played = (self.progress() * samples.len() as f64).round() as usize;
+
+        for (i, sample) in samples.iter().enumerate() {
+            let alpha = if i < played { 1.0 } else { 0.4 };
+            let bar_height =
+                (f64::from(*sample) / f64::from(voice::WAVEFORM_MAX) * height).max(2.0);
+            cr.set_source_rgba(color.red, color.green, color.blue, color.alpha * alpha);
+            cr.rectangle(
+                i as f64 * bar_width + bar_width * 0.15,
+                (height - bar_height) / 2.0,
+                bar_width * 0.7,
+                bar_height,
+            );
+            cr.fill();
+        }
+    }
 }
 
 fn format_duration(seconds: u32) -> String {
@@ -143,6 +204,8 @@ pub trait MediaPlayer {
     fn get_player(&self) -> gst_player::Player;
     fn get_controls(&self) -> Option<PlayerControls>;
     fn get_local_path_access(&self) -> Rc<RefCell<Option<PathBuf>>>;
+    /// Called once the media has been downloaded to `_path`.
+    fn media_downloaded(&self, _path: &Path) {}
 }
 
 trait ControlsConnection {
@@ -216,6 +279,13 @@ impl AudioPlayerWidget {
     }
 }
 
+impl AudioPlayerWidget {
+    /// Shows the waveform of a voice message instead of the slider.
+    pub fn set_waveform(&self, samples: Vec<u16>) {
+        self.controls.timer.set_waveform(samples);
+    }
+}
+
 impl MediaPlayer for AudioPlayerWidget {
     fn get_player(&self) -> gst_player::Player {
         self.player.clone()
@@ -228,6 +298,36 @@ impl MediaPlayer for AudioPlayerWidget {
     fn get_local_path_access(&self) -> Rc<RefCell<Option<PathBuf>>> {
         self.local_path.clone()
     }
+
+    // The waveform sent with voice messages is only kept in the events loaded
+    // with their JSON, otherwise it's computed from the file
+    fn media_downloaded(&self, path: &Path) {
+        if !self.controls.timer.samples.borrow().is_empty() {
+            return;
+        }
+
+        let path = path.to_path_buf();
+        let levels = RUNTIME.spawn_blocking(move || {
+            let uri = Url::from_file_path(&path)
+                .map_err(|_| anyhow!("Invalid audio path: {}", path.display()))?;
+            let duration = get_media_duration(&uri)?.seconds().unwrap_or_default();
+            if duration > WAVEFORM_MAX_DURATION {
+                return Ok(None);
+            }
+            voice::audio_levels(&path).map(Some)
+        });
+        let timer = self.controls.timer.clone();
+        glib::MainContext::default().spawn_local(async move {
+            match levels.await {
+                Ok(Ok(Some(levels))) => {
+                    timer.set_waveform(voice::waveform(&levels, voice::WAVEFORM_SAMPLES))
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(err)) => warn!("Couldn't compute the waveform: {:?}", err),
+                Err(err) => error!("Couldn't compute the waveform: {:?}", err),
+            }
+        });
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -516,6 +616,7 @@ impl<T: MediaPlayer + 'static> PlayerExt for T {
                 }
                 Ok(Ok(path)) => match Url::from_file_path(&path) {
                     Ok(uri) => {
+                        player.media_downloaded(&path);
                         *local_path.borrow_mut() = Some(path);
                         if !start_playing {
                             if let Some(controls) = player.get_controls() {
@@ -637,13 +738,34 @@ fn create_controls(player: &gst_player::Player) -> PlayerControls {
     let slider: gtk::Scale = builder.get_object("seek").unwrap();
     slider.set_range(0.0, 1.0);
     let slider_update = Rc::new(connect_update_slider(&slider, player));
+    let waveform: gtk::DrawingArea = builder.get_object("waveform").unwrap();
     let timer = PlayerTimes {
         container: timer_container,
         progressed,
         duration,
         slider,
         slider_update,
+        waveform: waveform.clone(),
+        samples: Rc::new(RefCell::new(vec![])),
     };
+
+    waveform.connect_draw(clone!(@strong timer => move |_, cr| {
+        timer.draw_waveform(cr);
+        Inhibit(false)
+    }));
+    // Seek to the clicked bar through the hidden slider
+    waveform.connect_button_press_event(clone!(@strong timer => move |waveform, event| {
+        let width = f64::from(waveform.get_allocated_width());
+        if width > 0.0 {
+            let adjustment = timer.slider.get_adjustment();
+            let (x, _) = event.get_position();
+            let fraction = (x / width).max(0.0).min(1.0);
+            let range = adjustment.get_upper() - adjustment.get_lower();
+            timer.slider.set_value(adjustment.get_lower() + fraction * range);
+        }
+        Inhibit(true)
+    }));
+
     PlayerControls {
         container,
         buttons,
@@ -700,3 +822,40 @@ pub fn get_media_duration(uri: &Url) -> Result<ClockTime, glib::Error> {
     let info = discoverer.discover_uri(uri.as_str())?;
     Ok(info.get_duration())
 }
+
+/// Decodes the first frame of a video.
+pub fn get_video_frame(uri: &Url) -> Result<Pixbuf, anyhow::Error> {
+    let pipeline = gst::parse_launch(
+        "uridecodebin name=decode ! videoconvert ! videoscale ! gdkpixbufsink name=sink",
+    )?
+    .downcast::<gst::Pipeline>()
+    .map_err(|_| anyhow!("The video frame pipeline is not a gst::Pipeline"))?;
+    pipeline
+        .get_by_name("decode")
+        .ok_or_else(|| anyhow!("The video frame pipeline has no decoder"))?
+        .set_property("uri", &uri.as_str())?;
+    let sink = pipeline
+        .get_by_name("sink")
+        .ok_or_else(|| anyhow!("The video frame pipeline has no sink"))?;
+    let bus = pipeline
+        .get_bus()
+        .ok_or_else(|| anyhow!("The video frame pipeline has no bus"))?;
+
+    // The sink keeps the frame it prerolled with once the pipeline is paused
+    pipeline.set_state(gst::State::Paused)?;
+    let msg = bus.timed_pop_filtered(
+        ClockTime::from_seconds(5),
+        &[gst::MessageType::AsyncDone, gst::MessageType::Error],
+    );
+    let frame = match msg.as_ref().map(|msg| msg.view()) {
+        Some(gst::MessageView::AsyncDone(_)) => sink
+            .get_property("last-pixbuf")?
+            .get::<Pixbuf>()?
+            .ok_or_else(|| anyhow!("The video has no frame")),
+        Some(gst::MessageView::Error(err)) => Err(err.get_error().into()),
+        _ => Err(anyhow!("Timed out decoding the video frame")),
+    };
+    let _ = pipeline.set_state(gst::State::Null);
+
+    frame
+}
@@ -2,24 +2,31 @@ use crate::appop::UserInfoCache;
 use crate::cache::download_to_cache;
 use crate::globals;
 use crate::ui::MessageContent as Message;
+use crate::ui::ReceiptContent;
+use crate::ui::ReplyContent;
 use crate::ui::RowType;
-use crate::util::i18n::i18n;
+use crate::util::html::{self, Block, Inline};
+use crate::util::i18n::{i18n, ni18n_f};
 use crate::util::markup_text;
+use crate::util::permalink;
 use crate::widgets;
 use crate::widgets::message_menu::MessageMenu;
+use crate::widgets::pill_text;
 use crate::widgets::AvatarExt;
 use crate::widgets::ClipContainer;
 use crate::widgets::{AudioPlayerWidget, PlayerExt, VideoPlayerWidget};
-use anyhow::Context;
 use chrono::prelude::*;
 use either::Either;
+use gio::ActionGroupExt;
 use glib::clone;
+use glib::ToVariant;
 use gtk::{prelude::*, ButtonExt, ContainerExt, LabelExt, Overlay, WidgetExt};
-use html2pango::block::{markup_html, HtmlBlock};
 use itertools::Itertools;
 use matrix_sdk::Client as MatrixClient;
-use sourceview4::BufferExt;
+use sourceview4::prelude::*;
+use std::cell::RefCell;
 use std::cmp::max;
+use std::collections::HashSet;
 use std::rc::Rc;
 
 // A message row in the room history
@@ -38,7 +45,7 @@ impl MessageBox {
         has_header: bool,
         is_temp: bool,
     ) -> Self {
-        let container = MessageBoxContainer::new();
+        let container = MessageBoxContainer::new(session_client.clone(), user_info_cache.clone());
 
         container.set_msg_styles(msg.mtype);
         let msg_widget = match msg.mtype {
@@ -79,6 +86,33 @@ impl MessageBox {
         Self::create(session_client, user_info_cache, msg, true, true)
     }
 
+    // create the row of a tmp message that couldn't be sent, with the
+    // actions to send it again or to give up
+    pub fn create_failed(
+        session_client: MatrixClient,
+        user_info_cache: UserInfoCache,
+        msg: &Message,
+        txn_id: &str,
+    ) -> Self {
+        let mb = Self::create(session_client, user_info_cache, msg, true, true);
+        let container = &mb.container;
+        container.root.get_style_context().add_class("msg-failed");
+
+        // +-----------------------------+
+        // | msg                         |
+        // +-----------------------------+
+        // | error | retry | cancel      |
+        // +-----------------------------+
+        let bx = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        container.eventbox.remove(mb.msg_widget.root());
+        bx.pack_start(mb.msg_widget.root(), true, true, 0);
+        bx.pack_start(&build_failed_bar(txn_id), false, false, 0);
+        container.eventbox.add(&bx);
+        container.root.show_all();
+
+        mb
+    }
+
     pub fn update_header(
         &mut self,
         session_client: MatrixClient,
@@ -127,15 +161,44 @@ impl MessageBox {
     }
 }
 
+fn build_failed_bar(txn_id: &str) -> gtk::Box {
+    let bar = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    bar.set_margin_start(50);
+    bar.get_style_context().add_class("msg-failed-bar");
+
+    let label = gtk::Label::new(Some(i18n("Couldn’t send the message").as_str()));
+    label.set_xalign(0.0);
+    label.get_style_context().add_class("msg-failed-label");
+    bar.pack_start(&label, false, false, 0);
+
+    let retry = gtk::Button::with_label(i18n("Retry").as_str());
+    retry.set_action_name(Some("app.retry-message"));
+    retry.set_action_target_value(Some(&glib::Variant::from(txn_id)));
+    let cancel = gtk::Button::with_label(i18n("Cancel").as_str());
+    cancel.set_action_name(Some("app.cancel-message"));
+    cancel.set_action_target_value(Some(&glib::Variant::from(txn_id)));
+
+    for button in &[&retry, &cancel] {
+        button.set_valign(gtk::Align::Center);
+        button.get_style_context().add_class("flat");
+        bar.pack_start(*button, false, false, 0);
+    }
+
+    bar
+}
+
 #[derive(Clone, Debug)]
 struct MessageBoxContainer {
     root: gtk::ListBoxRow,
     eventbox: gtk::EventBox,
     gesture: gtk::GestureLongPress,
+    // To show the users mentioned in the message
+    session_client: MatrixClient,
+    user_info_cache: UserInfoCache,
 }
 
 impl MessageBoxContainer {
-    fn new() -> Self {
+    fn new(session_client: MatrixClient, user_info_cache: UserInfoCache) -> Self {
         let eventbox = gtk::EventBox::new();
 
         let root = gtk::ListBoxRow::new();
@@ -150,6 +213,8 @@ impl MessageBoxContainer {
             root,
             eventbox,
             gesture,
+            session_client,
+            user_info_cache,
         }
     }
 
@@ -175,6 +240,8 @@ impl MessageBoxContainer {
     fn connect_right_click_menu(&self, msg: &Message, w: Option<&gtk::Widget>) -> Option<()> {
         let mtype = msg.mtype;
         let redactable = msg.redactable;
+        let editable = msg.editable;
+        let edited = msg.msg.replace.is_some();
         let widget = if let Some(l) = w {
             l
         } else {
@@ -184,7 +251,14 @@ impl MessageBoxContainer {
         let id = msg.msg.id.clone();
         widget.connect_button_press_event(move |w, e| {
             if e.triggers_context_menu() {
-                let menu = MessageMenu::new(id.as_ref(), &mtype, &redactable, Some(w));
+                let menu = MessageMenu::new(
+                    id.as_ref(),
+                    &mtype,
+                    &redactable,
+                    &editable,
+                    &edited,
+                    Some(w),
+                );
                 let coords = e.get_position();
                 menu.show_at_coords(w, coords);
                 Inhibit(true)
@@ -196,7 +270,14 @@ impl MessageBoxContainer {
         let id = msg.msg.id.clone();
         self.gesture
             .connect_pressed(clone!(@weak widget => move |_, x, y| {
-                let menu = MessageMenu::new(id.as_ref(), &mtype, &redactable, Some(&widget));
+                let menu = MessageMenu::new(
+                    id.as_ref(),
+                    &mtype,
+                    &redactable,
+                    &editable,
+                    &edited,
+                    Some(&widget),
+                );
                 menu.show_at_coords(&widget, (x, y));
             }));
         None
@@ -291,11 +372,19 @@ impl MessageBoxContent {
         info_header: bool,
     ) -> Self {
         // content
-        // +---------+
-        // | info    |
-        // +---------+
-        // | body_bx |
-        // +---------+
+        // +-----------+
+        // | info      |
+        // +-----------+
+        // | reply     |
+        // +-----------+
+        // | body_bx   |
+        // +-----------+
+        // | reactions |
+        // +-----------+
+        // | thread    |
+        // +-----------+
+        // | receipts  |
+        // +-----------+
         let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
 
         let info = if info_header {
@@ -309,9 +398,25 @@ impl MessageBoxContent {
             None
         };
 
+        if let Some(reply) = msg.reply.as_ref() {
+            content.pack_start(&build_reply_quote(reply), false, false, 0);
+        }
+
         let body_bx = MessageBodyBox::build(&container, session_client, msg);
         content.pack_start(&body_bx.root, true, true, 0);
 
+        if let Some(reactions) = build_reactions(msg) {
+            content.pack_start(&reactions, false, false, 0);
+        }
+
+        if let Some(summary) = build_thread_summary(msg) {
+            content.pack_start(&summary, false, false, 0);
+        }
+
+        if let Some(receipts) = build_receipts(container, msg) {
+            content.pack_start(&receipts, false, false, 0);
+        }
+
         Self {
             root: content,
             info,
@@ -320,6 +425,207 @@ impl MessageBoxContent {
     }
 }
 
+fn build_reply_quote(reply: &ReplyContent) -> gtk::Button {
+    let quote = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+    if let Some(name) = reply.sender_name.as_ref() {
+        let sender = gtk::Label::new(Some(name));
+        sender.set_xalign(0.0);
+        sender.set_ellipsize(pango::EllipsizeMode::End);
+        sender.get_style_context().add_class("reply-sender");
+        quote.pack_start(&sender, false, false, 0);
+    }
+
+    let text = reply
+        .body
+        .clone()
+        .unwrap_or_else(|| i18n("In reply to a message that isn’t loaded"));
+    let body = gtk::Label::new(Some(&text));
+    body.set_xalign(0.0);
+    body.set_lines(2);
+    body.set_line_wrap(true);
+    body.set_ellipsize(pango::EllipsizeMode::End);
+    quote.pack_start(&body, false, false, 0);
+
+    let button = gtk::Button::new();
+    button.add(&quote);
+    button.set_relief(gtk::ReliefStyle::None);
+    button.set_halign(gtk::Align::Start);
+    button.get_style_context().add_class("reply-quote");
+    button.set_tooltip_text(Some(&i18n("Jump to the replied message")));
+    let data = glib::Variant::from(reply.id.to_string());
+    button.set_action_name(Some("message.jump_to"));
+    button.set_action_target_value(Some(&data));
+
+    button
+}
+
+fn build_reactions(msg: &Message) -> Option<gtk::FlowBox> {
+    if msg.reactions.is_empty() {
+        return None;
+    }
+    // Reactions always relate to the original event, not to its edits
+    let event_id = msg.msg.replace.clone().or_else(|| msg.msg.id.clone())?;
+
+    let flowbox = gtk::FlowBox::new();
+    flowbox.set_selection_mode(gtk::SelectionMode::None);
+    flowbox.set_halign(gtk::Align::Start);
+    flowbox.set_max_children_per_line(20);
+    flowbox.set_column_spacing(6);
+    flowbox.set_row_spacing(6);
+    flowbox.set_margin_top(3);
+    flowbox.get_style_context().add_class("reactions");
+
+    for reaction in msg.reactions.iter() {
+        let label = format!("{} {}", reaction.key, reaction.senders.len());
+        let button = gtk::Button::with_label(&label);
+        button.set_tooltip_text(Some(&reaction.senders.join(", ")));
+        button.get_style_context().add_class("reaction");
+        if reaction.reacted {
+            button.get_style_context().add_class("reacted");
+        }
+
+        let key = reaction.key.clone();
+        button.connect_clicked(clone!(@strong event_id => move |_| {
+            APPOP!(toggle_reaction, (event_id, key));
+        }));
+
+        flowbox.add(&button);
+    }
+
+    Some(flowbox)
+}
+
+fn build_thread_summary(msg: &Message) -> Option<gtk::Button> {
+    if msg.thread_replies == 0 {
+        return None;
+    }
+    // Threads always start at the original event, not at its edits
+    let event_id = msg.msg.replace.clone().or_else(|| msg.msg.id.clone())?;
+
+    let n = msg.thread_replies as u32;
+    let label = ni18n_f("{} reply", "{} replies", n, &[&n.to_string()]);
+    let button = gtk::Button::with_label(&label);
+    button.set_halign(gtk::Align::Start);
+    button.set_margin_top(3);
+    button.get_style_context().add_class("thread-summary");
+    button.set_tooltip_text(Some(&i18n("Open the thread")));
+    let data = glib::Variant::from(event_id.to_string());
+    button.set_action_name(Some("message.open_thread"));
+    button.set_action_target_value(Some(&data));
+
+    Some(button)
+}
+
+// The avatars of the users who read up to the message, with a popover listing them
+fn build_receipts(container: &MessageBoxContainer, msg: &Message) -> Option<gtk::MenuButton> {
+    const MAX_AVATARS: usize = 5;
+
+    if msg.receipts.is_empty() {
+        return None;
+    }
+
+    let avatars = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    avatars.get_style_context().add_class("receipt-avatars");
+    for receipt in msg.receipts.iter().take(MAX_AVATARS) {
+        avatars.pack_start(
+            &build_receipt_avatar(container, receipt, globals::RECEIPT_ICON_SIZE),
+            false,
+            false,
+            0,
+        );
+    }
+    if msg.receipts.len() > MAX_AVATARS {
+        let more = gtk::Label::new(Some(&format!("+{}", msg.receipts.len() - MAX_AVATARS)));
+        more.get_style_context().add_class("receipt-more");
+        avatars.pack_start(&more, false, false, 3);
+    }
+
+    let list = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    list.set_border_width(12);
+    for receipt in msg.receipts.iter() {
+        // +--------+------+------+
+        // | avatar | name | time |
+        // +--------+------+------+
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        row.pack_start(
+            &build_receipt_avatar(container, receipt, globals::PILL_ICON_SIZE),
+            false,
+            false,
+            0,
+        );
+
+        let name = gtk::Label::new(Some(
+            receipt
+                .name
+                .as_deref()
+                .unwrap_or_else(|| receipt.uid.as_str()),
+        ));
+        name.set_tooltip_text(Some(receipt.uid.as_str()));
+        name.set_xalign(0.0);
+        name.set_ellipsize(pango::EllipsizeMode::End);
+        row.pack_start(&name, true, true, 0);
+
+        // Some servers send receipts without a time
+        if receipt.ts != 0 {
+            let time = Local.timestamp(receipt.ts, 0);
+            let format = if time.date() == Local::today() {
+                i18n("%R")
+            } else {
+                i18n("%B %e, %R")
+            };
+            let time = gtk::Label::new(Some(&time.format(&format).to_string()));
+            time.get_style_context().add_class("dim-label");
+            row.pack_start(&time, false, false, 0);
+        }
+
+        list.pack_start(&row, false, false, 0);
+    }
+    list.show_all();
+
+    let popover = gtk::Popover::new(None::<&gtk::Widget>);
+    popover.add(&list);
+
+    let n = msg.receipts.len() as u32;
+    let button = gtk::MenuButton::new();
+    button.add(&avatars);
+    button.set_popover(Some(&popover));
+    button.set_relief(gtk::ReliefStyle::None);
+    button.set_halign(gtk::Align::End);
+    button.get_style_context().add_class("receipts");
+    button.set_tooltip_text(Some(&ni18n_f(
+        "Read by {} person",
+        "Read by {} people",
+        n,
+        &[&n.to_string()],
+    )));
+
+    Some(button)
+}
+
+fn build_receipt_avatar(
+    container: &MessageBoxContainer,
+    receipt: &ReceiptContent,
+    size: i32,
+) -> widgets::Avatar {
+    let avatar = widgets::Avatar::avatar_new(Some(size));
+    let data = avatar.circle(
+        receipt.uid.to_string(),
+        receipt.name.clone(),
+        size,
+        None,
+        None,
+    );
+    download_to_cache(
+        container.session_client.clone(),
+        container.user_info_cache.clone(),
+        receipt.uid.clone(),
+        data,
+    );
+
+    avatar
+}
+
 fn build_room_msg_avatar(
     session_client: MatrixClient,
     user_info_cache: UserInfoCache,
@@ -530,101 +836,209 @@ fn build_room_msg_body_html(
         anyhow::bail!("Empty message omited: <!-- raw HTML omitted -->, using plain text instead.");
     }
 
-    let blocks =
-        markup_html(&raw).with_context(|| format!("Could not render message: {}", &raw))?;
+    let blocks = html::parse(&raw);
+    if blocks.is_empty() {
+        anyhow::bail!("Could not render message: {}", &raw);
+    }
+
     let bx = gtk::Box::new(gtk::Orientation::Vertical, 6);
-    for b in blocks {
-        let widget = render_html_block(container, msg, &b);
+    for b in blocks.iter() {
+        let widget = render_html_block(container, msg, b);
         bx.add(&widget);
     }
     Ok(bx)
 }
 
-fn render_html_block(
-    container: &MessageBoxContainer,
-    msg: &Message,
-    block: &HtmlBlock,
-) -> gtk::Widget {
-    let widget = match block {
-        HtmlBlock::Heading(n, s) => {
-            let w = gtk::Label::new(None);
-            set_label_styles(&w);
-            w.set_markup(&s);
+fn render_html_block(container: &MessageBoxContainer, msg: &Message, block: &Block) -> gtk::Widget {
+    match block {
+        Block::Paragraph(inlines) => build_html_text(container, msg, inlines),
+        Block::Heading(n, inlines) => {
+            let w = build_html_text(container, msg, inlines);
             w.get_style_context().add_class(&format!("h{}", n));
-            container.connect_right_click_menu(msg, Some(&w.upcast_ref::<gtk::Widget>()));
-            w.upcast::<gtk::Widget>()
+            w
         }
-        HtmlBlock::UList(elements) => {
+        Block::List { start, items } => {
             let bx = gtk::Box::new(gtk::Orientation::Vertical, 6);
             bx.set_margin_end(6);
             bx.set_margin_start(6);
 
-            for li in elements.iter() {
+            for (i, item) in items.iter().enumerate() {
                 let h_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-                let bullet = gtk::Label::new(Some("•"));
+                let bullet = match start {
+                    Some(start) => gtk::Label::new(Some(&format!("{}.", *start as usize + i))),
+                    None => gtk::Label::new(Some("•")),
+                };
                 bullet.set_valign(gtk::Align::Start);
-                let w = gtk::Label::new(None);
-                set_label_styles(&w);
                 h_box.add(&bullet);
-                h_box.add(&w);
-                w.set_markup(&li);
-                container.connect_right_click_menu(msg, Some(&w.upcast_ref::<gtk::Widget>()));
-                bx.add(&h_box);
-            }
 
-            bx.upcast::<gtk::Widget>()
-        }
-        HtmlBlock::OList(elements) => {
-            let bx = gtk::Box::new(gtk::Orientation::Vertical, 6);
-            bx.set_margin_end(6);
-            bx.set_margin_start(6);
-
-            for (i, ol) in elements.iter().enumerate() {
-                let h_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-                let bullet = gtk::Label::new(Some(&format!("{}.", i + 1)));
-                bullet.set_valign(gtk::Align::Start);
-                let w = gtk::Label::new(None);
-                set_label_styles(&w);
-                h_box.add(&bullet);
-                h_box.add(&w);
-                w.set_markup(&ol);
+                let item_bx = gtk::Box::new(gtk::Orientation::Vertical, 6);
+                for b in item.iter() {
+                    item_bx.add(&render_html_block(container, msg, b));
+                }
+                h_box.pack_start(&item_bx, true, true, 0);
                 bx.add(&h_box);
-                container.connect_right_click_menu(msg, Some(&w.upcast_ref::<gtk::Widget>()));
             }
 
-            bx.upcast::<gtk::Widget>()
+            bx.upcast()
         }
-        HtmlBlock::Code(s) => {
-            let scrolled = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
-            scrolled.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Never);
-            let buffer = sourceview4::Buffer::new::<gtk::TextTagTable>(None);
-            buffer.set_highlight_matching_brackets(false);
-            buffer.set_text(&s);
-            let view = sourceview4::View::with_buffer(&buffer);
-            view.set_editable(false);
-            view.get_style_context().add_class("codeview");
-            container.connect_right_click_menu(msg, Some(&view.upcast_ref::<gtk::Widget>()));
-            scrolled.add(&view);
-            scrolled.upcast::<gtk::Widget>()
+        Block::Code { language, text } => {
+            build_code_block(container, msg, language.as_deref(), text)
         }
-        HtmlBlock::Quote(blocks) => {
+        Block::Quote(blocks) => {
             let bx = gtk::Box::new(gtk::Orientation::Vertical, 6);
             bx.get_style_context().add_class("quote");
             for b in blocks.iter() {
-                let w = render_html_block(container, msg, &b);
+                let w = render_html_block(container, msg, b);
                 bx.add(&w);
             }
-            bx.upcast::<gtk::Widget>()
+            bx.upcast()
         }
-        HtmlBlock::Text(s) => {
-            let w = gtk::Label::new(None);
-            set_label_styles(&w);
-            w.set_markup(&s);
-            container.connect_right_click_menu(msg, Some(&w.upcast_ref::<gtk::Widget>()));
-            w.upcast::<gtk::Widget>()
+        Block::Table(rows) => {
+            let grid = gtk::Grid::new();
+            grid.set_row_spacing(3);
+            grid.set_column_spacing(12);
+            grid.get_style_context().add_class("msg-table");
+            for (y, row) in rows.iter().enumerate() {
+                for (x, cell) in row.iter().enumerate() {
+                    let w = build_html_text(container, msg, &cell.content);
+                    if cell.header {
+                        w.get_style_context().add_class("table-header");
+                    }
+                    grid.attach(&w, x as i32, y as i32, 1, 1);
+                }
+            }
+            grid.upcast()
         }
+        Block::Rule => gtk::Separator::new(gtk::Orientation::Horizontal).upcast(),
+    }
+}
+
+// Formatted text, in a text view when it has pills for the users and rooms it
+// links to since labels can't show them
+fn build_html_text(
+    container: &MessageBoxContainer,
+    msg: &Message,
+    inlines: &[Inline],
+) -> gtk::Widget {
+    if pill_text::has_pills(inlines) {
+        build_pill_text(container, msg, inlines).upcast()
+    } else {
+        build_html_label(container, msg, inlines).upcast()
+    }
+}
+
+fn build_pill_text(
+    container: &MessageBoxContainer,
+    msg: &Message,
+    inlines: &[Inline],
+) -> gtk::TextView {
+    let w = pill_text::build(
+        container.session_client.clone(),
+        container.user_info_cache.clone(),
+        inlines,
+    );
+    container.connect_right_click_menu(msg, Some(w.upcast_ref::<gtk::Widget>()));
+    w
+}
+
+// A label showing formatted text, where spoilers are revealed by clicking on them
+fn build_html_label(
+    container: &MessageBoxContainer,
+    msg: &Message,
+    inlines: &[Inline],
+) -> gtk::Label {
+    let w = gtk::Label::new(None);
+    set_label_styles(&w);
+    w.set_markup(&html::to_markup(inlines, &HashSet::new()));
+
+    let spoilers = if html::has_spoilers(inlines) {
+        Some((inlines.to_vec(), RefCell::new(HashSet::new())))
+    } else {
+        None
     };
-    widget
+    w.connect_activate_link(move |w, uri| {
+        if let (Some((inlines, revealed)), Some(id)) = (&spoilers, html::spoiler_id(uri)) {
+            revealed.borrow_mut().insert(id);
+            w.set_markup(&html::to_markup(inlines, &revealed.borrow()));
+            return Inhibit(true);
+        }
+        // Links to rooms and messages are opened here
+        if permalink::parse(uri).is_some() {
+            if let Some(app) = gio::Application::get_default() {
+                app.activate_action("open-permalink", Some(&uri.to_variant()));
+            }
+            return Inhibit(true);
+        }
+        Inhibit(false)
+    });
+
+    container.connect_right_click_menu(msg, Some(w.upcast_ref::<gtk::Widget>()));
+    w
+}
+
+fn build_code_block(
+    container: &MessageBoxContainer,
+    msg: &Message,
+    language: Option<&str>,
+    text: &str,
+) -> gtk::Widget {
+    // +-------------+------+
+    // | code        | copy |
+    // +-------------+------+
+    let overlay = gtk::Overlay::new();
+    let scrolled = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+    scrolled.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Never);
+    let buffer = sourceview4::Buffer::new::<gtk::TextTagTable>(None);
+    buffer.set_highlight_matching_brackets(false);
+    buffer.set_text(text);
+
+    let lang = language.and_then(|language| {
+        sourceview4::LanguageManager::get_default()
+            .and_then(|lm| lm.get_language(&source_language_id(language)))
+    });
+    if let Some(ref lang) = lang {
+        buffer.set_language(Some(lang));
+        buffer.set_highlight_syntax(true);
+    }
+
+    let view = sourceview4::View::with_buffer(&buffer);
+    view.set_editable(false);
+    view.get_style_context().add_class("codeview");
+    container.connect_right_click_menu(msg, Some(view.upcast_ref::<gtk::Widget>()));
+    scrolled.add(&view);
+    overlay.add(&scrolled);
+
+    let copy = gtk::Button::from_icon_name(Some("edit-copy-symbolic"), gtk::IconSize::Button);
+    copy.set_tooltip_text(Some(i18n("Copy code").as_str()));
+    copy.set_halign(gtk::Align::End);
+    copy.set_valign(gtk::Align::Start);
+    copy.get_style_context().add_class("code-copy");
+    let text = text.to_string();
+    copy.connect_clicked(move |_| {
+        let atom = gdk::Atom::intern("CLIPBOARD");
+        let clipboard = gtk::Clipboard::get(&atom);
+        clipboard.set_text(&text);
+    });
+    overlay.add_overlay(&copy);
+
+    overlay.upcast()
+}
+
+// The GtkSourceView id of the language of a code block, as named in Markdown
+fn source_language_id(language: &str) -> String {
+    let language = language.to_lowercase();
+    match language.as_str() {
+        "javascript" => "js",
+        "py" => "python",
+        "rs" => "rust",
+        "shell" | "bash" | "zsh" => "sh",
+        "c++" => "cpp",
+        "c#" | "csharp" => "c-sharp",
+        "yml" => "yaml",
+        "md" => "markdown",
+        _ => language.as_str(),
+    }
+    .to_string()
 }
 
 fn build_room_msg_sticker(session_client: MatrixClient, msg: &Message) -> BodyAndType {
@@ -654,6 +1068,9 @@ fn build_room_audio_player(session_client: MatrixClient, msg: &Message) -> BodyA
             bx.clone(),
             start_playing,
         );
+        if let Some(waveform) = msg.msg.voice_waveform() {
+            player.set_waveform(waveform);
+        }
 
         let control_box = PlayerExt::get_controls_container(&player)
             .expect("Every AudioPlayer must have controls.");
@@ -702,8 +1119,13 @@ fn build_room_msg_image(session_client: MatrixClient, msg: &Message) -> BodyAndT
         .or_else(|| Some(Either::Right(msg.msg.local_path.clone()?)));
 
     let image = if let Some(img_path) = img {
+        let blurhash = msg
+            .msg
+            .blurhash()
+            .map(|(hash, (w, h))| (hash, (w as i32, h as i32)));
         let image = widgets::image::Image::new(img_path)
             .size(Some(globals::MAX_IMAGE_SIZE))
+            .blurhash(blurhash)
             .build(session_client);
 
         image.widget.get_style_context().add_class("image-widget");
@@ -782,7 +1204,7 @@ fn build_room_video_player(session_client: MatrixClient, msg: &Message) -> BodyA
 
         let evid = msg.msg.id.as_ref();
         let redactable = msg.redactable;
-        let menu = MessageMenu::new(evid, &RowType::Video, &redactable, None);
+        let menu = MessageMenu::new(evid, &RowType::Video, &redactable, &false, &false, None);
         menu_button.set_popover(Some(&menu.get_popover()));
 
         let clip_container = ClipContainer::new();
@@ -886,20 +1308,27 @@ fn build_room_msg_body_text(container: &MessageBoxContainer, msg: &Message) -> g
         if v.ends_with(&[""]) {
             v.pop();
         }
-        let part = v.join("\n");
-
-        let part_widget = gtk::Label::new(None);
-        part_widget.set_markup(&markup_text(&part));
-        set_label_styles(&part_widget);
+        (k, v.join("\n"))
+    });
 
-        if k == MsgPartType::Quote {
-            part_widget.get_style_context().add_class("quote");
+    for (k, text) in msg_parts {
+        let inlines = pill_text::linkify(&text);
+        if pill_text::has_pills(&inlines) {
+            let view = build_pill_text(container, msg, &inlines);
+            if k == MsgPartType::Quote {
+                view.get_style_context().add_class("quote");
+            }
+            bx.add(&view);
+            continue;
         }
 
-        part_widget
-    });
+        let part = gtk::Label::new(None);
+        part.set_markup(&markup_text(&text));
+        set_label_styles(&part);
 
-    for part in msg_parts {
+        if k == MsgPartType::Quote {
+            part.get_style_context().add_class("quote");
+        }
         if msg.mtype == RowType::Mention {
             let highlights = msg.highlights.clone();
             part.connect_property_cursor_position_notify(move |w| {
@@ -1,13 +1,22 @@
 use gdk::prelude::*;
+use glib::clone;
 use gtk::prelude::*;
 use matrix_sdk::identifiers::EventId;
 
 use crate::ui::RowType;
 
+// Offered in the "React" submenu
+const QUICK_REACTIONS: &[&str] = &["👍", "👎", "😄", "🎉", "😕", "❤️", "🚀", "👀"];
+
 #[derive(Clone)]
 struct Widgets {
     popover: gtk::Popover,
     reply_button: gtk::ModelButton,
+    thread_button: gtk::ModelButton,
+    react_button: gtk::ModelButton,
+    reaction_flowbox: gtk::FlowBox,
+    edit_button: gtk::ModelButton,
+    edit_history_button: gtk::ModelButton,
     open_with_button: gtk::ModelButton,
     save_image_as_button: gtk::ModelButton,
     save_video_as_button: gtk::ModelButton,
@@ -15,12 +24,21 @@ struct Widgets {
     delete_message_button: gtk::ModelButton,
     view_source_button: gtk::ModelButton,
     copy_text_button: gtk::ModelButton,
+    copy_link_button: gtk::ModelButton,
+    copy_uri_button: gtk::ModelButton,
+    forward_button: gtk::ModelButton,
     copy_selected_button: gtk::ModelButton,
     menu_separator: gtk::Widget,
 }
 
 impl Widgets {
-    pub fn new(id: Option<&EventId>, mtype: &RowType, redactable: &bool) -> Widgets {
+    pub fn new(
+        id: Option<&EventId>,
+        mtype: &RowType,
+        redactable: &bool,
+        editable: &bool,
+        edited: &bool,
+    ) -> Widgets {
         let builder = gtk::Builder::new();
         builder
             .add_from_resource("/org/gnome/Fractal/ui/message_menu.ui")
@@ -34,6 +52,26 @@ impl Widgets {
             .get_object("reply_button")
             .expect("Can't find reply_button in ui file.");
 
+        let thread_button: gtk::ModelButton = builder
+            .get_object("thread_button")
+            .expect("Can't find thread_button in ui file.");
+
+        let react_button: gtk::ModelButton = builder
+            .get_object("react_button")
+            .expect("Can't find react_button in ui file.");
+
+        let reaction_flowbox: gtk::FlowBox = builder
+            .get_object("reaction_flowbox")
+            .expect("Can't find reaction_flowbox in ui file.");
+
+        let edit_button: gtk::ModelButton = builder
+            .get_object("edit_button")
+            .expect("Can't find edit_button in ui file.");
+
+        let edit_history_button: gtk::ModelButton = builder
+            .get_object("edit_history_button")
+            .expect("Can't find edit_history_button in ui file.");
+
         let open_with_button: gtk::ModelButton = builder
             .get_object("open_with_button")
             .expect("Can't find open_with_button in ui file.");
@@ -54,6 +92,18 @@ impl Widgets {
             .get_object("copy_text_button")
             .expect("Can't find copy_text_button in ui file.");
 
+        let copy_link_button: gtk::ModelButton = builder
+            .get_object("copy_link_button")
+            .expect("Can't find copy_link_button in ui file.");
+
+        let copy_uri_button: gtk::ModelButton = builder
+            .get_object("copy_uri_button")
+            .expect("Can't find copy_uri_button in ui file.");
+
+        let forward_button: gtk::ModelButton = builder
+            .get_object("forward_button")
+            .expect("Can't find forward_button in ui file.");
+
         let delete_message_button: gtk::ModelButton = builder
             .get_object("delete_message_button")
             .expect("Can't find delete_message_button in ui file.");
@@ -72,6 +122,13 @@ impl Widgets {
 
         /* Set visibility of buttons */
         copy_selected_button.hide();
+        thread_button.set_visible(id.is_some());
+        react_button.set_visible(id.is_some());
+        copy_link_button.set_visible(id.is_some());
+        copy_uri_button.set_visible(id.is_some());
+        forward_button.set_visible(id.is_some());
+        edit_button.set_visible(*editable);
+        edit_history_button.set_visible(*edited && id.is_some());
         delete_message_button.set_visible(*redactable);
         menu_separator.set_visible(*redactable);
         open_with_button.set_visible(mtype == &RowType::Image || mtype == &RowType::Video);
@@ -83,6 +140,9 @@ impl Widgets {
         let evid = id.map(|evid| evid.to_string()).unwrap_or_default();
         let data = glib::Variant::from(evid);
         reply_button.set_action_target_value(Some(&data));
+        thread_button.set_action_target_value(Some(&data));
+        edit_button.set_action_target_value(Some(&data));
+        edit_history_button.set_action_target_value(Some(&data));
         open_with_button.set_action_target_value(Some(&data));
         view_source_button.set_action_target_value(Some(&data));
         delete_message_button.set_action_target_value(Some(&data));
@@ -91,10 +151,18 @@ impl Widgets {
         save_video_as_button.set_action_target_value(Some(&data));
         copy_image_button.set_action_target_value(Some(&data));
         copy_text_button.set_action_target_value(Some(&data));
+        copy_link_button.set_action_target_value(Some(&data));
+        copy_uri_button.set_action_target_value(Some(&data));
+        forward_button.set_action_target_value(Some(&data));
 
         Widgets {
             popover,
             reply_button,
+            thread_button,
+            react_button,
+            reaction_flowbox,
+            edit_button,
+            edit_history_button,
             open_with_button,
             save_image_as_button,
             save_video_as_button,
@@ -102,6 +170,9 @@ impl Widgets {
             delete_message_button,
             view_source_button,
             copy_text_button,
+            copy_link_button,
+            copy_uri_button,
+            forward_button,
             copy_selected_button,
             menu_separator,
         }
@@ -125,16 +196,21 @@ impl MessageMenu {
         id: Option<&EventId>,
         mtype: &RowType,
         redactable: &bool,
+        editable: &bool,
+        edited: &bool,
         label: Option<&gtk::Widget>,
     ) -> MessageMenu {
         let menu = MessageMenu {
-            widgets: Widgets::new(id, mtype, redactable),
+            widgets: Widgets::new(id, mtype, redactable, editable, edited),
         };
         /* Copy selected text works a little different then the other actions, because it need the
          * label */
         if let Some(label) = label {
             menu.connect_copy_selected_text(label);
         }
+        if let Some(id) = id {
+            menu.connect_reactions(id);
+        }
         menu
     }
 
@@ -170,6 +246,23 @@ impl MessageMenu {
         None
     }
 
+    /* Reactions need the key besides the event id, so they can't be a message action */
+    fn connect_reactions(&self, id: &EventId) {
+        let popover = &self.widgets.popover;
+        for key in QUICK_REACTIONS.iter() {
+            let button = gtk::Button::with_label(key);
+            button.get_style_context().add_class("flat");
+            let event_id = id.clone();
+            let key = key.to_string();
+            button.connect_clicked(clone!(@weak popover => move |_| {
+                popover.popdown();
+                APPOP!(toggle_reaction, (event_id, key));
+            }));
+            button.show();
+            self.widgets.reaction_flowbox.add(&button);
+        }
+    }
+
     pub fn get_popover(&self) -> gtk::Popover {
         self.widgets.popover.clone()
     }
@@ -5,6 +5,7 @@ mod autocomplete;
 pub mod avatar;
 mod clip_container;
 mod divider;
+mod emoji_picker;
 pub mod error_dialog;
 pub mod file_dialog;
 pub mod image;
@@ -15,12 +16,14 @@ pub mod media_viewer;
 pub mod members_list;
 mod message;
 pub mod message_menu;
+mod pill_text;
 pub mod room_history;
 mod roomlist;
 mod roomrow;
 mod scroll_widget;
 mod source_dialog;
 mod sourceview_entry;
+mod state_group;
 
 pub use self::address::Address;
 pub use self::address::AddressType;
@@ -31,6 +34,7 @@ pub use self::avatar::AvatarData;
 pub use self::avatar::AvatarExt;
 pub use self::clip_container::ClipContainer;
 pub use self::divider::NewMessageDivider;
+pub use self::emoji_picker::EmojiPicker;
 pub use self::error_dialog as ErrorDialog;
 pub use self::file_dialog as FileDialog;
 pub use self::inline_player::AudioPlayerWidget;
@@ -50,3 +54,4 @@ pub use self::scroll_widget::page_up;
 pub use self::scroll_widget::ScrollWidget;
 pub use self::source_dialog::SourceDialog;
 pub use self::sourceview_entry::SVEntry;
+pub use self::state_group::StateGroup;
@@ -0,0 +1,333 @@
+use crate::appop::UserInfoCache;
+use crate::cache::download_to_cache;
+use crate::globals;
+use crate::ui::user::show_member_info;
+use crate::util::html::{Inline, Style};
+use crate::util::mention::{self, Target};
+use crate::util::permalink;
+use crate::widgets;
+use crate::widgets::AvatarExt;
+use gio::ActionGroupExt;
+use glib::clone;
+use glib::ToVariant;
+use gtk::prelude::*;
+use log::error;
+use matrix_sdk::Client as MatrixClient;
+use std::rc::Rc;
+
+// The color hiding spoilers, the same as in labels
+const SPOILER_COLOR: &str = "#888a85";
+
+// What clicking on a tagged part of the text does
+enum Click {
+    Open(String),
+    Reveal,
+}
+
+/// Whether the text links to users or rooms, which are shown as pills.
+pub fn has_pills(inlines: &[Inline]) -> bool {
+    inlines.iter().any(|inline| match inline {
+        Inline::Link(href, _) => mention::parse_matrix_to(href).is_some(),
+        Inline::Styled(_, content) | Inline::Spoiler(_, content) => has_pills(content),
+        Inline::Text(_) | Inline::LineBreak => false,
+    })
+}
+
+/// Splits plain text into links and text, so the users and rooms it links to
+/// can be shown as pills.
+pub fn linkify(text: &str) -> Vec<Inline> {
+    let mut inlines = vec![];
+    let mut plain = String::new();
+    for (idx, word) in split_words(text) {
+        let link = word.trim_end_matches(|c: char| ",.;:!?)".contains(c));
+        if link.starts_with("https://") || link.starts_with("http://") {
+            if !plain.is_empty() {
+                inlines.push(Inline::Text(std::mem::take(&mut plain)));
+            }
+            let name = match mention::parse_matrix_to(link) {
+                Some(Target::User(user_id)) => user_id.to_string(),
+                Some(Target::Room(room_id)) => room_id.to_string(),
+                None => link.to_string(),
+            };
+            inlines.push(Inline::Link(link.to_string(), vec![Inline::Text(name)]));
+            plain.push_str(&text[idx + link.len()..idx + word.len()]);
+        } else {
+            plain.push_str(word);
+        }
+    }
+    if !plain.is_empty() {
+        inlines.push(Inline::Text(plain));
+    }
+
+    inlines
+}
+
+// The words of the text with their position, the whitespace being kept as words
+fn split_words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start = 0;
+    let mut in_space = None;
+    for (idx, c) in text.char_indices() {
+        let space = c.is_whitespace();
+        if in_space.map_or(false, |in_space| in_space != space) {
+            words.push((start, &text[start..idx]));
+            start = idx;
+        }
+        in_space = Some(space);
+    }
+    if start < text.len() {
+        words.push((start, &text[start..]));
+    }
+
+    words
+}
+
+/// Builds a read-only text view showing formatted text, with the users and
+/// rooms it links to as pills.
+pub fn build(
+    session_client: MatrixClient,
+    user_info_cache: UserInfoCache,
+    inlines: &[Inline],
+) -> gtk::TextView {
+    let view = gtk::TextView::new();
+    view.set_editable(false);
+    view.set_cursor_visible(false);
+    view.set_wrap_mode(gtk::WrapMode::WordChar);
+    view.set_hexpand(true);
+    view.get_style_context().add_class("pill-text");
+
+    let buffer = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
+    view.set_buffer(Some(&buffer));
+
+    let mut writer = Writer {
+        view: view.clone(),
+        buffer,
+        session_client,
+        user_info_cache,
+        clicks: vec![],
+    };
+    writer.write(inlines, &[]);
+
+    let clicks = Rc::new(writer.clicks);
+    view.connect_button_release_event(move |view, ev| {
+        let has_selection = view.get_buffer().map_or(false, |b| b.get_has_selection());
+        // Selecting text doesn't follow links
+        if ev.get_button() != 1 || has_selection {
+            return Inhibit(false);
+        }
+
+        let (x, y) = ev.get_position();
+        let (x, y) = view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+        let tags = match view.get_iter_at_location(x, y) {
+            Some(iter) => iter.get_tags(),
+            None => return Inhibit(false),
+        };
+        let click = clicks.iter().find(|(tag, _)| tags.contains(tag));
+        match click {
+            // Links to rooms and messages are opened here
+            Some((_, Click::Open(uri))) if permalink::parse(uri).is_some() => {
+                if let Some(app) = gio::Application::get_default() {
+                    app.activate_action("open-permalink", Some(&uri.to_variant()));
+                }
+                Inhibit(true)
+            }
+            Some((_, Click::Open(uri))) => {
+                let window = view
+                    .get_toplevel()
+                    .and_then(|w| w.downcast::<gtk::Window>().ok());
+                let time = gtk::get_current_event_time();
+                if let Err(err) = gtk::show_uri_on_window(window.as_ref(), uri, time) {
+                    error!("Can't open {}: {}", uri, err);
+                }
+                Inhibit(true)
+            }
+            Some((tag, Click::Reveal)) => {
+                tag.set_property_foreground_set(false);
+                tag.set_property_background_set(false);
+                Inhibit(true)
+            }
+            None => Inhibit(false),
+        }
+    });
+
+    view
+}
+
+struct Writer {
+    view: gtk::TextView,
+    buffer: gtk::TextBuffer,
+    session_client: MatrixClient,
+    user_info_cache: UserInfoCache,
+    clicks: Vec<(gtk::TextTag, Click)>,
+}
+
+impl Writer {
+    // Tags have to be in the table of the buffer to be applied
+    fn new_tag(&self) -> gtk::TextTag {
+        let tag = gtk::TextTag::new(None);
+        if let Some(table) = self.buffer.get_tag_table() {
+            table.add(&tag);
+        }
+        tag
+    }
+
+    fn write(&mut self, inlines: &[Inline], tags: &[gtk::TextTag]) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => self.insert(text, tags),
+                Inline::LineBreak => self.insert("\n", tags),
+                Inline::Styled(style, content) => {
+                    let tag = self.new_tag();
+                    set_style(&tag, style);
+                    let tags = with_tag(tags, tag);
+                    self.write(content, &tags);
+                }
+                Inline::Link(href, content) => match mention::parse_matrix_to(href) {
+                    Some(target) => self.insert_pill(&target, &plain_text(content)),
+                    None => {
+                        let tag = self.new_tag();
+                        tag.set_property_underline(pango::Underline::Single);
+                        tag.set_property_foreground_rgba(self.link_color().as_ref());
+                        self.clicks.push((tag.clone(), Click::Open(href.clone())));
+                        self.write(content, &with_tag(tags, tag));
+                    }
+                },
+                Inline::Spoiler(reason, content) => {
+                    if let Some(reason) = reason {
+                        let italic = self.new_tag();
+                        italic.set_property_style(pango::Style::Italic);
+                        self.insert(&format!("({}) ", reason), &with_tag(tags, italic));
+                    }
+
+                    let tag = self.new_tag();
+                    tag.set_property_foreground(Some(SPOILER_COLOR));
+                    tag.set_property_background(Some(SPOILER_COLOR));
+                    self.clicks.push((tag.clone(), Click::Reveal));
+                    self.write(content, &with_tag(tags, tag));
+                }
+            }
+        }
+    }
+
+    fn insert(&self, text: &str, tags: &[gtk::TextTag]) {
+        let start = self.buffer.get_end_iter().get_offset();
+        self.buffer.insert(&mut self.buffer.get_end_iter(), text);
+
+        let start = self.buffer.get_iter_at_offset(start);
+        let end = self.buffer.get_end_iter();
+        for tag in tags {
+            self.buffer.apply_tag(tag, &start, &end);
+        }
+    }
+
+    fn insert_pill(&self, target: &Target, name: &str) {
+        let mut end = self.buffer.get_end_iter();
+        let anchor = unwrap_or_unit_return!(self.buffer.create_child_anchor(&mut end));
+        let pill = self.build_pill(target, name);
+        self.view.add_child_at_anchor(&pill, &anchor);
+    }
+
+    fn build_pill(&self, target: &Target, name: &str) -> gtk::Button {
+        let id = match target {
+            Target::User(user_id) => user_id.to_string(),
+            Target::Room(room_id) => room_id.to_string(),
+        };
+        let name = Some(name.to_string()).filter(|name| !name.is_empty());
+
+        let avatar = widgets::Avatar::avatar_new(Some(globals::PILL_ICON_SIZE));
+        let data = avatar.circle(
+            id.clone(),
+            name.clone(),
+            globals::PILL_ICON_SIZE,
+            None,
+            None,
+        );
+        let label = gtk::Label::new(Some(name.as_deref().unwrap_or(&id)));
+
+        let bx = gtk::Box::new(gtk::Orientation::Horizontal, 3);
+        bx.pack_start(&avatar, false, false, 0);
+        bx.pack_start(&label, false, false, 0);
+
+        let pill = gtk::Button::new();
+        pill.add(&bx);
+        pill.set_valign(gtk::Align::Center);
+        pill.get_style_context().add_class("pill");
+        pill.set_tooltip_text(Some(&id));
+        pill.show_all();
+
+        match target {
+            Target::User(user_id) => {
+                let session_client = self.session_client.clone();
+                let user_info_cache = self.user_info_cache.clone();
+                download_to_cache(
+                    session_client.clone(),
+                    user_info_cache.clone(),
+                    user_id.clone(),
+                    data,
+                );
+
+                let user_id = user_id.clone();
+                pill.connect_clicked(clone!(@strong name => move |pill| {
+                    show_member_info(
+                        pill.upcast_ref(),
+                        session_client.clone(),
+                        user_info_cache.clone(),
+                        user_id.clone(),
+                        name.clone(),
+                    );
+                }));
+            }
+            Target::Room(_) => {
+                pill.set_action_name(Some("app.open-room-link"));
+                pill.set_action_target_value(Some(&id.to_variant()));
+            }
+        }
+
+        pill
+    }
+
+    fn link_color(&self) -> Option<gdk::RGBA> {
+        self.view
+            .get_style_context()
+            .lookup_color("theme_selected_bg_color")
+    }
+}
+
+fn set_style(tag: &gtk::TextTag, style: &Style) {
+    match style {
+        Style::Bold => tag.set_property_weight(700),
+        Style::Italic => tag.set_property_style(pango::Style::Italic),
+        Style::Underline => tag.set_property_underline(pango::Underline::Single),
+        Style::Strikethrough => tag.set_property_strikethrough(true),
+        Style::Code => tag.set_property_family(Some("monospace")),
+        Style::Superscript => {
+            tag.set_property_rise(4 * pango::SCALE);
+            tag.set_property_scale(0.8);
+        }
+        Style::Subscript => {
+            tag.set_property_rise(-2 * pango::SCALE);
+            tag.set_property_scale(0.8);
+        }
+        Style::Color(color) => tag.set_property_foreground(Some(color)),
+        Style::Background(color) => tag.set_property_background(Some(color)),
+    }
+}
+
+fn with_tag(tags: &[gtk::TextTag], tag: gtk::TextTag) -> Vec<gtk::TextTag> {
+    let mut tags = tags.to_vec();
+    tags.push(tag);
+    tags
+}
+
+fn plain_text(inlines: &[Inline]) -> String {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => text.clone(),
+            Inline::LineBreak => String::from(" "),
+            Inline::Styled(_, content) | Inline::Link(_, content) | Inline::Spoiler(_, content) => {
+                plain_text(content)
+            }
+        })
+        .collect()
+}
@@ -25,7 +25,7 @@ use glib::source::Continue;
 use glib::SignalHandlerId;
 use glib::Source;
 use gtk::prelude::*;
-use matrix_sdk::identifiers::RoomId;
+use matrix_sdk::identifiers::{EventId, RoomId};
 use matrix_sdk::Client as MatrixClient;
 
 struct List {
@@ -36,6 +36,9 @@ struct List {
     new_divider_index: Option<usize>,
     playing_videos: Vec<(Rc<VideoPlayerWidget>, SignalHandlerId)>,
     video_scroll_debounce: Option<source::SourceId>,
+    read_marker_debounce: Option<source::SourceId>,
+    // The message to scroll to once the queued messages are shown
+    scroll_target: Option<EventId>,
     view: widgets::ScrollWidget,
 }
 
@@ -46,10 +49,47 @@ impl List {
             new_divider_index: None,
             playing_videos: Vec::new(),
             video_scroll_debounce: None,
+            read_marker_debounce: None,
+            scroll_target: None,
             view,
         }
     }
 
+    /// Scrolls to the message with the given id and highlights it for a moment.
+    ///
+    /// Returns `false` if the message isn't shown.
+    fn scroll_to_message(&self, event_id: &EventId) -> bool {
+        let row = self.list.iter().find_map(|e| match e {
+            Element::Message(content)
+                if content.msg.id.as_ref() == Some(event_id)
+                    || content.msg.replace.as_ref() == Some(event_id) =>
+            {
+                content.widget.as_ref().map(|w| w.get_widget().clone())
+            }
+            _ => None,
+        });
+        let row = match row {
+            Some(row) => row,
+            None => return false,
+        };
+
+        let listbox = self.view.get_listbox();
+        let vadjustment = self.view.get_scrolled_window().get_vadjustment();
+        if let (Some((_, y)), Some(vadjustment)) =
+            (row.translate_coordinates(&listbox, 0, 0), vadjustment)
+        {
+            vadjustment.set_value(f64::from(y));
+        }
+
+        row.get_style_context().add_class("msg-highlight");
+        glib::timeout_add_local(2000, move || {
+            row.get_style_context().remove_class("msg-highlight");
+            Continue(false)
+        });
+
+        true
+    }
+
     /// Adds the element at the given position to the history.
     ///
     /// An index of 0 points to the uppermost element.
@@ -147,6 +187,30 @@ impl List {
             .collect()
     }
 
+    /// Returns the newest message the user scrolled past, whose bottom is in
+    /// sight or above the view.
+    fn find_last_read_message(&self) -> Option<EventId> {
+        let scrolled_window = self.view.get_scrolled_window();
+        let height_visible_area = scrolled_window.get_allocated_height();
+        self.list.iter().find_map(|element| match element {
+            Element::Message(content) => {
+                let row = element.get_listbox_row();
+                let (_, rel_y) = row.translate_coordinates(&scrolled_window, 0, 0)?;
+                if rel_y + row.get_allocated_height() <= height_visible_area {
+                    // Edits are shown in place of the original message
+                    content
+                        .msg
+                        .replace
+                        .clone()
+                        .or_else(|| content.msg.id.clone())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+    }
+
     fn find_all_visible_indices(&self) -> Vec<usize> {
         let len = self.list.len();
         let mut indices = Vec::new();
@@ -244,10 +308,11 @@ enum RelativePosition {
 }
 
 /* These Enum contains all differnet types of rows the room history can have, e.g room message, new
- * message divider, day divider */
+ * message divider, day divider, changes of the room state */
 #[derive(Clone)]
 enum Element {
     Message(MessageContent),
+    State(widgets::StateGroup),
     NewDivider(widgets::NewMessageDivider),
     DayDivider(gtk::ListBoxRow),
 }
@@ -260,10 +325,19 @@ impl Element {
                 .as_ref()
                 .expect("The content of every message element must have widget.")
                 .get_widget(),
+            Element::State(group) => group.get_widget(),
             Element::NewDivider(widgets) => widgets.get_widget(),
             Element::DayDivider(widget) => widget,
         }
     }
+
+    fn get_date(&self) -> Option<DateTime<Local>> {
+        match self {
+            Element::Message(content) => Some(content.msg.date),
+            Element::State(group) => group.date(),
+            _ => None,
+        }
+    }
 }
 
 pub struct RoomHistory {
@@ -281,7 +355,8 @@ impl RoomHistory {
             .get_object::<gtk::Box>("history_container")
             .expect("Can't find history_container in ui file.");
         let action = actions.lookup_action("request_older_messages");
-        let scroll = widgets::ScrollWidget::new(action, room_id);
+        let newer_action = actions.lookup_action("request_newer_messages");
+        let scroll = widgets::ScrollWidget::new(action, newer_action, room_id);
         /* remove previous room history widget */
         for ch in history_container.get_children().iter() {
             history_container.remove(ch);
@@ -301,6 +376,7 @@ impl RoomHistory {
 
         rh.connect_video_auto_play();
         rh.connect_video_focus();
+        rh.connect_read_marker();
 
         Some(rh)
     }
@@ -367,6 +443,34 @@ impl RoomHistory {
         }));
     }
 
+    /// Moves the fully read marker once the user scrolled past new messages.
+    fn connect_read_marker(&self) {
+        let scrolled_window = self.rows.borrow().view.get_scrolled_window();
+        let vadjustment = scrolled_window
+            .get_vadjustment()
+            .expect("The scrolled window must have a vertical adjustment.");
+        let rows = &self.rows;
+        vadjustment.connect_value_changed(clone!(@weak rows => move |_| {
+            if scrolled_window.get_state_flags().contains(gtk::StateFlags::BACKDROP) {
+                /* Fractal isn't focused, so the user isn't reading */
+                return;
+            }
+            let new_id = glib::timeout_add_local(500, clone!(
+                @weak rows
+                => @default-return Continue(false), move || {
+                    rows.borrow_mut().read_marker_debounce = None;
+                    let last_read = rows.borrow().find_last_read_message();
+                    if let Some(event_id) = last_read {
+                        APPOP!(read_up_to, (event_id));
+                    }
+                    Continue(false)
+                }));
+            if let Some(old_id) = rows.borrow_mut().read_marker_debounce.replace(new_id) {
+                let _ = Source::remove(old_id);
+            }
+        }));
+    }
+
     fn connect_video_focus(&mut self) {
         let rows = &self.rows;
 
@@ -483,12 +587,11 @@ impl RoomHistory {
                     let mut prev_day_divider = None;
                     let mut day_divider = None;
 
-                    if let Some(first) = rows.borrow().list.back() {
-                        if let Element::Message(ref message) = first {
-                            if item.msg.date.day() != message.msg.date.day() {
-                                prev_day_divider =
-                                    Some(Element::DayDivider(create_day_divider(message.msg.date)));
-                            }
+                    let first_date = rows.borrow().list.back().and_then(Element::get_date);
+                    if let Some(first_date) = first_date {
+                        if item.msg.date.day() != first_date.day() {
+                            prev_day_divider =
+                                Some(Element::DayDivider(create_day_divider(first_date)));
                         }
                     };
                     let has_header = {
@@ -497,36 +600,59 @@ impl RoomHistory {
                                 day_divider =
                                     Some(Element::DayDivider(create_day_divider(item.msg.date)));
                             }
-                            last.mtype == RowType::Emote || !should_group_message(&item, &last)
+                            last.mtype == RowType::Emote
+                                || last.mtype == RowType::State
+                                || !should_group_message(&item, &last)
                         } else {
                             true
                         }
                     };
+                    let new_divider = item.last_viewed && !rows.borrow().list.is_empty();
 
-                    if let Some(prev_day_divider) = prev_day_divider {
-                        rows.borrow_mut().add_top(prev_day_divider);
-                    }
-                    if item.last_viewed && !rows.borrow().list.is_empty() {
-                        let divider =
-                            Element::NewDivider(List::create_new_message_divider(rows.clone()));
-                        rows.borrow_mut().add_top(divider);
-                        let new_divider_index = rows.borrow().list.len() - 1;
-                        rows.borrow_mut().new_divider_index = Some(new_divider_index);
+                    /* Membership changes following each other share a row */
+                    let grouped = item.mtype == RowType::State
+                        && prev_day_divider.is_none()
+                        && !new_divider
+                        && match rows.borrow_mut().list.back_mut() {
+                            Some(Element::State(group)) => group.push_front(item.clone()),
+                            _ => false,
+                        };
+
+                    if !grouped {
+                        if let Some(prev_day_divider) = prev_day_divider {
+                            rows.borrow_mut().add_top(prev_day_divider);
+                        }
+                        if new_divider {
+                            let divider =
+                                Element::NewDivider(List::create_new_message_divider(rows.clone()));
+                            rows.borrow_mut().add_top(divider);
+                            let new_divider_index = rows.borrow().list.len() - 1;
+                            rows.borrow_mut().new_divider_index = Some(new_divider_index);
+                        }
+                        if item.mtype == RowType::State {
+                            let group = widgets::StateGroup::new(item);
+                            rows.borrow_mut().add_top(Element::State(group));
+                        } else {
+                            item.widget = Some(create_row(
+                                session_client.clone(),
+                                user_info_cache.clone(),
+                                item.clone(),
+                                has_header,
+                                &rows,
+                            ));
+                            rows.borrow_mut().add_top(Element::Message(item));
+                        }
                     }
-                    item.widget = Some(create_row(
-                        session_client.clone(),
-                        user_info_cache.clone(),
-                        item.clone(),
-                        has_header,
-                        &rows,
-                    ));
-                    rows.borrow_mut().add_top(Element::Message(item));
                     if let Some(day_divider) = day_divider {
                         rows.borrow_mut().add_top(day_divider);
                     }
                 } else {
                     /* Remove the source id, since the closure is destroyed */
                     source_id.borrow_mut().take();
+                    let scroll_target = rows.borrow_mut().scroll_target.take();
+                    if let Some(event_id) = scroll_target {
+                        rows.borrow().scroll_to_message(&event_id);
+                    }
                     return Continue(false);
                 }
                 Continue(true)
@@ -539,6 +665,23 @@ impl RoomHistory {
         if let Some(id) = self.source_id.borrow_mut().take() {
             source::source_remove(id);
         }
+        if let Some(id) = self.rows.borrow_mut().read_marker_debounce.take() {
+            source::source_remove(id);
+        }
+    }
+
+    /// Removes the new messages divider, once all messages are read.
+    pub fn dismiss_new_divider(&self) {
+        let rows = self.rows.borrow();
+        let divider = rows
+            .new_divider_index
+            .and_then(|i| match rows.list.get(i)? {
+                Element::NewDivider(divider) => Some(divider.clone()),
+                _ => None,
+            });
+        if let Some(divider) = divider {
+            divider.dismiss();
+        }
     }
 
     /* This is a temporary function to make the listbox accessible from outside the history, it is
@@ -571,6 +714,16 @@ impl RoomHistory {
                         }
                         message.mtype == RowType::Emote || !should_group_message(&item, &message)
                     }
+                    Element::State(ref group) => {
+                        if group
+                            .date()
+                            .map_or(false, |date| item.msg.date.day() != date.day())
+                        {
+                            day_divider =
+                                Some(Element::DayDivider(create_day_divider(item.msg.date)));
+                        }
+                        true
+                    }
                     _ => false,
                 }
             } else {
@@ -578,6 +731,15 @@ impl RoomHistory {
             }
         };
 
+        if item.mtype == RowType::State && day_divider.is_none() && !item.last_viewed {
+            /* Membership changes following each other share a row */
+            if let Some(Element::State(group)) = rows.list.front_mut() {
+                if group.push_back(item.clone()) {
+                    return None;
+                }
+            }
+        }
+
         if item.last_viewed {
             let divider = Element::NewDivider(List::create_new_message_divider(self.rows.clone()));
             rows.add_bottom(divider);
@@ -588,6 +750,11 @@ impl RoomHistory {
             rows.add_bottom(day_divider);
         }
 
+        if item.mtype == RowType::State {
+            rows.add_bottom(Element::State(widgets::StateGroup::new(item)));
+            return None;
+        }
+
         let b = create_row(
             session_client,
             user_info_cache,
@@ -636,6 +803,63 @@ impl RoomHistory {
         None
     }
 
+    /// Rebuilds the row of a message already in the history.
+    pub fn update_message(
+        &mut self,
+        session_client: MatrixClient,
+        user_info_cache: UserInfoCache,
+        item: MessageContent,
+    ) -> Option<()> {
+        self.rebuild_message(session_client, user_info_cache, item, true)
+    }
+
+    /// Rebuilds the row of a message sent from here once its event comes back
+    /// from the server, with the date given by the server.
+    pub fn confirm_message(
+        &mut self,
+        session_client: MatrixClient,
+        user_info_cache: UserInfoCache,
+        item: MessageContent,
+    ) -> Option<()> {
+        self.rebuild_message(session_client, user_info_cache, item, false)
+    }
+
+    fn rebuild_message(
+        &mut self,
+        session_client: MatrixClient,
+        user_info_cache: UserInfoCache,
+        mut item: MessageContent,
+        keep_date: bool,
+    ) -> Option<()> {
+        let mut rows = self.rows.borrow_mut();
+
+        let (i, ref mut msg) =
+            rows.list
+                .iter_mut()
+                .rev()
+                .enumerate()
+                .find_map(|(i, e)| match e {
+                    Element::Message(ref mut itermessage) if itermessage.msg.id == item.msg.id => {
+                        Some((i, itermessage))
+                    }
+                    _ => None,
+                })?;
+        if keep_date {
+            item.msg.date = msg.msg.date;
+        }
+        let msg_widget = msg.widget.clone()?;
+
+        item.widget = Some(create_row(
+            session_client,
+            user_info_cache,
+            item.clone(),
+            msg_widget.has_header(),
+            &self.rows,
+        ));
+        rows.replace_item(i, Element::Message(item));
+        None
+    }
+
     pub fn remove_message(
         &mut self,
         session_client: MatrixClient,
@@ -700,9 +924,16 @@ impl RoomHistory {
         for item in messages {
             self.add_new_message(session_client.clone(), user_info_cache.clone(), item);
         }
+        self.rows.borrow().view.reset_newer_request_sent();
         None
     }
 
+    /// Sets whether the history shows messages apart from the latest ones, loading
+    /// newer messages when scrolling down.
+    pub fn set_detached(&self, detached: bool) {
+        self.rows.borrow().view.set_detached(detached);
+    }
+
     pub fn add_old_messages_in_batch(
         &mut self,
         session_client: MatrixClient,
@@ -719,6 +950,29 @@ impl RoomHistory {
         None
     }
 
+    /// Scrolls to the message with the given id and highlights it for a moment.
+    ///
+    /// Returns `false` if the message isn't loaded in the history.
+    pub fn scroll_to_message(&self, event_id: &EventId) -> bool {
+        self.rows.borrow().scroll_to_message(event_id)
+    }
+
+    /// Scrolls to the message with the given id, once it's shown if the history is
+    /// still loading.
+    ///
+    /// Returns `false` if the message isn't loaded in the history.
+    pub fn scroll_to_message_when_shown(&self, event_id: EventId) -> bool {
+        if self.scroll_to_message(&event_id) {
+            return true;
+        }
+        if self.source_id.borrow().is_none() {
+            return false;
+        }
+
+        self.rows.borrow_mut().scroll_target = Some(event_id);
+        true
+    }
+
     pub fn typing_notification(&mut self, typing_str: &str) {
         self.rows.borrow().view.typing_notification(typing_str);
     }
@@ -19,6 +19,8 @@ use chrono::prelude::*;
 pub struct RoomUpdated {
     pub room: Room,
     pub updated: DateTime<Local>,
+    // Whether there's an unsent message in the room
+    pub draft: bool,
 }
 
 impl RoomUpdated {
@@ -28,7 +30,11 @@ impl RoomUpdated {
             None => Local.ymd(1970, 1, 1).and_hms(0, 0, 0),
         };
 
-        RoomUpdated { room, updated }
+        RoomUpdated {
+            room,
+            updated,
+            draft: false,
+        }
     }
 
     pub fn up(&mut self) {
@@ -168,9 +174,12 @@ impl RoomListGroup {
             }
         }
 
-        rv.insert(pos, RoomUpdated::new(r.room.clone()));
+        let mut updated = RoomUpdated::new(r.room.clone());
+        updated.draft = r.draft;
+        rv.insert(pos, updated);
 
         let row = RoomRow::new(r.room);
+        row.set_draft(r.draft);
         self.list.insert(&row.widget(), pos as i32);
 
         self.rooms.insert(rid, row);
@@ -183,6 +192,16 @@ impl RoomListGroup {
         }
     }
 
+    pub fn set_draft(&mut self, room_id: &RoomId, draft: bool) {
+        if let Some(r) = self.rooms.get(room_id) {
+            r.set_draft(draft);
+        }
+
+        self.edit_room(room_id, move |rv| {
+            rv.draft = draft;
+        });
+    }
+
     pub fn rooms_with_notifications(&self) -> usize {
         self.rooms
             .iter()
@@ -659,6 +678,10 @@ impl RoomList {
         run_in_group!(self, &room_id, set_bold, room_id, bold)
     }
 
+    pub fn set_draft(&mut self, room_id: &RoomId, draft: bool) {
+        run_in_group!(self, room_id, set_draft, room_id, draft)
+    }
+
     pub fn add_room(&mut self, r: Room) {
         if r.membership.is_invited() {
             self.inv.get().add_room(r);
@@ -2,22 +2,24 @@ use gtk::prelude::*;
 use url::Url;
 
 use crate::model::room::Room;
+use crate::util::i18n::i18n;
 
 use crate::widgets;
 use crate::widgets::AvatarExt;
 
 const ICON_SIZE: i32 = 24;
 
-// Room row for the room sidebar. This widget shows the room avatar, the room name and the unread
-// messages in the room
-// +-----+--------------------------+------+
-// | IMG | Fractal                  |  32  |
-// +-----+--------------------------+------+
+// Room row for the room sidebar. This widget shows the room avatar, the room name, a pencil if
+// there's a draft and the unread messages in the room
+// +-----+--------------------------+---+------+
+// | IMG | Fractal                  | ✎ |  32  |
+// +-----+--------------------------+---+------+
 pub struct RoomRow {
     pub room: Room,
     pub icon: widgets::Avatar,
     pub direct: gtk::Image,
     pub text: gtk::Label,
+    pub draft: gtk::Image,
     pub notifications: gtk::Label,
     pub widget: gtk::EventBox,
 }
@@ -37,6 +39,11 @@ impl RoomRow {
         text.set_halign(gtk::Align::Start);
         text.set_ellipsize(pango::EllipsizeMode::End);
 
+        let draft = gtk::Image::from_icon_name(Some("document-edit-symbolic"), gtk::IconSize::Menu);
+        draft.set_tooltip_text(Some(&i18n("Unsent message")));
+        draft.get_style_context().add_class("room-draft");
+        draft.set_no_show_all(true);
+
         let n = room.notifications;
         let h = room.highlight;
         let ntext = if room.membership.is_invited() {
@@ -66,6 +73,7 @@ impl RoomRow {
             room,
             icon,
             text,
+            draft,
             notifications,
             widget,
             direct,
@@ -103,6 +111,10 @@ impl RoomRow {
         }
     }
 
+    pub fn set_draft(&self, draft: bool) {
+        self.draft.set_visible(draft);
+    }
+
     pub fn render_notifies(&self) {
         let n = self.room.notifications;
         if n > 0 || self.room.membership.is_invited() {
@@ -143,6 +155,7 @@ impl RoomRow {
         self.text.set_valign(gtk::Align::Center);
         self.notifications.set_valign(gtk::Align::Center);
         b.pack_start(&self.text, true, true, 0);
+        b.pack_start(&self.draft, false, false, 0);
         b.pack_start(&self.notifications, false, false, 5);
         self.widget.show_all();
 
@@ -3,6 +3,7 @@ use std::rc::Rc;
 
 use gio::Action;
 use gio::ActionExt;
+use gio::ActionGroupExt;
 use glib::clone;
 use glib::source::Continue;
 use gtk::prelude::*;
@@ -29,6 +30,10 @@ pub struct ScrollWidget {
     autoscroll: Rc<Cell<bool>>,
     /// Whether a request for more messages has been send or not.
     request_sent: Rc<Cell<bool>>,
+    /// Whether the history shown is apart from the latest messages.
+    detached: Rc<Cell<bool>>,
+    /// Whether a request for newer messages of a detached history has been send or not.
+    newer_request_sent: Rc<Cell<bool>>,
     widgets: Widgets,
 }
 
@@ -118,7 +123,11 @@ impl Widgets {
 }
 
 impl ScrollWidget {
-    pub fn new(action: Option<Action>, room_id: RoomId) -> ScrollWidget {
+    pub fn new(
+        action: Option<Action>,
+        newer_action: Option<Action>,
+        room_id: RoomId,
+    ) -> ScrollWidget {
         let builder = gtk::Builder::new();
 
         builder
@@ -137,14 +146,21 @@ impl ScrollWidget {
             upper: Rc::new(Cell::new(upper)),
             autoscroll: Rc::new(Cell::new(false)),
             request_sent: Rc::new(Cell::new(false)),
+            detached: Rc::new(Cell::new(false)),
+            newer_request_sent: Rc::new(Cell::new(false)),
             balance: Rc::new(Cell::new(None)),
         };
-        scroll.connect(action, room_id);
+        scroll.connect(action, newer_action, room_id);
         scroll
     }
 
     /* Keep the same position if new messages are added */
-    pub fn connect(&mut self, action: Option<Action>, room_id: RoomId) -> Option<()> {
+    pub fn connect(
+        &mut self,
+        action: Option<Action>,
+        newer_action: Option<Action>,
+        room_id: RoomId,
+    ) -> Option<()> {
         let adj = self.widgets.view.get_vadjustment()?;
         let upper = &self.upper;
         let balance = &self.balance;
@@ -175,12 +191,19 @@ impl ScrollWidget {
         let request_sent = Rc::downgrade(&self.request_sent);
         let spinner = self.widgets.spinner.downgrade();
         let action_weak = action.map(|a| a.downgrade());
+        let newer_action_weak = newer_action.map(|a| a.downgrade());
+        let detached = &self.detached;
+        let newer_request_sent = &self.newer_request_sent;
         adj.connect_value_changed(clone!(
         @weak autoscroll,
+        @weak detached,
+        @weak newer_request_sent,
         @weak revealer as r
         => move |adj| {
             let bottom = adj.get_upper() - adj.get_page_size();
-            if (adj.get_value() - bottom).abs() < std::f64::EPSILON {
+            // A detached history doesn't end with the latest messages, so the button
+            // to go there stays
+            if (adj.get_value() - bottom).abs() < std::f64::EPSILON && !detached.get() {
                 r.set_reveal_child(false);
                 autoscroll.set(true);
             } else {
@@ -207,13 +230,33 @@ impl ScrollWidget {
                 Some(())
             }();
             debug_assert!(check.is_some(), "Can't request more messages");
+
+            /* Load newer messages once the user is nearly at the end of a detached history */
+            if detached.get()
+                && !newer_request_sent.get()
+                && adj.get_value() > bottom - adj.get_page_size() * 2.0
+            {
+                if let Some(action) = newer_action_weak.as_ref().and_then(|a| a.upgrade()) {
+                    let data = glib::Variant::from(&room_id.to_string());
+                    action.activate(Some(&data));
+                    newer_request_sent.set(true);
+                }
+            }
         }));
 
         self.widgets.button.connect_clicked(clone!(
         @weak autoscroll,
+        @weak detached,
         @weak revealer as r,
         @weak view as s
         => move |_| {
+            // The latest messages have to be loaded again
+            if detached.get() {
+                if let Some(app) = gio::Application::get_default() {
+                    app.activate_action("jump-to-latest", None);
+                }
+                return;
+            }
             r.set_reveal_child(false);
             autoscroll.set(true);
             scroll_down(&s, true);
@@ -263,6 +306,21 @@ impl ScrollWidget {
         self.widgets.spinner.stop();
     }
 
+    pub fn reset_newer_request_sent(&self) {
+        self.newer_request_sent.set(false);
+    }
+
+    /// Sets whether the history shown is apart from the latest messages, which are
+    /// then loaded again when scrolling down.
+    pub fn set_detached(&self, detached: bool) {
+        self.detached.set(detached);
+        self.newer_request_sent.set(false);
+        if detached {
+            self.autoscroll.set(false);
+            self.widgets.btn_revealer.set_reveal_child(true);
+        }
+    }
+
     pub fn typing_notification(&self, typing_str: &str) {
         if typing_str.is_empty() {
             self.widgets.typing_label.set_visible(false);
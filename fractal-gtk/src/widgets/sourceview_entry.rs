@@ -1,6 +1,11 @@
+use crate::util::i18n::i18n;
+use crate::util::mention::{self, Mention};
 use gtk::prelude::*;
 use libhandy::prelude::*;
 use sourceview4::ViewExt;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Instant;
 // This alias is necessary to avoid conflict with gtk's TextViewExt
 use gspell::TextViewExt as GspellTextViewExt;
 
@@ -11,10 +16,18 @@ pub struct SVEntry {
     pub attach: gtk::Button,
     pub markdown: gtk::MenuButton,
     pub markdown_img: gtk::Image,
+    pub emoji: gtk::MenuButton,
     pub entry_box: gtk::Box,
+    pub relation_box: gtk::Box,
+    pub relation_label: gtk::Label,
     pub scroll: gtk::ScrolledWindow,
     pub view: sourceview4::View,
     pub buffer: sourceview4::Buffer,
+    pub record: gtk::Button,
+    pub recording_box: gtk::Box,
+    pub recording_label: gtk::Label,
+    // When the voice message being recorded was started, if any
+    recording_start: Rc<Cell<Option<Instant>>>,
     pub send: gtk::Button,
 }
 
@@ -54,9 +67,34 @@ impl Default for SVEntry {
         </object>
         */
 
+        let emoji = gtk::MenuButton::new();
+        let emoji_img = gtk::Image::from_icon_name(Some("face-smile-symbolic"), size);
+        emoji.set_image(Some(&emoji_img));
+        emoji.set_valign(gtk::Align::End);
+        emoji.set_receives_default(true);
+
         let entry_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
         entry_box.get_style_context().add_class("message-input");
 
+        // Shows which message is being edited or replied to, hidden otherwise
+        let relation_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        relation_box
+            .get_style_context()
+            .add_class("message-relation");
+        let relation_label = gtk::Label::new(None);
+        relation_label.set_ellipsize(pango::EllipsizeMode::End);
+        relation_label.set_xalign(0.0);
+        relation_label.show();
+        let relation_cancel =
+            gtk::Button::from_icon_name(Some("window-close-symbolic"), gtk::IconSize::Menu);
+        relation_cancel.get_style_context().add_class("flat");
+        relation_cancel.set_action_name(Some("app.cancel-relation"));
+        relation_cancel.show();
+        relation_box.pack_start(&relation_label, true, true, 0);
+        relation_box.pack_start(&relation_cancel, false, false, 0);
+        relation_box.set_no_show_all(true);
+        entry_box.add(&relation_box);
+
         let hadjust: Option<&gtk::Adjustment> = None;
         let vadjust: Option<&gtk::Adjustment> = None;
         let scroll = gtk::ScrolledWindow::new(hadjust, vadjust);
@@ -79,6 +117,49 @@ impl Default for SVEntry {
         scroll.set_propagate_natural_height(true);
         entry_box.add(&scroll);
 
+        let record = gtk::Button::new();
+        let record_img = gtk::Image::from_icon_name(Some("audio-input-microphone-symbolic"), size);
+        record.set_image(Some(&record_img));
+        record.set_valign(gtk::Align::End);
+        record.set_receives_default(true);
+        record.set_tooltip_text(Some(i18n("Record a voice message").as_str()));
+        record.set_action_name(Some("app.record-voice"));
+
+        // Replaces the text entry while a voice message is being recorded
+        let recording_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        recording_box.set_hexpand(true);
+        recording_box.set_valign(gtk::Align::End);
+        let recording_cancel = gtk::Button::new();
+        let recording_cancel_img = gtk::Image::from_icon_name(Some("user-trash-symbolic"), size);
+        recording_cancel.set_image(Some(&recording_cancel_img));
+        recording_cancel.set_tooltip_text(Some(i18n("Cancel recording").as_str()));
+        recording_cancel.set_action_name(Some("app.cancel-voice"));
+        let recording_indicator =
+            gtk::Image::from_icon_name(Some("media-record-symbolic"), gtk::IconSize::Menu);
+        recording_indicator
+            .get_style_context()
+            .add_class("recording-indicator");
+        let recording_label = gtk::Label::new(None);
+        recording_label.set_hexpand(true);
+        recording_label.set_xalign(0.0);
+        let recording_send = gtk::Button::new();
+        let recording_send_img = gtk::Image::from_icon_name(Some("send-symbolic"), size);
+        recording_send.set_image(Some(&recording_send_img));
+        recording_send.set_tooltip_text(Some(i18n("Send voice message").as_str()));
+        recording_send
+            .get_style_context()
+            .add_class("suggested-action");
+        recording_send.set_action_name(Some("app.send-voice"));
+        recording_box.pack_start(&recording_cancel, false, false, 0);
+        recording_box.pack_start(&recording_indicator, false, false, 0);
+        recording_box.pack_start(&recording_label, true, true, 0);
+        recording_box.pack_start(&recording_send, false, false, 0);
+        recording_cancel.show();
+        recording_indicator.show();
+        recording_label.show();
+        recording_send.show();
+        recording_box.set_no_show_all(true);
+
         let send = gtk::Button::new();
         let send_img = gtk::Image::from_icon_name(Some("send-symbolic"), size);
         send.set_image(Some(&send_img));
@@ -89,7 +170,10 @@ impl Default for SVEntry {
 
         container.pack_start(&attach, false, false, 0);
         container.pack_start(&markdown, false, false, 0);
+        container.pack_start(&emoji, false, false, 0);
         container.pack_start(&entry_box, false, true, 0);
+        container.pack_start(&recording_box, true, true, 0);
+        container.pack_start(&record, false, false, 0);
         container.pack_start(&send, false, false, 0);
 
         clamp.add(&container);
@@ -101,11 +185,97 @@ impl Default for SVEntry {
             attach,
             markdown,
             markdown_img,
+            emoji,
             entry_box,
+            relation_box,
+            relation_label,
             scroll,
             view,
             buffer,
+            record,
+            recording_box,
+            recording_label,
+            recording_start: Rc::new(Cell::new(None)),
             send,
         }
     }
 }
+
+impl SVEntry {
+    /// Replaces the text entry with the duration of the voice message being
+    /// recorded and the buttons to send or cancel it.
+    pub fn show_recording(&self) {
+        let start = Instant::now();
+        self.recording_start.set(Some(start));
+        self.recording_label.set_text(&format_recording_duration(0));
+        self.entry_box.hide();
+        self.record.hide();
+        self.send.hide();
+        self.recording_box.show();
+
+        let recording_start = self.recording_start.clone();
+        let label = self.recording_label.clone();
+        glib::timeout_add_local(250, move || {
+            // Stop once this recording is over, a new one gets its own timeout
+            if recording_start.get() != Some(start) {
+                return glib::Continue(false);
+            }
+            label.set_text(&format_recording_duration(start.elapsed().as_secs()));
+            glib::Continue(true)
+        });
+    }
+
+    pub fn hide_recording(&self) {
+        self.recording_start.set(None);
+        self.recording_box.hide();
+        self.entry_box.show();
+        self.record.show();
+        self.send.show();
+    }
+
+    /// The users and rooms inserted in the text from the autocompletion.
+    pub fn mentions(&self) -> Vec<Mention> {
+        let text = self
+            .buffer
+            .get_text(
+                &self.buffer.get_start_iter(),
+                &self.buffer.get_end_iter(),
+                false,
+            )
+            .map(|text| text.to_string())
+            .unwrap_or_default();
+        // The buffer is indexed by characters, mentions by bytes
+        let byte_offset = |offset: i32| {
+            text.char_indices()
+                .nth(offset as usize)
+                .map_or(text.len(), |(idx, _)| idx)
+        };
+
+        let mut mentions = vec![];
+        let mut iter = self.buffer.get_start_iter();
+        loop {
+            let tagged = iter.get_tags().into_iter().find_map(|tag| {
+                let name = tag.get_property_name()?;
+                mention::id_from_tag_name(&name).map(|id| (tag.clone(), id.to_string()))
+            });
+
+            if let Some((tag, id)) = tagged {
+                let start = iter.get_offset();
+                iter.forward_to_tag_toggle(Some(&tag));
+                mentions.push(Mention {
+                    start: byte_offset(start),
+                    end: byte_offset(iter.get_offset()),
+                    id,
+                });
+            } else if !iter.forward_to_tag_toggle(None::<&gtk::TextTag>) {
+                break;
+            }
+        }
+
+        mentions
+    }
+}
+
+fn format_recording_duration(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
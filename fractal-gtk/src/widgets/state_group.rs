@@ -0,0 +1,328 @@
+use crate::model::state_change::{MembershipChange, StateChange};
+use crate::ui::MessageContent;
+use crate::util::i18n::{i18n_k, ni18n_f};
+use chrono::prelude::*;
+use gtk::prelude::*;
+use std::collections::BTreeMap;
+
+/// A row of the room history showing a change of the room state, e.g. a member
+/// joining.
+///
+/// Membership changes following each other share a single row, collapsed to a
+/// summary like "5 people joined".
+#[derive(Clone)]
+pub struct StateGroup {
+    // The changes shown in the row, the oldest first
+    messages: Vec<MessageContent>,
+    widget: gtk::ListBoxRow,
+    label: gtk::Label,
+    expander: gtk::Expander,
+    summary: gtk::Label,
+    details: gtk::Box,
+}
+
+impl StateGroup {
+    pub fn new(content: MessageContent) -> Self {
+        let row = gtk::ListBoxRow::new();
+        row.set_selectable(false);
+        row.set_activatable(false);
+
+        let label = state_label(&content);
+
+        let summary = gtk::Label::new(None);
+        summary.set_xalign(0.0);
+        summary.set_line_wrap(true);
+        let details = gtk::Box::new(gtk::Orientation::Vertical, 3);
+        details.set_margin_top(3);
+        let expander = gtk::Expander::new(None);
+        expander.set_label_widget(Some(&summary));
+        expander.add(&details);
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        container.get_style_context().add_class("state-event");
+        container.get_style_context().add_class("dim-label");
+        container.set_margin_top(3);
+        container.set_margin_bottom(3);
+        container.set_margin_start(56);
+        container.set_margin_end(12);
+        container.pack_start(&label, false, false, 0);
+        container.pack_start(&expander, false, false, 0);
+        details.pack_start(&state_label(&content), false, false, 0);
+        row.add(&container);
+        row.show_all();
+
+        let group = Self {
+            messages: vec![content],
+            widget: row,
+            label,
+            expander,
+            summary,
+            details,
+        };
+        group.update();
+        group
+    }
+
+    /// Adds a newer change to the row, returns `false` if it can't be shown
+    /// together with the others.
+    pub fn push_back(&mut self, content: MessageContent) -> bool {
+        if !can_group(self.messages.last(), &content) {
+            return false;
+        }
+
+        let label = state_label(&content);
+        self.details.pack_start(&label, false, false, 0);
+        label.show();
+        self.messages.push(content);
+        self.update();
+        true
+    }
+
+    /// Adds an older change to the row, returns `false` if it can't be shown
+    /// together with the others.
+    pub fn push_front(&mut self, content: MessageContent) -> bool {
+        if !can_group(self.messages.first(), &content) {
+            return false;
+        }
+
+        let label = state_label(&content);
+        self.details.pack_start(&label, false, false, 0);
+        self.details.reorder_child(&label, 0);
+        label.show();
+        self.messages.insert(0, content);
+        self.update();
+        true
+    }
+
+    /// The date of the oldest change in the row.
+    pub fn date(&self) -> Option<DateTime<Local>> {
+        self.messages.first().map(|content| content.msg.date)
+    }
+
+    pub fn get_widget(&self) -> &gtk::ListBoxRow {
+        &self.widget
+    }
+
+    fn update(&self) {
+        let grouped = self.messages.len() > 1;
+        self.label.set_visible(!grouped);
+        self.expander.set_visible(grouped);
+        if !grouped {
+            return;
+        }
+
+        self.summary.set_text(&group_summary(&self.messages));
+    }
+}
+
+// The number of members for each kind of change, e.g. "2 people joined, 1 person left"
+fn group_summary(messages: &[MessageContent]) -> String {
+    let mut counts: BTreeMap<MembershipChange, u32> = BTreeMap::new();
+    for change in messages
+        .iter()
+        .filter_map(|content| content.msg.state.as_ref()?.membership())
+    {
+        *counts.entry(change).or_default() += 1;
+    }
+    let summary: Vec<String> = counts
+        .into_iter()
+        .map(|(change, count)| membership_summary(change, count))
+        .collect();
+    summary.join(", ")
+}
+
+// Only membership changes are shown together
+fn can_group(content: Option<&MessageContent>, other: &MessageContent) -> bool {
+    let is_membership = |content: &MessageContent| {
+        content
+            .msg
+            .state
+            .as_ref()
+            .and_then(StateChange::membership)
+            .is_some()
+    };
+    content.map_or(false, is_membership) && is_membership(other)
+}
+
+fn state_label(content: &MessageContent) -> gtk::Label {
+    let label = gtk::Label::new(Some(state_text(content).as_str()));
+    label.set_xalign(0.0);
+    label.set_line_wrap(true);
+    label.set_line_wrap_mode(pango::WrapMode::WordChar);
+    label.set_tooltip_text(Some(content.msg.date.format("%c").to_string().as_str()));
+    label
+}
+
+fn state_text(content: &MessageContent) -> String {
+    let sender = content
+        .sender_name
+        .clone()
+        .unwrap_or_else(|| content.msg.sender.to_string());
+    let state = match content.msg.state.as_ref() {
+        Some(state) => state,
+        None => return String::new(),
+    };
+
+    match state {
+        StateChange::Membership { change, name } => match change {
+            MembershipChange::Joined => i18n_k("{name} joined", &[("name", name)]),
+            MembershipChange::Left => i18n_k("{name} left", &[("name", name)]),
+            MembershipChange::Invited => i18n_k(
+                "{sender} invited {name}",
+                &[("sender", &sender), ("name", name)],
+            ),
+            MembershipChange::Kicked => i18n_k(
+                "{sender} removed {name}",
+                &[("sender", &sender), ("name", name)],
+            ),
+            MembershipChange::Banned => i18n_k(
+                "{sender} banned {name}",
+                &[("sender", &sender), ("name", name)],
+            ),
+            MembershipChange::Unbanned => i18n_k(
+                "{sender} unbanned {name}",
+                &[("sender", &sender), ("name", name)],
+            ),
+            MembershipChange::ProfileChanged => {
+                i18n_k("{name} changed their profile", &[("name", name)])
+            }
+        },
+        StateChange::Name(Some(name)) => i18n_k(
+            "{sender} changed the room name to {name}",
+            &[("sender", &sender), ("name", name)],
+        ),
+        StateChange::Name(None) => i18n_k("{sender} removed the room name", &[("sender", &sender)]),
+        StateChange::Topic(topic) if topic.is_empty() => {
+            i18n_k("{sender} removed the topic", &[("sender", &sender)])
+        }
+        StateChange::Topic(topic) => i18n_k(
+            "{sender} changed the topic to “{topic}”",
+            &[("sender", &sender), ("topic", topic)],
+        ),
+        StateChange::Avatar => i18n_k("{sender} changed the room avatar", &[("sender", &sender)]),
+        StateChange::Encryption => i18n_k(
+            "{sender} enabled end-to-end encryption",
+            &[("sender", &sender)],
+        ),
+    }
+}
+
+fn membership_summary(change: MembershipChange, count: u32) -> String {
+    let count_str = count.to_string();
+    let args = &[count_str.as_str()];
+    match change {
+        MembershipChange::Joined => ni18n_f("{} person joined", "{} people joined", count, args),
+        MembershipChange::Left => ni18n_f("{} person left", "{} people left", count, args),
+        MembershipChange::Invited => ni18n_f(
+            "{} person was invited",
+            "{} people were invited",
+            count,
+            args,
+        ),
+        MembershipChange::Kicked => ni18n_f(
+            "{} person was removed",
+            "{} people were removed",
+            count,
+            args,
+        ),
+        MembershipChange::Banned => {
+            ni18n_f("{} person was banned", "{} people were banned", count, args)
+        }
+        MembershipChange::Unbanned => ni18n_f(
+            "{} person was unbanned",
+            "{} people were unbanned",
+            count,
+            args,
+        ),
+        MembershipChange::ProfileChanged => ni18n_f(
+            "{} person changed their profile",
+            "{} people changed their profile",
+            count,
+            args,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::message::Message;
+    use crate::ui::RowType;
+    use matrix_sdk::identifiers::{RoomId, UserId};
+    use std::convert::TryFrom;
+
+    fn state_content(state: StateChange) -> MessageContent {
+        let mut msg = Message::new(
+            RoomId::try_from("!room:example.org").unwrap(),
+            UserId::try_from("@alice:example.org").unwrap(),
+            String::new(),
+            String::from("m.room.member"),
+            None,
+        );
+        msg.state = Some(state);
+        MessageContent {
+            msg,
+            sender_name: Some(String::from("Alice")),
+            mtype: RowType::State,
+            highlights: vec![],
+            redactable: false,
+            editable: false,
+            reactions: vec![],
+            reply: None,
+            thread_replies: 0,
+            receipts: vec![],
+            last_viewed: false,
+            widget: None,
+        }
+    }
+
+    fn membership(change: MembershipChange, name: &str) -> MessageContent {
+        state_content(StateChange::Membership {
+            change,
+            name: name.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_can_group() {
+        let joined = membership(MembershipChange::Joined, "Bob");
+        let left = membership(MembershipChange::Left, "Carol");
+        let topic = state_content(StateChange::Topic(String::from("Cats")));
+
+        assert!(can_group(Some(&joined), &left));
+        assert!(!can_group(Some(&joined), &topic));
+        assert!(!can_group(Some(&topic), &joined));
+        assert!(!can_group(None, &joined));
+    }
+
+    #[test]
+    fn test_state_text() {
+        assert_eq!(
+            state_text(&membership(MembershipChange::Invited, "Bob")),
+            "Alice invited Bob"
+        );
+        assert_eq!(
+            state_text(&membership(MembershipChange::Joined, "Bob")),
+            "Bob joined"
+        );
+        assert_eq!(
+            state_text(&state_content(StateChange::Topic(String::from("Cats")))),
+            "Alice changed the topic to “Cats”"
+        );
+        assert_eq!(
+            state_text(&state_content(StateChange::Topic(String::new()))),
+            "Alice removed the topic"
+        );
+    }
+
+    #[test]
+    fn test_group_summary() {
+        let messages = vec![
+            membership(MembershipChange::Left, "Dave"),
+            membership(MembershipChange::Joined, "Bob"),
+            membership(MembershipChange::Joined, "Carol"),
+        ];
+
+        assert_eq!(group_summary(&messages), "2 people joined, 1 person left");
+    }
+}